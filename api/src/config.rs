@@ -3,7 +3,11 @@
 //! Handles loading configuration from environment variables with sensible defaults.
 
 use anyhow::Result;
+use shared::query::{DEFAULT_MAX_CELLS, DEFAULT_MAX_ENTRY_CELLS};
+use shared::storage::QueryLimits;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// Server configuration.
 ///
@@ -11,6 +15,20 @@ use std::net::SocketAddr;
 /// - `HEIMSIGHT_HOST`: The host address to bind to (default: "0.0.0.0")
 /// - `HEIMSIGHT_PORT`: The HTTP port to listen on (default: 8080)
 /// - `HEIMSIGHT_GRPC_PORT`: The gRPC port to listen on (default: 4317)
+/// - `HEIMSIGHT_QUERY_TIMEOUT_MS`: Maximum query run time in milliseconds (default: unlimited)
+/// - `HEIMSIGHT_QUERY_MAX_ROWS_SCANNED`: Maximum rows a query may scan (default: unlimited)
+/// - `HEIMSIGHT_QUERY_MAX_RESULT_BYTES`: Maximum size of a query result (default: unlimited)
+/// - `HEIMSIGHT_QUERY_CACHE_TTL_MS`: How long query results are cached in milliseconds
+///   (default: no caching)
+/// - `HEIMSIGHT_QUERY_CACHE_MAX_ENTRIES`: Maximum number of cached results (default: 1000)
+/// - `HEIMSIGHT_QUERY_CACHE_MAX_CELLS`: Maximum number of cells (rows × columns) held by the
+///   cache (default: 10000000)
+/// - `HEIMSIGHT_QUERY_CACHE_MAX_ENTRY_CELLS`: Maximum number of cells in a single cached
+///   result; larger results are not cached (default: 100000)
+/// - `HEIMSIGHT_SAVED_QUERIES_PATH`: JSON file to store saved queries in (default: the
+///   database, or memory without one)
+/// - `HEIMSIGHT_SETTINGS_PATH`: JSON file to store the retention and aggregation
+///   configuration in (default: the database, or memory without one)
 #[derive(Debug, Clone)]
 pub struct Config {
     /// The host address to bind to.
@@ -19,8 +37,25 @@ pub struct Config {
     pub port: u16,
     /// The gRPC port to listen on.
     pub grpc_port: u16,
+    /// Limits applied to every query.
+    pub query_limits: QueryLimits,
+    /// How long query results are cached, or `None` to disable caching.
+    pub query_cache_ttl: Option<Duration>,
+    /// Maximum number of cached query results.
+    pub query_cache_max_entries: usize,
+    /// Maximum number of cells (rows × columns) held by the query cache.
+    pub query_cache_max_cells: usize,
+    /// Maximum number of cells in a single cached query result.
+    pub query_cache_max_entry_cells: usize,
+    /// File to store saved queries in, instead of the database.
+    pub saved_queries_path: Option<PathBuf>,
+    /// File to store the retention and aggregation configuration in, instead of the database.
+    pub settings_path: Option<PathBuf>,
 }
 
+/// Default maximum number of cached query results.
+const DEFAULT_QUERY_CACHE_MAX_ENTRIES: usize = 1000;
+
 impl Config {
     /// Creates a new configuration from environment variables.
     ///
@@ -29,6 +64,7 @@ impl Config {
     /// Returns an error if:
     /// - `HEIMSIGHT_PORT` is set but cannot be parsed as a valid port number
     /// - `HEIMSIGHT_GRPC_PORT` is set but cannot be parsed as a valid port number
+    /// - A `HEIMSIGHT_QUERY_*` limit or cache setting is set but cannot be parsed as a number
     pub fn from_env() -> Result<Self> {
         let host = std::env::var("HEIMSIGHT_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
 
@@ -44,10 +80,43 @@ impl Config {
             .transpose()?
             .unwrap_or(4317);
 
+        let mut query_limits = QueryLimits::new();
+        if let Some(ms) = parse_env::<u64>("HEIMSIGHT_QUERY_TIMEOUT_MS")? {
+            query_limits = query_limits.with_timeout(Duration::from_millis(ms));
+        }
+        if let Some(rows) = parse_env::<u64>("HEIMSIGHT_QUERY_MAX_ROWS_SCANNED")? {
+            query_limits = query_limits.with_max_rows_scanned(rows);
+        }
+        if let Some(bytes) = parse_env::<u64>("HEIMSIGHT_QUERY_MAX_RESULT_BYTES")? {
+            query_limits = query_limits.with_max_result_bytes(bytes);
+        }
+
+        let query_cache_ttl = parse_env::<u64>("HEIMSIGHT_QUERY_CACHE_TTL_MS")?
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis);
+        let query_cache_max_entries = parse_env::<usize>("HEIMSIGHT_QUERY_CACHE_MAX_ENTRIES")?
+            .unwrap_or(DEFAULT_QUERY_CACHE_MAX_ENTRIES);
+        let query_cache_max_cells =
+            parse_env::<usize>("HEIMSIGHT_QUERY_CACHE_MAX_CELLS")?.unwrap_or(DEFAULT_MAX_CELLS);
+        let query_cache_max_entry_cells =
+            parse_env::<usize>("HEIMSIGHT_QUERY_CACHE_MAX_ENTRY_CELLS")?
+                .unwrap_or(DEFAULT_MAX_ENTRY_CELLS);
+
+        let saved_queries_path =
+            std::env::var_os("HEIMSIGHT_SAVED_QUERIES_PATH").map(PathBuf::from);
+        let settings_path = std::env::var_os("HEIMSIGHT_SETTINGS_PATH").map(PathBuf::from);
+
         Ok(Self {
             host,
             port,
             grpc_port,
+            query_limits,
+            query_cache_ttl,
+            query_cache_max_entries,
+            query_cache_max_cells,
+            query_cache_max_entry_cells,
+            saved_queries_path,
+            settings_path,
         })
     }
 
@@ -82,6 +151,26 @@ impl Default for Config {
             host: "0.0.0.0".to_string(),
             port: 8080,
             grpc_port: 4317,
+            query_limits: QueryLimits::default(),
+            query_cache_ttl: None,
+            query_cache_max_entries: DEFAULT_QUERY_CACHE_MAX_ENTRIES,
+            query_cache_max_cells: DEFAULT_MAX_CELLS,
+            query_cache_max_entry_cells: DEFAULT_MAX_ENTRY_CELLS,
+            saved_queries_path: None,
+            settings_path: None,
         }
     }
 }
+
+/// Parses an optional environment variable.
+fn parse_env<T>(name: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    std::env::var(name)
+        .ok()
+        .map(|value| value.parse::<T>())
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Invalid {name}: {e}"))
+}
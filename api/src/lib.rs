@@ -92,6 +92,32 @@ pub async fn run_server() -> Result<()> {
             AppState::with_in_memory_store()
         }
     };
+    let mut state = state.with_query_limits(config.query_limits.clone());
+    if let Some(ttl) = config.query_cache_ttl {
+        tracing::info!(
+            ?ttl,
+            max_entries = config.query_cache_max_entries,
+            max_cells = config.query_cache_max_cells,
+            max_entry_cells = config.query_cache_max_entry_cells,
+            "Caching query results"
+        );
+        state = state.with_query_cache(
+            shared::query::QueryCache::new(ttl, config.query_cache_max_entries)
+                .with_max_cells(config.query_cache_max_cells)
+                .with_max_entry_cells(config.query_cache_max_entry_cells),
+        );
+    }
+    if let Some(path) = &config.saved_queries_path {
+        let store = shared::storage::FileSavedQueryStore::open(path)?;
+        tracing::info!(path = %path.display(), "Storing saved queries in file");
+        state = state.with_saved_query_store(std::sync::Arc::new(store));
+    }
+    if let Some(path) = &config.settings_path {
+        let store = shared::storage::FileSettingsStore::open(path)?;
+        tracing::info!(path = %path.display(), "Storing settings in file");
+        state = state.with_settings_store(std::sync::Arc::new(store));
+    }
+    state.restore_config().await;
 
     run_server_with_config_and_state(config, state).await
 }
@@ -133,12 +159,24 @@ pub async fn run_server_with_config_and_state(config: Config, state: AppState) -
     // Start data age monitoring background job
     let monitor = std::sync::Arc::new(metrics::DataAgeMonitor::new(
         state.clone(),
-        std::time::Duration::from_secs(3600), // Check every hour
+        std::time::Duration::from_hours(1), // Check every hour
     ));
     tokio::spawn(async move {
         monitor.run().await;
     });
 
+    // Expire data past its retention from stores that don't do so themselves
+    let retention_state = state.clone();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_mins(1));
+        loop {
+            tick.tick().await;
+            if let Err(e) = retention_state.enforce_retention(chrono::Utc::now()) {
+                tracing::error!(error = %e, "Failed to enforce retention");
+            }
+        }
+    });
+
     // Create HTTP server
     let app = create_router(state.clone());
     let listener = TcpListener::bind(http_addr).await?;
@@ -197,8 +235,11 @@ pub fn create_router(state: AppState) -> Router {
         .merge(routes::health_routes())
         .merge(routes::logs_routes(state.clone()))
         .merge(routes::query_routes(state.clone()))
+        .merge(routes::saved_queries_routes(state.clone()))
+        .merge(routes::fields_routes(state.clone()))
         .merge(routes::metrics_routes(state.clone()))
         .merge(routes::traces_routes(state.clone()))
+        .merge(routes::rollups_routes(state.clone()))
         .merge(routes::otlp_routes(state.clone()))
         .merge(routes::retention_routes(state.clone()))
         .merge(routes::aggregation_routes(state))
@@ -299,6 +340,7 @@ mod tests {
             host: "127.0.0.1".to_string(),
             port: 3000,
             grpc_port: 4317,
+            ..Config::default()
         };
         let addr = config.socket_addr();
         assert_eq!(addr.to_string(), "127.0.0.1:3000");
@@ -310,6 +352,7 @@ mod tests {
             host: "127.0.0.1".to_string(),
             port: 3000,
             grpc_port: 9090,
+            ..Config::default()
         };
         let addr = config.grpc_socket_addr();
         assert_eq!(addr.to_string(), "127.0.0.1:9090");
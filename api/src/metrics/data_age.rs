@@ -208,29 +208,29 @@ impl DataAgeMonitor {
                         "Data age metrics collected"
                     );
 
-                    // Check against configured retention policies
+                    // Check against the longest TTL of each retention policy
                     let config = self.state.get_retention_config();
 
-                    if metrics.logs.exceeds_ttl(config.logs.ttl_days) {
+                    if metrics.logs.exceeds_ttl(config.logs.max_ttl_days()) {
                         tracing::warn!(
                             age_days = metrics.logs.oldest_age_days,
-                            ttl_days = config.logs.ttl_days,
+                            ttl_days = config.logs.max_ttl_days(),
                             "Logs data exceeds configured TTL (ClickHouse should auto-delete)"
                         );
                     }
 
-                    if metrics.metrics.exceeds_ttl(config.metrics.ttl_days) {
+                    if metrics.metrics.exceeds_ttl(config.metrics.max_ttl_days()) {
                         tracing::warn!(
                             age_days = metrics.metrics.oldest_age_days,
-                            ttl_days = config.metrics.ttl_days,
+                            ttl_days = config.metrics.max_ttl_days(),
                             "Metrics data exceeds configured TTL (ClickHouse should auto-delete)"
                         );
                     }
 
-                    if metrics.traces.exceeds_ttl(config.traces.ttl_days) {
+                    if metrics.traces.exceeds_ttl(config.traces.max_ttl_days()) {
                         tracing::warn!(
                             age_days = metrics.traces.oldest_age_days,
-                            ttl_days = config.traces.ttl_days,
+                            ttl_days = config.traces.max_ttl_days(),
                             "Traces data exceeds configured TTL (ClickHouse should auto-delete)"
                         );
                     }
@@ -262,7 +262,7 @@ mod tests {
 
         // Age should be approximately 30 days
         let age = stats.oldest_age_days.unwrap();
-        assert!(age >= 29.9 && age <= 30.1);
+        assert!((29.9..=30.1).contains(&age));
     }
 
     #[test]
@@ -332,15 +332,15 @@ mod tests {
     #[test]
     fn test_data_age_monitor_creation() {
         let state = AppState::with_in_memory_store();
-        let monitor = DataAgeMonitor::new(state, Duration::from_secs(60));
+        let monitor = DataAgeMonitor::new(state, Duration::from_mins(1));
 
-        assert_eq!(monitor.interval_duration, Duration::from_secs(60));
+        assert_eq!(monitor.interval_duration, Duration::from_mins(1));
     }
 
     #[tokio::test]
     async fn test_data_age_monitor_collect_metrics_empty() {
         let state = AppState::with_in_memory_store();
-        let monitor = DataAgeMonitor::new(state, Duration::from_secs(60));
+        let monitor = DataAgeMonitor::new(state, Duration::from_mins(1));
 
         let metrics = monitor.collect_metrics().unwrap();
 
@@ -374,7 +374,7 @@ mod tests {
         let span = Span::new("trace1", "span1", "test", "service");
         state.trace_store().insert_span(span).unwrap();
 
-        let monitor = DataAgeMonitor::new(state, Duration::from_secs(60));
+        let monitor = DataAgeMonitor::new(state, Duration::from_mins(1));
         let metrics = monitor.collect_metrics().unwrap();
 
         assert_eq!(metrics.logs.count, 1);
@@ -4,6 +4,7 @@
 
 use axum::{
     extract::State,
+    http::StatusCode,
     response::{IntoResponse, Json, Response},
     routing::get,
     Router,
@@ -11,7 +12,7 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use shared::config::AggregationConfig;
 
-use crate::state::AppState;
+use crate::state::{AppState, TierFailure, TtlDrift, ViewDrift};
 
 /// Response body for aggregation operations.
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +25,16 @@ pub struct AggregationResponse {
     /// The current aggregation configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config: Option<AggregationConfig>,
+    /// Tables whose TTL differed from the saved configuration at startup.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<TtlDrift>,
+    /// Tier views whose attachment differed from the saved configuration at startup.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub view_drift: Vec<ViewDrift>,
+    /// Tier tables that could not be updated, when an update was only
+    /// partially applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<TierFailure>,
 }
 
 impl AggregationResponse {
@@ -32,15 +43,20 @@ impl AggregationResponse {
             success: true,
             message: None,
             config: Some(config),
+            drift: Vec::new(),
+            view_drift: Vec::new(),
+            failures: Vec::new(),
         }
     }
 
-    #[allow(dead_code)] // Will be used when PUT endpoint is added
     fn error(message: impl Into<String>) -> Self {
         Self {
             success: false,
             message: Some(message.into()),
             config: None,
+            drift: Vec::new(),
+            view_drift: Vec::new(),
+            failures: Vec::new(),
         }
     }
 }
@@ -50,9 +66,13 @@ impl AggregationResponse {
 /// # Routes
 ///
 /// - `GET /api/v1/config/aggregation` - Get current aggregation configuration
+/// - `PUT /api/v1/config/aggregation` - Update the aggregation configuration
 pub fn aggregation_routes(state: AppState) -> Router {
     Router::new()
-        .route("/api/v1/config/aggregation", get(get_aggregation_config))
+        .route(
+            "/api/v1/config/aggregation",
+            get(get_aggregation_config).put(update_aggregation_config),
+        )
         .with_state(state)
 }
 
@@ -60,7 +80,77 @@ pub fn aggregation_routes(state: AppState) -> Router {
 ///
 /// Returns the current aggregation configuration.
 async fn get_aggregation_config(State(state): State<AppState>) -> Response {
-    let config = state.get_aggregation_config();
+    let mut response = AggregationResponse::success(state.get_aggregation_config());
+    response.drift = state.aggregation_drift();
+    response.view_drift = state.view_drift();
+    Json(response).into_response()
+}
+
+/// Handler for PUT /api/v1/config/aggregation.
+///
+/// Updates the aggregation configuration, reconfiguring the rollup tables and
+/// their materialized views first.
+///
+/// If some tier tables could not be updated, the others keep their update,
+/// the configuration is not saved, and the failed tables are listed under
+/// `failures`. Updating a table again is harmless, so the request can be retried.
+async fn update_aggregation_config(
+    State(state): State<AppState>,
+    Json(config): Json<AggregationConfig>,
+) -> Response {
+    // Validate the configuration
+    if let Err(e) = config.validate() {
+        return (StatusCode::BAD_REQUEST, Json(AggregationResponse::error(e))).into_response();
+    }
+
+    // Reconfigure the ClickHouse rollup pipeline if available
+    match state.update_clickhouse_aggregation(&config).await {
+        Ok(failures) if !failures.is_empty() => {
+            tracing::error!(
+                failed_tables = failures.len(),
+                "Failed to update some ClickHouse tier tables"
+            );
+            let tables: Vec<&str> = failures.iter().map(|f| f.table.as_str()).collect();
+            let mut response = AggregationResponse::error(format!(
+                "Failed to update the tier tables {}; the other tables were updated \
+                 and the configuration was not saved",
+                tables.join(", ")
+            ));
+            response.failures = failures;
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response();
+        }
+        Ok(_) => {}
+        // Only fail if we have a ClickHouse client but the operation failed
+        Err(e) if state.clickhouse_client().is_some() => {
+            tracing::error!(error = %e, "Failed to update ClickHouse aggregation pipeline");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AggregationResponse::error(format!(
+                    "Failed to update database aggregation: {e}"
+                ))),
+            )
+                .into_response();
+        }
+        // Otherwise just log a debug message (in-memory mode)
+        Err(_) => tracing::debug!("ClickHouse not available, skipping aggregation update"),
+    }
+
+    save_aggregation_config(&state, config)
+}
+
+/// Saves the configuration so it survives restarts, and responds with it.
+fn save_aggregation_config(state: &AppState, config: AggregationConfig) -> Response {
+    if let Err(e) = state.save_aggregation_config(config.clone()) {
+        tracing::error!(error = %e, "Failed to save aggregation configuration");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(AggregationResponse::error(format!(
+                "Failed to save aggregation configuration: {e}"
+            ))),
+        )
+            .into_response();
+    }
+
     Json(AggregationResponse::success(config)).into_response()
 }
 
@@ -68,7 +158,8 @@ async fn get_aggregation_config(State(state): State<AppState>) -> Response {
 mod tests {
     use super::*;
     use axum::body::Body;
-    use axum::http::{Request, StatusCode};
+    use axum::http::Request;
+    use shared::config::AggregationInterval;
     use tower::ServiceExt;
 
     fn create_test_router() -> Router {
@@ -99,8 +190,60 @@ mod tests {
         assert!(agg_response.success);
         assert!(agg_response.config.is_some());
         let config = agg_response.config.unwrap();
-        assert!(!config.enabled); // Disabled by default
+        assert!(config.enabled); // Enabled by default
         assert_eq!(config.one_minute.retention_days, 30);
         assert_eq!(config.one_hour.retention_days, 365);
     }
+
+    async fn put_config(app: Router, body: String) -> (StatusCode, AggregationResponse) {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri("/api/v1/config/aggregation")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_update_aggregation_config_valid() {
+        let state = AppState::with_in_memory_store();
+        let app = aggregation_routes(state.clone());
+
+        let mut new_config = AggregationConfig {
+            enabled: true,
+            ..AggregationConfig::default()
+        };
+        new_config.update_policy(AggregationInterval::OneMinute, 7, false);
+        let (status, response) = put_config(app, serde_json::to_string(&new_config).unwrap()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(response.success);
+        assert_eq!(response.config.as_ref(), Some(&new_config));
+        assert_eq!(state.get_aggregation_config(), new_config);
+    }
+
+    #[tokio::test]
+    async fn test_update_aggregation_config_invalid() {
+        let state = AppState::with_in_memory_store();
+        let app = aggregation_routes(state.clone());
+
+        let mut new_config = AggregationConfig::default();
+        new_config.one_day.retention_days = 0;
+        let (status, response) = put_config(app, serde_json::to_string(&new_config).unwrap()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(!response.success);
+        assert!(response.message.unwrap().contains("greater than zero"));
+        assert_eq!(state.get_aggregation_config(), AggregationConfig::default());
+    }
 }
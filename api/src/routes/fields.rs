@@ -0,0 +1,147 @@
+//! Field discovery endpoints.
+//!
+//! Lists the fields that can be queried in logs, metrics and traces, with their
+//! types and cardinality, and the most frequent values of a field.
+
+use super::limits::{run_cancellable, LimitParams};
+use super::query::{execution_error, QueryError};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::query::{discover_fields, top_values, FieldFilter, FieldInfo, FieldValueCount, Source};
+use tokio_util::sync::CancellationToken;
+
+/// Maximum number of values returned for a field.
+const MAX_VALUES_LIMIT: usize = 100;
+
+/// Default number of values returned for a field.
+const DEFAULT_VALUES_LIMIT: usize = 10;
+
+/// Query parameters for field discovery.
+#[derive(Debug, Deserialize)]
+pub struct FieldParams {
+    /// The source to discover fields in: `logs`, `metrics` or `traces`.
+    pub source: Source,
+
+    /// Only consider records of this service (for metrics, the `service` label).
+    pub service: Option<String>,
+
+    /// Only consider records from this time (inclusive).
+    pub start_time: Option<DateTime<Utc>>,
+
+    /// Only consider records up to this time (exclusive).
+    pub end_time: Option<DateTime<Utc>>,
+
+    /// Maximum number of values to return (default: 10, max: 100). Only used
+    /// when listing the values of a field.
+    pub limit: Option<usize>,
+}
+
+impl FieldParams {
+    fn filter(&self) -> FieldFilter {
+        let mut filter = FieldFilter::new(self.source.clone());
+        if let Some(ref service) = self.service {
+            filter = filter.with_service(service);
+        }
+        if let Some(start) = self.start_time {
+            filter = filter.with_start_time(start);
+        }
+        if let Some(end) = self.end_time {
+            filter = filter.with_end_time(end);
+        }
+        filter
+    }
+}
+
+/// Response for `GET /api/v1/fields`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldsResponse {
+    /// The source the fields belong to.
+    pub source: Source,
+    /// Built-in fields, then attributes or labels by name.
+    pub fields: Vec<FieldInfo>,
+    /// Number of fields returned.
+    pub total_count: usize,
+}
+
+/// Response for `GET /api/v1/fields/{name}/values`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldValuesResponse {
+    /// The field the values belong to.
+    pub field: String,
+    /// The most frequent values, most frequent first.
+    pub values: Vec<FieldValueCount>,
+}
+
+/// Creates the field discovery routes.
+///
+/// # Routes
+///
+/// - `GET /api/v1/fields?source=logs&service=api` - List fields with their types and cardinality
+/// - `GET /api/v1/fields/{name}/values?source=logs` - List the most frequent values of a field
+pub fn fields_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/fields", get(list_fields))
+        .route("/api/v1/fields/{name}/values", get(list_field_values))
+        .with_state(state)
+}
+
+/// Handler for GET /api/v1/fields.
+async fn list_fields(
+    State(state): State<AppState>,
+    Query(params): Query<FieldParams>,
+) -> Result<Json<FieldsResponse>, (StatusCode, Json<QueryError>)> {
+    let filter = params.filter();
+    let token = CancellationToken::new();
+    let limits = LimitParams::default().limits(state.query_limits(), &token);
+
+    let fields = run_cancellable(&token, move || {
+        discover_fields(&filter, state.query_stores().with_limits(&limits))
+    })
+    .await
+    .map_err(execution_error)?;
+
+    Ok(Json(FieldsResponse {
+        source: params.source,
+        total_count: fields.len(),
+        fields,
+    }))
+}
+
+/// Handler for GET /api/v1/fields/{name}/values.
+async fn list_field_values(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<FieldParams>,
+) -> Result<Json<FieldValuesResponse>, (StatusCode, Json<QueryError>)> {
+    let filter = params.filter();
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_VALUES_LIMIT)
+        .clamp(1, MAX_VALUES_LIMIT);
+    let token = CancellationToken::new();
+    let limits = LimitParams::default().limits(state.query_limits(), &token);
+
+    let field = name.clone();
+    let values = run_cancellable(&token, move || {
+        top_values(
+            &filter,
+            &field,
+            limit,
+            state.query_stores().with_limits(&limits),
+        )
+    })
+    .await
+    .map_err(execution_error)?;
+
+    Ok(Json(FieldValuesResponse {
+        field: name,
+        values,
+    }))
+}
@@ -0,0 +1,137 @@
+//! Query limits for request handlers.
+//!
+//! Queries run on a blocking thread with the server's limits, tightened by the
+//! limits given in the request. Each query has a cancellation token that is
+//! cancelled when the handler is dropped, which happens when the client
+//! disconnects, so the store stops waiting for the backend and the backend
+//! cancels the query.
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+use shared::storage::{Limit, LimitError, QueryLimits};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Per-request query limits.
+///
+/// Request limits can only tighten the limits configured for the server.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct LimitParams {
+    /// Maximum time the query may run, in milliseconds.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// Maximum number of rows the query may scan.
+    #[serde(default)]
+    pub max_rows_scanned: Option<u64>,
+
+    /// Maximum size of the query result, in bytes.
+    #[serde(default)]
+    pub max_result_bytes: Option<u64>,
+}
+
+impl LimitParams {
+    /// Combines the server's limits with the request's, cancelled by `token`.
+    pub fn limits(self, server: &QueryLimits, token: &CancellationToken) -> QueryLimits {
+        let mut request = QueryLimits::new().with_cancellation(token.clone());
+        if let Some(ms) = self.timeout_ms {
+            request = request.with_timeout(Duration::from_millis(ms));
+        }
+        if let Some(rows) = self.max_rows_scanned {
+            request = request.with_max_rows_scanned(rows);
+        }
+        if let Some(bytes) = self.max_result_bytes {
+            request = request.with_max_result_bytes(bytes);
+        }
+        server.restrict(&request)
+    }
+}
+
+/// Runs `f` on a blocking thread, cancelling `token` if the returned future is
+/// dropped before `f` completes.
+///
+/// # Panics
+///
+/// Resumes the panic if `f` panics.
+pub async fn run_cancellable<T, F>(token: &CancellationToken, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let guard = token.clone().drop_guard();
+    let result = tokio::task::spawn_blocking(f).await;
+    guard.disarm();
+
+    match result {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Returns the HTTP status, error type and exceeded limit for a limit error.
+pub fn limit_error(e: &LimitError) -> (StatusCode, &'static str, Option<Limit>) {
+    match e {
+        LimitError::Exceeded {
+            limit: Limit::Timeout,
+            ..
+        } => (
+            StatusCode::GATEWAY_TIMEOUT,
+            "query_limit_exceeded",
+            Some(Limit::Timeout),
+        ),
+        LimitError::Exceeded { limit, .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "query_limit_exceeded",
+            Some(*limit),
+        ),
+        LimitError::Cancelled => (StatusCode::SERVICE_UNAVAILABLE, "query_cancelled", None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_limits_tighten_server_limits() {
+        let server = QueryLimits::new()
+            .with_timeout(Duration::from_secs(10))
+            .with_max_rows_scanned(1_000);
+        let params = LimitParams {
+            timeout_ms: Some(60_000),
+            max_rows_scanned: Some(10),
+            max_result_bytes: None,
+        };
+
+        let token = CancellationToken::new();
+        let limits = params.limits(&server, &token);
+        assert_eq!(limits.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(limits.max_rows_scanned, Some(10));
+        assert_eq!(limits.max_result_bytes, None);
+
+        token.cancel();
+        assert!(limits.cancellation.unwrap().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_run_cancellable_cancels_when_dropped() {
+        let token = CancellationToken::new();
+        assert_eq!(run_cancellable(&token, || 42).await, 42);
+        assert!(!token.is_cancelled());
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let blocked = token.clone();
+        let task = run_cancellable(&token, move || {
+            started_tx.send(()).unwrap();
+            while !blocked.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+        // Start the query, then drop it as a disconnecting client would
+        tokio::select! {
+            () = task => panic!("query should still be running"),
+            () = async { while started_rx.try_recv().is_err() { tokio::task::yield_now().await } } => {}
+        }
+        assert!(token.is_cancelled());
+    }
+}
@@ -2,18 +2,22 @@
 //!
 //! Provides HTTP endpoints for ingesting and querying log data in Heimsight.
 
+use super::limits::{limit_error, run_cancellable, LimitParams};
+use super::ndjson;
 use crate::state::AppState;
 use axum::{
     extract::{rejection::JsonRejection, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::post,
     Json, Router,
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use shared::models::{LogEntry, LogLevel};
-use shared::storage::LogQuery;
+use shared::storage::{Cursor, Limit, LogQuery, LogStoreError};
 use std::collections::HashMap;
+use tokio_util::sync::CancellationToken;
 
 /// Request body for log ingestion - can be a single log or a batch.
 #[derive(Debug, Deserialize)]
@@ -127,6 +131,18 @@ pub struct LogQueryParams {
 
     /// Number of logs to skip (for pagination).
     pub offset: Option<usize>,
+
+    /// Cursor from a previous response's `next_cursor`, to fetch the next page.
+    pub cursor: Option<String>,
+
+    /// Maximum time the query may run, in milliseconds.
+    pub timeout_ms: Option<u64>,
+
+    /// Maximum number of logs the query may scan.
+    pub max_rows_scanned: Option<u64>,
+
+    /// Maximum size of the query result, in bytes.
+    pub max_result_bytes: Option<u64>,
 }
 
 /// Response for log queries.
@@ -146,6 +162,10 @@ pub struct LogQueryResponse {
 
     /// Offset used for this query.
     pub offset: usize,
+
+    /// Cursor for the next page, or `null` if this is the last page.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 /// Generic API error response.
@@ -155,6 +175,9 @@ pub struct ApiError {
     pub error: String,
     /// Detailed error message.
     pub message: String,
+    /// The limit that stopped the query, for `query_limit_exceeded` errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Limit>,
 }
 
 /// Creates the log routes with application state.
@@ -278,20 +301,36 @@ async fn ingest_logs(
 
 /// Handler for log queries.
 ///
-/// Returns logs matching the provided query parameters.
+/// Returns logs matching the provided query parameters. With an
+/// `Accept: application/x-ndjson` header, the logs are streamed one per line
+/// instead; streamed responses have no default or maximum limit and no counts.
+///
+/// The query runs within the server's limits, tightened by `timeout_ms`,
+/// `max_rows_scanned` and `max_result_bytes`, and is cancelled if the client
+/// disconnects.
 async fn query_logs(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<LogQueryParams>,
-) -> Result<Json<LogQueryResponse>, (StatusCode, Json<ApiError>)> {
-    // Apply defaults and limits
-    let limit = params
-        .limit
-        .unwrap_or(DEFAULT_QUERY_LIMIT)
-        .min(MAX_QUERY_LIMIT);
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
     let offset = params.offset.unwrap_or(0);
 
     // Build the query
-    let mut query = LogQuery::new().with_limit(limit).with_offset(offset);
+    let mut query = LogQuery::new().with_offset(offset);
+
+    if let Some(cursor) = params.cursor {
+        let cursor = Cursor::decode(&cursor).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiError {
+                    error: "invalid_cursor".to_string(),
+                    message: e.to_string(),
+                    limit: None,
+                }),
+            )
+        })?;
+        query = query.with_cursor(cursor);
+    }
 
     // Time range filters
     if let Some(start) = params.start_time {
@@ -312,17 +351,61 @@ async fn query_logs(
         query = query.with_message_contains(contains);
     }
 
-    // Execute the query
-    let result = state.log_store().query(query).map_err(|e| {
+    let token = CancellationToken::new();
+    let limits = LimitParams {
+        timeout_ms: params.timeout_ms,
+        max_rows_scanned: params.max_rows_scanned,
+        max_result_bytes: params.max_result_bytes,
+    }
+    .limits(state.query_limits(), &token);
+    query = query.with_limits(limits);
+
+    let storage_error = |e: LogStoreError| {
+        if let LogStoreError::Limit(ref limit_err) = e {
+            tracing::warn!(error = %e, "Log query stopped by its limits");
+            let (status, error, limit) = limit_error(limit_err);
+            return (
+                status,
+                Json(ApiError {
+                    error: error.to_string(),
+                    message: e.to_string(),
+                    limit,
+                }),
+            );
+        }
         tracing::error!(error = %e, "Failed to query logs");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError {
                 error: "storage_error".to_string(),
                 message: "Failed to query logs".to_string(),
+                limit: None,
             }),
         )
-    })?;
+    };
+
+    if ndjson::accepts_ndjson(&headers) {
+        if let Some(limit) = params.limit {
+            query = query.with_limit(limit);
+        }
+        let logs = run_cancellable(&token, move || state.log_store().stream(query))
+            .await
+            .map_err(storage_error)?;
+        return Ok(ndjson::stream(logs, token));
+    }
+
+    // Apply defaults and limits
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT);
+
+    // Execute the query
+    let result = run_cancellable(&token, move || {
+        state.log_store().query(query.with_limit(limit))
+    })
+    .await
+    .map_err(storage_error)?;
 
     Ok(Json(LogQueryResponse {
         returned_count: result.logs.len(),
@@ -330,7 +413,9 @@ async fn query_logs(
         total_count: result.total_count,
         limit,
         offset,
-    }))
+        next_cursor: result.next_cursor.as_ref().map(Cursor::encode),
+    })
+    .into_response())
 }
 
 #[cfg(test)]
@@ -1107,7 +1192,7 @@ mod tests {
 
         assert_eq!(result.total_count, 10); // Total errors before pagination
         assert_eq!(result.logs.len(), 3); // After limit
-        assert_eq!(result.logs[0].message, "Error 2"); // After offset
+        assert_eq!(result.logs[0].message, "Error 7"); // Newest first, after offset
     }
 
     #[tokio::test]
@@ -1138,4 +1223,59 @@ mod tests {
         assert_eq!(result.total_count, 0);
         assert!(result.logs.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_query_with_cursor() {
+        let (app, state) = create_test_router_with_state();
+
+        let base = chrono::Utc::now() - chrono::Duration::hours(1);
+        for i in 0..5 {
+            let mut log = LogEntry::new(LogLevel::Info, format!("Log {i}"), "api");
+            log.timestamp = base + chrono::Duration::seconds(i);
+            state.log_store().insert(log).unwrap();
+        }
+
+        let mut messages = Vec::new();
+        let mut uri = "/api/v1/logs?limit=2".to_string();
+        loop {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(&uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let result: LogQueryResponse = serde_json::from_slice(&body).unwrap();
+            messages.extend(result.logs.into_iter().map(|log| log.message));
+
+            match result.next_cursor {
+                Some(cursor) => uri = format!("/api/v1/logs?limit=2&cursor={cursor}"),
+                None => break,
+            }
+        }
+
+        assert_eq!(messages, ["Log 4", "Log 3", "Log 2", "Log 1", "Log 0"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_with_invalid_cursor() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/logs?cursor=garbage")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.error, "invalid_cursor");
+    }
 }
@@ -7,8 +7,10 @@ use axum::{
     routing::post,
     Json, Router,
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use shared::models::{Metric, MetricType, MetricValue};
+use shared::query::{record_cells, CacheStatus, Source};
 use shared::storage::{AggregationFunction, MetricQuery};
 use std::collections::HashMap;
 
@@ -69,15 +71,20 @@ pub struct MetricQueryParams {
 }
 
 /// Response for metric queries.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricQueryResponse {
     pub metrics: Vec<Metric>,
     pub total_count: usize,
     pub aggregation: Option<AggregationResponse>,
+    /// Whether an aggregation was served from the query cache (`hit`, `miss`,
+    /// or `bypass` when the response is too large to cache). Omitted without
+    /// `aggregate` or when caching is disabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStatus>,
 }
 
 /// Aggregation result.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AggregationResponse {
     pub function: String,
     pub value: f64,
@@ -139,6 +146,11 @@ async fn ingest_metrics(
     ))
 }
 
+/// Handler for GET /api/v1/metrics.
+///
+/// Aggregations (`aggregate=...`) are served from the query cache when it is
+/// enabled, keyed by the normalized query and the current time rounded down
+/// to the cache's TTL, like `/api/v1/query`.
 async fn query_metrics(
     State(state): State<AppState>,
     Query(params): Query<MetricQueryParams>,
@@ -158,25 +170,46 @@ async fn query_metrics(
         query = query.with_offset(offset);
     }
 
-    // Handle aggregation
-    let aggregation = if let Some(ref agg) = params.aggregate {
-        let func = match agg.to_lowercase().as_str() {
-            "sum" => AggregationFunction::Sum,
-            "avg" => AggregationFunction::Avg,
-            "min" => AggregationFunction::Min,
-            "max" => AggregationFunction::Max,
-            "count" => AggregationFunction::Count,
-            _ => {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(MetricError {
-                        error: "invalid_aggregation".to_string(),
-                        message: format!("Unknown aggregation: {agg}"),
-                    }),
-                ))
-            }
-        };
+    // Aggregation names are case-insensitive
+    let aggregate = params.aggregate.map(|agg| agg.to_lowercase());
+    let function = match aggregate.as_deref() {
+        None => None,
+        Some("sum") => Some(AggregationFunction::Sum),
+        Some("avg") => Some(AggregationFunction::Avg),
+        Some("min") => Some(AggregationFunction::Min),
+        Some("max") => Some(AggregationFunction::Max),
+        Some("count") => Some(AggregationFunction::Count),
+        Some(agg) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(MetricError {
+                    error: "invalid_aggregation".to_string(),
+                    message: format!("Unknown aggregation: {agg}"),
+                }),
+            ))
+        }
+    };
 
+    let cache = state.query_cache().zip(function).map(|(cache, func)| {
+        let key = serde_json::json!({
+            "function": func,
+            "name": query.name,
+            "metric_type": query.metric_type,
+            "limit": query.limit,
+            "offset": query.offset,
+            "window": cache.align(Utc::now()),
+        });
+        (cache, format!("metrics:{key}"))
+    });
+    if let Some((cache, key)) = &cache {
+        if let Some(mut response) = cache.get::<MetricQueryResponse>(key) {
+            response.cache = Some(CacheStatus::Hit);
+            return Ok(Json(response));
+        }
+    }
+
+    // Handle aggregation
+    let aggregation = if let Some(func) = function {
         let result = state
             .metric_store()
             .aggregate(query.clone(), func)
@@ -191,7 +224,7 @@ async fn query_metrics(
             })?;
 
         Some(AggregationResponse {
-            function: agg.clone(),
+            function: aggregate.unwrap_or_default(),
             value: result.value,
             count: result.count,
         })
@@ -209,11 +242,20 @@ async fn query_metrics(
         )
     })?;
 
-    Ok(Json(MetricQueryResponse {
+    let mut response = MetricQueryResponse {
         metrics: result.metrics,
         total_count: result.total_count,
         aggregation,
-    }))
+        cache: None,
+    };
+    if let Some((cache, key)) = cache {
+        let cells = record_cells(&Source::Metrics, response.metrics.len());
+        response.cache = Some(CacheStatus::Miss);
+        if !cache.insert(key, response.clone(), cells) {
+            response.cache = Some(CacheStatus::Bypass);
+        }
+    }
+    Ok(Json(response))
 }
 
 #[cfg(test)]
@@ -6,12 +6,17 @@ use crate::state::AppState;
 use axum::Router;
 
 mod aggregation;
+mod fields;
 mod health;
+mod limits;
 mod logs;
 mod metrics;
+mod ndjson;
 mod otlp;
 mod query;
 mod retention;
+mod rollups;
+mod saved_queries;
 mod traces;
 
 pub use health::health_routes;
@@ -26,6 +31,16 @@ pub fn query_routes(state: AppState) -> Router {
     query::query_routes(state)
 }
 
+/// Creates field discovery routes with the given application state.
+pub fn fields_routes(state: AppState) -> Router {
+    fields::fields_routes(state)
+}
+
+/// Creates saved query routes with the given application state.
+pub fn saved_queries_routes(state: AppState) -> Router {
+    saved_queries::saved_queries_routes(state)
+}
+
 /// Creates metrics routes with the given application state.
 pub fn metrics_routes(state: AppState) -> Router {
     metrics::metrics_routes(state)
@@ -36,6 +51,11 @@ pub fn traces_routes(state: AppState) -> Router {
     traces::traces_routes(state)
 }
 
+/// Creates aggregation tier routes with the given application state.
+pub fn rollups_routes(state: AppState) -> Router {
+    rollups::rollups_routes(state)
+}
+
 /// Creates OTLP routes with the given application state.
 pub fn otlp_routes(state: AppState) -> Router {
     otlp::otlp_routes(state)
@@ -0,0 +1,209 @@
+//! Newline-delimited JSON (NDJSON) streaming responses.
+//!
+//! Query endpoints stream their results as NDJSON when the request has an
+//! `Accept: application/x-ndjson` header. Records are read from the store on a
+//! blocking thread and written to the response one line at a time. A bounded
+//! channel between the two provides backpressure: the store is only read as fast
+//! as the client receives. When the client disconnects, the query's cancellation
+//! token is cancelled, which also aborts a read that is waiting on the store.
+
+use super::limits::limit_error;
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use shared::query::ExecutionError;
+use shared::storage::{Limit, LimitError, LogStoreError};
+use std::convert::Infallible;
+use std::fmt::Display;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+/// Media type of NDJSON responses.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Number of lines buffered between the store and the client.
+const BUFFERED_LINES: usize = 256;
+
+/// Error line written when reading fails after the response has started.
+#[derive(Serialize)]
+struct ErrorLine {
+    error: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<Limit>,
+}
+
+/// An error that ends a stream.
+pub trait StreamError: Display {
+    /// Returns the limit error if the query was stopped by its limits.
+    fn limit_error(&self) -> Option<&LimitError> {
+        None
+    }
+}
+
+impl StreamError for ExecutionError {
+    fn limit_error(&self) -> Option<&LimitError> {
+        self.limit()
+    }
+}
+
+impl StreamError for LogStoreError {
+    fn limit_error(&self) -> Option<&LimitError> {
+        match self {
+            Self::Limit(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl StreamError for String {}
+
+/// Returns `true` if the request accepts an NDJSON response.
+pub fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            media_type
+                .split(';')
+                .next()
+                .is_some_and(|t| t.trim().eq_ignore_ascii_case(NDJSON_CONTENT_TYPE))
+        })
+}
+
+/// Streams `records` as an NDJSON response, one JSON object per line.
+///
+/// The status is `200 OK` as soon as streaming starts, so an error while reading
+/// is written as a final `{"error": "storage_error", "message": ...}` line, or
+/// `{"error": "query_limit_exceeded", "message": ..., "limit": ...}` if the query
+/// was stopped by its limits. `token` is cancelled when the response body is
+/// dropped.
+pub fn stream<I, T, E>(records: I, token: CancellationToken) -> Response
+where
+    I: Iterator<Item = Result<T, E>> + Send + 'static,
+    T: Serialize,
+    E: StreamError,
+{
+    let (tx, rx) = mpsc::channel::<Result<String, Infallible>>(BUFFERED_LINES);
+    let guard = token.drop_guard();
+
+    tokio::task::spawn_blocking(move || {
+        for record in records {
+            let (line, last) = match record {
+                Ok(record) => match serde_json::to_string(&record) {
+                    Ok(line) => (line, false),
+                    Err(e) => (error_line("serialization_error", &e, None), true),
+                },
+                Err(e) => {
+                    if let Some(limit_err) = e.limit_error() {
+                        tracing::warn!(error = %e, "Query stream stopped by its limits");
+                        let (_, error, limit) = limit_error(limit_err);
+                        (error_line(error, &e, limit), true)
+                    } else {
+                        tracing::error!(error = %e, "Failed to stream query results");
+                        (error_line("storage_error", &e, None), true)
+                    }
+                }
+            };
+
+            // A send error means the client has gone away
+            if tx.blocking_send(Ok(line + "\n")).is_err() || last {
+                break;
+            }
+        }
+    });
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(NDJSON_CONTENT_TYPE),
+        )],
+        Body::from_stream(ReceiverStream::new(rx).map(move |line| {
+            // Dropping the body drops the guard, cancelling the query
+            let _ = &guard;
+            line
+        })),
+    )
+        .into_response()
+}
+
+/// Serializes an error line.
+fn error_line(error: &'static str, e: &impl Display, limit: Option<Limit>) -> String {
+    let error = ErrorLine {
+        error,
+        message: e.to_string(),
+        limit,
+    };
+    serde_json::to_string(&error).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[test]
+    fn test_accepts_ndjson() {
+        let accepts = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            accepts_ndjson(&headers)
+        };
+
+        assert!(accepts("application/x-ndjson"));
+        assert!(accepts("application/json, application/x-ndjson;q=0.9"));
+        assert!(!accepts("application/json"));
+        assert!(!accepts("*/*"));
+        assert!(!accepts_ndjson(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn test_stream_writes_lines_and_errors() {
+        let records: Vec<Result<serde_json::Value, String>> = vec![
+            Ok(serde_json::json!({"a": 1})),
+            Ok(serde_json::json!({"a": 2})),
+            Err("connection reset".to_string()),
+            Ok(serde_json::json!({"a": 3})),
+        ];
+
+        let token = CancellationToken::new();
+        let response = stream(records.into_iter(), token.clone());
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            NDJSON_CONTENT_TYPE
+        );
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "{\"a\":1}\n{\"a\":2}\n\
+             {\"error\":\"storage_error\",\"message\":\"connection reset\"}\n"
+        );
+        // The body has been dropped
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_stream_writes_limit_errors() {
+        let records: Vec<Result<serde_json::Value, LogStoreError>> = vec![
+            Ok(serde_json::json!({"a": 1})),
+            Err(LogStoreError::Limit(LimitError::Exceeded {
+                limit: Limit::ResultBytes,
+                message: "result too large".to_string(),
+            })),
+        ];
+
+        let response = stream(records.into_iter(), CancellationToken::new());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let last = std::str::from_utf8(&body).unwrap().lines().last().unwrap();
+        let line: serde_json::Value = serde_json::from_str(last).unwrap();
+        assert_eq!(line["error"], "query_limit_exceeded");
+        assert_eq!(line["limit"], "max_result_bytes");
+    }
+}
@@ -1,36 +1,97 @@
 //! SQL-like query endpoint.
 //!
-//! Provides an endpoint for executing SQL-like queries against the log store.
+//! Provides an endpoint for executing SQL-like queries against the log, metric and
+//! trace stores.
 
+use super::limits::{limit_error, run_cancellable, LimitParams};
+use super::ndjson;
 use crate::state::AppState;
-use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
 use serde::{Deserialize, Serialize};
-use shared::models::LogEntry;
-use shared::query::{execute_query, parse_query, ExecutionError, ParseError, Query};
+use shared::query::{
+    execute_query_cached, execute_query_with_cursor, explain_query, parse_query, stream_query,
+    BindError, CacheStatus, ExecutionError, ParseError, Query, QueryData, QueryParams, QueryPlan,
+    SyntaxError,
+};
+use shared::storage::{Cursor, CursorError, Limit};
+use tokio_util::sync::CancellationToken;
 
 /// Request body for SQL-like queries.
 #[derive(Debug, Deserialize)]
 pub struct QueryRequest {
     /// The SQL-like query string.
     pub query: String,
+
+    /// Values for the query's `$name` parameters.
+    #[serde(default)]
+    pub params: QueryParams,
+
+    /// Cursor from a previous response's `next_cursor`, to fetch the next page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+
+    /// Limits for this query (`timeout_ms`, `max_rows_scanned`,
+    /// `max_result_bytes`), tightening the server's limits.
+    #[serde(flatten)]
+    pub limits: LimitParams,
 }
 
 /// Response for successful query execution.
+///
+/// The records are tagged by data type: `{"type": "logs", "logs": [...]}`,
+/// `{"type": "metrics", "metrics": [...]}` or `{"type": "spans", "spans": [...]}`.
+/// Queries with a projection list or `GROUP BY` return a table instead:
+/// `{"type": "rows", "columns": [...], "rows": [[...], ...]}`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryResponse {
-    /// The logs matching the query.
-    pub logs: Vec<LogEntry>,
+    /// The records matching the query.
+    #[serde(flatten)]
+    pub data: QueryData,
 
-    /// Total count of matching logs (before limit/offset applied).
+    /// Total count of matching records (before limit/offset applied).
     pub total_count: usize,
 
-    /// Number of logs returned in this response.
+    /// Number of records returned in this response.
     pub returned_count: usize,
 
+    /// Cursor for the next page, or `null` if this is the last page.
+    ///
+    /// Only set for non-aggregate queries with a `LIMIT` in the default order.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+
+    /// Whether the result came from the query cache: `hit`, `partial` (cached
+    /// time buckets plus newly computed ones), `miss` or `bypass`. Omitted when
+    /// caching is disabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStatus>,
+
     /// The parsed query (for debugging/transparency).
     pub parsed_query: Query,
 }
 
+/// Response for `POST /api/v1/query/explain`.
+///
+/// Lists the operations compiled into backend statements (`pushed_down`) and
+/// those evaluated in memory (`in_memory`). For `ClickHouse` stores, `statements`
+/// holds the exact SQL with `ClickHouse`'s `EXPLAIN ESTIMATE` of the rows and
+/// granules each statement reads, and `params` the values bound to it.
+#[derive(Debug, Serialize)]
+pub struct ExplainResponse {
+    /// How the query would be executed.
+    #[serde(flatten)]
+    pub plan: QueryPlan,
+
+    /// The parsed query.
+    pub parsed_query: Query,
+}
+
 /// Error response for query operations.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryError {
@@ -38,6 +99,12 @@ pub struct QueryError {
     pub error: String,
     /// Detailed error message.
     pub message: String,
+    /// The limit that stopped the query, for `query_limit_exceeded` errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Limit>,
+    /// Where the query is invalid, for `parse_error` errors from bad syntax.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syntax: Option<Box<SyntaxError>>,
 }
 
 impl From<ParseError> for QueryError {
@@ -45,15 +112,50 @@ impl From<ParseError> for QueryError {
         Self {
             error: "parse_error".to_string(),
             message: e.to_string(),
+            limit: None,
+            syntax: e.syntax().cloned().map(Box::new),
+        }
+    }
+}
+
+impl From<BindError> for QueryError {
+    fn from(e: BindError) -> Self {
+        Self {
+            error: "invalid_parameter".to_string(),
+            message: e.to_string(),
+            limit: None,
+            syntax: None,
+        }
+    }
+}
+
+impl From<CursorError> for QueryError {
+    fn from(e: CursorError) -> Self {
+        Self {
+            error: "invalid_cursor".to_string(),
+            message: e.to_string(),
+            limit: None,
+            syntax: None,
         }
     }
 }
 
 impl From<ExecutionError> for QueryError {
     fn from(e: ExecutionError) -> Self {
+        if let Some(limit_err) = e.limit() {
+            let (_, error, limit) = limit_error(limit_err);
+            return Self {
+                error: error.to_string(),
+                message: limit_err.to_string(),
+                limit,
+                syntax: None,
+            };
+        }
         Self {
             error: "execution_error".to_string(),
             message: e.to_string(),
+            limit: None,
+            syntax: None,
         }
     }
 }
@@ -62,44 +164,151 @@ impl From<ExecutionError> for QueryError {
 pub fn query_routes(state: AppState) -> Router {
     Router::new()
         .route("/api/v1/query", post(execute_sql_query))
+        .route("/api/v1/query/explain", post(explain_sql_query))
         .with_state(state)
 }
 
+/// Maps an execution error to its HTTP status and error body.
+pub(super) fn execution_error(e: ExecutionError) -> (StatusCode, Json<QueryError>) {
+    if let Some(limit_err) = e.limit() {
+        tracing::warn!(error = %e, "Query stopped by its limits");
+        let (status, _, _) = limit_error(limit_err);
+        return (status, Json(QueryError::from(e)));
+    }
+    tracing::error!(error = %e, "Failed to execute query");
+    let status = match &e {
+        ExecutionError::StorageError(_)
+        | ExecutionError::MetricStorageError(_)
+        | ExecutionError::TraceStorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        ExecutionError::UnsupportedSource(_)
+        | ExecutionError::UnknownField { .. }
+        | ExecutionError::TypeMismatch { .. }
+        | ExecutionError::InvalidAggregation(_)
+        | ExecutionError::InvalidCursor(_)
+        | ExecutionError::UnboundParameter(_) => StatusCode::BAD_REQUEST,
+    };
+    (status, Json(QueryError::from(e)))
+}
+
+/// Parses the request's query and binds its parameters.
+fn parse_request(request: &QueryRequest) -> Result<Query, (StatusCode, Json<QueryError>)> {
+    let query = parse_query(&request.query).map_err(|e| {
+        tracing::debug!(query = %request.query, error = %e, "Failed to parse query");
+        (StatusCode::BAD_REQUEST, Json(QueryError::from(e)))
+    })?;
+    query.bind(&request.params).map_err(|e| {
+        tracing::debug!(query = %request.query, error = %e, "Failed to bind query parameters");
+        (StatusCode::BAD_REQUEST, Json(QueryError::from(e)))
+    })
+}
+
 /// Handler for SQL-like query execution.
 ///
-/// Parses and executes a SQL-like query against the log store.
+/// Parses a SQL-like query, binds its `$name` parameters from `params` and
+/// executes it against the store for its source; see [`run_query`].
 async fn execute_sql_query(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<QueryRequest>,
-) -> Result<Json<QueryResponse>, (StatusCode, Json<QueryError>)> {
-    // Parse the query
-    let query = parse_query(&request.query).map_err(|e| {
-        tracing::debug!(query = %request.query, error = %e, "Failed to parse query");
-        (StatusCode::BAD_REQUEST, Json(QueryError::from(e)))
-    })?;
+) -> Result<Response, (StatusCode, Json<QueryError>)> {
+    let query = parse_request(&request)?;
+    let cursor = request
+        .cursor
+        .as_deref()
+        .map(Cursor::decode)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(QueryError::from(e))))?;
+
+    run_query(state, &headers, query, cursor, request.limits).await
+}
+
+/// Runs a parsed query and builds its response.
+///
+/// With an `Accept: application/x-ndjson` header, the records are streamed one
+/// per line instead of being returned in a single JSON document. The query runs
+/// on a blocking thread within the server's limits tightened by `limits`, and is
+/// cancelled if the client disconnects. With a query cache, JSON results are
+/// served from and stored in the cache; streamed results are never cached.
+pub(super) async fn run_query(
+    state: AppState,
+    headers: &HeaderMap,
+    query: Query,
+    cursor: Option<Cursor>,
+    limits: LimitParams,
+) -> Result<Response, (StatusCode, Json<QueryError>)> {
+    let token = CancellationToken::new();
+    let limits = limits.limits(state.query_limits(), &token);
+
+    if ndjson::accepts_ndjson(headers) {
+        let records = run_cancellable(&token, move || {
+            let stores = state.query_stores().with_limits(&limits);
+            stream_query(&query, cursor.as_ref(), stores)
+        })
+        .await
+        .map_err(execution_error)?;
+        return Ok(ndjson::stream(records, token));
+    }
 
     // Execute the query
-    let result = execute_query(&query, state.log_store()).map_err(|e| {
-        tracing::error!(error = %e, "Failed to execute query");
-        let status = match &e {
-            ExecutionError::StorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            ExecutionError::UnsupportedSource(_)
-            | ExecutionError::UnknownField(_)
-            | ExecutionError::TypeMismatch { .. } => StatusCode::BAD_REQUEST,
+    let (query, result) = run_cancellable(&token, move || {
+        let stores = state.query_stores().with_limits(&limits);
+        let result = match state.query_cache() {
+            Some(cache) => execute_query_cached(&query, cursor.as_ref(), stores, cache)
+                .map(|(result, status)| (result, Some(status))),
+            None => execute_query_with_cursor(&query, cursor.as_ref(), stores)
+                .map(|result| (result, None)),
         };
-        (status, Json(QueryError::from(e)))
-    })?;
+        (query, result)
+    })
+    .await;
+    let (result, cache) = result.map_err(execution_error)?;
 
     tracing::debug!(
         total = result.total_count,
-        returned = result.logs.len(),
+        returned = result.data.len(),
+        ?cache,
         "Query executed successfully"
     );
 
     Ok(Json(QueryResponse {
-        returned_count: result.logs.len(),
-        logs: result.logs,
+        returned_count: result.data.len(),
+        data: result.data,
         total_count: result.total_count,
+        next_cursor: result.next_cursor.as_ref().map(Cursor::encode),
+        cache,
+        parsed_query: query,
+    })
+    .into_response())
+}
+
+/// Handler for query explanation.
+///
+/// Parses a query and describes how it would be executed, without running it.
+/// Like [`run_query`], the explanation runs on a blocking thread within the
+/// request's limits and is cancelled if the client disconnects.
+async fn explain_sql_query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<ExplainResponse>, (StatusCode, Json<QueryError>)> {
+    let query = parse_request(&request)?;
+    let cursor = request
+        .cursor
+        .as_deref()
+        .map(Cursor::decode)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(QueryError::from(e))))?;
+
+    let token = CancellationToken::new();
+    let limits = request.limits.limits(state.query_limits(), &token);
+    let (query, plan) = run_cancellable(&token, move || {
+        let stores = state.query_stores().with_limits(&limits);
+        let plan = explain_query(&query, cursor.as_ref(), stores);
+        (query, plan)
+    })
+    .await;
+
+    Ok(Json(ExplainResponse {
+        plan: plan.map_err(execution_error)?,
         parsed_query: query,
     }))
 }
@@ -110,7 +319,7 @@ mod tests {
     use axum::body::Body;
     use axum::http::{header, Request, StatusCode};
     use http_body_util::BodyExt;
-    use shared::models::LogLevel;
+    use shared::models::{LogEntry, LogLevel, Metric, Span};
     use tower::ServiceExt;
 
     fn create_test_router() -> Router {
@@ -123,6 +332,13 @@ mod tests {
         (router, state)
     }
 
+    fn logs(response: &QueryResponse) -> &[LogEntry] {
+        match &response.data {
+            QueryData::Logs { logs } => logs,
+            other => panic!("Expected logs, got {other:?}"),
+        }
+    }
+
     #[tokio::test]
     async fn test_query_simple_select() {
         let (app, state) = create_test_router_with_state();
@@ -151,7 +367,7 @@ mod tests {
         let result: QueryResponse = serde_json::from_slice(&body).unwrap();
 
         assert_eq!(result.total_count, 1);
-        assert_eq!(result.logs.len(), 1);
+        assert_eq!(logs(&result).len(), 1);
     }
 
     #[tokio::test]
@@ -187,7 +403,7 @@ mod tests {
         let result: QueryResponse = serde_json::from_slice(&body).unwrap();
 
         assert_eq!(result.total_count, 1);
-        assert!(result.logs.iter().all(|l| l.level == LogLevel::Error));
+        assert!(logs(&result).iter().all(|l| l.level == LogLevel::Error));
     }
 
     #[tokio::test]
@@ -227,7 +443,7 @@ mod tests {
         let result: QueryResponse = serde_json::from_slice(&body).unwrap();
 
         assert_eq!(result.total_count, 1);
-        assert_eq!(result.logs[0].message, "API error");
+        assert_eq!(logs(&result)[0].message, "API error");
     }
 
     #[tokio::test]
@@ -258,7 +474,7 @@ mod tests {
         let body = response.into_body().collect().await.unwrap().to_bytes();
         let result: QueryResponse = serde_json::from_slice(&body).unwrap();
 
-        assert_eq!(result.logs.len(), 5);
+        assert_eq!(logs(&result).len(), 5);
         assert_eq!(result.total_count, 10);
     }
 
@@ -286,6 +502,35 @@ mod tests {
         assert_eq!(error.error, "parse_error");
     }
 
+    #[tokio::test]
+    async fn test_query_invalid_pattern() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT * FROM logs WHERE message MATCHES 'timeout (\\d+'"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let error: QueryError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(error.error, "parse_error");
+        assert!(error
+            .message
+            .starts_with(r"Invalid regular expression 'timeout (\d+': "));
+    }
+
     #[tokio::test]
     async fn test_query_empty_query() {
         let app = create_test_router();
@@ -306,7 +551,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_query_unsupported_source() {
+    async fn test_query_unknown_source() {
         let app = create_test_router();
 
         let response = app
@@ -315,7 +560,7 @@ mod tests {
                     .method("POST")
                     .uri("/api/v1/query")
                     .header(header::CONTENT_TYPE, "application/json")
-                    .body(Body::from(r#"{"query": "SELECT * FROM metrics"}"#))
+                    .body(Body::from(r#"{"query": "SELECT * FROM events"}"#))
                     .unwrap(),
             )
             .await
@@ -326,7 +571,7 @@ mod tests {
         let body = response.into_body().collect().await.unwrap().to_bytes();
         let error: QueryError = serde_json::from_slice(&body).unwrap();
 
-        assert_eq!(error.error, "execution_error");
+        assert_eq!(error.error, "parse_error");
     }
 
     #[tokio::test]
@@ -393,4 +638,354 @@ mod tests {
 
         assert_eq!(result.total_count, 1);
     }
+
+    #[tokio::test]
+    async fn test_query_metrics_source() {
+        let (app, state) = create_test_router_with_state();
+
+        state
+            .metric_store()
+            .insert(Metric::gauge("cpu_usage", 91.0).with_label("host", "web-1"))
+            .unwrap();
+        state
+            .metric_store()
+            .insert(Metric::gauge("cpu_usage", 12.0).with_label("host", "web-2"))
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT * FROM metrics WHERE name = 'cpu_usage' AND value > 50"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "metrics");
+
+        let result: QueryResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(result.total_count, 1);
+        match result.data {
+            QueryData::Metrics { metrics } => {
+                assert_eq!(metrics[0].labels["host"], "web-1");
+            }
+            other => panic!("Expected metrics, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_traces_source() {
+        let (app, state) = create_test_router_with_state();
+
+        let start = chrono::Utc::now();
+        state
+            .trace_store()
+            .insert_spans(vec![
+                Span::new("trace-1", "span-1", "GET /users", "api")
+                    .with_start_time(start)
+                    .with_end_time(start + chrono::Duration::milliseconds(800)),
+                Span::new("trace-1", "span-2", "SELECT users", "db")
+                    .with_parent("span-1")
+                    .with_start_time(start)
+                    .with_end_time(start + chrono::Duration::milliseconds(20)),
+            ])
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT * FROM traces WHERE duration_ms >= 500"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "spans");
+
+        let result: QueryResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(result.total_count, 1);
+        match result.data {
+            QueryData::Spans { spans } => assert_eq!(spans[0].span_id, "span-1"),
+            other => panic!("Expected spans, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_projection() {
+        let (app, state) = create_test_router_with_state();
+
+        state
+            .log_store()
+            .insert(
+                LogEntry::new(LogLevel::Error, "Payment failed", "api")
+                    .with_attribute("user_id", "u-7"),
+            )
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT service, level, attributes.user_id AS user FROM logs"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "rows");
+        assert_eq!(
+            json["columns"],
+            serde_json::json!(["service", "level", "user"])
+        );
+        assert_eq!(json["rows"], serde_json::json!([["api", "error", "u-7"]]));
+        assert_eq!(json["returned_count"], 1);
+        assert_eq!(
+            json["parsed_query"]["projection"][2],
+            serde_json::json!({"expr": {"field": "attributes.user_id"}, "alias": "user"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_group_by() {
+        let (app, state) = create_test_router_with_state();
+
+        state
+            .log_store()
+            .insert_batch(vec![
+                LogEntry::new(LogLevel::Error, "Error 1", "api"),
+                LogEntry::new(LogLevel::Error, "Error 2", "api"),
+                LogEntry::new(LogLevel::Error, "Error 3", "db"),
+            ])
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT service, count(*) AS n FROM logs GROUP BY service ORDER BY n DESC"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "rows");
+        assert_eq!(json["columns"], serde_json::json!(["service", "n"]));
+        assert_eq!(json["rows"], serde_json::json!([["api", 2], ["db", 1]]));
+        assert_eq!(json["total_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_time_buckets() {
+        let (app, state) = create_test_router_with_state();
+
+        let mut logs = Vec::new();
+        for time in ["2024-01-01T10:00:00Z", "2024-01-01T10:10:59Z"] {
+            let mut log = LogEntry::new(LogLevel::Error, "Error", "api");
+            log.timestamp = chrono::DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .with_timezone(&chrono::Utc);
+            logs.push(log);
+        }
+        state.log_store().insert_batch(logs).unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT bucket(timestamp, 5m) AS t, count(*) FROM logs GROUP BY bucket(timestamp, 5m)"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["rows"],
+            serde_json::json!([
+                ["2024-01-01T10:00:00Z", 1],
+                ["2024-01-01T10:05:00Z", 0],
+                ["2024-01-01T10:10:00Z", 1]
+            ])
+        );
+        assert_eq!(
+            json["parsed_query"]["group_by"],
+            serde_json::json!([{"bucket": {"field": "timestamp", "interval": {"amount": 5, "unit": "minutes"}}}])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_since() {
+        let (app, state) = create_test_router_with_state();
+
+        let mut old = LogEntry::new(LogLevel::Error, "Old error", "api");
+        old.timestamp = chrono::Utc::now() - chrono::Duration::hours(2);
+        state
+            .log_store()
+            .insert_batch(vec![
+                old,
+                LogEntry::new(LogLevel::Error, "New error", "api"),
+            ])
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT message FROM logs SINCE now() - 1h"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["rows"], serde_json::json!([["New error"]]));
+        assert_eq!(
+            json["parsed_query"]["since"],
+            serde_json::json!({"offset_secs": -3600})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_in_operator() {
+        let (app, state) = create_test_router_with_state();
+
+        state
+            .log_store()
+            .insert_batch(vec![
+                LogEntry::new(LogLevel::Error, "Error 1", "api"),
+                LogEntry::new(LogLevel::Error, "Error 2", "auth"),
+                LogEntry::new(LogLevel::Error, "Error 3", "db"),
+            ])
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT * FROM logs WHERE service NOT IN ('api', 'auth')"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["total_count"], 1);
+        assert_eq!(json["logs"][0]["service"], "db");
+        assert_eq!(
+            json["parsed_query"]["where_clause"],
+            serde_json::json!({"In": {"field": "service", "values": ["api", "auth"], "negated": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_invalid_group_by() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT * FROM logs GROUP BY service"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_explain_query() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/query/explain")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"query": "SELECT message FROM logs WHERE level = 'error' LIMIT 5"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["normalized_query"],
+            "SELECT message FROM logs WHERE level = 'error' LIMIT 5"
+        );
+        assert_eq!(json["backend"], "memory");
+        assert_eq!(json["pushed_down"], serde_json::json!([]));
+        assert_eq!(
+            json["in_memory"],
+            serde_json::json!(["WHERE level = 'error'", "LIMIT 5", "SELECT message"])
+        );
+        assert_eq!(json["statements"], serde_json::json!([]));
+        assert_eq!(json["parsed_query"]["limit"], 5);
+    }
 }
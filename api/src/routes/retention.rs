@@ -10,9 +10,9 @@ use axum::{
     Router,
 };
 use serde::{Deserialize, Serialize};
-use shared::config::{DataType, RetentionConfig, RetentionPolicy};
+use shared::config::{DataType, RetentionConfig, RetentionOverride};
 
-use crate::state::AppState;
+use crate::state::{AppState, TtlDrift};
 
 /// Request body for updating a single retention policy.
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +21,9 @@ pub struct UpdateRetentionPolicyRequest {
     pub data_type: DataType,
     /// New TTL in days.
     pub ttl_days: u32,
+    /// New overrides of the TTL, replacing the current ones. Omit to keep them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<Vec<RetentionOverride>>,
 }
 
 /// Response body for retention operations.
@@ -34,6 +37,9 @@ pub struct RetentionResponse {
     /// The current retention configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config: Option<RetentionConfig>,
+    /// Tables whose TTL differed from the saved configuration at startup.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<TtlDrift>,
 }
 
 impl RetentionResponse {
@@ -42,6 +48,7 @@ impl RetentionResponse {
             success: true,
             message: None,
             config: Some(config),
+            drift: Vec::new(),
         }
     }
 
@@ -50,6 +57,7 @@ impl RetentionResponse {
             success: false,
             message: Some(message.into()),
             config: None,
+            drift: Vec::new(),
         }
     }
 }
@@ -81,8 +89,9 @@ pub fn retention_routes(state: AppState) -> Router {
 ///
 /// Returns the current retention configuration for all data types.
 async fn get_retention_config(State(state): State<AppState>) -> Response {
-    let config = state.get_retention_config();
-    Json(RetentionResponse::success(config)).into_response()
+    let mut response = RetentionResponse::success(state.get_retention_config());
+    response.drift = state.retention_drift();
+    Json(response).into_response()
 }
 
 /// Handler for PUT /api/v1/config/retention.
@@ -114,28 +123,25 @@ async fn update_retention_config(
         tracing::debug!("ClickHouse not available, skipping TTL update");
     }
 
-    // Update the configuration
-    state.set_retention_config(config.clone());
-
-    Json(RetentionResponse::success(config)).into_response()
+    save_retention_config(&state, config)
 }
 
 /// Handler for PUT /api/v1/config/retention/policy.
 ///
-/// Updates a single retention policy.
+/// Updates a single retention policy, replacing its overrides if given.
 async fn update_retention_policy(
     State(state): State<AppState>,
     Json(req): Json<UpdateRetentionPolicyRequest>,
 ) -> Response {
-    // Validate the new policy
-    let policy = RetentionPolicy::new(req.data_type, req.ttl_days);
-    if let Err(e) = policy.validate() {
-        return (StatusCode::BAD_REQUEST, Json(RetentionResponse::error(e))).into_response();
-    }
-
-    // Update the policy
+    // Update and validate the policy
     let mut config = state.get_retention_config();
     config.update_policy(req.data_type, req.ttl_days);
+    if let Some(overrides) = req.overrides {
+        config.set_overrides(req.data_type, overrides);
+    }
+    if let Err(e) = config.get_policy(req.data_type).validate() {
+        return (StatusCode::BAD_REQUEST, Json(RetentionResponse::error(e))).into_response();
+    }
 
     // Update ClickHouse TTL if available
     if let Err(e) = state.update_clickhouse_ttl(&config).await {
@@ -154,7 +160,21 @@ async fn update_retention_policy(
         tracing::debug!("ClickHouse not available, skipping TTL update");
     }
 
-    state.set_retention_config(config.clone());
+    save_retention_config(&state, config)
+}
+
+/// Saves the configuration so it survives restarts, and responds with it.
+fn save_retention_config(state: &AppState, config: RetentionConfig) -> Response {
+    if let Err(e) = state.save_retention_config(config.clone()) {
+        tracing::error!(error = %e, "Failed to save retention configuration");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(RetentionResponse::error(format!(
+                "Failed to save retention configuration: {e}"
+            ))),
+        )
+            .into_response();
+    }
 
     Json(RetentionResponse::success(config)).into_response()
 }
@@ -166,7 +186,7 @@ async fn get_data_age_metrics(State(state): State<AppState>) -> Response {
     use crate::metrics::DataAgeMonitor;
     use std::time::Duration;
 
-    let monitor = DataAgeMonitor::new(state, Duration::from_secs(60));
+    let monitor = DataAgeMonitor::new(state, Duration::from_mins(1));
     match monitor.collect_metrics() {
         Ok(metrics) => Json(metrics).into_response(),
         Err(e) => (
@@ -326,6 +346,7 @@ mod tests {
         let update_request = UpdateRetentionPolicyRequest {
             data_type: DataType::Logs,
             ttl_days: 60,
+            overrides: None,
         };
         let json_body = serde_json::to_string(&update_request).unwrap();
 
@@ -363,6 +384,7 @@ mod tests {
         let update_request = UpdateRetentionPolicyRequest {
             data_type: DataType::Metrics,
             ttl_days: 0,
+            overrides: None,
         };
         let json_body = serde_json::to_string(&update_request).unwrap();
 
@@ -389,6 +411,58 @@ mod tests {
         assert!(retention_response.message.is_some());
     }
 
+    #[tokio::test]
+    async fn test_update_single_retention_policy_overrides() {
+        let state = AppState::with_in_memory_store();
+        let app = retention_routes(state.clone());
+        let put = |body: serde_json::Value| {
+            Request::builder()
+                .method("PUT")
+                .uri("/api/v1/config/retention/policy")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(put(serde_json::json!({
+                "data_type": "logs",
+                "ttl_days": 30,
+                "overrides": [
+                    {"field": "service", "value": "billing", "ttl_days": 400},
+                    {"field": "level", "value": "debug", "ttl_days": 3}
+                ]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.get_retention_config().logs.overrides.len(), 2);
+
+        // Omitting the overrides keeps them
+        let response = app
+            .clone()
+            .oneshot(put(
+                serde_json::json!({"data_type": "logs", "ttl_days": 14}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let logs = state.get_retention_config().logs;
+        assert_eq!((logs.ttl_days, logs.overrides.len()), (14, 2));
+
+        let response = app
+            .oneshot(put(serde_json::json!({
+                "data_type": "metrics",
+                "ttl_days": 90,
+                "overrides": [{"field": "level", "value": "debug", "ttl_days": 3}]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(state.get_retention_config().metrics.overrides.is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_data_age_metrics() {
         let app = create_test_router();
@@ -0,0 +1,424 @@
+//! Aggregation tier endpoints.
+//!
+//! Reads the series of the downsampled aggregation tiers: metric rollups, log
+//! counts by message pattern, and span and trace statistics.
+
+use super::limits::{run_cancellable, LimitParams};
+use super::query::{execution_error, QueryError};
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::config::{AggregationInterval, DataType};
+use shared::models::LogLevel;
+use shared::query::{parse_duration, ExecutionError};
+use shared::storage::{
+    AggregationFunction, LogCount, MetricRollup, RollupQuery, SeriesPoint, SeriesQuery, SpanStats,
+    TierSegment, TraceStats,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Maximum number of rows returned.
+const MAX_ROLLUP_LIMIT: usize = 10_000;
+
+/// Default number of rows returned.
+const DEFAULT_ROLLUP_LIMIT: usize = 1000;
+
+/// Maximum number of steps of a metric series.
+const MAX_SERIES_STEPS: u64 = 10_000;
+
+/// Prefix of the query parameters filtering by metric label, e.g. `label.host=web-1`.
+const LABEL_PREFIX: &str = "label.";
+
+/// Query parameters for reading a tier.
+#[derive(Debug, Deserialize)]
+pub struct RollupParams {
+    /// The tier to read: `one_minute`, `five_minutes`, `one_hour` or `one_day`.
+    /// Logs and traces only have the `one_hour` and `one_day` tiers.
+    pub interval: AggregationInterval,
+
+    /// Only buckets starting from this time (inclusive).
+    pub start_time: Option<DateTime<Utc>>,
+
+    /// Only buckets starting before this time (exclusive).
+    pub end_time: Option<DateTime<Utc>>,
+
+    /// Filter by service (for metrics, the `service` label).
+    pub service: Option<String>,
+
+    /// Filter by metric name.
+    pub name: Option<String>,
+
+    /// Filter by log level.
+    pub level: Option<LogLevel>,
+
+    /// Filter span statistics by operation.
+    pub operation: Option<String>,
+
+    /// Group trace statistics by `operation` (default) or by `service`.
+    #[serde(default)]
+    pub by: StatsGrouping,
+
+    /// Maximum number of rows to return (default: 1000, max: 10000).
+    pub limit: Option<usize>,
+
+    /// Maximum time the query may run, in milliseconds.
+    pub timeout_ms: Option<u64>,
+
+    /// Maximum number of rows the query may scan.
+    pub max_rows_scanned: Option<u64>,
+
+    /// Maximum size of the query result, in bytes.
+    pub max_result_bytes: Option<u64>,
+}
+
+impl RollupParams {
+    /// Builds the store query for `data_type`, cancelled by `token`.
+    ///
+    /// Fails if `data_type` has no tier at the requested interval.
+    fn query(
+        &self,
+        data_type: DataType,
+        state: &AppState,
+        token: &CancellationToken,
+    ) -> Result<RollupQuery, (StatusCode, Json<QueryError>)> {
+        if !self.interval.applies_to(data_type) {
+            return Err(bad_request(
+                "invalid_interval",
+                format!(
+                    "{data_type:?} are only aggregated at the one_hour and one_day intervals, not {}",
+                    self.interval
+                ),
+            ));
+        }
+
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_ROLLUP_LIMIT)
+            .min(MAX_ROLLUP_LIMIT);
+        let limits = LimitParams {
+            timeout_ms: self.timeout_ms,
+            max_rows_scanned: self.max_rows_scanned,
+            max_result_bytes: self.max_result_bytes,
+        }
+        .limits(state.query_limits(), token);
+
+        let mut query = RollupQuery::new(self.interval)
+            .with_limit(limit)
+            .with_limits(limits);
+        if let Some(start) = self.start_time {
+            query = query.with_start_time(start);
+        }
+        if let Some(end) = self.end_time {
+            query = query.with_end_time(end);
+        }
+        if let Some(ref service) = self.service {
+            query = query.with_service(service);
+        }
+        Ok(query)
+    }
+}
+
+/// Query parameters for a metric series.
+#[derive(Debug, Deserialize)]
+pub struct SeriesParams {
+    /// Aggregate computed for each step: `sum`, `avg`, `min`, `max` or `count`.
+    pub aggregate: AggregationFunction,
+
+    /// Width of the steps, e.g. `5m` or `1h`.
+    pub step: String,
+
+    /// Start of the series (inclusive).
+    pub start_time: DateTime<Utc>,
+
+    /// End of the series (exclusive, default: now).
+    pub end_time: Option<DateTime<Utc>>,
+
+    /// Filter by metric name.
+    pub name: Option<String>,
+
+    /// Maximum time the query may run, in milliseconds.
+    pub timeout_ms: Option<u64>,
+
+    /// Maximum number of rows the query may scan.
+    pub max_rows_scanned: Option<u64>,
+
+    /// Maximum size of the query result, in bytes.
+    pub max_result_bytes: Option<u64>,
+}
+
+/// What trace statistics are grouped by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsGrouping {
+    /// Span latency statistics by service, operation, kind and status.
+    #[default]
+    Operation,
+    /// Trace and span counts by service.
+    Service,
+}
+
+/// Response for `GET /api/v1/metrics/rollups`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricRollupsResponse {
+    /// The tier the rollups were read from.
+    pub interval: AggregationInterval,
+    /// The rollups, ordered by series, then time.
+    pub rollups: Vec<MetricRollup>,
+    /// Number of rollups returned.
+    pub total_count: usize,
+}
+
+/// Response for `GET /api/v1/metrics/series`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricSeriesResponse {
+    /// Aggregate computed for each step.
+    pub aggregate: AggregationFunction,
+    /// Width of the steps, in seconds.
+    pub step_secs: u64,
+    /// The steps with values, ordered by time.
+    pub points: Vec<SeriesPoint>,
+    /// The segments of the time range and the tier each was read from.
+    pub segments: Vec<TierSegment>,
+    /// Number of points returned.
+    pub total_count: usize,
+}
+
+/// Response for `GET /api/v1/logs/counts`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogCountsResponse {
+    /// The tier the counts were read from.
+    pub interval: AggregationInterval,
+    /// The counts, ordered by service, level and message pattern, then time.
+    pub counts: Vec<LogCount>,
+    /// Number of counts returned.
+    pub total_count: usize,
+}
+
+/// Response for `GET /api/v1/traces/stats`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TraceStatsResponse {
+    /// The tier the statistics were read from.
+    pub interval: AggregationInterval,
+    /// What the statistics are grouped by.
+    pub by: StatsGrouping,
+    /// The statistics, ordered by their grouping, then time.
+    pub stats: TraceStatsRows,
+    /// Number of statistics returned.
+    pub total_count: usize,
+}
+
+/// Trace statistics rows, depending on the grouping.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TraceStatsRows {
+    /// Span statistics by operation.
+    Operations(Vec<SpanStats>),
+    /// Trace counts by service.
+    Services(Vec<TraceStats>),
+}
+
+fn bad_request(error: &str, message: String) -> (StatusCode, Json<QueryError>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(QueryError {
+            error: error.to_string(),
+            message,
+            limit: None,
+            syntax: None,
+        }),
+    )
+}
+
+/// Creates the aggregation tier routes.
+///
+/// # Routes
+///
+/// - `GET /api/v1/metrics/rollups?interval=one_minute&name=cpu&label.host=web-1` - Metric rollups
+/// - `GET /api/v1/metrics/series?name=cpu&aggregate=avg&step=1h&start_time=...` - Metric series read from the coarsest suitable tiers
+/// - `GET /api/v1/logs/counts?interval=one_hour&level=error` - Log counts by message pattern
+/// - `GET /api/v1/traces/stats?interval=one_day&by=service` - Span or trace statistics
+pub fn rollups_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/metrics/rollups", get(metric_rollups))
+        .route("/api/v1/metrics/series", get(metric_series))
+        .route("/api/v1/logs/counts", get(log_counts))
+        .route("/api/v1/traces/stats", get(trace_stats))
+        .with_state(state)
+}
+
+/// Handler for GET /api/v1/metrics/rollups.
+///
+/// Label filters are given as `label.<key>=<value>` parameters.
+async fn metric_rollups(
+    State(state): State<AppState>,
+    Query(params): Query<RollupParams>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Result<Json<MetricRollupsResponse>, (StatusCode, Json<QueryError>)> {
+    let token = CancellationToken::new();
+    let mut query = params.query(DataType::Metrics, &state, &token)?;
+    if let Some(name) = params.name {
+        query = query.with_name(name);
+    }
+    for (key, value) in pairs {
+        if let Some(label) = key.strip_prefix(LABEL_PREFIX) {
+            query = query.with_label(label, value);
+        }
+    }
+
+    let rollups = run_cancellable(&token, move || state.metric_store().rollups(query))
+        .await
+        .map_err(|e| execution_error(ExecutionError::from(e)))?;
+
+    Ok(Json(MetricRollupsResponse {
+        interval: params.interval,
+        total_count: rollups.len(),
+        rollups,
+    }))
+}
+
+/// Handler for GET /api/v1/metrics/series.
+///
+/// Each part of the time range is read from the coarsest tier enabled in the
+/// aggregation configuration that still retains it and whose interval divides
+/// the step, and from the raw metrics otherwise. `min` and `max` are read
+/// from the raw metrics until the configuration marks the tiers as migrated.
+/// The response lists the segments and their tiers. Label filters are given as `label.<key>=<value>` parameters.
+async fn metric_series(
+    State(state): State<AppState>,
+    Query(params): Query<SeriesParams>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Result<Json<MetricSeriesResponse>, (StatusCode, Json<QueryError>)> {
+    let step_secs = parse_duration(&params.step)
+        .map(|step| step.as_secs())
+        .ok()
+        .filter(|secs| *secs > 0)
+        .ok_or_else(|| {
+            bad_request(
+                "invalid_step",
+                format!("Invalid step '{}', expected e.g. 5m or 1h", params.step),
+            )
+        })?;
+    let now = Utc::now();
+    let end = params.end_time.unwrap_or(now);
+    if params.start_time >= end {
+        return Err(bad_request(
+            "invalid_time_range",
+            "start_time must be before end_time".to_string(),
+        ));
+    }
+
+    let token = CancellationToken::new();
+    let limits = LimitParams {
+        timeout_ms: params.timeout_ms,
+        max_rows_scanned: params.max_rows_scanned,
+        max_result_bytes: params.max_result_bytes,
+    }
+    .limits(state.query_limits(), &token);
+    let mut query = SeriesQuery::new(params.aggregate, step_secs, params.start_time, end)
+        .with_limits(limits)
+        .with_tiers(&state.get_aggregation_config(), now);
+    if query.step_count() > MAX_SERIES_STEPS {
+        return Err(bad_request(
+            "too_many_steps",
+            format!(
+                "The time range spans more than {MAX_SERIES_STEPS} steps of {}, use a larger step",
+                params.step
+            ),
+        ));
+    }
+    if let Some(name) = params.name {
+        query = query.with_name(name);
+    }
+    for (key, value) in pairs {
+        if let Some(label) = key.strip_prefix(LABEL_PREFIX) {
+            query = query.with_label(label, value);
+        }
+    }
+
+    let segments = query.segments.clone();
+    let points = run_cancellable(&token, move || state.metric_store().series(query))
+        .await
+        .map_err(|e| execution_error(ExecutionError::from(e)))?;
+
+    Ok(Json(MetricSeriesResponse {
+        aggregate: params.aggregate,
+        step_secs,
+        total_count: points.len(),
+        points,
+        segments,
+    }))
+}
+
+/// Handler for GET /api/v1/logs/counts.
+async fn log_counts(
+    State(state): State<AppState>,
+    Query(params): Query<RollupParams>,
+) -> Result<Json<LogCountsResponse>, (StatusCode, Json<QueryError>)> {
+    let token = CancellationToken::new();
+    let mut query = params.query(DataType::Logs, &state, &token)?;
+    if let Some(level) = params.level {
+        query = query.with_level(level);
+    }
+
+    let counts = run_cancellable(&token, move || state.log_store().counts(query))
+        .await
+        .map_err(|e| execution_error(ExecutionError::from(e)))?;
+
+    Ok(Json(LogCountsResponse {
+        interval: params.interval,
+        total_count: counts.len(),
+        counts,
+    }))
+}
+
+/// Handler for GET /api/v1/traces/stats.
+///
+/// With `by=operation`, returns span latency statistics, which can be filtered
+/// by `operation`; with `by=service`, trace and span counts.
+async fn trace_stats(
+    State(state): State<AppState>,
+    Query(params): Query<RollupParams>,
+) -> Result<Json<TraceStatsResponse>, (StatusCode, Json<QueryError>)> {
+    let token = CancellationToken::new();
+    let mut query = params.query(DataType::Traces, &state, &token)?;
+
+    let rows = match params.by {
+        StatsGrouping::Operation => {
+            if let Some(operation) = params.operation {
+                query = query.with_name(operation);
+            }
+            run_cancellable(&token, move || state.trace_store().span_stats(query))
+                .await
+                .map(TraceStatsRows::Operations)
+        }
+        StatsGrouping::Service => {
+            if params.operation.is_some() {
+                return Err(bad_request(
+                    "invalid_parameter",
+                    "operation cannot be used with by=service".to_string(),
+                ));
+            }
+            run_cancellable(&token, move || state.trace_store().trace_stats(query))
+                .await
+                .map(TraceStatsRows::Services)
+        }
+    }
+    .map_err(|e| execution_error(ExecutionError::from(e)))?;
+
+    let total_count = match rows {
+        TraceStatsRows::Operations(ref rows) => rows.len(),
+        TraceStatsRows::Services(ref rows) => rows.len(),
+    };
+    Ok(Json(TraceStatsResponse {
+        interval: params.interval,
+        by: params.by,
+        stats: rows,
+        total_count,
+    }))
+}
@@ -0,0 +1,367 @@
+//! Saved query endpoints.
+//!
+//! Provides CRUD endpoints for named, reusable queries and an endpoint to run
+//! them with parameter values.
+
+use super::limits::LimitParams;
+use super::query::{run_query, QueryError};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use shared::models::{SavedQuery, SavedQueryError};
+use shared::storage::{Cursor, SavedQueryStoreError};
+use std::collections::BTreeMap;
+
+/// Request body for creating or replacing a saved query.
+#[derive(Debug, Deserialize)]
+pub struct SavedQueryRequest {
+    /// Human-readable name. The id of a new query is derived from it.
+    pub name: String,
+
+    /// What the query is for.
+    #[serde(default)]
+    pub description: String,
+
+    /// Who owns the query.
+    #[serde(default)]
+    pub owner: Option<String>,
+
+    /// Tags for finding the query.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The query text, possibly with `$name` parameters.
+    pub query: String,
+
+    /// Default parameter values. A `null` default makes the parameter required.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, serde_json::Value>,
+
+    /// Time range applied when the query has no `SINCE` clause, e.g. `15m`.
+    #[serde(default)]
+    pub default_time_range: Option<String>,
+}
+
+impl SavedQueryRequest {
+    /// Applies the request to `saved`, keeping its id and creation time.
+    fn apply(self, mut saved: SavedQuery) -> SavedQuery {
+        saved.name = self.name.trim().to_string();
+        saved.description = self.description;
+        saved.owner = self.owner;
+        saved.tags = self.tags;
+        saved.query = self.query;
+        saved.parameters = self.parameters;
+        saved.default_time_range = self.default_time_range;
+        saved
+    }
+}
+
+/// Query parameters for listing saved queries.
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    /// Only list queries with this tag.
+    pub tag: Option<String>,
+    /// Only list queries owned by this owner.
+    pub owner: Option<String>,
+}
+
+/// Response for listing saved queries.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedQueryListResponse {
+    /// The saved queries, ordered by name.
+    pub saved_queries: Vec<SavedQuery>,
+    /// Number of saved queries returned.
+    pub total_count: usize,
+}
+
+/// Request body for running a saved query.
+#[derive(Debug, Default, Deserialize)]
+pub struct RunRequest {
+    /// Parameter values, overriding the saved defaults.
+    #[serde(default)]
+    pub params: BTreeMap<String, serde_json::Value>,
+
+    /// Time range to query, e.g. `1h`, replacing the query's `SINCE`.
+    #[serde(default)]
+    pub time_range: Option<String>,
+
+    /// Cursor from a previous response's `next_cursor`, to fetch the next page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+
+    /// Limits for this run, tightening the server's limits.
+    #[serde(flatten)]
+    pub limits: LimitParams,
+}
+
+type ApiError = (StatusCode, Json<QueryError>);
+
+fn error(status: StatusCode, error: &str, message: &dyn std::fmt::Display) -> ApiError {
+    (
+        status,
+        Json(QueryError {
+            error: error.to_string(),
+            message: message.to_string(),
+            limit: None,
+            syntax: None,
+        }),
+    )
+}
+
+/// Maps a saved query error to its HTTP status and error body.
+fn saved_query_error(e: &SavedQueryError) -> ApiError {
+    let kind = match e {
+        SavedQueryError::InvalidQuery(_) => "parse_error",
+        SavedQueryError::InvalidParameter(_) => "invalid_parameter",
+        SavedQueryError::InvalidTimeRange(_) => "invalid_time_range",
+        SavedQueryError::InvalidName(_)
+        | SavedQueryError::EmptyTag
+        | SavedQueryError::InvalidParameterName(_) => "validation_error",
+    };
+    let (status, mut body) = error(StatusCode::BAD_REQUEST, kind, e);
+    if let SavedQueryError::InvalidQuery(parse_error) = e {
+        body.syntax = parse_error.syntax().cloned().map(Box::new);
+    }
+    (status, body)
+}
+
+/// Maps a store error to its HTTP status and error body.
+fn store_error(e: &SavedQueryStoreError) -> ApiError {
+    match e {
+        SavedQueryStoreError::NotFound(_) => error(StatusCode::NOT_FOUND, "not_found", e),
+        SavedQueryStoreError::AlreadyExists(_) => error(StatusCode::CONFLICT, "already_exists", e),
+        SavedQueryStoreError::LockError | SavedQueryStoreError::StorageError(_) => {
+            tracing::error!(error = %e, "Saved query store operation failed");
+            error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e)
+        }
+    }
+}
+
+/// Looks up a saved query, failing with 404 if it does not exist.
+fn find(state: &AppState, id: &str) -> Result<SavedQuery, ApiError> {
+    state
+        .saved_query_store()
+        .get(id)
+        .map_err(|e| store_error(&e))?
+        .ok_or_else(|| store_error(&SavedQueryStoreError::NotFound(id.to_string())))
+}
+
+/// Creates saved query routes.
+///
+/// # Routes
+///
+/// - `GET /api/v1/saved-queries` - List saved queries, optionally by `tag` or `owner`
+/// - `POST /api/v1/saved-queries` - Create a saved query
+/// - `GET /api/v1/saved-queries/{id}` - Get a saved query
+/// - `PUT /api/v1/saved-queries/{id}` - Replace a saved query
+/// - `DELETE /api/v1/saved-queries/{id}` - Delete a saved query
+/// - `POST /api/v1/saved-queries/{id}/run` - Run a saved query with parameters
+pub fn saved_queries_routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/saved-queries",
+            get(list_saved_queries).post(create_saved_query),
+        )
+        .route(
+            "/api/v1/saved-queries/{id}",
+            get(get_saved_query)
+                .put(update_saved_query)
+                .delete(delete_saved_query),
+        )
+        .route("/api/v1/saved-queries/{id}/run", post(run_saved_query))
+        .with_state(state)
+}
+
+/// Handler for GET /api/v1/saved-queries.
+async fn list_saved_queries(
+    State(state): State<AppState>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<SavedQueryListResponse>, ApiError> {
+    let saved_queries: Vec<SavedQuery> = state
+        .saved_query_store()
+        .list()
+        .map_err(|e| store_error(&e))?
+        .into_iter()
+        .filter(|saved| {
+            params
+                .tag
+                .as_ref()
+                .is_none_or(|tag| saved.tags.contains(tag))
+        })
+        .filter(|saved| {
+            params
+                .owner
+                .as_ref()
+                .is_none_or(|owner| saved.owner.as_ref() == Some(owner))
+        })
+        .collect();
+
+    Ok(Json(SavedQueryListResponse {
+        total_count: saved_queries.len(),
+        saved_queries,
+    }))
+}
+
+/// Handler for POST /api/v1/saved-queries.
+///
+/// Validates the query and stores it under an id derived from its name.
+async fn create_saved_query(
+    State(state): State<AppState>,
+    Json(request): Json<SavedQueryRequest>,
+) -> Result<(StatusCode, Json<SavedQuery>), ApiError> {
+    let saved = SavedQuery::new(request.name.trim(), String::new());
+    let saved = request.apply(saved);
+    saved.validate().map_err(|e| saved_query_error(&e))?;
+
+    state
+        .saved_query_store()
+        .insert(saved.clone())
+        .map_err(|e| store_error(&e))?;
+
+    tracing::debug!(id = %saved.id, "Saved query created");
+    Ok((StatusCode::CREATED, Json(saved)))
+}
+
+/// Handler for GET /api/v1/saved-queries/{id}.
+async fn get_saved_query(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SavedQuery>, ApiError> {
+    find(&state, &id).map(Json)
+}
+
+/// Handler for PUT /api/v1/saved-queries/{id}.
+///
+/// Replaces the saved query, keeping its id and creation time.
+async fn update_saved_query(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<SavedQueryRequest>,
+) -> Result<Json<SavedQuery>, ApiError> {
+    let mut saved = request.apply(find(&state, &id)?);
+    saved.updated_at = Utc::now();
+    saved.validate().map_err(|e| saved_query_error(&e))?;
+
+    state
+        .saved_query_store()
+        .update(saved.clone())
+        .map_err(|e| store_error(&e))?;
+
+    Ok(Json(saved))
+}
+
+/// Handler for DELETE /api/v1/saved-queries/{id}.
+async fn delete_saved_query(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .saved_query_store()
+        .delete(&id)
+        .map_err(|e| store_error(&e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler for POST /api/v1/saved-queries/{id}/run.
+///
+/// Binds the parameters to the saved query and runs it like
+/// `POST /api/v1/query`, including NDJSON streaming and query limits. The body
+/// may be omitted to run the query with its defaults.
+async fn run_saved_query(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    request: Option<Json<RunRequest>>,
+) -> Result<Response, ApiError> {
+    let Json(request) = request.unwrap_or_default();
+    let saved = find(&state, &id)?;
+
+    let query = saved
+        .compile(&request.params, request.time_range.as_deref())
+        .map_err(|e| {
+            tracing::debug!(id = %id, error = %e, "Failed to compile saved query");
+            saved_query_error(&e)
+        })?;
+    let cursor = request
+        .cursor
+        .as_deref()
+        .map(Cursor::decode)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(QueryError::from(e))))?;
+
+    run_query(state, &headers, query, cursor, request.limits).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use http_body_util::BodyExt;
+    use shared::models::{LogEntry, LogLevel};
+    use tower::ServiceExt;
+
+    async fn send(
+        app: Router,
+        method: &str,
+        uri: &str,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_create_and_run_with_defaults() {
+        let state = AppState::with_in_memory_store();
+        let app = saved_queries_routes(state.clone());
+        state
+            .log_store()
+            .insert(LogEntry::new(LogLevel::Error, "Boom", "api"))
+            .unwrap();
+        state
+            .log_store()
+            .insert(LogEntry::new(LogLevel::Error, "Boom", "web"))
+            .unwrap();
+
+        let (status, saved) = send(
+            app.clone(),
+            "POST",
+            "/api/v1/saved-queries",
+            r#"{"name": "API errors", "query": "SELECT * FROM logs WHERE service = $service", "parameters": {"service": "api"}}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(saved["id"], "api-errors");
+        assert!(state
+            .saved_query_store()
+            .get("api-errors")
+            .unwrap()
+            .is_some());
+
+        let (status, result) =
+            send(app, "POST", "/api/v1/saved-queries/api-errors/run", "{}").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["total_count"], 1);
+        assert_eq!(result["logs"][0]["service"], "api");
+    }
+}
@@ -9,7 +9,7 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use shared::models::{Span, SpanKind, SpanStatus, Trace};
-use shared::storage::TraceQuery;
+use shared::storage::{Cursor, TraceQuery};
 use std::collections::HashMap;
 
 /// Request for span ingestion.
@@ -76,6 +76,8 @@ pub struct TraceQueryParams {
     pub max_duration_ms: Option<i64>,
     pub limit: Option<usize>,
     pub offset: Option<usize>,
+    /// Cursor from a previous response's `next_cursor`, to fetch the next page.
+    pub cursor: Option<String>,
 }
 
 /// Response for trace queries.
@@ -83,6 +85,9 @@ pub struct TraceQueryParams {
 pub struct TraceQueryResponse {
     pub traces: Vec<TraceResponse>,
     pub total_count: usize,
+    /// Cursor for the next page, or `null` if this is the last page.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 /// A trace in the response.
@@ -184,6 +189,18 @@ async fn query_traces(
     if let Some(offset) = params.offset {
         query = query.with_offset(offset);
     }
+    if let Some(cursor) = params.cursor {
+        let cursor = Cursor::decode(&cursor).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(TraceError {
+                    error: "invalid_cursor".to_string(),
+                    message: e.to_string(),
+                }),
+            )
+        })?;
+        query = query.with_cursor(cursor);
+    }
 
     let result = state.trace_store().query(query).map_err(|e| {
         (
@@ -198,6 +215,7 @@ async fn query_traces(
     Ok(Json(TraceQueryResponse {
         traces: result.traces.into_iter().map(Into::into).collect(),
         total_count: result.total_count,
+        next_cursor: result.next_cursor.as_ref().map(Cursor::encode),
     }))
 }
 
@@ -302,6 +320,67 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_query_traces_with_cursor() {
+        let state = AppState::with_in_memory_store();
+        let app = traces_routes(state.clone());
+
+        let base = chrono::Utc::now() - chrono::Duration::hours(1);
+        for i in 0..3 {
+            let mut span = Span::new(format!("trace-{i}"), "span-1", "root", "api");
+            span.start_time = base + chrono::Duration::seconds(i);
+            state.trace_store().insert_span(span).unwrap();
+        }
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/traces?limit=2")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: TraceQueryResponse = serde_json::from_slice(&body).unwrap();
+        let ids: Vec<&str> = page.traces.iter().map(|t| t.trace_id.as_str()).collect();
+        assert_eq!(ids, ["trace-2", "trace-1"]);
+
+        let cursor = page.next_cursor.unwrap();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/v1/traces?limit=2&cursor={cursor}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: TraceQueryResponse = serde_json::from_slice(&body).unwrap();
+        let ids: Vec<&str> = page.traces.iter().map(|t| t.trace_id.as_str()).collect();
+        assert_eq!(ids, ["trace-0"]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_traces_with_invalid_cursor() {
+        let app = create_test_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/traces?cursor=garbage")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_get_trace_by_id() {
         let state = AppState::with_in_memory_store();
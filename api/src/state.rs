@@ -2,13 +2,60 @@
 //!
 //! Defines the shared application state that is passed to route handlers.
 
-use shared::config::{AggregationConfig, RetentionConfig};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::config::{
+    AggregationConfig, AggregationInterval, DataType, RetentionConfig, RetentionField,
+    RetentionOverride, RetentionPolicy,
+};
+use shared::query::{QueryCache, QueryStores};
 use shared::storage::{
-    ClickHouseLogStore, ClickHouseMetricStore, ClickHouseTraceStore, InMemoryLogStore,
-    InMemoryMetricStore, InMemoryTraceStore, LogStore, MetricStore, TraceStore,
+    ClickHouseLogStore, ClickHouseMetricStore, ClickHouseSavedQueryStore, ClickHouseSettingsStore,
+    ClickHouseTraceStore, InMemoryLogStore, InMemoryMetricStore, InMemorySavedQueryStore,
+    InMemorySettingsStore, InMemoryTraceStore, LogStore, MetricStore, QueryLimits, SavedQueryStore,
+    Settings, SettingsStore, SettingsStoreError, TraceStore,
 };
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+/// The raw data tables, the retention policy that sets their TTL, and their time column.
+const RETENTION_TABLES: [(DataType, &str, &str); 3] = [
+    (DataType::Logs, "logs", "timestamp"),
+    (DataType::Metrics, "metrics", "timestamp"),
+    (DataType::Traces, "spans", "start_time"),
+];
+
+/// A table whose TTL differs from the saved configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlDrift {
+    /// The table name.
+    pub table: String,
+    /// TTL in days according to the saved configuration.
+    pub configured_days: u32,
+    /// TTL in days the table actually has.
+    pub actual_days: u32,
+}
+
+/// A tier view whose attachment differs from the saved configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewDrift {
+    /// The materialized view name.
+    pub view: String,
+    /// Whether the saved configuration enables the view's tier.
+    pub configured_enabled: bool,
+    /// Whether the view is actually attached.
+    pub attached: bool,
+}
+
+/// A tier table the aggregation pipeline could not be updated for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierFailure {
+    /// The table name.
+    pub table: String,
+    /// The error `ClickHouse` returned.
+    pub error: String,
+}
+
 /// Application state shared across all request handlers.
 ///
 /// This struct contains all the shared resources needed by the API,
@@ -22,12 +69,26 @@ pub struct AppState {
     metric_store: Arc<dyn MetricStore>,
     /// The trace storage backend.
     trace_store: Arc<dyn TraceStore>,
+    /// The saved query storage backend.
+    saved_query_store: Arc<dyn SavedQueryStore>,
+    /// The storage backend for the retention and aggregation configuration.
+    settings_store: Arc<dyn SettingsStore>,
     /// Retention configuration (TTL policies).
     retention_config: Arc<RwLock<RetentionConfig>>,
     /// Aggregation configuration (downsampling policies).
     aggregation_config: Arc<RwLock<AggregationConfig>>,
+    /// Raw tables whose TTL differed from the configuration at startup.
+    retention_drift: Arc<RwLock<Vec<TtlDrift>>>,
+    /// Tier tables whose TTL differed from the configuration at startup.
+    aggregation_drift: Arc<RwLock<Vec<TtlDrift>>>,
+    /// Tier views whose attachment differed from the configuration at startup.
+    view_drift: Arc<RwLock<Vec<ViewDrift>>>,
     /// Optional `ClickHouse` client for direct database operations.
     clickhouse_client: Option<Arc<clickhouse::Client>>,
+    /// Limits applied to every query.
+    query_limits: Arc<QueryLimits>,
+    /// Cache for query results, if enabled.
+    query_cache: Option<Arc<QueryCache>>,
 }
 
 impl AppState {
@@ -41,9 +102,16 @@ impl AppState {
             log_store,
             metric_store,
             trace_store,
+            saved_query_store: Arc::new(InMemorySavedQueryStore::new()),
+            settings_store: Arc::new(InMemorySettingsStore::new()),
             retention_config: Arc::new(RwLock::new(RetentionConfig::default())),
             aggregation_config: Arc::new(RwLock::new(AggregationConfig::default())),
+            retention_drift: Arc::default(),
+            aggregation_drift: Arc::default(),
+            view_drift: Arc::default(),
             clickhouse_client: None,
+            query_limits: Arc::default(),
+            query_cache: None,
         }
     }
 
@@ -56,9 +124,16 @@ impl AppState {
             log_store: Arc::new(InMemoryLogStore::new()),
             metric_store: Arc::new(InMemoryMetricStore::new()),
             trace_store: Arc::new(InMemoryTraceStore::new()),
+            saved_query_store: Arc::new(InMemorySavedQueryStore::new()),
+            settings_store: Arc::new(InMemorySettingsStore::new()),
             retention_config: Arc::new(RwLock::new(RetentionConfig::default())),
             aggregation_config: Arc::new(RwLock::new(AggregationConfig::default())),
+            retention_drift: Arc::default(),
+            aggregation_drift: Arc::default(),
+            view_drift: Arc::default(),
             clickhouse_client: None,
+            query_limits: Arc::default(),
+            query_cache: None,
         }
     }
 
@@ -71,9 +146,16 @@ impl AppState {
             log_store: Arc::new(ClickHouseLogStore::new(Arc::clone(&client))),
             metric_store: Arc::new(ClickHouseMetricStore::new(Arc::clone(&client))),
             trace_store: Arc::new(ClickHouseTraceStore::new(Arc::clone(&client))),
+            saved_query_store: Arc::new(ClickHouseSavedQueryStore::new(Arc::clone(&client))),
+            settings_store: Arc::new(ClickHouseSettingsStore::new(Arc::clone(&client))),
             retention_config: Arc::new(RwLock::new(RetentionConfig::default())),
             aggregation_config: Arc::new(RwLock::new(AggregationConfig::default())),
+            retention_drift: Arc::default(),
+            aggregation_drift: Arc::default(),
+            view_drift: Arc::default(),
             clickhouse_client: Some(client),
+            query_limits: Arc::default(),
+            query_cache: None,
         }
     }
 
@@ -95,6 +177,69 @@ impl AppState {
         self.trace_store.as_ref()
     }
 
+    /// Returns a reference to the saved query store.
+    #[must_use]
+    pub fn saved_query_store(&self) -> &dyn SavedQueryStore {
+        self.saved_query_store.as_ref()
+    }
+
+    /// Replaces the saved query store.
+    #[must_use]
+    pub fn with_saved_query_store(mut self, store: Arc<dyn SavedQueryStore>) -> Self {
+        self.saved_query_store = store;
+        self
+    }
+
+    /// Returns a reference to the settings store.
+    #[must_use]
+    pub fn settings_store(&self) -> &dyn SettingsStore {
+        self.settings_store.as_ref()
+    }
+
+    /// Replaces the settings store.
+    #[must_use]
+    pub fn with_settings_store(mut self, store: Arc<dyn SettingsStore>) -> Self {
+        self.settings_store = store;
+        self
+    }
+
+    /// Returns the stores used by the query engine, with the server's query limits.
+    #[must_use]
+    pub fn query_stores(&self) -> QueryStores<'_> {
+        QueryStores {
+            logs: self.log_store(),
+            metrics: self.metric_store(),
+            traces: self.trace_store(),
+            limits: &self.query_limits,
+        }
+    }
+
+    /// Sets the limits applied to every query.
+    #[must_use]
+    pub fn with_query_limits(mut self, limits: QueryLimits) -> Self {
+        self.query_limits = Arc::new(limits);
+        self
+    }
+
+    /// Returns the limits applied to every query.
+    #[must_use]
+    pub fn query_limits(&self) -> &QueryLimits {
+        &self.query_limits
+    }
+
+    /// Enables caching of query results.
+    #[must_use]
+    pub fn with_query_cache(mut self, cache: QueryCache) -> Self {
+        self.query_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Returns the query result cache, if enabled.
+    #[must_use]
+    pub fn query_cache(&self) -> Option<&QueryCache> {
+        self.query_cache.as_deref()
+    }
+
     /// Gets the current retention configuration.
     ///
     /// # Panics
@@ -145,6 +290,201 @@ impl AppState {
             .expect("Aggregation config lock poisoned") = config;
     }
 
+    /// Saves the retention configuration to the settings store and makes it current.
+    ///
+    /// The retention drift found at startup is cleared, as the tables were
+    /// just updated to match.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the settings store fails to save the configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the retention config lock is poisoned.
+    pub fn save_retention_config(&self, config: RetentionConfig) -> Result<(), SettingsStoreError> {
+        self.settings_store.save_retention(&config)?;
+        self.set_retention_config(config);
+        self.retention_drift
+            .write()
+            .expect("Retention drift lock poisoned")
+            .clear();
+        Ok(())
+    }
+
+    /// Saves the aggregation configuration to the settings store and makes it current.
+    ///
+    /// The aggregation and view drift found at startup is cleared, as the
+    /// tables and views were just updated to match.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the settings store fails to save the configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the aggregation config lock is poisoned.
+    pub fn save_aggregation_config(
+        &self,
+        config: AggregationConfig,
+    ) -> Result<(), SettingsStoreError> {
+        self.settings_store.save_aggregation(&config)?;
+        self.set_aggregation_config(config);
+        self.aggregation_drift
+            .write()
+            .expect("Aggregation drift lock poisoned")
+            .clear();
+        self.view_drift
+            .write()
+            .expect("View drift lock poisoned")
+            .clear();
+        Ok(())
+    }
+
+    /// Returns the raw tables whose TTL differed from the saved retention
+    /// configuration at startup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the retention drift lock is poisoned.
+    #[must_use]
+    pub fn retention_drift(&self) -> Vec<TtlDrift> {
+        self.retention_drift
+            .read()
+            .expect("Retention drift lock poisoned")
+            .clone()
+    }
+
+    /// Returns the tier tables whose TTL differed from the saved aggregation
+    /// configuration at startup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the aggregation drift lock is poisoned.
+    #[must_use]
+    pub fn aggregation_drift(&self) -> Vec<TtlDrift> {
+        self.aggregation_drift
+            .read()
+            .expect("Aggregation drift lock poisoned")
+            .clone()
+    }
+
+    /// Returns the tier views whose attachment differed from the saved
+    /// aggregation configuration at startup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the view drift lock is poisoned.
+    #[must_use]
+    pub fn view_drift(&self) -> Vec<ViewDrift> {
+        self.view_drift
+            .read()
+            .expect("View drift lock poisoned")
+            .clone()
+    }
+
+    /// Restores the retention and aggregation configuration from the settings
+    /// store, and reconciles it with the TTLs the `ClickHouse` tables actually
+    /// have and the tier views actually attached.
+    ///
+    /// Settings that were never saved, could not be loaded or are no longer
+    /// valid keep their defaults. Tables are never altered here: the
+    /// configuration takes the TTL of each table, and every difference, in
+    /// TTL or in attachment, is logged and kept as drift until the
+    /// configuration is next updated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a config or drift lock is poisoned.
+    pub async fn restore_config(&self) {
+        let settings = self.settings_store.load().unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Failed to load saved settings, using the defaults");
+            Settings::default()
+        });
+        let mut retention = self.get_retention_config();
+        if let Some(saved) = settings.retention {
+            match saved.validate() {
+                Ok(()) => retention = saved,
+                Err(e) => tracing::warn!(error = %e, "Ignoring invalid saved retention config"),
+            }
+        }
+        let mut aggregation = self.get_aggregation_config();
+        if let Some(saved) = settings.aggregation {
+            match saved.validate() {
+                Ok(()) => aggregation = saved,
+                Err(e) => tracing::warn!(error = %e, "Ignoring invalid saved aggregation config"),
+            }
+        }
+
+        if let Some(client) = &self.clickhouse_client {
+            let tables = fetch_tables(client).await.unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Failed to read the tables, skipping reconciliation");
+                Vec::new()
+            });
+            let (retention_drift, aggregation_drift) =
+                reconcile_ttls(&mut retention, &mut aggregation, &table_ttls(&tables));
+            for drift in retention_drift.iter().chain(&aggregation_drift) {
+                tracing::warn!(
+                    table = %drift.table,
+                    configured_days = drift.configured_days,
+                    actual_days = drift.actual_days,
+                    "Table TTL differs from the saved configuration"
+                );
+            }
+            *self
+                .retention_drift
+                .write()
+                .expect("Retention drift lock poisoned") = retention_drift;
+            *self
+                .aggregation_drift
+                .write()
+                .expect("Aggregation drift lock poisoned") = aggregation_drift;
+
+            // Without the table list, every view would look detached
+            let view_drift = if tables.is_empty() {
+                Vec::new()
+            } else {
+                reconcile_views(&aggregation, &attached_views(&tables))
+            };
+            for drift in &view_drift {
+                tracing::warn!(
+                    view = %drift.view,
+                    configured_enabled = drift.configured_enabled,
+                    attached = drift.attached,
+                    "Tier view attachment differs from the saved configuration"
+                );
+            }
+            *self.view_drift.write().expect("View drift lock poisoned") = view_drift;
+        }
+
+        tracing::info!(
+            logs_ttl_days = retention.logs.ttl_days,
+            metrics_ttl_days = retention.metrics.ttl_days,
+            traces_ttl_days = retention.traces.ttl_days,
+            aggregation_enabled = aggregation.enabled,
+            "Restored retention and aggregation configuration"
+        );
+        self.set_retention_config(retention);
+        self.set_aggregation_config(aggregation);
+    }
+
+    /// Deletes the data that has outlived the retention configuration from
+    /// stores that do not expire data themselves, returning how much was deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a store fails to expire its data.
+    pub fn enforce_retention(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+        let config = self.get_retention_config();
+        let logs = self.log_store.expire(&config.logs, now)?;
+        let metrics = self.metric_store.expire(&config.metrics, now)?;
+        let spans = self.trace_store.expire(&config.traces, now)?;
+        if logs + metrics + spans > 0 {
+            tracing::info!(logs, metrics, spans, "Deleted data past its retention");
+        }
+        Ok(logs + metrics + spans)
+    }
+
     /// Returns a reference to the `ClickHouse` client, if available.
     ///
     /// This is `None` when using in-memory stores.
@@ -155,6 +495,8 @@ impl AppState {
 
     /// Updates `ClickHouse` TTL policies to match the retention configuration.
     ///
+    /// Overrides become conditional `TTL ... WHERE` rules; see [`ttl_expression`].
+    ///
     /// # Errors
     ///
     /// Returns an error if the database operation fails or no `ClickHouse` client is available.
@@ -164,26 +506,16 @@ impl AppState {
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("ClickHouse client not available"))?;
 
-        // Update logs table TTL
-        let logs_sql = format!(
-            "ALTER TABLE logs MODIFY TTL toDateTime(timestamp / 1000000000) + INTERVAL {} DAY",
-            config.logs.ttl_days
-        );
-        client.query(&logs_sql).execute().await?;
-
-        // Update metrics table TTL
-        let metrics_sql = format!(
-            "ALTER TABLE metrics MODIFY TTL toDateTime(timestamp / 1000000000) + INTERVAL {} DAY",
-            config.metrics.ttl_days
-        );
-        client.query(&metrics_sql).execute().await?;
-
-        // Update spans table TTL
-        let traces_sql = format!(
-            "ALTER TABLE spans MODIFY TTL toDateTime(start_time / 1000000000) + INTERVAL {} DAY",
-            config.traces.ttl_days
-        );
-        client.query(&traces_sql).execute().await?;
+        for (data_type, table, time_column) in RETENTION_TABLES {
+            let ttl = ttl_expression(
+                config.get_policy(data_type),
+                &format!("toDateTime({time_column} / 1000000000)"),
+            );
+            client
+                .query(&format!("ALTER TABLE {table} MODIFY TTL {ttl}"))
+                .execute()
+                .await?;
+        }
 
         tracing::info!(
             logs_ttl_days = config.logs.ttl_days,
@@ -194,6 +526,273 @@ impl AppState {
 
         Ok(())
     }
+
+    /// Updates the `ClickHouse` rollup pipeline to match the aggregation
+    /// configuration: the TTL of each tier table, and whether the materialized
+    /// view feeding it is attached.
+    ///
+    /// Views of disabled tiers are detached permanently, so they stay detached
+    /// across server restarts until the tier is enabled again. Tiers do not
+    /// receive the data inserted while they were disabled.
+    ///
+    /// Nothing is changed unless every tier table exists. The tables are then
+    /// updated one by one, and the tables that could not be updated are
+    /// returned with their errors; every other table was updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no `ClickHouse` client is available, the tables
+    /// cannot be listed or a tier table is missing.
+    pub async fn update_clickhouse_aggregation(
+        &self,
+        config: &AggregationConfig,
+    ) -> anyhow::Result<Vec<TierFailure>> {
+        let client = self
+            .clickhouse_client
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("ClickHouse client not available"))?;
+
+        let tables = fetch_tables(client).await?;
+        let missing: Vec<String> = AggregationInterval::ALL
+            .iter()
+            .flat_map(AggregationInterval::tables)
+            .filter(|table| !tables.iter().any(|row| row.name == *table))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Missing tier tables: {}", missing.join(", "));
+        }
+        let attached = attached_views(&tables);
+
+        let mut failures = Vec::new();
+        for interval in AggregationInterval::ALL {
+            let policy = config.get_policy(interval);
+            let enabled = config.enabled && policy.enabled;
+            for table in interval.tables() {
+                let view = format!("{table}_mv");
+                let attach = match (enabled, attached.contains(&view)) {
+                    (true, false) => Some(format!("ATTACH TABLE {}", quote_identifier(&view))),
+                    (false, true) => Some(format!(
+                        "DETACH TABLE {} PERMANENTLY",
+                        quote_identifier(&view)
+                    )),
+                    _ => None,
+                };
+                let ttl = format!(
+                    "ALTER TABLE {} MODIFY TTL timestamp + INTERVAL {} DAY",
+                    quote_identifier(&table),
+                    policy.retention_days
+                );
+
+                for sql in std::iter::once(ttl).chain(attach) {
+                    if let Err(e) = client.query(&sql).execute().await {
+                        tracing::warn!(table = %table, error = %e, "Failed to update tier table");
+                        failures.push(TierFailure {
+                            table,
+                            error: e.to_string(),
+                        });
+                        break;
+                    }
+                }
+            }
+        }
+
+        tracing::info!(
+            enabled = config.enabled,
+            one_minute_days = config.one_minute.retention_days,
+            five_minutes_days = config.five_minutes.retention_days,
+            one_hour_days = config.one_hour.retention_days,
+            one_day_days = config.one_day.retention_days,
+            failed_tables = failures.len(),
+            "Updated ClickHouse aggregation pipeline"
+        );
+
+        Ok(failures)
+    }
+}
+
+/// A row of `system.tables`.
+#[derive(clickhouse::Row, Deserialize)]
+struct TableRow {
+    name: String,
+    engine: String,
+    engine_full: String,
+}
+
+/// Returns the tables and views of the database.
+async fn fetch_tables(client: &clickhouse::Client) -> anyhow::Result<Vec<TableRow>> {
+    Ok(client
+        .query(
+            "SELECT name, engine, engine_full FROM system.tables \
+             WHERE database = currentDatabase()",
+        )
+        .fetch_all::<TableRow>()
+        .await?)
+}
+
+/// Returns the names of the attached materialized views among `tables`.
+/// Detached views are not listed in `system.tables`.
+fn attached_views(tables: &[TableRow]) -> Vec<String> {
+    tables
+        .iter()
+        .filter(|row| row.engine == "MaterializedView")
+        .map(|row| row.name.clone())
+        .collect()
+}
+
+/// Returns the TTL in days of every table among `tables` that has one.
+fn table_ttls(tables: &[TableRow]) -> HashMap<String, u32> {
+    tables
+        .iter()
+        .filter_map(|row| Some((row.name.clone(), parse_ttl_days(&row.engine_full)?)))
+        .collect()
+}
+
+/// Quotes a string as a `ClickHouse` string literal.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Quotes a name as a `ClickHouse` identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+/// Returns the SQL condition matching the rows of a retention override, or
+/// `None` if its field does not exist for the policy's data type.
+///
+/// Log and span attributes are stored as JSON text, so a value matches both
+/// the string and the JSON-encoded attribute.
+fn override_condition(data_type: DataType, rule: &RetentionOverride) -> Option<String> {
+    let value = quote(&rule.value);
+    Some(match RetentionField::parse(&rule.field, data_type).ok()? {
+        RetentionField::Service => format!("service = {value}"),
+        RetentionField::Level => format!("level = {value}"),
+        RetentionField::Name => format!("name = {value}"),
+        RetentionField::Attribute(key) if data_type == DataType::Metrics => {
+            format!("labels[{}] = {value}", quote(&key))
+        }
+        RetentionField::Attribute(key) => format!(
+            "attributes[{}] IN ({}, {value})",
+            quote(&key),
+            quote(&serde_json::Value::from(rule.value.as_str()).to_string())
+        ),
+    })
+}
+
+/// Returns the `ClickHouse` TTL expression for a retention policy, given the
+/// expression of the row time.
+///
+/// Each override becomes a `DELETE WHERE` rule for the rows it matches and no
+/// earlier override matches, so the first matching override applies. The
+/// policy's own TTL is the last rule, for the rows no override matches.
+fn ttl_expression(policy: &RetentionPolicy, time: &str) -> String {
+    let mut rules = Vec::new();
+    let mut earlier: Vec<String> = Vec::new();
+    for rule in &policy.overrides {
+        let Some(condition) = override_condition(policy.data_type, rule) else {
+            continue;
+        };
+        let condition_sql = if earlier.is_empty() {
+            condition.clone()
+        } else {
+            format!("({condition}) AND NOT ({})", earlier.join(" OR "))
+        };
+        rules.push(format!(
+            "{time} + INTERVAL {} DAY DELETE WHERE {condition_sql}",
+            rule.ttl_days
+        ));
+        earlier.push(format!("({condition})"));
+    }
+
+    let default = format!("{time} + INTERVAL {} DAY", policy.ttl_days);
+    if earlier.is_empty() {
+        rules.push(default);
+    } else {
+        rules.push(format!(
+            "{default} DELETE WHERE NOT ({})",
+            earlier.join(" OR ")
+        ));
+    }
+    rules.join(", ")
+}
+
+/// Parses the TTL in days from a table's engine definition.
+///
+/// `ClickHouse` normalizes `INTERVAL 30 DAY` to `toIntervalDay(30)`. The
+/// policy's own TTL is the last rule of a table with overrides. TTLs in other
+/// units are not parsed.
+fn parse_ttl_days(engine_full: &str) -> Option<u32> {
+    let (_, ttl) = engine_full.split_once(" TTL ")?;
+    let (_, days) = ttl.rsplit_once("toIntervalDay(")?;
+    days.split_once(')')?.0.trim().parse().ok()
+}
+
+/// Makes the configuration match the table TTLs in `ttls`, returning the raw
+/// and tier tables whose TTL differed.
+///
+/// A tier takes the TTL of its first table, so tier tables that disagree with
+/// each other stay reported as drift.
+fn reconcile_ttls(
+    retention: &mut RetentionConfig,
+    aggregation: &mut AggregationConfig,
+    ttls: &HashMap<String, u32>,
+) -> (Vec<TtlDrift>, Vec<TtlDrift>) {
+    let drift = |table: &str, configured_days: u32| {
+        ttls.get(table)
+            .filter(|actual| **actual != configured_days)
+            .map(|&actual_days| TtlDrift {
+                table: table.to_string(),
+                configured_days,
+                actual_days,
+            })
+    };
+
+    let mut retention_drift = Vec::new();
+    for (data_type, table, _) in RETENTION_TABLES {
+        if let Some(drift) = drift(table, retention.get_policy(data_type).ttl_days) {
+            retention.update_policy(data_type, drift.actual_days);
+            retention_drift.push(drift);
+        }
+    }
+
+    let mut aggregation_drift = Vec::new();
+    for interval in AggregationInterval::ALL {
+        let policy = aggregation.get_policy(interval).clone();
+        let tables = interval.tables();
+        let drifts: Vec<TtlDrift> = tables
+            .iter()
+            .filter_map(|table| drift(table, policy.retention_days))
+            .collect();
+        if let Some(&actual_days) = tables.first().and_then(|table| ttls.get(table)) {
+            aggregation.update_policy(interval, actual_days, policy.enabled);
+        }
+        aggregation_drift.extend(drifts);
+    }
+
+    (retention_drift, aggregation_drift)
+}
+
+/// Returns the tier views whose attachment differs from the aggregation
+/// configuration, given the names of the `attached` views.
+///
+/// A view should be attached exactly when aggregation and its tier are enabled.
+fn reconcile_views(aggregation: &AggregationConfig, attached: &[String]) -> Vec<ViewDrift> {
+    AggregationInterval::ALL
+        .iter()
+        .flat_map(|&interval| {
+            let configured_enabled =
+                aggregation.enabled && aggregation.get_policy(interval).enabled;
+            interval.tables().into_iter().filter_map(move |table| {
+                let view = format!("{table}_mv");
+                let is_attached = attached.contains(&view);
+                (is_attached != configured_enabled).then_some(ViewDrift {
+                    view,
+                    configured_enabled,
+                    attached: is_attached,
+                })
+            })
+        })
+        .collect()
 }
 
 impl Default for AppState {
@@ -225,6 +824,207 @@ mod tests {
         assert_eq!(state.trace_store().span_count().unwrap(), 1);
     }
 
+    #[test]
+    fn test_parse_ttl_days() {
+        assert_eq!(
+            parse_ttl_days(
+                "MergeTree PARTITION BY toYYYYMMDD(toDateTime(timestamp / 1000000000)) \
+                 ORDER BY (service, timestamp) \
+                 TTL toDateTime(timestamp / 1000000000) + toIntervalDay(45) \
+                 SETTINGS index_granularity = 8192"
+            ),
+            Some(45)
+        );
+        assert_eq!(
+            parse_ttl_days("SummingMergeTree ORDER BY name TTL timestamp + toIntervalDay(365)"),
+            Some(365)
+        );
+        assert_eq!(
+            parse_ttl_days(
+                "MergeTree ORDER BY name \
+                 TTL toDateTime(timestamp / 1000000000) + toIntervalDay(400) WHERE service = 'billing', \
+                 toDateTime(timestamp / 1000000000) + toIntervalDay(30) WHERE NOT (service = 'billing') \
+                 SETTINGS index_granularity = 8192"
+            ),
+            Some(30)
+        );
+        assert_eq!(parse_ttl_days("MergeTree ORDER BY name"), None);
+        assert_eq!(
+            parse_ttl_days("MergeTree ORDER BY name TTL timestamp + toIntervalMonth(1)"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ttl_expression() {
+        let time = "toDateTime(timestamp / 1000000000)";
+        assert_eq!(
+            ttl_expression(&RetentionPolicy::new(DataType::Logs, 30), time),
+            "toDateTime(timestamp / 1000000000) + INTERVAL 30 DAY"
+        );
+
+        let policy = RetentionPolicy::new(DataType::Logs, 30)
+            .with_override("service", "billing", 400)
+            .with_override("level", "debug", 3)
+            .with_override("attributes.team", "o'neil", 7);
+        assert_eq!(
+            ttl_expression(&policy, "t"),
+            "t + INTERVAL 400 DAY DELETE WHERE service = 'billing', \
+             t + INTERVAL 3 DAY DELETE WHERE (level = 'debug') AND NOT ((service = 'billing')), \
+             t + INTERVAL 7 DAY DELETE WHERE (attributes['team'] IN ('\"o\\'neil\"', 'o\\'neil')) \
+             AND NOT ((service = 'billing') OR (level = 'debug')), \
+             t + INTERVAL 30 DAY DELETE WHERE NOT ((service = 'billing') OR (level = 'debug') \
+             OR (attributes['team'] IN ('\"o\\'neil\"', 'o\\'neil')))"
+        );
+
+        let metrics =
+            RetentionPolicy::new(DataType::Metrics, 90).with_override("labels.env", "dev", 7);
+        assert_eq!(
+            ttl_expression(&metrics, "t"),
+            "t + INTERVAL 7 DAY DELETE WHERE labels['env'] = 'dev', \
+             t + INTERVAL 90 DAY DELETE WHERE NOT ((labels['env'] = 'dev'))"
+        );
+    }
+
+    #[test]
+    fn test_enforce_retention() {
+        let state = AppState::with_in_memory_store();
+        state.set_retention_config(RetentionConfig {
+            logs: RetentionPolicy::new(DataType::Logs, 30)
+                .with_override("service", "billing", 400)
+                .with_override("level", "debug", 3),
+            traces: RetentionPolicy::new(DataType::Traces, 30).with_override("name", "health", 1),
+            ..Default::default()
+        });
+
+        let now = Utc::now();
+        let log = |level, service: &str, days| {
+            let mut log = LogEntry::new(level, "message", service);
+            log.timestamp = now - chrono::Duration::days(days);
+            log
+        };
+        state
+            .log_store()
+            .insert_batch(vec![
+                log(LogLevel::Debug, "batch", 5),
+                log(LogLevel::Debug, "batch", 1),
+                log(LogLevel::Debug, "billing", 100),
+                log(LogLevel::Info, "api", 31),
+                log(LogLevel::Info, "api", 29),
+            ])
+            .unwrap();
+        let span = |id: &str, name: &str, days| {
+            let start = now - chrono::Duration::days(days);
+            Span::new("trace-1", id, name, "api")
+                .with_start_time(start)
+                .with_end_time(start)
+        };
+        state
+            .trace_store()
+            .insert_spans(vec![span("s1", "health", 2), span("s2", "GET /", 2)])
+            .unwrap();
+
+        assert_eq!(state.enforce_retention(now).unwrap(), 3);
+        assert_eq!(state.log_store().count().unwrap(), 3);
+        assert_eq!(state.trace_store().span_count().unwrap(), 1);
+        assert_eq!(state.enforce_retention(now).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("metrics_1min_mv"), "`metrics_1min_mv`");
+        assert_eq!(quote_identifier("a`b\\c"), "`a\\`b\\\\c`");
+    }
+
+    #[test]
+    fn test_reconcile_ttls() {
+        let mut retention = RetentionConfig::default();
+        let mut aggregation = AggregationConfig::default();
+        let ttls: HashMap<String, u32> = [
+            ("logs", 30),
+            ("metrics", 45),
+            ("metrics_1hour", 200),
+            ("logs_1hour_counts", 200),
+            ("spans_1hour_stats", 365),
+            ("metrics_1day", 730),
+        ]
+        .into_iter()
+        .map(|(table, days)| (table.to_string(), days))
+        .collect();
+
+        let (retention_drift, aggregation_drift) =
+            reconcile_ttls(&mut retention, &mut aggregation, &ttls);
+
+        assert_eq!(retention, RetentionConfig::new(30, 45, 30));
+        assert_eq!(
+            retention_drift,
+            vec![TtlDrift {
+                table: "metrics".to_string(),
+                configured_days: 90,
+                actual_days: 45,
+            }]
+        );
+        assert_eq!(aggregation.one_hour.retention_days, 200);
+        assert!(aggregation.one_hour.enabled);
+        assert_eq!(aggregation.one_day.retention_days, 730);
+        let tables: Vec<&str> = aggregation_drift.iter().map(|d| d.table.as_str()).collect();
+        assert_eq!(tables, vec!["metrics_1hour", "logs_1hour_counts"]);
+    }
+
+    #[test]
+    fn test_reconcile_views() {
+        let mut aggregation = AggregationConfig::default();
+        aggregation.update_policy(AggregationInterval::FiveMinutes, 90, false);
+        let mut attached: Vec<String> = AggregationInterval::ALL
+            .iter()
+            .flat_map(AggregationInterval::tables)
+            .map(|table| format!("{table}_mv"))
+            .filter(|view| view != "logs_1day_counts_mv")
+            .collect();
+        attached.push("other_mv".to_string());
+
+        assert_eq!(
+            reconcile_views(&aggregation, &attached),
+            vec![
+                ViewDrift {
+                    view: "metrics_5min_mv".to_string(),
+                    configured_enabled: false,
+                    attached: true,
+                },
+                ViewDrift {
+                    view: "logs_1day_counts_mv".to_string(),
+                    configured_enabled: true,
+                    attached: false,
+                },
+            ]
+        );
+
+        aggregation.enabled = false;
+        assert_eq!(reconcile_views(&aggregation, &[]), Vec::new());
+    }
+
+    #[tokio::test]
+    async fn test_restore_config() {
+        let state = AppState::with_in_memory_store();
+        let retention = RetentionConfig::new(7, 180, 14);
+        state.settings_store().save_retention(&retention).unwrap();
+        let invalid = AggregationConfig {
+            one_minute: shared::config::AggregationPolicy::new(
+                AggregationInterval::OneMinute,
+                0,
+                true,
+            ),
+            ..Default::default()
+        };
+        state.settings_store().save_aggregation(&invalid).unwrap();
+
+        state.restore_config().await;
+
+        assert_eq!(state.get_retention_config(), retention);
+        assert_eq!(state.get_aggregation_config(), AggregationConfig::default());
+        assert!(state.retention_drift().is_empty());
+    }
+
     #[test]
     fn test_app_state_is_clone() {
         let state = AppState::with_in_memory_store();
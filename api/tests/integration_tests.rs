@@ -5,8 +5,13 @@
 //!
 //! Tests are organized into separate modules:
 //! - `aggregation_tests` - ClickHouse aggregation/materialized views (requires ClickHouse)
+//! - `fields_tests` - Field discovery
 //! - `logs_tests` - Log ingestion and querying
 //! - `query_tests` - SQL-like query functionality
+//! - `retention_tests` - Retention overrides
+//! - `rollup_tests` - Aggregation tier endpoints
+//! - `saved_query_tests` - Saved query management and execution
+//! - `settings_tests` - Persisting the retention and aggregation configuration
 //! - `metrics_tests` - Metrics ingestion and aggregation
 //! - `traces_tests` - Trace ingestion and querying
 //! - `health_tests` - Health check and general API functionality
@@ -15,10 +20,15 @@
 mod integration_tests {
     pub mod aggregation_tests;
     pub mod common;
+    pub mod fields_tests;
     pub mod grpc_tests;
     pub mod health_tests;
     pub mod logs_tests;
     pub mod metrics_tests;
     pub mod query_tests;
+    pub mod retention_tests;
+    pub mod rollup_tests;
+    pub mod saved_query_tests;
+    pub mod settings_tests;
     pub mod traces_tests;
 }
@@ -6,14 +6,20 @@
 //! - Spans: Hourly and daily span statistics (latency percentiles, throughput)
 //! - Traces: Hourly and daily trace statistics (unique traces, spans per trace)
 //!
+//! Updating the aggregation configuration reconfigures the tier tables and
+//! their materialized views.
+//!
 //! These tests require a running ClickHouse instance with the schema applied.
 //! Run with: `cargo test -- --ignored`
 
 use axum::http::StatusCode;
 use serde_json::json;
+use shared::config::AggregationConfig;
 use std::time::Duration;
 
-use super::common::{create_clickhouse_client, get, post_json, test_app_with_clickhouse};
+use super::common::{
+    create_clickhouse_client, get, post_json, request_json, test_app, test_app_with_clickhouse,
+};
 
 // ============================================================================
 // AGGREGATION CONFIG API TESTS
@@ -31,13 +37,108 @@ async fn test_get_aggregation_config_with_clickhouse() {
     assert!(response["config"].is_object());
 
     let config = &response["config"];
-    assert!(!config["enabled"].as_bool().unwrap()); // Disabled by default
+    assert!(config["enabled"].as_bool().unwrap()); // Enabled by default
     assert_eq!(config["one_minute"]["retention_days"], 30);
     assert_eq!(config["five_minutes"]["retention_days"], 90);
     assert_eq!(config["one_hour"]["retention_days"], 365);
     assert_eq!(config["one_day"]["retention_days"], 730);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_update_aggregation_config_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let client = create_clickhouse_client();
+
+    let attached = |client: clickhouse::Client| async move {
+        client
+            .query("SELECT count() FROM system.tables WHERE database = currentDatabase() AND name = 'metrics_5min_mv'")
+            .fetch_one::<u64>()
+            .await
+            .unwrap()
+            == 1
+    };
+
+    // Disable the five-minute tier and shorten the hourly retention
+    let mut config = json!({
+        "enabled": true,
+        "one_minute": {"interval": "one_minute", "retention_days": 30, "enabled": true},
+        "five_minutes": {"interval": "five_minutes", "retention_days": 90, "enabled": false},
+        "one_hour": {"interval": "one_hour", "retention_days": 200, "enabled": true},
+        "one_day": {"interval": "one_day", "retention_days": 730, "enabled": true}
+    });
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/aggregation",
+        Some(config.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert!(!attached((*client).clone()).await);
+
+    let ttl: String = client
+        .query("SELECT engine_full FROM system.tables WHERE database = currentDatabase() AND name = 'logs_1hour_counts'")
+        .fetch_one()
+        .await
+        .unwrap();
+    assert!(ttl.contains("toIntervalDay(200)"), "{ttl}");
+
+    // Restore the defaults with every tier enabled
+    config["five_minutes"]["enabled"] = json!(true);
+    config["one_hour"]["retention_days"] = json!(365);
+    let (status, response) =
+        request_json(app, "PUT", "/api/v1/config/aggregation", Some(config)).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert!(attached((*client).clone()).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_saving_default_aggregation_config_keeps_views_attached() {
+    let (app, _state) = test_app_with_clickhouse();
+    let client = create_clickhouse_client();
+    let views = |client: clickhouse::Client| async move {
+        client
+            .query("SELECT count() FROM system.tables WHERE database = currentDatabase() AND engine = 'MaterializedView' AND name LIKE '%\\_mv'")
+            .fetch_one::<u64>()
+            .await
+            .unwrap()
+    };
+    let before = views((*client).clone()).await;
+
+    // Saving the configuration as read leaves every view attached
+    let (_, response) = get(app.clone(), "/api/v1/config/aggregation").await;
+    let (status, response) = request_json(
+        app,
+        "PUT",
+        "/api/v1/config/aggregation",
+        Some(response["config"].clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert!(response.get("failures").is_none());
+    assert_eq!(views((*client).clone()).await, before);
+}
+
+#[tokio::test]
+async fn test_update_aggregation_config_rejects_invalid_policy() {
+    let (app, state) = test_app();
+
+    let config = json!({
+        "enabled": true,
+        "one_minute": {"interval": "one_minute", "retention_days": 30, "enabled": true},
+        "five_minutes": {"interval": "one_hour", "retention_days": 90, "enabled": true},
+        "one_hour": {"interval": "one_hour", "retention_days": 365, "enabled": true},
+        "one_day": {"interval": "one_day", "retention_days": 730, "enabled": true}
+    });
+    let (status, response) =
+        request_json(app, "PUT", "/api/v1/config/aggregation", Some(config)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(!response["success"].as_bool().unwrap());
+    assert_eq!(state.get_aggregation_config(), AggregationConfig::default());
+}
+
 // ============================================================================
 // METRIC AGGREGATION TESTS
 // ============================================================================
@@ -22,6 +22,16 @@ pub fn test_app() -> (Router, AppState) {
     (router, state)
 }
 
+/// Creates a test router with fresh in-memory stores and a query result cache.
+///
+/// Results are cached for a minute, so repeated queries within a test hit the cache.
+pub fn test_app_with_query_cache() -> (Router, AppState) {
+    let cache = shared::query::QueryCache::new(std::time::Duration::from_mins(1), 100);
+    let state = AppState::with_in_memory_store().with_query_cache(cache);
+    let router = create_router(state.clone());
+    (router, state)
+}
+
 /// Creates a ClickHouse client for integration tests.
 ///
 /// Uses default test database configuration:
@@ -118,3 +128,83 @@ pub async fn get(app: Router, uri: &str) -> (StatusCode, Value) {
 
     (status, json)
 }
+
+/// Helper to make a request with any method and an optional JSON body.
+///
+/// # Arguments
+///
+/// * `app` - The Axum router to send the request to
+/// * `method` - The HTTP method
+/// * `uri` - The URI path
+/// * `body` - The JSON body to send, if any
+///
+/// # Returns
+///
+/// A tuple containing the response status code and parsed JSON response body.
+pub async fn request_json(
+    app: Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if body.is_some() {
+        request = request.header(header::CONTENT_TYPE, "application/json");
+    }
+    let response = tower::ServiceExt::oneshot(
+        app,
+        request
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let status = response.status();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+
+    (status, json)
+}
+
+/// Helper to make a request accepting an NDJSON response.
+///
+/// # Arguments
+///
+/// * `app` - The Axum router to send the request to
+/// * `method` - The HTTP method
+/// * `uri` - The URI path
+/// * `body` - The JSON body to send, if any
+///
+/// # Returns
+///
+/// A tuple containing the response status code and the parsed JSON lines.
+pub async fn request_ndjson(
+    app: Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Vec<Value>) {
+    let response = tower::ServiceExt::oneshot(
+        app,
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/x-ndjson")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let status = response.status();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let lines = std::str::from_utf8(&body_bytes)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    (status, lines)
+}
@@ -0,0 +1,172 @@
+//! Integration tests for field discovery.
+//!
+//! Tests cover:
+//! - Listing built-in fields, attributes and labels with types and cardinality
+//! - Filtering by service and time range
+//! - Listing the most frequent values of a field
+//! - Field discovery in `ClickHouse` (requires running `ClickHouse`)
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::common::{get, post_json, test_app, test_app_with_clickhouse};
+
+fn field<'a>(response: &'a Value, name: &str) -> &'a Value {
+    response["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|field| field["name"] == name)
+        .unwrap_or_else(|| panic!("no field {name} in {response}"))
+}
+
+#[tokio::test]
+async fn test_discover_log_fields() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "Boom", "service": "api", "attributes": {"http.status_code": 500, "user_id": "u1", "service": "shadow"}},
+        {"level": "info", "message": "OK", "service": "api", "attributes": {"http.status_code": 200, "user_id": "u2"}},
+        {"level": "info", "message": "OK", "service": "api", "attributes": {"http.status_code": 200}},
+        {"level": "info", "message": "Hello", "service": "web", "attributes": {"path": "/"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/fields?source=logs&service=api").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["source"], "logs");
+    assert_eq!(response["fields"][0]["name"], "timestamp");
+    assert_eq!(
+        field(&response, "level"),
+        &json!({"name": "level", "kind": "builtin", "type": "string", "count": 3, "cardinality": 2})
+    );
+    assert_eq!(
+        field(&response, "http.status_code"),
+        &json!({"name": "http.status_code", "kind": "attribute", "type": "number", "count": 3, "cardinality": 2})
+    );
+    assert_eq!(field(&response, "user_id")["count"], 2);
+    // Attributes named like built-in fields are listed under their query name
+    assert_eq!(field(&response, "attributes.service")["kind"], "attribute");
+    // Only fields of the service's logs are listed
+    assert!(!response["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|field| field["name"] == "path"));
+
+    let (status, response) = get(
+        app.clone(),
+        "/api/v1/fields/http.status_code/values?source=logs&service=api",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response,
+        json!({
+            "field": "http.status_code",
+            "values": [{"value": 200, "count": 2}, {"value": 500, "count": 1}]
+        })
+    );
+
+    let (_, response) = get(
+        app.clone(),
+        "/api/v1/fields/service/values?source=logs&limit=1",
+    )
+    .await;
+    assert_eq!(response["values"], json!([{"value": "api", "count": 3}]));
+
+    // Logs outside the time range are not considered
+    let (_, response) = get(
+        app.clone(),
+        "/api/v1/fields?source=logs&start_time=2000-01-01T00:00:00Z&end_time=2000-01-02T00:00:00Z",
+    )
+    .await;
+    assert_eq!(field(&response, "service")["count"], 0);
+    assert_eq!(response["total_count"], 6);
+
+    let (status, _) = get(app, "/api/v1/fields?source=events").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_discover_metric_and_trace_fields() {
+    let (app, _state) = test_app();
+
+    let metrics = json!([
+        {"name": "cpu_usage", "metric_type": "gauge", "value": 91.5, "labels": {"host": "web-1", "service": "api"}},
+        {"name": "cpu_usage", "metric_type": "gauge", "value": 20.0, "labels": {"host": "web-2", "service": "api"}},
+        {"name": "memory_usage", "metric_type": "gauge", "value": 512.0, "labels": {"host": "web-1", "service": "db"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/metrics", metrics).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/fields?source=metrics&service=api").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(field(&response, "name")["cardinality"], 1);
+    assert_eq!(field(&response, "value")["type"], "number");
+    assert_eq!(
+        field(&response, "host"),
+        &json!({"name": "host", "kind": "label", "type": "string", "count": 2, "cardinality": 2})
+    );
+
+    let spans = json!([
+        {"trace_id": "t1", "span_id": "s1", "name": "GET /checkout", "service": "api", "duration_ms": 900, "attributes": {"http.method": "GET"}},
+        {"trace_id": "t1", "span_id": "s2", "parent_span_id": "s1", "name": "charge", "service": "payments", "duration_ms": 850}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/traces", spans).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/fields?source=traces").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(field(&response, "parent_span_id")["count"], 1);
+    assert_eq!(field(&response, "duration_ms")["type"], "number");
+    assert_eq!(field(&response, "http.method")["count"], 1);
+
+    let (_, response) = get(app, "/api/v1/fields/name/values?source=traces").await;
+    assert_eq!(response["values"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_discover_fields_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "fields-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "Boom", "service": service, "attributes": {"http.status_code": 500, "user_id": "u1"}},
+        {"level": "info", "message": "OK", "service": service, "attributes": {"http.status_code": 200, "user_id": 7}},
+        {"level": "info", "message": "OK", "service": service, "attributes": {"http.status_code": 200}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(
+        app.clone(),
+        &format!("/api/v1/fields?source=logs&service={service}"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(field(&response, "level")["count"], 3);
+    assert_eq!(field(&response, "level")["cardinality"], 2);
+    assert_eq!(field(&response, "trace_id")["count"], 0);
+    assert_eq!(
+        field(&response, "http.status_code"),
+        &json!({"name": "http.status_code", "kind": "attribute", "type": "number", "count": 3, "cardinality": 2})
+    );
+    assert_eq!(field(&response, "user_id")["type"], "mixed");
+
+    let (status, response) = get(
+        app,
+        &format!("/api/v1/fields/http.status_code/values?source=logs&service={service}"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["values"],
+        json!([{"value": 200, "count": 2}, {"value": 500, "count": 1}])
+    );
+}
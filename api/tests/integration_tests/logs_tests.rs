@@ -4,12 +4,13 @@
 //! - Single and batch log ingestion
 //! - Filtering by level, service, and message content
 //! - Pagination
+//! - NDJSON streaming
 //! - SQL-like query syntax
 
 use axum::http::StatusCode;
 use serde_json::json;
 
-use super::common::{get, post_json, test_app};
+use super::common::{get, post_json, request_ndjson, test_app};
 
 #[tokio::test]
 async fn test_ingest_and_query_single_log() {
@@ -126,15 +127,15 @@ async fn test_pagination() {
     let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
     assert_eq!(status, StatusCode::CREATED);
 
-    // Get first 2 logs
+    // Get first 2 logs: newest first, no longer in insertion order
     let (status, response) = get(app.clone(), "/api/v1/logs?limit=2").await;
     assert_eq!(status, StatusCode::OK);
     assert_eq!(response["total_count"], 5);
     assert_eq!(response["logs"].as_array().unwrap().len(), 2);
     let first_page = response["logs"].as_array().unwrap();
 
-    assert_eq!(first_page[0]["message"], "Log 1");
-    assert_eq!(first_page[1]["message"], "Log 2");
+    assert_eq!(first_page[0]["message"], "Log 5");
+    assert_eq!(first_page[1]["message"], "Log 4");
 
     // Get next 2 logs
     let (status, response) = get(app, "/api/v1/logs?limit=2&offset=2").await;
@@ -143,7 +144,7 @@ async fn test_pagination() {
 
     let second_page = response["logs"].as_array().unwrap();
     assert_eq!(second_page[0]["message"], "Log 3");
-    assert_eq!(second_page[1]["message"], "Log 4");
+    assert_eq!(second_page[1]["message"], "Log 2");
 }
 
 #[tokio::test]
@@ -162,3 +163,58 @@ async fn test_log_validation_errors_return_400() {
     assert_eq!(status, StatusCode::BAD_REQUEST);
     assert_eq!(response["error"], "validation_failed");
 }
+
+#[tokio::test]
+async fn test_stream_logs_as_ndjson() {
+    let (app, _state) = test_app();
+
+    // More logs than the maximum page size of the JSON response
+    let logs: Vec<_> = (0..1500)
+        .map(|i| json!({"level": "info", "message": format!("Log {i}"), "service": "export"}))
+        .collect();
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", json!(logs)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, lines) =
+        request_ndjson(app.clone(), "GET", "/api/v1/logs?service=export", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lines.len(), 1500);
+    assert!(lines.iter().all(|log| log["service"] == "export"));
+
+    // Limit and offset still apply when given
+    let (status, lines) = request_ndjson(
+        app.clone(),
+        "GET",
+        "/api/v1/logs?service=export&limit=10&offset=1495",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lines.len(), 5);
+
+    // Without the Accept header the response is capped
+    let (status, response) = get(app, "/api/v1/logs?service=export&limit=5000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["returned_count"], 1000);
+}
+
+#[tokio::test]
+async fn test_query_logs_limits() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "info", "message": "a", "service": "api"},
+        {"level": "info", "message": "b", "service": "api"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/logs?max_rows_scanned=1").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "query_limit_exceeded");
+    assert_eq!(response["limit"], "max_rows_scanned");
+
+    let (status, response) = get(app, "/api/v1/logs?max_rows_scanned=2&timeout_ms=5000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["returned_count"], 2);
+}
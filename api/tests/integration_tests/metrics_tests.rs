@@ -4,11 +4,12 @@
 //! - Single and batch metric ingestion
 //! - Filtering by name and metric type
 //! - Aggregations (sum, avg, min, max, count)
+//! - Caching of aggregations
 
 use axum::http::StatusCode;
 use serde_json::json;
 
-use super::common::{get, post_json, test_app};
+use super::common::{get, post_json, test_app, test_app_with_query_cache};
 
 #[tokio::test]
 async fn test_ingest_and_query_single_metric() {
@@ -164,3 +165,50 @@ async fn test_empty_metrics_store_returns_empty_results() {
     assert_eq!(status, StatusCode::OK);
     assert_eq!(response["total_count"], 0);
 }
+
+#[tokio::test]
+async fn test_aggregation_cache() {
+    let (app, _state) = test_app_with_query_cache();
+
+    let metric = json!({"name": "cpu_usage", "metric_type": "gauge", "value": 10.0});
+    let (status, _) = post_json(app.clone(), "/api/v1/metrics", metric.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/metrics?name=cpu_usage&aggregate=sum").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["cache"], "miss");
+    assert_eq!(response["aggregation"]["value"], 10.0);
+
+    let (status, _) = post_json(app.clone(), "/api/v1/metrics", metric).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, response) = get(app.clone(), "/api/v1/metrics?name=cpu_usage&aggregate=sum").await;
+    assert_eq!(response["cache"], "hit");
+    assert_eq!(response["aggregation"]["value"], 10.0);
+
+    // Other aggregations and plain queries are computed
+    let (_, response) = get(
+        app.clone(),
+        "/api/v1/metrics?name=cpu_usage&aggregate=count",
+    )
+    .await;
+    assert_eq!(response["cache"], "miss");
+    assert_eq!(response["aggregation"]["value"], 2.0);
+    let (_, response) = get(app.clone(), "/api/v1/metrics?name=cpu_usage").await;
+    assert!(response.get("cache").is_none());
+    assert_eq!(response["total_count"], 2);
+
+    // Equivalent parameters share the cached aggregation
+    let (_, response) = get(
+        app.clone(),
+        "/api/v1/metrics?aggregate=SUM&limit=5000&name=cpu_usage",
+    )
+    .await;
+    assert_eq!(response["cache"], "miss");
+    let (_, response) = get(
+        app,
+        "/api/v1/metrics?name=cpu_usage&limit=1000&aggregate=Sum",
+    )
+    .await;
+    assert_eq!(response["cache"], "hit");
+    assert_eq!(response["aggregation"]["function"], "sum");
+}
@@ -6,11 +6,28 @@
 //! - CONTAINS operator for text search
 //! - LIMIT and OFFSET
 //! - Error handling for invalid syntax
+//! - Metric and trace sources
+//! - Projections, GROUP BY aggregations and time buckets
+//! - HAVING on group keys, in memory and in `ClickHouse`
+//! - Relative times (`now()`) and `SINCE` / `UNTIL` time ranges
+//! - `IN`, `BETWEEN`, `IS [NOT] NULL` and `NOT`
+//! - Regular expression matching with `MATCHES` / `=~`
+//! - Multi-key `ORDER BY`, by attributes and by aggregate aliases
+//! - `SELECT DISTINCT` and `topk(k, field)`
+//! - Typed and nested attribute comparisons
+//! - `$name` bind parameters
+//! - Result caching of repeated queries and completed time buckets
+//! - Keyset pagination with `cursor` / `next_cursor`
+//! - NDJSON streaming
+//! - Query plans with `/api/v1/query/explain`
+//! - Filter pushdown to `ClickHouse` (requires running `ClickHouse`)
 
 use axum::http::StatusCode;
 use serde_json::json;
 
-use super::common::{post_json, test_app};
+use super::common::{
+    post_json, request_ndjson, test_app, test_app_with_clickhouse, test_app_with_query_cache,
+};
 
 #[tokio::test]
 async fn test_sql_query_with_where_clause() {
@@ -32,6 +49,7 @@ async fn test_sql_query_with_where_clause() {
     let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
 
     assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "logs");
     assert_eq!(response["total_count"], 2);
 
     let logs = response["logs"].as_array().unwrap();
@@ -137,9 +155,8 @@ async fn test_sql_query_with_limit_offset() {
         .iter()
         .map(|l| l["message"].as_str().unwrap())
         .collect();
-    // With OFFSET 1 and LIMIT 2, we skip the first log and get the next 2
-    assert!(messages.contains(&"B"));
-    assert!(messages.contains(&"C"));
+    // Newest first: with OFFSET 1 and LIMIT 2, we skip the newest log and get the next 2
+    assert_eq!(messages, ["D", "C"]);
 }
 
 #[tokio::test]
@@ -151,4 +168,1344 @@ async fn test_sql_query_invalid_syntax_returns_error() {
 
     assert_eq!(status, StatusCode::BAD_REQUEST);
     assert_eq!(response["error"], "parse_error");
+    assert_eq!(response["syntax"]["line"], 1);
+    assert_eq!(response["syntax"]["column"], 1);
+    assert_eq!(response["syntax"]["found"], "SELEKT");
+    assert_eq!(response["syntax"]["suggestion"], "SELECT");
+}
+
+#[tokio::test]
+async fn test_sql_query_syntax_error_location() {
+    let (app, _state) = test_app();
+
+    let query = json!({"query": "SELECT *\nFROM logs\nWHERE level = 'error' LIMT 10"});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "parse_error");
+    let syntax = &response["syntax"];
+    assert_eq!(syntax["line"], 3);
+    assert_eq!(syntax["column"], 23);
+    assert_eq!(syntax["found"], "LIMT");
+    assert_eq!(syntax["suggestion"], "LIMIT");
+    assert_eq!(
+        syntax["snippet"],
+        "WHERE level = 'error' LIMT 10\n                      ^^^^"
+    );
+
+    // A bare name close to a built-in field is queried as an attribute
+    let query = json!({"query": "SELECT * FROM logs WHERE levle = 'error'"});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 0);
+
+    // Errors that name a misspelled field suggest the intended one
+    let query = json!({"query": "SELECT servce, count(*) FROM logs GROUP BY service"});
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(response["message"]
+        .as_str()
+        .unwrap()
+        .ends_with("did you mean 'service'?"));
+}
+
+#[tokio::test]
+async fn test_sql_query_metrics() {
+    let (app, _state) = test_app();
+
+    let metrics = json!([
+        {"name": "cpu_usage", "metric_type": "gauge", "value": 91.5, "labels": {"host": "web-1"}},
+        {"name": "cpu_usage", "metric_type": "gauge", "value": 20.0, "labels": {"host": "web-2"}},
+        {"name": "memory_usage", "metric_type": "gauge", "value": 512.0, "labels": {"host": "web-1"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/metrics", metrics).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT * FROM metrics WHERE labels.host = 'web-1' ORDER BY value DESC"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "metrics");
+    assert_eq!(response["total_count"], 2);
+
+    let metrics = response["metrics"].as_array().unwrap();
+    assert_eq!(metrics[0]["name"], "memory_usage");
+    assert_eq!(metrics[1]["name"], "cpu_usage");
+}
+
+#[tokio::test]
+async fn test_sql_query_traces() {
+    let (app, _state) = test_app();
+
+    let spans = json!([
+        {"trace_id": "t1", "span_id": "s1", "name": "GET /checkout", "service": "api", "duration_ms": 900, "status": "error"},
+        {"trace_id": "t1", "span_id": "s2", "parent_span_id": "s1", "name": "charge", "service": "payments", "duration_ms": 850, "status": "error"},
+        {"trace_id": "t2", "span_id": "s3", "name": "GET /health", "service": "api", "duration_ms": 2, "status": "ok"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/traces", spans).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT * FROM traces WHERE status = 'error' AND duration_ms > 100 AND service = 'api'"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "spans");
+    assert_eq!(response["total_count"], 1);
+    assert_eq!(response["spans"][0]["span_id"], "s1");
+}
+
+#[tokio::test]
+async fn test_sql_query_projection() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "Checkout failed", "service": "api", "attributes": {"user_id": "42"}},
+        {"level": "info", "message": "Checkout ok", "service": "web"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT service, message AS msg, attributes.user_id FROM logs ORDER BY service ASC"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "rows");
+    assert_eq!(
+        response["columns"],
+        json!(["service", "msg", "attributes.user_id"])
+    );
+    assert_eq!(
+        response["rows"],
+        json!([
+            ["api", "Checkout failed", "42"],
+            ["web", "Checkout ok", null]
+        ])
+    );
+    assert_eq!(response["total_count"], 2);
+}
+
+#[tokio::test]
+async fn test_sql_query_group_by() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "Checkout failed", "service": "api", "attributes": {"latency_ms": 120}},
+        {"level": "error", "message": "Payment failed", "service": "api", "attributes": {"latency_ms": 80}},
+        {"level": "error", "message": "Query timeout", "service": "db"},
+        {"level": "info", "message": "Checkout ok", "service": "web"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT service, count(*), avg(attributes.latency_ms) AS latency FROM logs \
+                  WHERE level = 'error' GROUP BY service HAVING count(*) > 1"
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "rows");
+    assert_eq!(
+        response["columns"],
+        json!(["service", "count(*)", "latency"])
+    );
+    assert_eq!(response["rows"], json!([["api", 2, 100]]));
+    assert_eq!(response["total_count"], 1);
+
+    let query = json!({"query": "SELECT service, level FROM logs GROUP BY service"});
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "execution_error");
+}
+
+/// HAVING on group keys, with the same results in memory and in `ClickHouse`.
+async fn check_having_on_group_keys(app: axum::Router, service: &str) {
+    let other = format!("{service}-other");
+    let logs = json!([
+        {"level": "error", "message": "a", "service": service, "attributes": {"region": "eu", "shard": 2}},
+        {"level": "error", "message": "b", "service": service, "attributes": {"region": "eu", "shard": 2.5}},
+        {"level": "info", "message": "c", "service": service, "attributes": {"region": "us", "shard": 1}},
+        {"level": "error", "message": "d", "service": other}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let cases = [
+        (
+            "service",
+            format!("service = '{}'", service.to_uppercase()),
+            json!([[service, 3]]),
+        ),
+        (
+            "level",
+            "level != 'info' AND n > 1".to_string(),
+            json!([["error", 3]]),
+        ),
+        (
+            "attributes.region",
+            "attributes.region = 'eu'".to_string(),
+            json!([["eu", 2]]),
+        ),
+        (
+            "attributes.region",
+            "attributes.region != 'eu'".to_string(),
+            json!([["us", 1], [null, 1]]),
+        ),
+        (
+            "attributes.shard",
+            "attributes.shard >= 2".to_string(),
+            json!([[2, 1], [2.5, 1]]),
+        ),
+    ];
+    for (key, having, rows) in cases {
+        let query = json!({
+            "query": format!(
+                "SELECT {key}, count(*) AS n FROM logs \
+                 WHERE service = '{service}' OR service = '{other}' \
+                 GROUP BY {key} HAVING {having} ORDER BY {key} ASC"
+            )
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+        assert_eq!(status, StatusCode::OK, "{response}");
+        assert_eq!(response["rows"], rows, "{having}");
+    }
+
+    // Fields that are neither grouped nor aggregated belong in WHERE
+    let query = json!({
+        "query": "SELECT service, count(*) FROM logs GROUP BY service HAVING level = 'error'"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "execution_error");
+}
+
+#[tokio::test]
+async fn test_having_on_group_keys() {
+    let (app, _state) = test_app();
+    check_having_on_group_keys(app, "having-keys").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_having_on_group_keys_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "having-keys-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+    check_having_on_group_keys(app, &service).await;
+}
+
+#[tokio::test]
+async fn test_sql_query_time_buckets() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"timestamp": "2024-01-01T10:00:30Z", "level": "error", "message": "a", "service": "api"},
+        {"timestamp": "2024-01-01T10:00:45Z", "level": "error", "message": "b", "service": "db"},
+        {"timestamp": "2024-01-01T10:02:10Z", "level": "error", "message": "c", "service": "api"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT bucket(timestamp, 1m) AS minute, service, count(*) FROM logs \
+                  WHERE timestamp >= '2024-01-01T10:00:00Z' AND timestamp < '2024-01-01T10:03:00Z' \
+                  GROUP BY bucket(timestamp, 1m), service"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["columns"],
+        json!(["minute", "service", "count(*)"])
+    );
+    assert_eq!(
+        response["rows"],
+        json!([
+            ["2024-01-01T10:00:00Z", "api", 1],
+            ["2024-01-01T10:00:00Z", "db", 1],
+            ["2024-01-01T10:01:00Z", "api", 0],
+            ["2024-01-01T10:01:00Z", "db", 0],
+            ["2024-01-01T10:02:00Z", "api", 1],
+            ["2024-01-01T10:02:00Z", "db", 0]
+        ])
+    );
+    assert_eq!(response["total_count"], 6);
+}
+
+#[tokio::test]
+async fn test_sql_query_set_range_and_null_operators() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "a", "service": "api", "trace_id": "t1", "attributes": {"latency_ms": 120}},
+        {"level": "warn", "message": "b", "service": "auth", "attributes": {"latency_ms": 80}},
+        {"level": "info", "message": "c", "service": "web"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        ("service IN ('api', 'auth')", json!([["a"], ["b"]])),
+        ("service NOT IN ('api', 'auth')", json!([["c"]])),
+        ("latency_ms BETWEEN 100 AND 200", json!([["a"]])),
+        ("latency_ms NOT BETWEEN 100 AND 200", json!([["b"], ["c"]])),
+        ("trace_id IS NOT NULL", json!([["a"]])),
+        ("latency_ms IS NULL", json!([["c"]])),
+        ("NOT (level = 'error' OR service = 'web')", json!([["b"]])),
+    ] {
+        let query = json!({
+            "query": format!("SELECT message FROM logs WHERE {filter} ORDER BY message ASC")
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_set_range_and_null_operators_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-operators-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "a", "service": service, "trace_id": "t1", "attributes": {"latency_ms": 120}},
+        {"level": "warn", "message": "b", "service": service, "attributes": {"latency_ms": 80, "user": null}},
+        {"level": "info", "message": "c", "service": service}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        ("level IN ('error', 'warn')", json!([["a"], ["b"]])),
+        ("latency_ms NOT BETWEEN 100 AND 200", json!([["b"], ["c"]])),
+        ("trace_id IS NULL", json!([["b"], ["c"]])),
+        ("user IS NULL", json!([["a"], ["b"], ["c"]])),
+        ("NOT latency_ms > 100", json!([["b"], ["c"]])),
+    ] {
+        let query = json!({
+            "query": format!(
+                "SELECT message FROM logs WHERE service = '{service}' AND {filter} \
+                 ORDER BY message ASC"
+            )
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+}
+
+/// Runs the same `ORDER BY` checks against any backend. Services are prefixed
+/// so that runs against a shared `ClickHouse` do not see each other's logs.
+async fn check_order_by(app: axum::Router, prefix: &str) {
+    let (api, web) = (format!("{prefix}-api"), format!("{prefix}-web"));
+    let logs = json!([
+        {"level": "error", "message": "a", "service": api, "attributes": {"rank": 2}},
+        {"level": "warn", "message": "b", "service": api, "attributes": {"rank": 10}},
+        {"level": "error", "message": "c", "service": web, "attributes": {"rank": 9}},
+        {"level": "info", "message": "d", "service": web},
+        {"level": "info", "message": "e", "service": api, "attributes": {"rank": "x"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let filter = format!("service IN ('{api}', '{web}')");
+    for (order, expected) in [
+        (
+            "service ASC, level DESC",
+            json!([["a"], ["b"], ["e"], ["c"], ["d"]]),
+        ),
+        (
+            "attributes.rank ASC",
+            json!([["a"], ["c"], ["b"], ["e"], ["d"]]),
+        ),
+        ("rank DESC", json!([["b"], ["c"], ["a"], ["e"], ["d"]])),
+    ] {
+        let query = json!({
+            "query": format!("SELECT message FROM logs WHERE {filter} ORDER BY {order}")
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{order}");
+        assert_eq!(response["rows"], expected, "{order}");
+    }
+
+    let query = json!({
+        "query": format!(
+            "SELECT level, count(*) AS n FROM logs WHERE {filter} \
+             GROUP BY level ORDER BY n DESC, level DESC"
+        )
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["rows"],
+        json!([["info", 2], ["error", 2], ["warn", 1]])
+    );
+}
+
+#[tokio::test]
+async fn test_sql_query_order_by_multiple_keys() {
+    let (app, _state) = test_app();
+    check_order_by(app, "order").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_order_by_multiple_keys_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let prefix = format!(
+        "query-order-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+    check_order_by(app, &prefix).await;
+}
+
+/// Runs the same `DISTINCT` and `topk` checks against any backend.
+async fn check_distinct_and_topk(app: axum::Router, prefix: &str) {
+    let (api, web) = (format!("{prefix}-api"), format!("{prefix}-web"));
+    let spans = json!([
+        {"trace_id": "t1", "span_id": "s1", "name": "GET /cart", "service": api, "duration_ms": 10},
+        {"trace_id": "t1", "span_id": "s2", "name": "GET /cart", "service": api, "duration_ms": 10},
+        {"trace_id": "t1", "span_id": "s3", "name": "POST /pay", "service": web, "duration_ms": 10},
+        {"trace_id": "t1", "span_id": "s4", "name": "GET /cart", "service": web, "duration_ms": 10},
+        {"trace_id": "t1", "span_id": "s5", "name": "GET /", "service": web, "duration_ms": 10},
+        {"trace_id": "t1", "span_id": "s6", "name": "GET /", "service": web, "duration_ms": 10}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/traces", spans).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let filter = format!("service IN ('{api}', '{web}')");
+    let query = json!({
+        "query": format!("SELECT DISTINCT service FROM traces WHERE {filter} ORDER BY service ASC")
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([[api], [web]]));
+    assert_eq!(response["total_count"], 2);
+
+    let query = json!({
+        "query": format!("SELECT topk(2, name) AS names FROM traces WHERE {filter}")
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["columns"], json!(["names"]));
+    assert_eq!(response["rows"], json!([[["GET /cart", "GET /"]]]));
+}
+
+#[tokio::test]
+async fn test_sql_query_distinct_and_topk() {
+    let (app, _state) = test_app();
+    check_distinct_and_topk(app, "distinct").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_distinct_and_topk_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let prefix = format!(
+        "query-distinct-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+    check_distinct_and_topk(app, &prefix).await;
+}
+
+#[tokio::test]
+async fn test_sql_query_regex_matching() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "timeout after 250ms", "service": "api", "attributes": {"path": "/api/v1/users"}},
+        {"level": "warn", "message": "timeout after a while", "service": "auth", "attributes": {"path": "/login"}},
+        {"level": "info", "message": "Request served", "service": "web", "attributes": {"path": "/API/v2/users"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        (r"message MATCHES 'timeout after \d+ms'", json!([["api"]])),
+        ("message =~ '^timeout'", json!([["api"], ["auth"]])),
+        ("path =~ '(?i)^/api/v[12]/'", json!([["api"], ["web"]])),
+        ("level =~ 'err|warn'", json!([["api"], ["auth"]])),
+        ("NOT message MATCHES 'timeout'", json!([["web"]])),
+    ] {
+        let query = json!({
+            "query": format!("SELECT service FROM logs WHERE {filter} ORDER BY service ASC")
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+
+    let query = json!({"query": "SELECT * FROM logs WHERE message MATCHES 'a(b'"});
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "parse_error");
+    assert!(response["message"]
+        .as_str()
+        .unwrap()
+        .contains("Invalid regular expression 'a(b'"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_regex_matching_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-regex-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "timeout after 250ms", "service": service, "attributes": {"path": "/api/v1/users"}},
+        {"level": "warn", "message": "timeout after a while", "service": service, "attributes": {"path": "/login"}},
+        {"level": "info", "message": "Request served", "service": service, "attributes": {"path": "/API/v2/users"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        (r"message MATCHES 'timeout after \d+ms'", json!([["error"]])),
+        ("message =~ '^timeout'", json!([["error"], ["warn"]])),
+        ("path =~ '(?i)^/api/v[12]/'", json!([["error"], ["info"]])),
+        ("level =~ 'err|warn'", json!([["error"], ["warn"]])),
+    ] {
+        let query = json!({
+            "query": format!(
+                "SELECT level FROM logs WHERE service = '{service}' AND {filter} \
+                 ORDER BY level ASC"
+            )
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+}
+
+#[tokio::test]
+async fn test_sql_query_bind_parameters() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "It's down", "service": "api", "attributes": {"http.status_code": 503}},
+        {"level": "error", "message": "Timeout", "service": "web", "attributes": {"http.status_code": 504}},
+        {"level": "info", "message": "OK", "service": "api", "attributes": {"http.status_code": 200}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT service FROM logs WHERE message = $message AND level IN ($levels) \
+                  AND http.status_code >= $status SINCE $since",
+        "params": {"message": "It's down", "levels": ["error", "warn"], "status": 500, "since": "1h"}
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([["api"]]));
+    assert_eq!(
+        response["parsed_query"]["since"],
+        json!({"offset_secs": -3600})
+    );
+
+    // Values are compared as literals, never parsed as query text
+    let query = json!({
+        "query": "SELECT * FROM logs WHERE service = $service",
+        "params": {"service": "api' OR service = 'web"}
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 0);
+
+    for (query, params, message) in [
+        (
+            "SELECT * FROM logs WHERE service = $service",
+            json!({}),
+            "Missing value for parameter '$service'",
+        ),
+        (
+            "SELECT * FROM logs WHERE service = $service",
+            json!({"service": "api", "level": "error"}),
+            "Unknown parameter '$level'",
+        ),
+        (
+            "SELECT * FROM logs WHERE service = $service",
+            json!({"service": 42}),
+            "Type mismatch for parameter '$service'",
+        ),
+        (
+            "SELECT * FROM logs WHERE message MATCHES $pattern",
+            json!({"pattern": "a(b"}),
+            "Invalid value for parameter '$pattern'",
+        ),
+    ] {
+        let body = json!({"query": query, "params": params});
+        let (status, response) = post_json(app.clone(), "/api/v1/query", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{params}");
+        assert_eq!(response["error"], "invalid_parameter", "{params}");
+        assert!(
+            response["message"].as_str().unwrap().contains(message),
+            "{response}"
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_bind_parameters_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-params-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "It's \"down\"", "service": service},
+        {"level": "error", "message": "Timeout", "service": service}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": "SELECT message FROM logs WHERE service = $service AND message = $message",
+        "params": {"service": service, "message": "It's \"down\""}
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([["It's \"down\""]]));
+
+    // The values are bound as ClickHouse query parameters
+    let (status, response) = post_json(app, "/api/v1/query/explain", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["params"][1],
+        json!({"name": "p1", "type": "String", "value": "It's \"down\""})
+    );
+    assert!(!response["statements"][0]["sql"]
+        .as_str()
+        .unwrap()
+        .contains("down"));
+}
+
+#[tokio::test]
+async fn test_sql_query_typed_attributes() {
+    let (app, _state) = test_app();
+    let service = "typed-attributes";
+
+    let logs = json!([
+        {"level": "error", "message": "a", "service": service, "attributes": {"http.status_code": 503, "retry": true, "user": {"id": 7}}},
+        {"level": "warn", "message": "b", "service": service, "attributes": {"http": {"status_code": 429}, "retry": false, "user": {"id": "7"}}},
+        {"level": "info", "message": "c", "service": service, "attributes": {"http.status_code": "500"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        ("http.status_code >= 500", json!([["a", 503]])),
+        ("http.status_code >= 400", json!([["a", 503], ["b", 429]])),
+        ("http.status_code = '500'", json!([["c", "500"]])),
+        ("retry = false", json!([["b", 429]])),
+        ("user.id = 7", json!([["a", 503]])),
+        ("user.id = '7'", json!([["b", 429]])),
+    ] {
+        let query = json!({
+            "query": format!(
+                "SELECT message, http.status_code FROM logs WHERE service = '{service}' AND {filter} \
+                 ORDER BY message ASC"
+            )
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_typed_attributes_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-typed-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "a", "service": service, "attributes": {"http.status_code": 503, "retry": true, "user": {"id": 7}}},
+        {"level": "warn", "message": "b", "service": service, "attributes": {"http": {"status_code": 429}, "retry": false, "user": {"id": "7"}}},
+        {"level": "info", "message": "c", "service": service, "attributes": {"http.status_code": "500"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (filter, expected) in [
+        ("http.status_code >= 500", json!([["a", 503]])),
+        ("http.status_code >= 400", json!([["a", 503], ["b", 429]])),
+        ("http.status_code = '500'", json!([["c", "500"]])),
+        ("retry = false", json!([["b", 429]])),
+        ("user.id = 7", json!([["a", 503]])),
+        ("user.id = '7'", json!([["b", 429]])),
+    ] {
+        let query = json!({
+            "query": format!(
+                "SELECT message, http.status_code FROM logs WHERE service = '{service}' AND {filter} \
+                 ORDER BY message ASC"
+            )
+        });
+        let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+        assert_eq!(status, StatusCode::OK, "{filter}");
+        assert_eq!(response["rows"], expected, "{filter}");
+    }
+}
+
+/// Pages through `query` with a page size of 2 and returns the messages of all pages.
+async fn page_with_cursor(app: axum::Router, query: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut cursor = serde_json::Value::Null;
+
+    loop {
+        let request = json!({"query": query, "cursor": cursor});
+        let (status, response) = post_json(app.clone(), "/api/v1/query", request).await;
+        assert_eq!(status, StatusCode::OK, "{response}");
+
+        let logs = response["logs"].as_array().unwrap();
+        assert!(logs.len() <= 2);
+        messages.extend(
+            logs.iter()
+                .map(|log| log["message"].as_str().unwrap().to_string()),
+        );
+
+        cursor = response["next_cursor"].clone();
+        if cursor.is_null() {
+            return messages;
+        }
+    }
+}
+
+/// Returns the messages of the logs of `service` in the default order, in a
+/// single page, after checking that "e" comes first and "a" last. The order of
+/// the logs sharing a timestamp depends on their hash.
+async fn default_order(app: axum::Router, service: &str) -> Vec<String> {
+    let query = json!({"query": format!("SELECT * FROM logs WHERE service = '{service}'")});
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let messages: Vec<String> = response["logs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|log| log["message"].as_str().unwrap().to_string())
+        .collect();
+
+    let mut middle: Vec<&str> = messages[1..4].iter().map(String::as_str).collect();
+    middle.sort_unstable();
+    assert_eq!(messages[0], "e");
+    assert_eq!(middle, ["b", "c", "d"]);
+    assert_eq!(messages[4], "a");
+    messages
+}
+
+/// Five logs, three of which share a timestamp.
+fn cursor_test_logs(service: &str) -> serde_json::Value {
+    let base = chrono::Utc::now() - chrono::Duration::hours(1);
+    let at = |seconds: i64| (base + chrono::Duration::seconds(seconds)).to_rfc3339();
+
+    json!([
+        {"level": "info", "message": "a", "service": service, "timestamp": at(0)},
+        {"level": "info", "message": "b", "service": service, "timestamp": at(1)},
+        {"level": "info", "message": "c", "service": service, "timestamp": at(1)},
+        {"level": "info", "message": "d", "service": service, "timestamp": at(1)},
+        {"level": "info", "message": "e", "service": service, "timestamp": at(2)}
+    ])
+}
+
+#[tokio::test]
+async fn test_sql_query_cursor_pagination() {
+    let (app, _state) = test_app();
+    let service = "cursor-pagination";
+
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", cursor_test_logs(service)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let expected = default_order(app.clone(), service).await;
+    let query = format!("SELECT * FROM logs WHERE service = '{service}' LIMIT 2");
+    let messages = page_with_cursor(app.clone(), &query).await;
+    assert_eq!(messages, expected);
+
+    // Logs ingested after the first page do not shift the following pages
+    let first = json!({"query": query});
+    let (_, response) = post_json(app.clone(), "/api/v1/query", first).await;
+    let logs = json!([{"level": "info", "message": "f", "service": service}]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let next = json!({"query": query, "cursor": response["next_cursor"]});
+    let (_, response) = post_json(app.clone(), "/api/v1/query", next).await;
+    assert_eq!(response["logs"][0]["message"], expected[2]);
+
+    // Cursors require the default order and a non-aggregate query
+    let cursor = response["next_cursor"].clone();
+    for query in [
+        "SELECT * FROM logs ORDER BY message ASC",
+        "SELECT service, count(*) FROM logs GROUP BY service",
+    ] {
+        let request = json!({"query": query, "cursor": cursor});
+        let (status, response) = post_json(app.clone(), "/api/v1/query", request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(response["error"], "execution_error", "{query}");
+    }
+
+    let request = json!({"query": query, "cursor": "garbage"});
+    let (status, response) = post_json(app, "/api/v1/query", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_cursor");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_cursor_pagination_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-cursor-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", cursor_test_logs(&service)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let expected = default_order(app.clone(), &service).await;
+    let query = format!("SELECT * FROM logs WHERE service = '{service}' LIMIT 2");
+    let messages = page_with_cursor(app, &query).await;
+    assert_eq!(messages, expected);
+}
+
+/// Six logs at one timestamp: three identical ones, and three sharing their
+/// service and message but not their level or attributes.
+fn duplicate_test_logs(service: &str) -> serde_json::Value {
+    let at = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+    let log = |level: &str, message: &str| json!({"level": level, "message": message, "service": service, "timestamp": at});
+
+    let mut with_attribute = log("info", "dup");
+    with_attribute["attributes"] = json!({"user": 42});
+    json!([
+        log("info", "dup"),
+        log("info", "dup"),
+        log("info", "dup"),
+        log("warn", "dup"),
+        with_attribute,
+        log("info", "other"),
+    ])
+}
+
+/// Pages of two cut through the duplicates, and no log is skipped or repeated.
+async fn check_duplicate_pagination(app: axum::Router, service: &str) {
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", duplicate_test_logs(service)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = format!("SELECT * FROM logs WHERE service = '{service}' LIMIT 2");
+    let mut messages = page_with_cursor(app, &query).await;
+    messages.sort_unstable();
+    assert_eq!(messages, ["dup", "dup", "dup", "dup", "dup", "other"]);
+}
+
+#[tokio::test]
+async fn test_sql_query_cursor_pagination_with_duplicates() {
+    let (app, _state) = test_app();
+    check_duplicate_pagination(app, "cursor-duplicates").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_cursor_pagination_with_duplicates_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "cursor-duplicates-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+    check_duplicate_pagination(app, &service).await;
+}
+
+#[tokio::test]
+async fn test_sql_query_stream_ndjson() {
+    let (app, _state) = test_app();
+    let service = "stream-ndjson";
+
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", cursor_test_logs(service)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Whole records, one per line
+    let query = json!({"query": format!("SELECT * FROM logs WHERE service = '{service}'")});
+    let (status, lines) = request_ndjson(app.clone(), "POST", "/api/v1/query", Some(query)).await;
+    assert_eq!(status, StatusCode::OK);
+    let messages: Vec<&str> = lines
+        .iter()
+        .map(|log| log["message"].as_str().unwrap())
+        .collect();
+    let expected = default_order(app.clone(), service).await;
+    assert_eq!(messages, expected);
+
+    // Projections and aggregates are objects keyed by column name
+    let query = json!({
+        "query": format!("SELECT message AS m FROM logs WHERE service = '{service}' LIMIT 2")
+    });
+    let (_, lines) = request_ndjson(app.clone(), "POST", "/api/v1/query", Some(query)).await;
+    assert_eq!(lines, [json!({"m": "e"}), json!({"m": expected[1]})]);
+
+    let query = json!({
+        "query": format!("SELECT service, count(*) AS n FROM logs WHERE service = '{service}' GROUP BY service")
+    });
+    let (_, lines) = request_ndjson(app.clone(), "POST", "/api/v1/query", Some(query)).await;
+    assert_eq!(lines, [json!({"service": service, "n": 5})]);
+
+    // Errors before streaming starts are returned as usual
+    let query = json!({"query": "SELECT * FROM nowhere"});
+    let (status, lines) = request_ndjson(app, "POST", "/api/v1/query", Some(query)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(lines[0]["error"], "parse_error");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_stream_ndjson_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-stream-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", cursor_test_logs(&service)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!("SELECT message FROM logs WHERE service = '{service}' LIMIT 4 OFFSET 1")
+    });
+    let (status, lines) = request_ndjson(app.clone(), "POST", "/api/v1/query", Some(query)).await;
+    assert_eq!(status, StatusCode::OK);
+    let expected = default_order(app.clone(), &service).await;
+    let messages: Vec<&str> = lines
+        .iter()
+        .map(|line| line["message"].as_str().unwrap())
+        .collect();
+    assert_eq!(messages, expected[1..]);
+
+    let uri = format!("/api/v1/logs?service={service}");
+    let (status, lines) = request_ndjson(app, "GET", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lines.len(), 5);
+}
+
+#[tokio::test]
+async fn test_explain_query() {
+    let (app, _state) = test_app();
+
+    let query = json!({
+        "query": "SELECT bucket(timestamp, 1m) AS t, count(*) FROM logs WHERE level = 'error' \
+                  SINCE now() - 1h GROUP BY bucket(timestamp, 1m)"
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query/explain", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["backend"], "memory");
+    let normalized = response["normalized_query"].as_str().unwrap();
+    assert!(!normalized.contains("now()"));
+    assert_eq!(response["in_memory"][0], "WHERE level = 'error'");
+    assert!(response["in_memory"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("fill empty buckets")));
+
+    // The query is validated without being executed
+    let query = json!({"query": "SELECT service, message FROM logs GROUP BY service"});
+    let (status, response) = post_json(app, "/api/v1/query/explain", query).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "execution_error");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_explain_query_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+
+    let query = json!({
+        "query": "SELECT service, count(*) AS n FROM logs WHERE level = 'error' \
+                  GROUP BY service HAVING count(*) > 1 LIMIT 10"
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query/explain", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["backend"], "clickhouse");
+    assert_eq!(
+        response["pushed_down"],
+        json!([
+            "WHERE level = 'error'",
+            "GROUP BY service",
+            "SELECT service, count(*) AS n",
+            "HAVING count(*) > 1",
+            "LIMIT 10"
+        ])
+    );
+    assert_eq!(response["in_memory"], json!([]));
+    assert_eq!(
+        response["params"][0],
+        json!({"name": "p0", "type": "String", "value": "error"})
+    );
+
+    // Count and select statements, each with an estimate of the data read
+    let statements = response["statements"].as_array().unwrap();
+    assert_eq!(statements.len(), 2);
+    assert!(statements[1]["sql"]
+        .as_str()
+        .unwrap()
+        .contains("level = {p0:String}"));
+    assert!(statements[1]["estimate"]["rows"].is_u64());
+    assert!(statements[1]["estimate"]["granules"].is_u64());
+
+    // Projections are evaluated in memory
+    let query = json!({"query": "SELECT message FROM logs WHERE service = 'api' LIMIT 5"});
+    let (_, response) = post_json(app.clone(), "/api/v1/query/explain", query).await;
+    assert_eq!(
+        response["pushed_down"],
+        json!(["WHERE service = 'api'", "LIMIT 5"])
+    );
+    assert_eq!(response["in_memory"], json!(["SELECT message"]));
+
+    // Estimates run within the query limits; they read no rows themselves
+    let query = json!({
+        "query": "SELECT * FROM logs WHERE service = 'api'",
+        "max_rows_scanned": 1,
+        "timeout_ms": 5000
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query/explain", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(response["statements"][1]["estimate"]["rows"].is_u64());
+
+    let query = json!({"query": "SELECT * FROM logs", "timeout_ms": 1});
+    let (status, response) = post_json(app, "/api/v1/query/explain", query).await;
+    if status != StatusCode::OK {
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(response["limit"], "timeout");
+    }
+}
+
+#[tokio::test]
+async fn test_sql_query_relative_time_range() {
+    let (app, _state) = test_app();
+    let now = chrono::Utc::now();
+    let minutes_ago = |minutes| (now - chrono::Duration::minutes(minutes)).to_rfc3339();
+
+    let logs = json!([
+        {"timestamp": minutes_ago(90), "level": "error", "message": "a", "service": "api"},
+        {"timestamp": minutes_ago(20), "level": "error", "message": "b", "service": "api"},
+        {"timestamp": minutes_ago(5), "level": "error", "message": "c", "service": "api"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({"query": "SELECT message FROM logs SINCE 1h UNTIL now() - 10m"});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([["b"]]));
+
+    let query = json!({"query": "SELECT * FROM logs WHERE timestamp > now() - 30m"});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 2);
+
+    // The SINCE range also bounds the filled time buckets
+    let query = json!({
+        "query": "SELECT bucket(timestamp, 10m), count(*) FROM logs SINCE 1h \
+                  GROUP BY bucket(timestamp, 10m)"
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let rows = response["rows"].as_array().unwrap();
+    assert!((6..=7).contains(&rows.len()));
+    let total: u64 = rows.iter().map(|row| row[1].as_u64().unwrap()).sum();
+    assert_eq!(total, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_relative_time_range_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-since-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+    let old = (chrono::Utc::now() - chrono::Duration::hours(3)).to_rfc3339();
+
+    let logs = json!([
+        {"timestamp": old, "level": "error", "message": "old", "service": service},
+        {"level": "error", "message": "new", "service": service}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!("SELECT message FROM logs WHERE service = '{service}' SINCE now() - 1h")
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([["new"]]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_time_buckets_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-bucket-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"timestamp": "2024-01-01T10:00:30Z", "level": "error", "message": "a", "service": service},
+        {"timestamp": "2024-01-01T10:00:45Z", "level": "error", "message": "b", "service": service},
+        {"timestamp": "2024-01-01T10:02:10Z", "level": "error", "message": "c", "service": service}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!(
+            "SELECT bucket(timestamp, 1m), count(*) FROM logs WHERE service = '{service}' \
+             GROUP BY bucket(timestamp, 1m)"
+        )
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["rows"],
+        json!([
+            ["2024-01-01T10:00:00Z", 2],
+            ["2024-01-01T10:01:00Z", 0],
+            ["2024-01-01T10:02:00Z", 1]
+        ])
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_group_by_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-group-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "a", "service": service, "attributes": {"latency_ms": 120, "user_id": "1"}},
+        {"level": "error", "message": "b", "service": service, "attributes": {"latency_ms": 80, "user_id": "2"}},
+        {"level": "warn", "message": "c", "service": service, "attributes": {"user_id": "1"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!(
+            "SELECT level, count(*), avg(attributes.latency_ms), count_distinct(attributes.user_id) \
+             FROM logs WHERE service = '{service}' GROUP BY level HAVING count(*) >= 1 \
+             ORDER BY count(*) DESC"
+        )
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 2);
+    assert_eq!(
+        response["rows"],
+        json!([["error", 2, 100, 2], ["warn", 1, null, 1]])
+    );
+
+    let spans = json!([
+        {"trace_id": service, "span_id": "s1", "name": "GET /", "service": service, "duration_ms": 900},
+        {"trace_id": service, "span_id": "s2", "name": "GET /", "service": service, "duration_ms": 100}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/traces", spans).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!(
+            "SELECT name, max(duration_ms), sum(duration_ms) FROM traces \
+             WHERE service = '{service}' GROUP BY name"
+        )
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["rows"], json!([["GET /", 900, 1000]]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_pushdown_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let service = format!(
+        "query-pushdown-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    );
+
+    let logs = json!([
+        {"level": "error", "message": "Checkout failed", "service": service, "attributes": {"user_id": "42"}},
+        {"level": "error", "message": "Payment failed", "service": service, "attributes": {"user_id": "7"}},
+        {"level": "info", "message": "Checkout ok", "service": service, "attributes": {"user_id": "42"}}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({
+        "query": format!(
+            "SELECT * FROM logs WHERE service = '{service}' AND level >= 'warn' \
+             AND attributes.user_id = '42' ORDER BY timestamp ASC"
+        )
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 1);
+    assert_eq!(response["logs"][0]["message"], "Checkout failed");
+}
+
+#[tokio::test]
+async fn test_sql_query_limits() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "info", "message": "a", "service": "api"},
+        {"level": "info", "message": "b", "service": "api"},
+        {"level": "info", "message": "c", "service": "api"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({"query": "SELECT * FROM logs", "max_rows_scanned": 2});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "query_limit_exceeded");
+    assert_eq!(response["limit"], "max_rows_scanned");
+
+    // Grouped queries scan the same rows
+    let query = json!({
+        "query": "SELECT service, count(*) FROM logs GROUP BY service",
+        "max_rows_scanned": 2
+    });
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["limit"], "max_rows_scanned");
+
+    let query = json!({"query": "SELECT * FROM logs", "max_rows_scanned": 3, "timeout_ms": 5000});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 3);
+
+    // The result size is that of the groups, not of the rows scanned
+    let query = json!({"query": "SELECT * FROM logs", "max_result_bytes": 100});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["limit"], "max_result_bytes");
+
+    let query = json!({
+        "query": "SELECT service, count(*) FROM logs GROUP BY service",
+        "max_result_bytes": 100
+    });
+    let (status, _) = post_json(app, "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_sql_query_cache() {
+    let (app, _state) = test_app_with_query_cache();
+
+    let log = json!({"level": "error", "message": "Boom", "service": "api"});
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", log.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query =
+        json!({"query": "SELECT service, count(*) FROM logs SINCE now() - 1h GROUP BY service"});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["cache"], "miss");
+    assert_eq!(response["rows"], json!([["api", 1]]));
+
+    // Identical queries are served from the cache until the result expires
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", log).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(response["cache"], "hit");
+    assert_eq!(response["rows"], json!([["api", 1]]));
+
+    // Differently written but equivalent queries share the entry
+    let query =
+        json!({"query": "select service, COUNT(*) from logs since now() - 60m group by service"});
+    let (_, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(response["cache"], "hit");
+
+    let query = json!({"query": "SELECT * FROM logs LIMIT 1"});
+    let (_, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(response["cache"], "miss");
+    let query = json!({"query": "SELECT * FROM logs LIMIT 1", "cursor": response["next_cursor"]});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["cache"], "bypass");
+
+    // Without a cache, the response has no cache status
+    let (app, _state) = test_app();
+    let query = json!({"query": "SELECT * FROM logs"});
+    let (_, response) = post_json(app, "/api/v1/query", query).await;
+    assert!(response.get("cache").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_sql_query_limits_with_clickhouse() {
+    let (app, state) = test_app_with_clickhouse();
+    state.log_store().clear().unwrap();
+
+    let logs: Vec<_> = (0..100)
+        .map(|i| json!({"level": "info", "message": format!("message {i}"), "service": "limits"}))
+        .collect();
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", json!(logs)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let query = json!({"query": "SELECT * FROM logs", "max_rows_scanned": 10});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "query_limit_exceeded");
+    assert_eq!(response["limit"], "max_rows_scanned");
+
+    let query = json!({"query": "SELECT * FROM logs", "max_result_bytes": 100});
+    let (status, response) = post_json(app.clone(), "/api/v1/query", query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["limit"], "max_result_bytes");
+
+    // A tiny table may be scanned within the deadline
+    let query = json!({
+        "query": "SELECT count(*) FROM logs WHERE message CONTAINS 'a'",
+        "timeout_ms": 1
+    });
+    let (status, response) = post_json(app, "/api/v1/query", query).await;
+    if status != StatusCode::OK {
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(response["limit"], "timeout");
+    }
 }
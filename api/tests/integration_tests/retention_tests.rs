@@ -0,0 +1,125 @@
+//! Integration tests for retention overrides.
+//!
+//! Tests cover:
+//! - Expiring in-memory data by service and level overrides
+//! - Rejecting overrides on fields a data type does not have
+//! - Conditional `TTL ... WHERE` rules on the `ClickHouse` tables (requires
+//!   running `ClickHouse`)
+
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use shared::models::{LogEntry, LogLevel};
+
+use super::common::{
+    create_clickhouse_client, get, request_json, test_app, test_app_with_clickhouse,
+};
+
+/// A retention configuration keeping billing logs for 400 days and debug logs for 3.
+fn config_with_overrides() -> serde_json::Value {
+    json!({
+        "logs": {
+            "data_type": "logs",
+            "ttl_days": 30,
+            "overrides": [
+                {"field": "service", "value": "billing", "ttl_days": 400},
+                {"field": "level", "value": "debug", "ttl_days": 3}
+            ]
+        },
+        "metrics": {"data_type": "metrics", "ttl_days": 90},
+        "traces": {"data_type": "traces", "ttl_days": 30}
+    })
+}
+
+#[tokio::test]
+async fn test_retention_overrides_expire_in_memory_data() {
+    let (app, state) = test_app();
+
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/retention",
+        Some(config_with_overrides()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(
+        response["config"]["logs"]["overrides"][0]["value"],
+        "billing"
+    );
+
+    let now = Utc::now();
+    let log = |level, service: &str, days| {
+        let mut log = LogEntry::new(level, format!("{service} {days}"), service);
+        log.timestamp = now - Duration::days(days);
+        log
+    };
+    state
+        .log_store()
+        .insert_batch(vec![
+            log(LogLevel::Debug, "batch", 4),
+            log(LogLevel::Debug, "batch", 2),
+            log(LogLevel::Debug, "billing", 200),
+            log(LogLevel::Info, "api", 40),
+            log(LogLevel::Info, "api", 20),
+        ])
+        .unwrap();
+
+    assert_eq!(state.enforce_retention(now).unwrap(), 2);
+
+    let (status, response) = get(app, "/api/v1/logs").await;
+    assert_eq!(status, StatusCode::OK);
+    let mut messages: Vec<&str> = response["logs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|log| log["message"].as_str().unwrap())
+        .collect();
+    messages.sort_unstable();
+    assert_eq!(messages, vec!["api 20", "batch 2", "billing 200"]);
+}
+
+#[tokio::test]
+async fn test_retention_overrides_rejected_for_unknown_fields() {
+    let (app, state) = test_app();
+
+    let mut config = config_with_overrides();
+    config["traces"]["overrides"] = json!([{"field": "labels.env", "value": "dev", "ttl_days": 7}]);
+    let (status, response) =
+        request_json(app, "PUT", "/api/v1/config/retention", Some(config)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(response["message"].as_str().unwrap().contains("labels.env"));
+    assert!(state.get_retention_config().logs.overrides.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_retention_overrides_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let client = create_clickhouse_client();
+
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/retention",
+        Some(config_with_overrides()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let engine: String = client
+        .query("SELECT engine_full FROM system.tables WHERE database = currentDatabase() AND name = 'logs'")
+        .fetch_one()
+        .await
+        .unwrap();
+    assert!(engine.contains("toIntervalDay(400)"), "{engine}");
+    assert!(engine.contains("service = 'billing'"), "{engine}");
+    assert!(engine.contains("level = 'debug'"), "{engine}");
+
+    // Restore the defaults
+    let mut config = config_with_overrides();
+    config["logs"]["overrides"] = json!([]);
+    let (status, response) =
+        request_json(app, "PUT", "/api/v1/config/retention", Some(config)).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+}
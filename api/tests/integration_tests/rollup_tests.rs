@@ -0,0 +1,307 @@
+//! Integration tests for the aggregation tier endpoints.
+//!
+//! Tests cover:
+//! - Metric rollups by interval, name and label
+//! - Metric series stitched from the raw metrics and the tiers
+//! - Log counts by level and normalized message
+//! - Span statistics by operation and trace counts by service
+//! - Rejecting intervals a data type is not aggregated at
+//! - Reading the `ClickHouse` tier tables, before and after their parts are
+//!   merged (requires running `ClickHouse`)
+
+use api::AppState;
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use serde_json::json;
+use shared::models::{LogEntry, LogLevel, Metric, Span, SpanStatus};
+
+use super::common::{create_clickhouse_client, get, test_app, test_app_with_clickhouse};
+
+fn time(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Runs the same tier checks against any backend, with data named after `prefix`.
+async fn check_rollups(app: axum::Router, state: &AppState, prefix: &str) {
+    // The previous hour, well within the retention of every tier
+    let base = (Utc::now() - Duration::hours(1))
+        .duration_trunc(Duration::hours(1))
+        .unwrap();
+    let service = format!("{prefix}-api");
+
+    let metric = |minute, value, host: &str| {
+        let mut metric = Metric::gauge(format!("{prefix}_cpu"), value)
+            .with_label("service", &service)
+            .with_label("host", host);
+        metric.timestamp = base + Duration::minutes(minute);
+        metric
+    };
+    state
+        .metric_store()
+        .insert_batch(vec![
+            metric(1, 10.0, "a"),
+            metric(3, 30.0, "a"),
+            metric(7, 50.0, "a"),
+            metric(2, 5.0, "b"),
+        ])
+        .unwrap();
+
+    let log = |minute, level, message: &str| {
+        let mut log = LogEntry::new(level, message, &service);
+        log.timestamp = base + Duration::minutes(minute);
+        log
+    };
+    state
+        .log_store()
+        .insert_batch(vec![
+            log(1, LogLevel::Error, "Timeout after 30s"),
+            log(2, LogLevel::Error, "Timeout after 45s"),
+            log(3, LogLevel::Info, "Started"),
+        ])
+        .unwrap();
+
+    let span = |trace: &str, id: &str, ms, status| {
+        let start = base + Duration::minutes(5);
+        Span::new(format!("{prefix}-{trace}"), id, "GET /", &service)
+            .with_start_time(start)
+            .with_end_time(start + Duration::milliseconds(ms))
+            .with_status(status)
+    };
+    state
+        .trace_store()
+        .insert_spans(vec![
+            span("t1", "s1", 10, SpanStatus::Ok),
+            span("t1", "s2", 20, SpanStatus::Ok),
+            span("t2", "s3", 30, SpanStatus::Ok),
+            span("t2", "s4", 40, SpanStatus::Ok),
+            span("t3", "s5", 50, SpanStatus::Error),
+        ])
+        .unwrap();
+
+    // Metric rollups
+    let uri = format!(
+        "/api/v1/metrics/rollups?interval=five_minutes&name={prefix}_cpu&label.host=a&start_time={}",
+        time(base)
+    );
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["interval"], "five_minutes");
+    assert_eq!(response["total_count"], 2);
+    let rollup = &response["rollups"][0];
+    assert_eq!(rollup["timestamp"], time(base));
+    assert_eq!(rollup["service"], service.as_str());
+    assert_eq!(rollup["labels"]["host"], "a");
+    assert_eq!(
+        (
+            &rollup["count"],
+            &rollup["min"],
+            &rollup["max"],
+            &rollup["avg"]
+        ),
+        (&json!(2), &json!(10.0), &json!(30.0), &json!(20.0))
+    );
+    assert_eq!(
+        response["rollups"][1]["timestamp"],
+        time(base + Duration::minutes(5))
+    );
+
+    let uri = format!("/api/v1/metrics/rollups?interval=one_hour&service={service}");
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["total_count"], 2);
+    assert_eq!(response["rollups"][0]["labels"]["host"], "a");
+    assert_eq!(response["rollups"][0]["sum"], 90.0);
+    assert_eq!(response["rollups"][1]["labels"]["host"], "b");
+
+    // Metric series: the partial step at the start is read from the raw metrics
+    let mut config = state.get_aggregation_config();
+    config.enabled = true;
+    state.set_aggregation_config(config);
+    let uri = format!(
+        "/api/v1/metrics/series?name={prefix}_cpu&aggregate=sum&step=5m&start_time={}&end_time={}",
+        time(base + Duration::minutes(2)),
+        time(base + Duration::hours(1))
+    );
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["step_secs"], 300);
+    assert_eq!(
+        response["points"],
+        json!([
+            {"timestamp": time(base), "value": 35.0, "count": 2},
+            {"timestamp": time(base + Duration::minutes(5)), "value": 50.0, "count": 1}
+        ])
+    );
+    assert_eq!(
+        response["segments"],
+        json!([
+            {
+                "start": time(base + Duration::minutes(2)),
+                "end": time(base + Duration::minutes(5)),
+                "tier": "raw"
+            },
+            {
+                "start": time(base + Duration::minutes(5)),
+                "end": time(base + Duration::hours(1)),
+                "tier": "five_minutes"
+            }
+        ])
+    );
+
+    // Extremes come from the tiers only once they are marked as migrated
+    let uri = format!(
+        "/api/v1/metrics/series?name={prefix}_cpu&aggregate=max&step=5m&start_time={}&end_time={}",
+        time(base),
+        time(base + Duration::hours(1))
+    );
+    let (status, raw) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{raw}");
+    assert_eq!(raw["segments"][0]["tier"], "raw");
+    let mut config = state.get_aggregation_config();
+    config.tiers_migrated = true;
+    state.set_aggregation_config(config);
+    let (status, tiered) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{tiered}");
+    assert_eq!(tiered["segments"][0]["tier"], "five_minutes");
+    assert_eq!(tiered["points"], raw["points"]);
+
+    // Log counts
+    let uri = format!("/api/v1/logs/counts?interval=one_hour&service={service}&level=error");
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["total_count"], 1);
+    let count = &response["counts"][0];
+    assert_eq!(count["timestamp"], time(base));
+    assert_eq!(count["normalized_message"], "Timeout after <NUM>s");
+    assert_eq!(count["count"], 2);
+
+    let uri = format!("/api/v1/logs/counts?interval=one_day&service={service}");
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["counts"][0]["level"], "error");
+    assert_eq!(response["counts"][1]["level"], "info");
+
+    // Span statistics and trace counts
+    let uri = format!("/api/v1/traces/stats?interval=one_hour&service={service}&operation=GET%20/");
+    let (status, response) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["by"], "operation");
+    assert_eq!(response["total_count"], 2);
+    assert_eq!(response["stats"][0]["status_code"], "error");
+    let stats = &response["stats"][1];
+    assert_eq!(stats["operation"], "GET /");
+    assert_eq!(stats["span_count"], 4);
+    assert_eq!(stats["avg_duration_ns"], 25_000_000.0);
+    assert_eq!(stats["min_duration_ns"], 10_000_000);
+    assert_eq!(stats["max_duration_ns"], 40_000_000);
+
+    let uri = format!("/api/v1/traces/stats?interval=one_day&by=service&service={service}");
+    let (status, response) = get(app, &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(
+        response["stats"],
+        json!([{
+            "timestamp": time(base.duration_trunc(Duration::days(1)).unwrap()),
+            "service": service,
+            "unique_traces": 3,
+            "total_spans": 5
+        }])
+    );
+}
+
+#[tokio::test]
+async fn test_rollup_endpoints() {
+    let (app, state) = test_app();
+    check_rollups(app, &state, "rollups").await;
+}
+
+#[tokio::test]
+async fn test_rollup_endpoints_validate_parameters() {
+    let (app, _state) = test_app();
+
+    let (status, response) = get(app.clone(), "/api/v1/logs/counts?interval=one_minute").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_interval");
+
+    let (status, response) = get(
+        app.clone(),
+        "/api/v1/traces/stats?interval=one_hour&by=service&operation=GET",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_parameter");
+
+    let (status, _) = get(app.clone(), "/api/v1/metrics/rollups").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, response) = get(
+        app.clone(),
+        "/api/v1/metrics/series?aggregate=avg&step=5x&start_time=2024-01-01T00:00:00Z",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_step");
+
+    let (status, response) = get(
+        app,
+        "/api/v1/metrics/series?aggregate=avg&step=1s&start_time=2024-01-01T00:00:00Z",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "too_many_steps");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_rollup_endpoints_with_clickhouse() {
+    let (app, state) = test_app_with_clickhouse();
+    let prefix = format!("rollups{}", Utc::now().timestamp_nanos_opt().unwrap());
+    check_rollups(app, &state, &prefix).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_metric_rollups_survive_merges_with_clickhouse() {
+    let (app, state) = test_app_with_clickhouse();
+    let name = format!("merged{}_cpu", Utc::now().timestamp_nanos_opt().unwrap());
+    let base = (Utc::now() - Duration::hours(1))
+        .duration_trunc(Duration::hours(1))
+        .unwrap();
+
+    // Each insert writes its own part of the tier tables
+    for (minute, value) in [(1, 10.0), (2, 40.0), (3, 25.0)] {
+        let mut metric = Metric::gauge(&name, value).with_label("service", "merged-api");
+        metric.timestamp = base + Duration::minutes(minute);
+        state.metric_store().insert_batch(vec![metric]).unwrap();
+    }
+    create_clickhouse_client()
+        .query("OPTIMIZE TABLE metrics_5min FINAL")
+        .execute()
+        .await
+        .unwrap();
+
+    let uri = format!(
+        "/api/v1/metrics/rollups?interval=five_minutes&name={name}&start_time={}",
+        time(base)
+    );
+    let (status, response) = get(app, &uri).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["total_count"], 1);
+    let rollup = &response["rollups"][0];
+    assert_eq!(
+        (
+            &rollup["count"],
+            &rollup["sum"],
+            &rollup["min"],
+            &rollup["max"],
+            &rollup["avg"]
+        ),
+        (
+            &json!(3),
+            &json!(75.0),
+            &json!(10.0),
+            &json!(40.0),
+            &json!(25.0)
+        )
+    );
+}
@@ -0,0 +1,278 @@
+//! Integration tests for saved queries.
+//!
+//! Tests cover:
+//! - Creating, listing, getting, updating and deleting saved queries
+//! - Validation of saved queries on save
+//! - Running saved queries with parameters, defaults and time ranges
+//! - NDJSON streaming of saved query results
+//! - Persistence in `ClickHouse` (requires running `ClickHouse`)
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::common::{
+    get, post_json, request_json, request_ndjson, test_app, test_app_with_clickhouse,
+};
+
+#[tokio::test]
+async fn test_saved_query_crud() {
+    let (app, _state) = test_app();
+
+    let saved = json!({
+        "name": "Errors by service",
+        "description": "Recent errors of one service",
+        "owner": "oncall",
+        "tags": ["errors", "triage"],
+        "query": "SELECT * FROM logs WHERE level = 'error' AND service = $service",
+        "parameters": {"service": "api"},
+        "default_time_range": "1h"
+    });
+    let (status, created) = post_json(app.clone(), "/api/v1/saved-queries", saved.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["id"], "errors-by-service");
+    assert_eq!(created["tags"], json!(["errors", "triage"]));
+
+    // Names map to ids, so the same name cannot be saved twice
+    let (status, response) = post_json(app.clone(), "/api/v1/saved-queries", saved).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(response["error"], "already_exists");
+
+    let other = json!({"name": "All spans", "query": "SELECT * FROM traces", "owner": "ops"});
+    let (status, _) = post_json(app.clone(), "/api/v1/saved-queries", other).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, response) = get(app.clone(), "/api/v1/saved-queries").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 2);
+    assert_eq!(response["saved_queries"][0]["name"], "All spans");
+
+    let (_, response) = get(app.clone(), "/api/v1/saved-queries?tag=triage").await;
+    assert_eq!(response["total_count"], 1);
+    let (_, response) = get(app.clone(), "/api/v1/saved-queries?owner=ops").await;
+    assert_eq!(response["saved_queries"][0]["id"], "all-spans");
+
+    let update = json!({
+        "name": "Service errors",
+        "query": "SELECT * FROM logs WHERE level = 'error' AND service = $service",
+        "parameters": {"service": "web"}
+    });
+    let (status, updated) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/saved-queries/errors-by-service",
+        Some(update),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["id"], "errors-by-service");
+    assert_eq!(updated["name"], "Service errors");
+    assert_eq!(updated["created_at"], created["created_at"]);
+    assert_eq!(updated["tags"], json!([]));
+
+    let (status, fetched) = get(app.clone(), "/api/v1/saved-queries/errors-by-service").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, updated);
+
+    let (status, _) = request_json(
+        app.clone(),
+        "DELETE",
+        "/api/v1/saved-queries/errors-by-service",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, response) = get(app.clone(), "/api/v1/saved-queries/errors-by-service").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(response["error"], "not_found");
+
+    let (status, _) = request_json(
+        app,
+        "DELETE",
+        "/api/v1/saved-queries/errors-by-service",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_saved_query_validation() {
+    let (app, _state) = test_app();
+
+    let cases = [
+        (
+            json!({"name": "q", "query": "SELECT * FROM nowhere"}),
+            "parse_error",
+        ),
+        (
+            json!({"name": "  ", "query": "SELECT * FROM logs"}),
+            "validation_error",
+        ),
+        (
+            json!({"name": "q", "query": "SELECT * FROM logs", "tags": [""]}),
+            "validation_error",
+        ),
+        (
+            json!({"name": "q", "query": "SELECT * FROM logs", "default_time_range": "soon"}),
+            "invalid_time_range",
+        ),
+        (
+            json!({
+                "name": "q",
+                "query": "SELECT * FROM logs WHERE service = $service",
+                "parameters": {"service": {"nested": true}}
+            }),
+            "invalid_parameter",
+        ),
+    ];
+    for (body, error) in cases {
+        let (status, response) =
+            post_json(app.clone(), "/api/v1/saved-queries", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(response["error"], error, "{body}");
+    }
+
+    let (_, response) = get(app.clone(), "/api/v1/saved-queries").await;
+    assert_eq!(response["total_count"], 0);
+
+    // Invalid updates leave the saved query unchanged
+    let saved = json!({"name": "q", "query": "SELECT * FROM logs"});
+    let (status, _) = post_json(app.clone(), "/api/v1/saved-queries", saved).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let update = json!({"name": "q", "query": "SELECT FROM"});
+    let (status, _) =
+        request_json(app.clone(), "PUT", "/api/v1/saved-queries/q", Some(update)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, fetched) = get(app.clone(), "/api/v1/saved-queries/q").await;
+    assert_eq!(fetched["query"], "SELECT * FROM logs");
+
+    let update = json!({"name": "q", "query": "SELECT * FROM logs"});
+    let (status, _) = request_json(app, "PUT", "/api/v1/saved-queries/missing", Some(update)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_saved_query_run_with_parameters() {
+    let (app, _state) = test_app();
+
+    let logs = json!([
+        {"level": "error", "message": "Payment declined", "service": "payment"},
+        {"level": "error", "message": "It's down", "service": "payment"},
+        {"level": "warn", "message": "Slow", "service": "payment"},
+        {"level": "error", "message": "Timeout", "service": "web"}
+    ]);
+    let (status, _) = post_json(app.clone(), "/api/v1/logs", logs).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let saved = json!({
+        "name": "Errors",
+        "query": "SELECT * FROM logs WHERE level = $level AND service IN ($services) LIMIT 10",
+        "parameters": {"level": "error", "services": null},
+        "default_time_range": "1h"
+    });
+    let (status, _) = post_json(app.clone(), "/api/v1/saved-queries", saved).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let run = json!({"params": {"services": ["payment"]}});
+    let (status, response) = post_json(app.clone(), "/api/v1/saved-queries/errors/run", run).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["type"], "logs");
+    assert_eq!(response["total_count"], 2);
+    assert_eq!(
+        response["parsed_query"]["since"],
+        json!({"offset_secs": -3600})
+    );
+
+    // Values are quoted as literals, not spliced into the query
+    let run = json!({"params": {"services": "payment", "level": "error' OR level = 'warn"}});
+    let (status, response) = post_json(app.clone(), "/api/v1/saved-queries/errors/run", run).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 0);
+
+    let run = json!({"params": {"services": ["payment", "web"]}, "time_range": "5m"});
+    let (status, response) = post_json(app.clone(), "/api/v1/saved-queries/errors/run", run).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 3);
+    assert_eq!(
+        response["parsed_query"]["since"],
+        json!({"offset_secs": -300})
+    );
+
+    // Required parameters must be given
+    let (status, response) = request_json(
+        app.clone(),
+        "POST",
+        "/api/v1/saved-queries/errors/run",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_parameter");
+
+    // Values must match the type of their field
+    let run = json!({"params": {"services": "web", "level": 3}});
+    let (status, response) = post_json(app.clone(), "/api/v1/saved-queries/errors/run", run).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], "invalid_parameter");
+
+    let run = json!({"params": {"services": "web"}});
+    let (status, lines) = request_ndjson(
+        app.clone(),
+        "POST",
+        "/api/v1/saved-queries/errors/run",
+        Some(run),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["message"], "Timeout");
+
+    let (status, _) = post_json(app, "/api/v1/saved-queries/missing/run", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_saved_query_crud_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let name = format!(
+        "saved-query-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    let uri = format!("/api/v1/saved-queries/{name}");
+
+    let saved = json!({
+        "name": name,
+        "tags": ["clickhouse"],
+        "query": "SELECT * FROM logs WHERE service = $service",
+        "parameters": {"service": name}
+    });
+    let (status, created) = post_json(app.clone(), "/api/v1/saved-queries", saved.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = post_json(app.clone(), "/api/v1/saved-queries", saved).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, fetched) = get(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, created);
+
+    let update = json!({"name": name, "query": "SELECT * FROM logs WHERE service = $service", "description": "updated"});
+    let (status, _) = request_json(app.clone(), "PUT", &uri, Some(update)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, fetched) = get(app.clone(), &uri).await;
+    assert_eq!(fetched["description"], "updated");
+
+    let run = json!({"params": {"service": name}});
+    let (status, response) = post_json(app.clone(), &format!("{uri}/run"), run).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["total_count"], 0);
+
+    let (status, _) = request_json(app.clone(), "DELETE", &uri, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = get(app, &uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,176 @@
+//! Integration tests for persisting the retention and aggregation configuration.
+//!
+//! Tests cover:
+//! - Restoring the saved configuration after a restart
+//! - Reconciling the configuration with the table TTLs and the attached tier
+//!   views, and reporting drift (requires running `ClickHouse`)
+
+use api::{create_router, AppState};
+use axum::http::StatusCode;
+use serde_json::json;
+use shared::storage::FileSettingsStore;
+use std::sync::Arc;
+
+use super::common::{create_clickhouse_client, get, request_json, test_app_with_clickhouse};
+
+/// Starts the server state on the settings file at `path`, as after a restart.
+async fn start(path: &std::path::Path) -> (axum::Router, AppState) {
+    let store = FileSettingsStore::open(path).unwrap();
+    let state = AppState::with_in_memory_store().with_settings_store(Arc::new(store));
+    state.restore_config().await;
+    (create_router(state.clone()), state)
+}
+
+#[tokio::test]
+async fn test_config_survives_restart() {
+    let dir = std::env::temp_dir().join(format!("heimsight-settings-it-{}", std::process::id()));
+    let path = dir.join("settings.json");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let (app, _state) = start(&path).await;
+    let retention = json!({
+        "logs": {"data_type": "logs", "ttl_days": 7},
+        "metrics": {"data_type": "metrics", "ttl_days": 180},
+        "traces": {"data_type": "traces", "ttl_days": 14}
+    });
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/retention",
+        Some(retention),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let (status, response) = request_json(
+        app,
+        "PUT",
+        "/api/v1/config/retention/policy",
+        Some(json!({"data_type": "logs", "ttl_days": 10})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let aggregation = json!({
+        "enabled": true,
+        "one_minute": {"interval": "one_minute", "retention_days": 7, "enabled": true},
+        "five_minutes": {"interval": "five_minutes", "retention_days": 90, "enabled": false},
+        "one_hour": {"interval": "one_hour", "retention_days": 365, "enabled": true},
+        "one_day": {"interval": "one_day", "retention_days": 730, "enabled": true},
+        "tiers_migrated": true
+    });
+    let (app, _state) = start(&path).await;
+    let (status, response) = request_json(
+        app,
+        "PUT",
+        "/api/v1/config/aggregation",
+        Some(aggregation.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let (app, _state) = start(&path).await;
+    let (status, response) = get(app.clone(), "/api/v1/config/retention").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["config"]["logs"]["ttl_days"], 10);
+    assert_eq!(response["config"]["metrics"]["ttl_days"], 180);
+    assert!(response.get("drift").is_none());
+    let (status, response) = get(app, "/api/v1/config/aggregation").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["config"], aggregation);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_config_reconciled_with_clickhouse() {
+    let retention = json!({
+        "logs": {"data_type": "logs", "ttl_days": 30},
+        "metrics": {"data_type": "metrics", "ttl_days": 90},
+        "traces": {"data_type": "traces", "ttl_days": 30}
+    });
+    let (app, _state) = test_app_with_clickhouse();
+    let (status, response) = request_json(
+        app,
+        "PUT",
+        "/api/v1/config/retention",
+        Some(retention.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    // The logs TTL is changed behind the server's back
+    create_clickhouse_client()
+        .query("ALTER TABLE logs MODIFY TTL toDateTime(timestamp / 1000000000) + INTERVAL 45 DAY")
+        .execute()
+        .await
+        .unwrap();
+
+    let (app, state) = test_app_with_clickhouse();
+    state.restore_config().await;
+    let (status, response) = get(app.clone(), "/api/v1/config/retention").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["config"]["logs"]["ttl_days"], 45);
+    assert_eq!(response["config"]["metrics"]["ttl_days"], 90);
+    assert_eq!(
+        response["drift"],
+        json!([{"table": "logs", "configured_days": 30, "actual_days": 45}])
+    );
+
+    // Saving the configuration again restores the TTL and clears the drift
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/retention",
+        Some(retention),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let (_, response) = get(app, "/api/v1/config/retention").await;
+    assert_eq!(response["config"]["logs"]["ttl_days"], 30);
+    assert!(response.get("drift").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires running ClickHouse instance"]
+async fn test_view_drift_reported_with_clickhouse() {
+    let (app, _state) = test_app_with_clickhouse();
+    let (_, response) = get(app.clone(), "/api/v1/config/aggregation").await;
+    let aggregation = response["config"].clone();
+    let (status, response) = request_json(
+        app,
+        "PUT",
+        "/api/v1/config/aggregation",
+        Some(aggregation.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    // A view is detached behind the server's back
+    create_clickhouse_client()
+        .query("DETACH TABLE metrics_5min_mv PERMANENTLY")
+        .execute()
+        .await
+        .unwrap();
+
+    let (app, state) = test_app_with_clickhouse();
+    state.restore_config().await;
+    let (status, response) = get(app.clone(), "/api/v1/config/aggregation").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["view_drift"],
+        json!([{"view": "metrics_5min_mv", "configured_enabled": true, "attached": false}])
+    );
+
+    // Saving the configuration again attaches the view and clears the drift
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/api/v1/config/aggregation",
+        Some(aggregation),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let (_, response) = get(app, "/api/v1/config/aggregation").await;
+    assert!(response.get("view_drift").is_none());
+}
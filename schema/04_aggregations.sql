@@ -1,5 +1,10 @@
 -- Aggregation tables and materialized views for Heimsight
 -- These provide automatic downsampling for long-term storage efficiency
+--
+-- The metric tiers keep count, sum, min and max as SimpleAggregateFunction
+-- columns, so background merges add the counts and sums and keep the extremes.
+-- The mean is derived from them. See MIGRATE_METRIC_TIERS.md to upgrade tables
+-- created with the earlier SummingMergeTree engine.
 
 USE heimsight;
 
@@ -14,16 +19,16 @@ CREATE TABLE IF NOT EXISTS metrics_1min (
     service LowCardinality(String) NOT NULL,
     
     -- Aggregated values
-    count UInt64,
-    sum Float64,
-    min Float64,
-    max Float64,
-    avg Float64,
+    count SimpleAggregateFunction(sum, UInt64),
+    sum SimpleAggregateFunction(sum, Float64),
+    min SimpleAggregateFunction(min, Float64),
+    max SimpleAggregateFunction(max, Float64),
+    avg Float64 ALIAS sum / count,
     
     -- Label hash for grouping
     labels_hash UInt64,
     labels Map(String, String) DEFAULT map()
-) ENGINE = SummingMergeTree()
+) ENGINE = AggregatingMergeTree()
 PARTITION BY toYYYYMMDD(timestamp)
 ORDER BY (service, name, labels_hash, timestamp)
 TTL timestamp + INTERVAL 30 DAY
@@ -39,7 +44,6 @@ SELECT
     sum(value) AS sum,
     min(value) AS min,
     max(value) AS max,
-    avg(value) AS avg,
     cityHash64(toString(labels)) AS labels_hash,
     labels
 FROM metrics
@@ -55,15 +59,15 @@ CREATE TABLE IF NOT EXISTS metrics_5min (
     metric_type LowCardinality(String) NOT NULL,
     service LowCardinality(String) NOT NULL,
     
-    count UInt64,
-    sum Float64,
-    min Float64,
-    max Float64,
-    avg Float64,
+    count SimpleAggregateFunction(sum, UInt64),
+    sum SimpleAggregateFunction(sum, Float64),
+    min SimpleAggregateFunction(min, Float64),
+    max SimpleAggregateFunction(max, Float64),
+    avg Float64 ALIAS sum / count,
     
     labels_hash UInt64,
     labels Map(String, String) DEFAULT map()
-) ENGINE = SummingMergeTree()
+) ENGINE = AggregatingMergeTree()
 PARTITION BY toYYYYMMDD(timestamp)
 ORDER BY (service, name, labels_hash, timestamp)
 TTL timestamp + INTERVAL 90 DAY
@@ -79,7 +83,6 @@ SELECT
     sum(value) AS sum,
     min(value) AS min,
     max(value) AS max,
-    avg(value) AS avg,
     cityHash64(toString(labels)) AS labels_hash,
     labels
 FROM metrics
@@ -95,15 +98,15 @@ CREATE TABLE IF NOT EXISTS metrics_1hour (
     metric_type LowCardinality(String) NOT NULL,
     service LowCardinality(String) NOT NULL,
     
-    count UInt64,
-    sum Float64,
-    min Float64,
-    max Float64,
-    avg Float64,
+    count SimpleAggregateFunction(sum, UInt64),
+    sum SimpleAggregateFunction(sum, Float64),
+    min SimpleAggregateFunction(min, Float64),
+    max SimpleAggregateFunction(max, Float64),
+    avg Float64 ALIAS sum / count,
     
     labels_hash UInt64,
     labels Map(String, String) DEFAULT map()
-) ENGINE = SummingMergeTree()
+) ENGINE = AggregatingMergeTree()
 PARTITION BY toYYYYMMDD(timestamp)
 ORDER BY (service, name, labels_hash, timestamp)
 TTL timestamp + INTERVAL 365 DAY
@@ -119,7 +122,6 @@ SELECT
     sum(value) AS sum,
     min(value) AS min,
     max(value) AS max,
-    avg(value) AS avg,
     cityHash64(toString(labels)) AS labels_hash,
     labels
 FROM metrics
@@ -135,15 +137,15 @@ CREATE TABLE IF NOT EXISTS metrics_1day (
     metric_type LowCardinality(String) NOT NULL,
     service LowCardinality(String) NOT NULL,
     
-    count UInt64,
-    sum Float64,
-    min Float64,
-    max Float64,
-    avg Float64,
+    count SimpleAggregateFunction(sum, UInt64),
+    sum SimpleAggregateFunction(sum, Float64),
+    min SimpleAggregateFunction(min, Float64),
+    max SimpleAggregateFunction(max, Float64),
+    avg Float64 ALIAS sum / count,
     
     labels_hash UInt64,
     labels Map(String, String) DEFAULT map()
-) ENGINE = SummingMergeTree()
+) ENGINE = AggregatingMergeTree()
 PARTITION BY toYYYYMMDD(timestamp)
 ORDER BY (service, name, labels_hash, timestamp)
 TTL timestamp + INTERVAL 730 DAY
@@ -159,7 +161,6 @@ SELECT
     sum(value) AS sum,
     min(value) AS min,
     max(value) AS max,
-    avg(value) AS avg,
     cityHash64(toString(labels)) AS labels_hash,
     labels
 FROM metrics
@@ -0,0 +1,25 @@
+-- Saved queries for Heimsight
+-- Named, reusable queries managed through /api/v1/saved-queries
+
+USE heimsight;
+
+-- Updates and deletes insert a newer version of a row (by updated_at);
+-- deleted queries are marked with deleted = 1. Read with FINAL.
+CREATE TABLE IF NOT EXISTS saved_queries (
+    id String NOT NULL,
+    name String NOT NULL,
+    description String DEFAULT '',
+    owner Nullable(String),
+    tags Array(String) DEFAULT [],
+    query String NOT NULL,
+
+    -- Default parameter values as a JSON object
+    parameters String DEFAULT '{}',
+    default_time_range Nullable(String),
+
+    -- Nanoseconds since Unix epoch
+    created_at Int64 NOT NULL,
+    updated_at Int64 NOT NULL,
+    deleted UInt8 DEFAULT 0
+) ENGINE = ReplacingMergeTree(updated_at)
+ORDER BY id;
@@ -0,0 +1,17 @@
+-- Server settings for Heimsight
+-- Retention and aggregation configuration, kept across restarts
+
+USE heimsight;
+
+-- One row per setting; updates insert a newer version of a row (by
+-- updated_at). Read with FINAL.
+CREATE TABLE IF NOT EXISTS settings (
+    name String NOT NULL,
+
+    -- The setting as a JSON document
+    value String NOT NULL,
+
+    -- Nanoseconds since Unix epoch
+    updated_at Int64 NOT NULL
+) ENGINE = ReplacingMergeTree(updated_at)
+ORDER BY name;
@@ -3,6 +3,7 @@
 //! This module defines configuration for downsampling observability data
 //! to reduce storage costs while maintaining queryability over longer time periods.
 
+use super::DataType;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -21,14 +22,22 @@ pub enum AggregationInterval {
 }
 
 impl AggregationInterval {
+    /// All intervals, from the finest to the coarsest.
+    pub const ALL: [Self; 4] = [
+        Self::OneMinute,
+        Self::FiveMinutes,
+        Self::OneHour,
+        Self::OneDay,
+    ];
+
     /// Returns the duration of this interval.
     #[must_use]
     pub const fn as_duration(&self) -> Duration {
         match self {
-            Self::OneMinute => Duration::from_secs(60),
-            Self::FiveMinutes => Duration::from_secs(300),
-            Self::OneHour => Duration::from_secs(3600),
-            Self::OneDay => Duration::from_secs(86400),
+            Self::OneMinute => Duration::from_mins(1),
+            Self::FiveMinutes => Duration::from_mins(5),
+            Self::OneHour => Duration::from_hours(1),
+            Self::OneDay => Duration::from_hours(24),
         }
     }
 
@@ -38,6 +47,42 @@ impl AggregationInterval {
         self.as_duration().as_secs()
     }
 
+    /// Returns the suffix of the tier tables at this interval, e.g. `1hour`
+    /// in `metrics_1hour`.
+    #[must_use]
+    pub const fn table_suffix(&self) -> &'static str {
+        match self {
+            Self::OneMinute => "1min",
+            Self::FiveMinutes => "5min",
+            Self::OneHour => "1hour",
+            Self::OneDay => "1day",
+        }
+    }
+
+    /// Returns whether `data_type` is downsampled at this interval. Logs and
+    /// traces only have hourly and daily tiers.
+    #[must_use]
+    pub const fn applies_to(&self, data_type: DataType) -> bool {
+        matches!(data_type, DataType::Metrics) || matches!(self, Self::OneHour | Self::OneDay)
+    }
+
+    /// Returns the tier tables at this interval, e.g. `metrics_1hour` and
+    /// `logs_1hour_counts`. Each is fed by a materialized view named after it
+    /// with an `_mv` suffix.
+    #[must_use]
+    pub fn tables(&self) -> Vec<String> {
+        let suffix = self.table_suffix();
+        let mut tables = vec![format!("metrics_{suffix}")];
+        if self.applies_to(DataType::Logs) {
+            tables.push(format!("logs_{suffix}_counts"));
+        }
+        if self.applies_to(DataType::Traces) {
+            tables.push(format!("spans_{suffix}_stats"));
+            tables.push(format!("traces_{suffix}_stats"));
+        }
+        tables
+    }
+
     /// Returns a human-readable string representation.
     #[must_use]
     pub const fn as_str(&self) -> &'static str {
@@ -118,11 +163,21 @@ pub struct AggregationConfig {
     pub one_hour: AggregationPolicy,
     /// 1-day aggregation policy
     pub one_day: AggregationPolicy,
+
+    /// Whether the metric tiers keep their extremes when they merge: they were
+    /// created by the current schema or migrated with
+    /// `schema/MIGRATE_METRIC_TIERS.md`. Until then, `min` and `max` series are
+    /// read from the raw metrics.
+    #[serde(default)]
+    pub tiers_migrated: bool,
 }
 
 impl AggregationConfig {
     /// Creates a new aggregation configuration with recommended defaults.
     ///
+    /// Aggregation is enabled, matching the materialized views the schema
+    /// creates, so saving the defaults leaves the rollup pipeline unchanged.
+    ///
     /// Default strategy:
     /// - **Metrics**:
     ///   - 1-minute aggregates: 30 days retention
@@ -138,11 +193,12 @@ impl AggregationConfig {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            enabled: false, // Disabled by default
+            enabled: true, // The schema creates the views attached
             one_minute: AggregationPolicy::new(AggregationInterval::OneMinute, 30, true),
             five_minutes: AggregationPolicy::new(AggregationInterval::FiveMinutes, 90, true),
             one_hour: AggregationPolicy::new(AggregationInterval::OneHour, 365, true),
             one_day: AggregationPolicy::new(AggregationInterval::OneDay, 730, true),
+            tiers_migrated: false,
         }
     }
 
@@ -150,12 +206,18 @@ impl AggregationConfig {
     ///
     /// # Errors
     ///
-    /// Returns an error if any policy is invalid.
+    /// Returns an error if any policy is invalid or is set for another interval.
     pub fn validate(&self) -> Result<(), String> {
-        self.one_minute.validate()?;
-        self.five_minutes.validate()?;
-        self.one_hour.validate()?;
-        self.one_day.validate()?;
+        for interval in AggregationInterval::ALL {
+            let policy = self.get_policy(interval);
+            if policy.interval != interval {
+                return Err(format!(
+                    "The {interval} policy has the interval {}",
+                    policy.interval
+                ));
+            }
+            policy.validate()?;
+        }
         Ok(())
     }
 
@@ -214,6 +276,32 @@ mod tests {
         assert_eq!(AggregationInterval::OneDay.to_string(), "1 day");
     }
 
+    #[test]
+    fn test_aggregation_interval_tiers() {
+        assert_eq!(AggregationInterval::FiveMinutes.table_suffix(), "5min");
+        assert_eq!(AggregationInterval::OneDay.table_suffix(), "1day");
+        assert!(AggregationInterval::OneMinute.applies_to(DataType::Metrics));
+        assert!(!AggregationInterval::OneMinute.applies_to(DataType::Logs));
+        assert!(AggregationInterval::OneHour.applies_to(DataType::Traces));
+    }
+
+    #[test]
+    fn test_aggregation_interval_tables() {
+        assert_eq!(
+            AggregationInterval::FiveMinutes.tables(),
+            vec!["metrics_5min"]
+        );
+        assert_eq!(
+            AggregationInterval::OneDay.tables(),
+            vec![
+                "metrics_1day",
+                "logs_1day_counts",
+                "spans_1day_stats",
+                "traces_1day_stats"
+            ]
+        );
+    }
+
     #[test]
     fn test_aggregation_policy_new() {
         let policy = AggregationPolicy::new(AggregationInterval::OneHour, 365, true);
@@ -247,11 +335,21 @@ mod tests {
     #[test]
     fn test_aggregation_config_default() {
         let config = AggregationConfig::default();
-        assert!(!config.enabled);
+        assert!(config.enabled);
         assert_eq!(config.one_minute.retention_days, 30);
         assert_eq!(config.five_minutes.retention_days, 90);
         assert_eq!(config.one_hour.retention_days, 365);
         assert_eq!(config.one_day.retention_days, 730);
+        assert!(!config.tiers_migrated);
+    }
+
+    #[test]
+    fn test_aggregation_config_without_tiers_migrated() {
+        // Configurations saved before the flag existed read as not migrated
+        let mut json = serde_json::to_value(AggregationConfig::default()).unwrap();
+        json.as_object_mut().unwrap().remove("tiers_migrated");
+        let config: AggregationConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(config, AggregationConfig::default());
     }
 
     #[test]
@@ -268,6 +366,14 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_aggregation_config_validate_mismatched_interval() {
+        let mut config = AggregationConfig::default();
+        config.one_hour.interval = AggregationInterval::OneDay;
+        let result = config.validate();
+        assert!(result.unwrap_err().contains("The 1 hour policy"));
+    }
+
     #[test]
     fn test_aggregation_config_get_policy() {
         let config = AggregationConfig::default();
@@ -6,4 +6,6 @@ pub mod aggregation;
 pub mod retention;
 
 pub use aggregation::{AggregationConfig, AggregationInterval, AggregationPolicy};
-pub use retention::{DataType, RetentionConfig, RetentionPolicy};
+pub use retention::{
+    DataType, RetentionConfig, RetentionField, RetentionOverride, RetentionPolicy, RetentionSubject,
+};
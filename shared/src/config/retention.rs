@@ -1,9 +1,13 @@
 //! Retention configuration for data expiration policies.
 //!
 //! This module defines structures for configuring data retention (TTL) policies
-//! for different data types (logs, metrics, traces).
+//! for different data types (logs, metrics, traces), and overrides of a policy
+//! for the data of a service or with a given attribute.
 
+use crate::models::{LogEntry, LogLevel, Metric, Span};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::time::Duration;
 
 /// Represents different types of observability data.
@@ -18,6 +22,172 @@ pub enum DataType {
     Traces,
 }
 
+/// A field that a retention override matches on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetentionField {
+    /// The service that produced the data.
+    Service,
+    /// The log level (logs only).
+    Level,
+    /// The metric or span name (metrics and traces only).
+    Name,
+    /// An attribute of a log or span, or a label of a metric, by key.
+    Attribute(String),
+}
+
+impl RetentionField {
+    /// Parses the field of an override for data of `data_type`.
+    ///
+    /// Logs accept `service`, `level` and `attributes.<key>`; metrics
+    /// `service`, `name` and `labels.<key>`; traces `service`, `name` and
+    /// `attributes.<key>`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the field does not exist for the data type.
+    pub fn parse(field: &str, data_type: DataType) -> Result<Self, String> {
+        let map_prefix = match data_type {
+            DataType::Metrics => "labels.",
+            DataType::Logs | DataType::Traces => "attributes.",
+        };
+        match (field, data_type) {
+            ("service", _) => Ok(Self::Service),
+            ("level", DataType::Logs) => Ok(Self::Level),
+            ("name", DataType::Metrics | DataType::Traces) => Ok(Self::Name),
+            _ => match field.strip_prefix(map_prefix) {
+                Some(key) if !key.is_empty() => Ok(Self::Attribute(key.to_string())),
+                _ => Err(format!(
+                    "Unknown retention override field '{field}' for {data_type:?}"
+                )),
+            },
+        }
+    }
+}
+
+/// Data whose retention can be overridden by the value of a field.
+pub trait RetentionSubject {
+    /// Returns the value of `field`, as compared with the value of an override.
+    ///
+    /// Attribute values that are not strings are compared as JSON, so `503`
+    /// matches the number 503.
+    fn field_value(&self, field: &RetentionField) -> Option<String>;
+}
+
+/// Returns the value of an attribute as compared with override values.
+fn attribute_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl RetentionSubject for LogEntry {
+    fn field_value(&self, field: &RetentionField) -> Option<String> {
+        match field {
+            RetentionField::Service => Some(self.service.clone()),
+            RetentionField::Level => Some(self.level.to_string()),
+            RetentionField::Name => None,
+            RetentionField::Attribute(key) => self.attributes.get(key).map(attribute_value),
+        }
+    }
+}
+
+impl RetentionSubject for Metric {
+    fn field_value(&self, field: &RetentionField) -> Option<String> {
+        match field {
+            // Stored metrics without a service label belong to "unknown"
+            RetentionField::Service => Some(
+                self.labels
+                    .get("service")
+                    .map_or_else(|| "unknown".to_string(), Clone::clone),
+            ),
+            RetentionField::Level => None,
+            RetentionField::Name => Some(self.name.clone()),
+            RetentionField::Attribute(key) => self.labels.get(key).cloned(),
+        }
+    }
+}
+
+impl RetentionSubject for Span {
+    fn field_value(&self, field: &RetentionField) -> Option<String> {
+        match field {
+            RetentionField::Service => Some(self.service.clone()),
+            RetentionField::Level => None,
+            RetentionField::Name => Some(self.name.clone()),
+            RetentionField::Attribute(key) => self.attributes.get(key).map(attribute_value),
+        }
+    }
+}
+
+/// Validates a TTL in days.
+fn validate_ttl(ttl_days: u32) -> Result<(), String> {
+    if ttl_days == 0 {
+        return Err("TTL must be greater than zero".to_string());
+    }
+    if ttl_days > 3650 {
+        return Err("TTL cannot exceed 3650 days (10 years)".to_string());
+    }
+    Ok(())
+}
+
+/// Overrides the TTL of a retention policy for the data whose field equals a value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionOverride {
+    /// The field to match, e.g. `service`, `level` or `attributes.tier`.
+    pub field: String,
+    /// The value the field must equal.
+    pub value: String,
+    /// Time-to-live (TTL) duration in days for the matching data.
+    pub ttl_days: u32,
+}
+
+impl RetentionOverride {
+    /// Creates a new retention override.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shared::config::RetentionOverride;
+    ///
+    /// let rule = RetentionOverride::new("service", "billing", 400);
+    /// assert_eq!(rule.ttl_days, 400);
+    /// ```
+    #[must_use]
+    pub fn new(field: impl Into<String>, value: impl Into<String>, ttl_days: u32) -> Self {
+        Self {
+            field: field.into(),
+            value: value.into(),
+            ttl_days,
+        }
+    }
+
+    /// Validates the override for data of `data_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the field does not exist for the data type, a
+    /// `level` is not a known log level, or the TTL is invalid.
+    pub fn validate(&self, data_type: DataType) -> Result<(), String> {
+        let field = RetentionField::parse(&self.field, data_type)?;
+        if field == RetentionField::Level
+            && serde_json::from_value::<LogLevel>(self.value.clone().into()).is_err()
+        {
+            return Err(format!("Unknown log level '{}'", self.value));
+        }
+        validate_ttl(self.ttl_days)
+            .map_err(|e| format!("Retention override {} = '{}': {e}", self.field, self.value))
+    }
+
+    /// Returns true if `subject`, data of `data_type`, matches the override.
+    #[must_use]
+    pub fn matches(&self, data_type: DataType, subject: &impl RetentionSubject) -> bool {
+        RetentionField::parse(&self.field, data_type)
+            .ok()
+            .and_then(|field| subject.field_value(&field))
+            .is_some_and(|value| value == self.value)
+    }
+}
+
 /// Retention policy for a specific data type.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RetentionPolicy {
@@ -25,6 +195,9 @@ pub struct RetentionPolicy {
     pub data_type: DataType,
     /// Time-to-live (TTL) duration in days.
     pub ttl_days: u32,
+    /// Overrides of the TTL for matching data. The first matching override applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<RetentionOverride>,
 }
 
 impl RetentionPolicy {
@@ -48,9 +221,65 @@ impl RetentionPolicy {
         Self {
             data_type,
             ttl_days,
+            overrides: Vec::new(),
         }
     }
 
+    /// Adds an override of the TTL for the data whose `field` equals `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shared::config::{DataType, RetentionPolicy};
+    ///
+    /// let policy = RetentionPolicy::new(DataType::Logs, 30)
+    ///     .with_override("service", "billing", 400)
+    ///     .with_override("level", "debug", 3);
+    /// assert!(policy.validate().is_ok());
+    /// ```
+    #[must_use]
+    pub fn with_override(
+        mut self,
+        field: impl Into<String>,
+        value: impl Into<String>,
+        ttl_days: u32,
+    ) -> Self {
+        self.overrides
+            .push(RetentionOverride::new(field, value, ttl_days));
+        self
+    }
+
+    /// Returns the TTL in days for `subject`: that of the first matching
+    /// override, or the policy's own.
+    #[must_use]
+    pub fn ttl_days_for(&self, subject: &impl RetentionSubject) -> u32 {
+        self.overrides
+            .iter()
+            .find(|rule| rule.matches(self.data_type, subject))
+            .map_or(self.ttl_days, |rule| rule.ttl_days)
+    }
+
+    /// Returns the longest TTL in days of the policy and its overrides.
+    #[must_use]
+    pub fn max_ttl_days(&self) -> u32 {
+        self.overrides
+            .iter()
+            .map(|rule| rule.ttl_days)
+            .fold(self.ttl_days, u32::max)
+    }
+
+    /// Returns true if `subject`, recorded at `timestamp`, has outlived its TTL at `now`.
+    #[must_use]
+    pub fn is_expired(
+        &self,
+        subject: &impl RetentionSubject,
+        timestamp: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let ttl = chrono::Duration::days(i64::from(self.ttl_days_for(subject)));
+        timestamp + ttl <= now
+    }
+
     /// Returns the TTL as a `Duration`.
     ///
     /// # Examples
@@ -74,12 +303,18 @@ impl RetentionPolicy {
     /// Returns an error if:
     /// - TTL is zero
     /// - TTL exceeds maximum allowed (3650 days / 10 years)
+    /// - An override is invalid, or repeats the field and value of another
     pub fn validate(&self) -> Result<(), String> {
-        if self.ttl_days == 0 {
-            return Err("TTL must be greater than zero".to_string());
-        }
-        if self.ttl_days > 3650 {
-            return Err("TTL cannot exceed 3650 days (10 years)".to_string());
+        validate_ttl(self.ttl_days)?;
+        let mut seen = HashSet::new();
+        for rule in &self.overrides {
+            rule.validate(self.data_type)?;
+            if !seen.insert((&rule.field, &rule.value)) {
+                return Err(format!(
+                    "Duplicate retention override {} = '{}'",
+                    rule.field, rule.value
+                ));
+            }
         }
         Ok(())
     }
@@ -179,6 +414,25 @@ impl RetentionConfig {
             DataType::Traces => self.traces.ttl_days = ttl_days,
         }
     }
+
+    /// Replaces the overrides of the retention policy for a specific data type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shared::config::{DataType, RetentionConfig, RetentionOverride};
+    ///
+    /// let mut config = RetentionConfig::default();
+    /// config.set_overrides(DataType::Logs, vec![RetentionOverride::new("level", "debug", 3)]);
+    /// assert_eq!(config.logs.overrides.len(), 1);
+    /// ```
+    pub fn set_overrides(&mut self, data_type: DataType, overrides: Vec<RetentionOverride>) {
+        match data_type {
+            DataType::Logs => self.logs.overrides = overrides,
+            DataType::Metrics => self.metrics.overrides = overrides,
+            DataType::Traces => self.traces.overrides = overrides,
+        }
+    }
 }
 
 impl Default for RetentionConfig {
@@ -308,6 +562,105 @@ mod tests {
         assert_eq!(config, deserialized);
     }
 
+    #[test]
+    fn test_retention_field_parse() {
+        assert_eq!(
+            RetentionField::parse("level", DataType::Logs),
+            Ok(RetentionField::Level)
+        );
+        assert_eq!(
+            RetentionField::parse("labels.env", DataType::Metrics),
+            Ok(RetentionField::Attribute("env".to_string()))
+        );
+        assert_eq!(
+            RetentionField::parse("name", DataType::Traces),
+            Ok(RetentionField::Name)
+        );
+        assert!(RetentionField::parse("level", DataType::Metrics).is_err());
+        assert!(RetentionField::parse("labels.env", DataType::Logs).is_err());
+        assert!(RetentionField::parse("attributes.", DataType::Traces).is_err());
+    }
+
+    #[test]
+    fn test_retention_policy_validate_overrides() {
+        let policy = RetentionPolicy::new(DataType::Logs, 30)
+            .with_override("service", "billing", 400)
+            .with_override("level", "debug", 3)
+            .with_override("attributes.audit", "true", 3650);
+        assert!(policy.validate().is_ok());
+
+        let invalid = |policy: RetentionPolicy| policy.validate().unwrap_err();
+        assert_eq!(
+            invalid(RetentionPolicy::new(DataType::Logs, 30).with_override("level", "verbose", 3)),
+            "Unknown log level 'verbose'"
+        );
+        assert_eq!(
+            invalid(RetentionPolicy::new(DataType::Logs, 30).with_override("service", "api", 0)),
+            "Retention override service = 'api': TTL must be greater than zero"
+        );
+        assert!(invalid(
+            RetentionPolicy::new(DataType::Metrics, 30).with_override("level", "debug", 3)
+        )
+        .contains("Unknown retention override field 'level'"));
+        assert_eq!(
+            invalid(
+                RetentionPolicy::new(DataType::Traces, 30)
+                    .with_override("service", "api", 3)
+                    .with_override("service", "api", 7)
+            ),
+            "Duplicate retention override service = 'api'"
+        );
+    }
+
+    #[test]
+    fn test_retention_policy_ttl_days_for() {
+        let policy = RetentionPolicy::new(DataType::Logs, 30)
+            .with_override("service", "billing", 400)
+            .with_override("level", "debug", 3)
+            .with_override("attributes.status", "503", 7);
+
+        let billing_debug = LogEntry::new(LogLevel::Debug, "Charged", "billing");
+        assert_eq!(policy.ttl_days_for(&billing_debug), 400);
+        let debug = LogEntry::new(LogLevel::Debug, "Tick", "batch");
+        assert_eq!(policy.ttl_days_for(&debug), 3);
+        let unavailable = LogEntry::new(LogLevel::Error, "Unavailable", "api")
+            .with_attribute("status", serde_json::json!(503));
+        assert_eq!(policy.ttl_days_for(&unavailable), 7);
+        let info = LogEntry::new(LogLevel::Info, "Started", "api");
+        assert_eq!(policy.ttl_days_for(&info), 30);
+
+        assert_eq!(policy.max_ttl_days(), 400);
+
+        let now = Utc::now();
+        assert!(policy.is_expired(&debug, now - chrono::Duration::days(3), now));
+        assert!(!policy.is_expired(&info, now - chrono::Duration::days(3), now));
+
+        let metrics = RetentionPolicy::new(DataType::Metrics, 90)
+            .with_override("labels.env", "dev", 7)
+            .with_override("service", "unknown", 1);
+        let dev = Metric::gauge("cpu", 1.0).with_label("env", "dev");
+        assert_eq!(metrics.ttl_days_for(&dev), 7);
+        let unlabeled = Metric::gauge("cpu", 1.0);
+        assert_eq!(metrics.ttl_days_for(&unlabeled), 1);
+    }
+
+    #[test]
+    fn test_retention_overrides_serialization() {
+        let json = serde_json::to_value(RetentionPolicy::new(DataType::Logs, 30)).unwrap();
+        assert!(json.get("overrides").is_none());
+
+        let policy: RetentionPolicy = serde_json::from_value(serde_json::json!({
+            "data_type": "logs",
+            "ttl_days": 30,
+            "overrides": [{"field": "service", "value": "billing", "ttl_days": 400}]
+        }))
+        .unwrap();
+        assert_eq!(
+            policy.overrides,
+            vec![RetentionOverride::new("service", "billing", 400)]
+        );
+    }
+
     #[test]
     fn test_data_type_serialization() {
         let data_type = DataType::Logs;
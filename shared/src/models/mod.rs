@@ -1,13 +1,16 @@
 //! Data models for the Heimsight observability platform.
 //!
-//! This module contains the core data structures for logs, metrics, and traces.
+//! This module contains the core data structures for logs, metrics, and traces,
+//! and for saved queries.
 
 pub mod log;
 pub mod metric;
+pub mod saved_query;
 pub mod trace;
 
 pub use log::{LogEntry, LogLevel, LogValidationError};
 pub use metric::{
     HistogramBucket, HistogramData, Metric, MetricType, MetricValidationError, MetricValue,
 };
+pub use saved_query::{SavedQuery, SavedQueryError};
 pub use trace::{Span, SpanEvent, SpanKind, SpanStatus, SpanValidationError, Trace};
@@ -0,0 +1,416 @@
+//! Saved query model.
+//!
+//! A saved query is a named SQL-like query with a description, owner, tags and a
+//! default time range. Its text may contain `$name` bind parameters that are
+//! given values when the query is run.
+
+use crate::query::{
+    parse_duration, parse_query, BindError, ParseError, Query, QueryParams, RelativeTime, Value,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Maximum length of a saved query's name.
+const MAX_NAME_LEN: usize = 128;
+
+/// Errors from validating or running a saved query.
+#[derive(Debug, Error)]
+pub enum SavedQueryError {
+    /// The name is empty, too long or has no letters or digits.
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+
+    /// A tag is empty.
+    #[error("Tags cannot be empty")]
+    EmptyTag,
+
+    /// A parameter name is not a valid identifier.
+    #[error("Invalid parameter name: '{0}'")]
+    InvalidParameterName(String),
+
+    /// A parameter is missing, unknown or has a value of the wrong type.
+    #[error(transparent)]
+    InvalidParameter(#[from] BindError),
+
+    /// The time range is not a duration such as `15m` or `24h`.
+    #[error("Invalid time range: {0}")]
+    InvalidTimeRange(ParseError),
+
+    /// The query does not parse.
+    #[error("Invalid query: {0}")]
+    InvalidQuery(#[from] ParseError),
+}
+
+/// A named, reusable query.
+///
+/// # Example
+///
+/// ```
+/// use shared::models::SavedQuery;
+/// use std::collections::BTreeMap;
+///
+/// let saved = SavedQuery::new(
+///     "Errors by service",
+///     "SELECT * FROM logs WHERE level = 'error' AND service = $service",
+/// )
+/// .with_default_time_range("1h");
+///
+/// assert_eq!(saved.id, "errors-by-service");
+/// assert!(saved.validate().is_ok());
+///
+/// let params = BTreeMap::from([("service".to_string(), serde_json::json!("api"))]);
+/// let query = saved.compile(&params, None).unwrap();
+/// assert!(query.since.is_some());
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    /// Identifier derived from the name when the query is created.
+    pub id: String,
+
+    /// Human-readable name.
+    pub name: String,
+
+    /// What the query is for.
+    #[serde(default)]
+    pub description: String,
+
+    /// Who owns the query.
+    #[serde(default)]
+    pub owner: Option<String>,
+
+    /// Tags for finding the query.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The query text, possibly with `$name` parameters.
+    pub query: String,
+
+    /// Default parameter values. A `null` default makes the parameter required.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, serde_json::Value>,
+
+    /// Time range applied when the query has no `SINCE` clause, e.g. `15m`.
+    #[serde(default)]
+    pub default_time_range: Option<String>,
+
+    /// When the query was created.
+    pub created_at: DateTime<Utc>,
+
+    /// When the query was last updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedQuery {
+    /// Creates a saved query with the given name and query text.
+    #[must_use]
+    pub fn new(name: impl Into<String>, query: impl Into<String>) -> Self {
+        let name = name.into();
+        let now = Utc::now();
+        Self {
+            id: slugify(&name),
+            name,
+            description: String::new(),
+            owner: None,
+            tags: Vec::new(),
+            query: query.into(),
+            parameters: BTreeMap::new(),
+            default_time_range: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Sets the description.
+    #[must_use]
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Sets the owner.
+    #[must_use]
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Adds a tag.
+    #[must_use]
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Sets the default value of a parameter.
+    #[must_use]
+    pub fn with_parameter(mut self, name: impl Into<String>, default: serde_json::Value) -> Self {
+        self.parameters.insert(name.into(), default);
+        self
+    }
+
+    /// Sets the default time range.
+    #[must_use]
+    pub fn with_default_time_range(mut self, range: impl Into<String>) -> Self {
+        self.default_time_range = Some(range.into());
+        self
+    }
+
+    /// Returns the names of the parameters used in the query, in order of first use.
+    ///
+    /// Returns no names if the query does not parse.
+    #[must_use]
+    pub fn placeholders(&self) -> Vec<String> {
+        parse_query(&self.query)
+            .map(|query| query.params().into_iter().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the default parameter values, without the `null` defaults of
+    /// required parameters.
+    fn defaults(&self) -> QueryParams {
+        self.parameters
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Validates the saved query.
+    ///
+    /// The query must parse, every parameter must be used by it and every
+    /// default must have the type its parameter expects.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first problem found with the name, tags, parameters, default
+    /// time range or query.
+    pub fn validate(&self) -> Result<(), SavedQueryError> {
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(SavedQueryError::InvalidName(format!(
+                "must be between 1 and {MAX_NAME_LEN} characters"
+            )));
+        }
+        if slugify(name).is_empty() {
+            return Err(SavedQueryError::InvalidName(
+                "must contain a letter or digit".to_string(),
+            ));
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err(SavedQueryError::EmptyTag);
+        }
+        if let Some(name) = self.parameters.keys().find(|name| !is_identifier(name)) {
+            return Err(SavedQueryError::InvalidParameterName(name.clone()));
+        }
+        if let Some(range) = &self.default_time_range {
+            parse_duration(range).map_err(SavedQueryError::InvalidTimeRange)?;
+        }
+
+        let query = parse_query(&self.query)?;
+        if let Some(name) = self
+            .parameters
+            .keys()
+            .find(|name| !query.params().contains(&name.as_str()))
+        {
+            return Err(BindError::Unknown(name.clone()).into());
+        }
+        query.check_params(&self.defaults())?;
+        Ok(())
+    }
+
+    /// Parses the query and binds `params` to its parameters.
+    ///
+    /// Parameters missing from `params` take their default value. If `time_range`
+    /// is given it replaces the query's `SINCE`; otherwise the default time range
+    /// applies to queries without a `SINCE` clause.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query does not parse, a parameter has no value,
+    /// is unknown or has a value of the wrong type, or the time range is invalid.
+    pub fn compile(
+        &self,
+        params: &QueryParams,
+        time_range: Option<&str>,
+    ) -> Result<Query, SavedQueryError> {
+        let mut values = self.defaults();
+        values.extend(
+            params
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        let mut query = parse_query(&self.query)?.bind(&values)?;
+
+        let since = match time_range {
+            Some(range) => Some(range),
+            None if query.since.is_none() => self.default_time_range.as_deref(),
+            None => None,
+        };
+        if let Some(range) = since {
+            let duration = parse_duration(range).map_err(SavedQueryError::InvalidTimeRange)?;
+            query.since = Some(Value::Time(RelativeTime::ago(duration)));
+        }
+        Ok(query)
+    }
+}
+
+/// Derives an identifier from a name: lowercase letters and digits separated by
+/// single dashes.
+#[must_use]
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(pairs: &[(&str, serde_json::Value)]) -> BTreeMap<String, serde_json::Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Errors by Service"), "errors-by-service");
+        assert_eq!(slugify("  p99 latency (API) "), "p99-latency-api");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn test_placeholders_skip_strings() {
+        let saved = SavedQuery::new(
+            "q",
+            "SELECT * FROM logs WHERE service = $service AND message CONTAINS '$not' \
+             AND level = $level AND service != $service",
+        );
+        assert_eq!(saved.placeholders(), vec!["service", "level"]);
+    }
+
+    #[test]
+    fn test_compile_binds_parameters() {
+        let saved = SavedQuery::new(
+            "q",
+            "SELECT * FROM logs WHERE service IN ($services) AND message CONTAINS $text",
+        )
+        .with_parameter("text", json!("timeout"));
+
+        let query = saved
+            .compile(&params(&[("services", json!(["api", "web"]))]), None)
+            .unwrap();
+        assert_eq!(
+            query.to_string(),
+            "SELECT * FROM logs WHERE service IN ('api', 'web') AND message CONTAINS 'timeout'"
+        );
+
+        let query = saved
+            .compile(
+                &params(&[("services", json!("api")), ("text", json!("it's \"down\""))]),
+                None,
+            )
+            .unwrap();
+        assert!(query.params().is_empty());
+        assert!(query.to_string().contains("it's"));
+    }
+
+    #[test]
+    fn test_compile_rejects_missing_and_invalid_values() {
+        let saved = SavedQuery::new("q", "SELECT * FROM logs WHERE service = $service")
+            .with_parameter("service", serde_json::Value::Null);
+        assert!(matches!(
+            saved.compile(&BTreeMap::new(), None),
+            Err(SavedQueryError::InvalidParameter(BindError::Missing(name))) if name == "service"
+        ));
+        for value in [json!(1), json!({"a": 1}), json!([])] {
+            assert!(matches!(
+                saved.compile(&params(&[("service", value)]), None),
+                Err(SavedQueryError::InvalidParameter(_))
+            ));
+        }
+        assert!(matches!(
+            saved.compile(&params(&[("service", json!("api")), ("other", json!(1))]), None),
+            Err(SavedQueryError::InvalidParameter(BindError::Unknown(name))) if name == "other"
+        ));
+    }
+
+    #[test]
+    fn test_compile_time_range() {
+        let saved = SavedQuery::new("q", "SELECT * FROM logs").with_default_time_range("1h");
+        let query = saved.compile(&BTreeMap::new(), None).unwrap();
+        assert_eq!(
+            query.since,
+            Some(Value::Time(RelativeTime { offset_secs: -3600 }))
+        );
+
+        let query = saved.compile(&BTreeMap::new(), Some("5m")).unwrap();
+        assert_eq!(
+            query.since,
+            Some(Value::Time(RelativeTime { offset_secs: -300 }))
+        );
+
+        // An explicit SINCE wins over the default but not over a requested range
+        let saved =
+            SavedQuery::new("q", "SELECT * FROM logs SINCE 2h").with_default_time_range("1h");
+        let query = saved.compile(&BTreeMap::new(), None).unwrap();
+        assert_eq!(
+            query.since,
+            Some(Value::Time(RelativeTime { offset_secs: -7200 }))
+        );
+        assert!(matches!(
+            saved.compile(&BTreeMap::new(), Some("soon")),
+            Err(SavedQueryError::InvalidTimeRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = SavedQuery::new(
+            "Slow requests",
+            "SELECT * FROM traces WHERE duration_ms > $min AND service = $service",
+        )
+        .with_parameter("service", json!("api"));
+        assert!(valid.validate().is_ok());
+
+        let required_string = SavedQuery::new("q", "SELECT * FROM logs WHERE message MATCHES $re");
+        assert!(required_string.validate().is_ok());
+
+        let cases = [
+            SavedQuery::new("", "SELECT * FROM logs"),
+            SavedQuery::new("---", "SELECT * FROM logs"),
+            SavedQuery::new("q", "SELECT * FROM logs").with_tag(" "),
+            SavedQuery::new("q", "SELECT * FROM logs").with_parameter("1x", json!(1)),
+            SavedQuery::new("q", "SELECT * FROM logs").with_parameter("unused", json!(1)),
+            SavedQuery::new("q", "SELECT * FROM logs WHERE service = $s")
+                .with_parameter("s", json!(1)),
+            SavedQuery::new("q", "SELECT * FROM logs").with_default_time_range("1 hour"),
+            SavedQuery::new("q", "SELECT * FROM nowhere"),
+        ];
+        for saved in cases {
+            assert!(saved.validate().is_err(), "{saved:?} should be invalid");
+        }
+    }
+}
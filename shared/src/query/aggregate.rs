@@ -0,0 +1,782 @@
+//! Grouped aggregation (`GROUP BY`, aggregate functions and `HAVING`).
+//!
+//! A [`GroupQuery`] describes the aggregation a store has to perform over the
+//! records matching its filter. The in-memory implementation in this module is
+//! the reference for the `ClickHouse` SQL generated in [`super::clickhouse`].
+
+use super::ast::{
+    AggregateFunction, DurationLiteral, Expr, OrderBy, SelectItem, SortOrder, WhereClause,
+};
+use super::executor::{matches_filter, number_to_json, FieldValue, Record};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A grouped aggregation over the records matching a store query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupQuery {
+    /// Output columns: group keys (fields and time buckets) and aggregates.
+    pub columns: Vec<SelectItem>,
+    /// Keys to group by. Each must also be one of the `columns`.
+    pub group_by: Vec<Expr>,
+    /// Filter on aggregated rows; fields name output columns (e.g. `count(*)`).
+    pub having: Option<WhereClause>,
+    /// Sort keys, most significant first; fields name output columns. Ties are
+    /// ordered by the group keys.
+    pub order_by: Vec<OrderBy>,
+    /// Maximum number of groups to return.
+    pub limit: Option<usize>,
+    /// Number of groups to skip.
+    pub offset: Option<usize>,
+}
+
+impl GroupQuery {
+    /// Returns the index of the output column referenced by `name`.
+    ///
+    /// A column can be referenced by its alias or by its expression
+    /// (`count(*)`, `service`).
+    #[must_use]
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.alias.as_deref() == Some(name))
+            .or_else(|| {
+                self.columns
+                    .iter()
+                    .position(|column| column.expr.to_string() == name)
+            })
+    }
+
+    /// Returns the output columns to sort by with their direction: the ORDER BY
+    /// keys, then the group keys in ascending order to break ties.
+    #[must_use]
+    pub fn sort_keys(&self) -> Vec<(usize, SortOrder)> {
+        let mut keys: Vec<(usize, SortOrder)> = self
+            .order_by
+            .iter()
+            .filter_map(|key| Some((self.column_index(&key.field)?, key.order.clone())))
+            .collect();
+        for i in self.key_indexes() {
+            if !keys.iter().any(|(key, _)| *key == i) {
+                keys.push((i, SortOrder::Asc));
+            }
+        }
+        keys
+    }
+
+    /// Returns the indexes of the group key columns, in `GROUP BY` order.
+    #[must_use]
+    pub fn key_indexes(&self) -> Vec<usize> {
+        self.group_by
+            .iter()
+            .filter_map(|key| self.columns.iter().position(|column| column.expr == *key))
+            .collect()
+    }
+}
+
+/// Result of a grouped aggregation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupQueryResult {
+    /// One value per output column for each group.
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Number of groups (after `HAVING`, before limit/offset).
+    pub total_count: usize,
+}
+
+/// Evaluates a non-aggregate expression (field or time bucket) on a record.
+pub(crate) fn key_value<R: Record>(record: &R, expr: &Expr) -> serde_json::Value {
+    match expr {
+        Expr::Field(field) => record.field(field).to_json(),
+        Expr::Bucket { field, interval } => match record.field(field) {
+            FieldValue::Timestamp(timestamp) => {
+                serde_json::json!(bucket_start(timestamp, interval))
+            }
+            _ => serde_json::Value::Null,
+        },
+        Expr::Aggregate { .. } => serde_json::Value::Null,
+    }
+}
+
+/// Returns the start of the bucket containing `timestamp`. Buckets are aligned
+/// to the Unix epoch, like `toStartOfInterval` with a `SECOND` interval.
+pub(crate) fn bucket_start(timestamp: DateTime<Utc>, interval: &DurationLiteral) -> DateTime<Utc> {
+    let width = i64::try_from(interval.as_secs()).unwrap_or(i64::MAX).max(1);
+    let secs = timestamp.timestamp().div_euclid(width) * width;
+    DateTime::from_timestamp(secs, 0).unwrap_or(timestamp)
+}
+
+/// Groups and aggregates records in memory.
+pub(crate) fn group_records<R: Record>(records: &[R], query: &GroupQuery) -> GroupQueryResult {
+    let mut groups: Vec<(Vec<serde_json::Value>, Vec<Option<Accumulator>>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let new_accumulators = || {
+        query
+            .columns
+            .iter()
+            .map(|column| match column.expr {
+                Expr::Aggregate { function, .. } => Some(Accumulator::new(function)),
+                Expr::Field(_) | Expr::Bucket { .. } => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Without GROUP BY there is exactly one group, even if no records match.
+    if query.group_by.is_empty() {
+        index.insert("[]".to_string(), 0);
+        groups.push((Vec::new(), new_accumulators()));
+    }
+
+    for record in records {
+        let keys: Vec<serde_json::Value> = query
+            .group_by
+            .iter()
+            .map(|key| key_value(record, key))
+            .collect();
+        let key = serde_json::Value::Array(keys.clone()).to_string();
+        let slot = *index.entry(key).or_insert_with(|| {
+            groups.push((keys, new_accumulators()));
+            groups.len() - 1
+        });
+
+        for (column, accumulator) in query.columns.iter().zip(&mut groups[slot].1) {
+            if let (Expr::Aggregate { field, .. }, Some(accumulator)) = (&column.expr, accumulator)
+            {
+                match field {
+                    Some(field) => accumulator.add(&record.field(field)),
+                    None => accumulator.add_row(),
+                }
+            }
+        }
+    }
+
+    let rows: Vec<Vec<serde_json::Value>> = groups
+        .into_iter()
+        .map(|(keys, accumulators)| {
+            query
+                .columns
+                .iter()
+                .zip(accumulators)
+                .map(|(column, accumulator)| match (&column.expr, accumulator) {
+                    (_, Some(accumulator)) => accumulator.finish(),
+                    (expr, None) => query
+                        .group_by
+                        .iter()
+                        .position(|key| key == expr)
+                        .map_or(serde_json::Value::Null, |i| keys[i].clone()),
+                })
+                .collect()
+        })
+        .collect();
+
+    finish_groups(rows, query)
+}
+
+/// Applies HAVING, ordering and pagination to aggregated rows.
+pub(crate) fn finish_groups(
+    mut rows: Vec<Vec<serde_json::Value>>,
+    query: &GroupQuery,
+) -> GroupQueryResult {
+    if let Some(ref having) = query.having {
+        rows.retain(|values| matches_filter(having, &GroupedRow { query, values }));
+    }
+    let total_count = rows.len();
+
+    sort_rows(&mut rows, query);
+
+    let offset = query.offset.unwrap_or(0);
+    let rows = rows
+        .into_iter()
+        .skip(offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    GroupQueryResult { rows, total_count }
+}
+
+/// Adds a row for every empty bucket of each series, so that time series have
+/// no gaps. A series is a combination of the other group keys.
+///
+/// Buckets span `start..end` when given, otherwise the buckets present in `rows`.
+/// Counts of empty buckets are `0`, other aggregates `null`. Returns `None` if
+/// the query has no bucket key or if more than `max_buckets` would be produced.
+pub(crate) fn fill_buckets(
+    rows: &mut Vec<Vec<serde_json::Value>>,
+    query: &GroupQuery,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    max_buckets: usize,
+) -> Option<()> {
+    let (bucket_index, interval) =
+        query
+            .columns
+            .iter()
+            .enumerate()
+            .find_map(|(i, column)| match column.expr {
+                Expr::Bucket { interval, .. } if query.group_by.contains(&column.expr) => {
+                    Some((i, interval))
+                }
+                _ => None,
+            })?;
+    let series_indexes: Vec<usize> = query
+        .key_indexes()
+        .into_iter()
+        .filter(|i| *i != bucket_index)
+        .collect();
+    let width = chrono::Duration::seconds(i64::try_from(interval.as_secs()).ok()?.max(1));
+
+    // Existing buckets per series, keyed by the series' key values
+    let mut series: HashMap<String, (Vec<serde_json::Value>, BTreeSet<DateTime<Utc>>)> =
+        HashMap::new();
+    for row in rows.iter() {
+        let keys: Vec<serde_json::Value> = series_indexes.iter().map(|i| row[*i].clone()).collect();
+        let entry = series
+            .entry(serde_json::Value::Array(keys.clone()).to_string())
+            .or_insert_with(|| (keys, BTreeSet::new()));
+        if let Some(bucket) = row[bucket_index]
+            .as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        {
+            entry.1.insert(bucket.with_timezone(&Utc));
+        }
+    }
+    // Without other keys, a known range still yields a (zero) series
+    if series.is_empty() && series_indexes.is_empty() && start.is_some() && end.is_some() {
+        series.insert("[]".to_string(), (Vec::new(), BTreeSet::new()));
+    }
+
+    let observed = series.values().flat_map(|(_, buckets)| buckets.iter());
+    let first = start
+        .map(|t| bucket_start(t, &interval))
+        .or_else(|| observed.clone().min().copied());
+    let last = end
+        .map(|t| t - chrono::Duration::nanoseconds(1))
+        .map(|t| bucket_start(t, &interval))
+        .or_else(|| observed.max().copied());
+    let (Some(first), Some(last)) = (first, last) else {
+        return Some(());
+    };
+
+    let bucket_count =
+        usize::try_from((last - first).num_seconds() / width.num_seconds() + 1).unwrap_or(0);
+    if bucket_count.saturating_mul(series.len().max(1)) > max_buckets {
+        return None;
+    }
+
+    for (keys, present) in series.values() {
+        let mut bucket = first;
+        while bucket <= last {
+            if !present.contains(&bucket) {
+                rows.push(empty_bucket_row(
+                    query,
+                    bucket_index,
+                    &series_indexes,
+                    keys,
+                    bucket,
+                ));
+            }
+            bucket += width;
+        }
+    }
+
+    Some(())
+}
+
+/// Builds the row of an empty bucket.
+fn empty_bucket_row(
+    query: &GroupQuery,
+    bucket_index: usize,
+    series_indexes: &[usize],
+    keys: &[serde_json::Value],
+    bucket: DateTime<Utc>,
+) -> Vec<serde_json::Value> {
+    query
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            if i == bucket_index {
+                return serde_json::json!(bucket);
+            }
+            if let Some(position) = series_indexes.iter().position(|s| *s == i) {
+                return keys[position].clone();
+            }
+            match column.expr {
+                Expr::Aggregate {
+                    function: AggregateFunction::Count | AggregateFunction::CountDistinct,
+                    ..
+                } => serde_json::json!(0),
+                Expr::Aggregate {
+                    function: AggregateFunction::TopK(_),
+                    ..
+                } => serde_json::json!([]),
+                _ => serde_json::Value::Null,
+            }
+        })
+        .collect()
+}
+
+/// Sorts grouped rows by the ORDER BY columns, then by the group keys.
+fn sort_rows(rows: &mut [Vec<serde_json::Value>], query: &GroupQuery) {
+    let keys = query.sort_keys();
+
+    rows.sort_by(|a, b| {
+        keys.iter()
+            .map(|(i, order)| compare_values(&a[*i], &b[*i], order))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Compares two output values. `null` sorts last in either direction.
+fn compare_values(a: &serde_json::Value, b: &serde_json::Value, order: &SortOrder) -> Ordering {
+    use serde_json::Value;
+
+    let ordering = match (a, b) {
+        (Value::Null, Value::Null) => return Ordering::Equal,
+        (Value::Null, _) => return Ordering::Greater,
+        (_, Value::Null) => return Ordering::Less,
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .unwrap_or_default()
+            .total_cmp(&b.as_f64().unwrap_or_default()),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    };
+
+    match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    }
+}
+
+/// An aggregated row, so HAVING can reuse the WHERE evaluator.
+struct GroupedRow<'a> {
+    query: &'a GroupQuery,
+    values: &'a [serde_json::Value],
+}
+
+impl Record for GroupedRow<'_> {
+    fn field(&self, name: &str) -> FieldValue<'_> {
+        match self.query.column_index(name).map(|i| &self.values[i]) {
+            Some(serde_json::Value::Number(n)) => {
+                n.as_f64().map_or(FieldValue::Missing, FieldValue::Number)
+            }
+            Some(serde_json::Value::Null) | None => FieldValue::Missing,
+            Some(value) => FieldValue::Json(value),
+        }
+    }
+}
+
+/// Running state of an aggregate function.
+enum Accumulator {
+    Count(u64),
+    Sum(Option<f64>),
+    Avg {
+        sum: f64,
+        count: u64,
+    },
+    Min(Option<f64>),
+    Max(Option<f64>),
+    Distinct(HashSet<String>),
+    /// Occurrences of each value, keyed by its JSON text.
+    TopK {
+        k: usize,
+        counts: HashMap<String, (serde_json::Value, u64)>,
+    },
+}
+
+impl Accumulator {
+    fn new(function: AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Count => Self::Count(0),
+            AggregateFunction::Sum => Self::Sum(None),
+            AggregateFunction::Avg => Self::Avg { sum: 0.0, count: 0 },
+            AggregateFunction::Min => Self::Min(None),
+            AggregateFunction::Max => Self::Max(None),
+            AggregateFunction::CountDistinct => Self::Distinct(HashSet::new()),
+            AggregateFunction::TopK(k) => Self::TopK {
+                k,
+                counts: HashMap::new(),
+            },
+        }
+    }
+
+    /// Adds a record to a `count(*)`.
+    fn add_row(&mut self) {
+        if let Self::Count(count) = self {
+            *count += 1;
+        }
+    }
+
+    /// Adds a field value. Absent values are skipped; only numbers are summed
+    /// or compared.
+    fn add(&mut self, value: &FieldValue<'_>) {
+        if matches!(value, FieldValue::Missing | FieldValue::OptionalText(None)) {
+            return;
+        }
+        let number = match value {
+            FieldValue::Number(n) => Some(*n),
+            FieldValue::Json(json) => json.as_f64(),
+            _ => None,
+        };
+
+        match (self, number) {
+            (Self::Count(count), _) => *count += 1,
+            (Self::Distinct(values), _) => {
+                values.insert(value.to_json().to_string());
+            }
+            (Self::TopK { counts, .. }, _) => {
+                let value = value.to_json();
+                counts.entry(value.to_string()).or_insert((value, 0)).1 += 1;
+            }
+            (Self::Sum(sum), Some(n)) => *sum = Some(sum.unwrap_or(0.0) + n),
+            (Self::Avg { sum, count }, Some(n)) => {
+                *sum += n;
+                *count += 1;
+            }
+            (Self::Min(min), Some(n)) => *min = Some(min.map_or(n, |m| m.min(n))),
+            (Self::Max(max), Some(n)) => *max = Some(max.map_or(n, |m| m.max(n))),
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> serde_json::Value {
+        match self {
+            Self::Count(count) => serde_json::json!(count),
+            Self::Distinct(values) => serde_json::json!(values.len()),
+            Self::TopK { k, counts } => {
+                // Exact counts; ties are broken by the value's JSON text
+                let mut counts: Vec<(String, (serde_json::Value, u64))> =
+                    counts.into_iter().collect();
+                counts.sort_by(|(a, (_, a_count)), (b, (_, b_count))| {
+                    b_count.cmp(a_count).then_with(|| a.cmp(b))
+                });
+                counts
+                    .into_iter()
+                    .take(k)
+                    .map(|(_, (value, _))| value)
+                    .collect()
+            }
+            #[allow(clippy::cast_precision_loss)]
+            Self::Avg { sum, count } if count > 0 => number_to_json(sum / count as f64),
+            Self::Sum(Some(n)) | Self::Min(Some(n)) | Self::Max(Some(n)) => number_to_json(n),
+            Self::Avg { .. } | Self::Sum(None) | Self::Min(None) | Self::Max(None) => {
+                serde_json::Value::Null
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LogEntry, LogLevel};
+    use crate::query::{ComparisonOp, Condition, Value};
+
+    fn logs() -> Vec<LogEntry> {
+        vec![
+            LogEntry::new(LogLevel::Error, "a", "api").with_attribute("latency_ms", 100),
+            LogEntry::new(LogLevel::Error, "b", "api").with_attribute("latency_ms", 50),
+            LogEntry::new(LogLevel::Info, "c", "api").with_attribute("user", "u1"),
+            LogEntry::new(LogLevel::Error, "d", "db").with_attribute("user", "u1"),
+            LogEntry::new(LogLevel::Warn, "e", "db").with_attribute("user", "u2"),
+        ]
+    }
+
+    fn group_by_service(columns: Vec<SelectItem>) -> GroupQuery {
+        GroupQuery {
+            columns: [vec![SelectItem::new("service")], columns].concat(),
+            group_by: vec![Expr::Field("service".to_string())],
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn test_group_records_aggregates() {
+        let query = group_by_service(vec![
+            SelectItem::aggregate(AggregateFunction::Count, None),
+            SelectItem::aggregate(AggregateFunction::Sum, Some("latency_ms")),
+            SelectItem::aggregate(AggregateFunction::Avg, Some("latency_ms")),
+            SelectItem::aggregate(AggregateFunction::Min, Some("latency_ms")),
+            SelectItem::aggregate(AggregateFunction::Max, Some("latency_ms")),
+            SelectItem::aggregate(AggregateFunction::Count, Some("user")),
+            SelectItem::aggregate(AggregateFunction::CountDistinct, Some("user")),
+        ]);
+
+        let result = group_records(&logs(), &query);
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(
+            result.rows[0],
+            serde_json::json!(["api", 3, 150, 75, 50, 100, 1, 1])
+                .as_array()
+                .unwrap()
+                .clone()
+        );
+        assert_eq!(
+            result.rows[1],
+            serde_json::json!(["db", 2, null, null, null, null, 2, 2])
+                .as_array()
+                .unwrap()
+                .clone()
+        );
+    }
+
+    #[test]
+    fn test_group_records_without_group_by_returns_one_row() {
+        let query = GroupQuery {
+            columns: vec![
+                SelectItem::aggregate(AggregateFunction::Count, None),
+                SelectItem::aggregate(AggregateFunction::Avg, Some("latency_ms")),
+            ],
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+
+        let result = group_records(&logs(), &query);
+        assert_eq!(
+            result.rows,
+            vec![vec![serde_json::json!(5), serde_json::json!(75)]]
+        );
+
+        let empty: Vec<LogEntry> = Vec::new();
+        let result = group_records(&empty, &query);
+        assert_eq!(result.total_count, 1);
+        assert_eq!(
+            result.rows,
+            vec![vec![serde_json::json!(0), serde_json::Value::Null]]
+        );
+    }
+
+    #[test]
+    fn test_group_records_having_order_and_pagination() {
+        let mut query =
+            group_by_service(vec![
+                SelectItem::aggregate(AggregateFunction::Count, None).with_alias("n")
+            ]);
+        query.group_by.push(Expr::Field("level".to_string()));
+        query.columns.push(SelectItem::new("level"));
+        query.having = Some(WhereClause::Condition(Condition {
+            field: "count(*)".to_string(),
+            operator: ComparisonOp::GtEq,
+            value: Value::Integer(1),
+        }));
+        query.order_by = vec![OrderBy {
+            field: "n".to_string(),
+            order: SortOrder::Desc,
+        }];
+        query.limit = Some(2);
+
+        let result = group_records(&logs(), &query);
+
+        assert_eq!(result.total_count, 4);
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(
+            result.rows[0],
+            vec![
+                serde_json::json!("api"),
+                serde_json::json!(2),
+                serde_json::json!("error"),
+            ]
+        );
+
+        query.having = Some(WhereClause::Condition(Condition {
+            field: "n".to_string(),
+            operator: ComparisonOp::Gt,
+            value: Value::Float(1.5),
+        }));
+        let result = group_records(&logs(), &query);
+        assert_eq!(result.total_count, 1);
+    }
+
+    fn at(minute: u32, second: u32) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2024-01-01T10:{minute:02}:{second:02}Z"))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn bucketed_query() -> GroupQuery {
+        let bucket = Expr::Bucket {
+            field: "timestamp".to_string(),
+            interval: DurationLiteral::new(5, crate::query::DurationUnit::Minutes),
+        };
+        GroupQuery {
+            columns: vec![
+                SelectItem {
+                    expr: bucket.clone(),
+                    alias: Some("time".to_string()),
+                },
+                SelectItem::new("service"),
+                SelectItem::aggregate(AggregateFunction::Count, None),
+                SelectItem::aggregate(AggregateFunction::Max, Some("latency_ms")),
+            ],
+            group_by: vec![bucket, Expr::Field("service".to_string())],
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn test_group_records_order_by_multiple_keys() {
+        let mut query = group_by_service(vec![
+            SelectItem::aggregate(AggregateFunction::Count, None).with_alias("n"),
+            SelectItem::new("level"),
+        ]);
+        query.group_by.push(Expr::Field("level".to_string()));
+        query.order_by = vec![
+            OrderBy {
+                field: "n".to_string(),
+                order: SortOrder::Desc,
+            },
+            OrderBy {
+                field: "service".to_string(),
+                order: SortOrder::Desc,
+            },
+        ];
+
+        // Ties on every ORDER BY key fall back to the group keys, ascending
+        assert_eq!(
+            query.sort_keys(),
+            vec![
+                (1, SortOrder::Desc),
+                (0, SortOrder::Desc),
+                (2, SortOrder::Asc)
+            ]
+        );
+
+        let result = group_records(&logs(), &query);
+        let keys: Vec<(&str, &str)> = result
+            .rows
+            .iter()
+            .map(|row| (row[0].as_str().unwrap(), row[2].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("api", "error"),
+                ("db", "error"),
+                ("db", "warn"),
+                ("api", "info")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_records_topk() {
+        let query = group_by_service(vec![
+            SelectItem::aggregate(AggregateFunction::TopK(2), Some("level")),
+            SelectItem::aggregate(AggregateFunction::TopK(5), Some("user")),
+        ]);
+
+        let result = group_records(&logs(), &query);
+
+        // Most frequent first, ties by value; records without the field are skipped
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    serde_json::json!("api"),
+                    serde_json::json!(["error", "info"]),
+                    serde_json::json!(["u1"]),
+                ],
+                vec![
+                    serde_json::json!("db"),
+                    serde_json::json!(["error", "warn"]),
+                    serde_json::json!(["u1", "u2"]),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bucket_start() {
+        let interval = DurationLiteral::new(5, crate::query::DurationUnit::Minutes);
+        assert_eq!(bucket_start(at(7, 42), &interval), at(5, 0));
+        assert_eq!(bucket_start(at(10, 0), &interval), at(10, 0));
+    }
+
+    #[test]
+    fn test_group_records_by_bucket() {
+        let logs: Vec<LogEntry> = [at(1, 0), at(4, 59), at(12, 0)]
+            .into_iter()
+            .map(|timestamp| {
+                let mut log = LogEntry::new(LogLevel::Error, "a", "api");
+                log.timestamp = timestamp;
+                log
+            })
+            .collect();
+
+        let result = group_records(&logs, &bucketed_query());
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.rows[0][0], serde_json::json!("2024-01-01T10:00:00Z"));
+        assert_eq!(result.rows[0][2], serde_json::json!(2));
+        assert_eq!(result.rows[1][0], serde_json::json!("2024-01-01T10:10:00Z"));
+    }
+
+    #[test]
+    fn test_fill_buckets_per_series() {
+        let query = bucketed_query();
+        let mut rows = vec![
+            serde_json::json!(["2024-01-01T10:00:00Z", "api", 2, 10]),
+            serde_json::json!(["2024-01-01T10:10:00Z", "api", 1, 20]),
+            serde_json::json!(["2024-01-01T10:05:00Z", "db", 4, null]),
+        ]
+        .into_iter()
+        .map(|row| row.as_array().unwrap().clone())
+        .collect();
+
+        fill_buckets(&mut rows, &query, None, None, 100).unwrap();
+        let result = finish_groups(rows, &query);
+
+        // 3 buckets (10:00, 10:05, 10:10) for each of the 2 services
+        assert_eq!(result.total_count, 6);
+        assert_eq!(
+            result.rows[1],
+            serde_json::json!(["2024-01-01T10:00:00Z", "db", 0, null])
+                .as_array()
+                .unwrap()
+                .clone()
+        );
+        assert_eq!(
+            result.rows[2],
+            serde_json::json!(["2024-01-01T10:05:00Z", "api", 0, null])
+                .as_array()
+                .unwrap()
+                .clone()
+        );
+    }
+
+    #[test]
+    fn test_fill_buckets_with_range_and_limit() {
+        let mut query = bucketed_query();
+        query.columns.remove(1);
+        query.group_by.pop();
+
+        let mut rows = Vec::new();
+        fill_buckets(&mut rows, &query, Some(at(2, 0)), Some(at(20, 0)), 100).unwrap();
+        let times: Vec<&str> = rows.iter().map(|row| row[0].as_str().unwrap()).collect();
+        assert_eq!(
+            times,
+            vec![
+                "2024-01-01T10:00:00Z",
+                "2024-01-01T10:05:00Z",
+                "2024-01-01T10:10:00Z",
+                "2024-01-01T10:15:00Z"
+            ]
+        );
+        assert!(rows.iter().all(|row| row[1] == serde_json::json!(0)));
+
+        let mut rows = Vec::new();
+        assert!(fill_buckets(&mut rows, &query, Some(at(0, 0)), Some(at(59, 0)), 5).is_none());
+    }
+}
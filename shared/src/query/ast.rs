@@ -1,5 +1,6 @@
 //! Abstract Syntax Tree definitions for the query language.
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// The data source to query.
@@ -45,6 +46,8 @@ pub enum ComparisonOp {
     StartsWith,
     /// Ends with
     EndsWith,
+    /// Regular expression match (MATCHES, =~), case-sensitive
+    Matches,
 }
 
 impl std::fmt::Display for ComparisonOp {
@@ -59,6 +62,7 @@ impl std::fmt::Display for ComparisonOp {
             Self::Contains => write!(f, "CONTAINS"),
             Self::StartsWith => write!(f, "STARTS WITH"),
             Self::EndsWith => write!(f, "ENDS WITH"),
+            Self::Matches => write!(f, "MATCHES"),
         }
     }
 }
@@ -93,6 +97,11 @@ pub enum Value {
     Float(f64),
     /// Boolean value
     Boolean(bool),
+    /// A time relative to when the query runs, e.g. `now() - 15m`
+    Time(RelativeTime),
+    /// A bind parameter (`$name`), replaced by its value by [`Query::bind`]
+    /// before the query runs
+    Param(String),
 }
 
 impl std::fmt::Display for Value {
@@ -102,6 +111,8 @@ impl std::fmt::Display for Value {
             Self::Integer(i) => write!(f, "{i}"),
             Self::Float(fl) => write!(f, "{fl}"),
             Self::Boolean(b) => write!(f, "{b}"),
+            Self::Time(t) => write!(f, "{t}"),
+            Self::Param(name) => write!(f, "${name}"),
         }
     }
 }
@@ -117,6 +128,18 @@ pub struct Condition {
     pub value: Value,
 }
 
+impl Condition {
+    /// Creates a condition.
+    #[must_use]
+    pub fn new(field: impl Into<String>, operator: ComparisonOp, value: Value) -> Self {
+        Self {
+            field: field.into(),
+            operator,
+            value,
+        }
+    }
+}
+
 impl std::fmt::Display for Condition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} {} {}", self.field, self.operator, self.value)
@@ -124,6 +147,9 @@ impl std::fmt::Display for Condition {
 }
 
 /// A WHERE clause expression (can be a single condition or combined with AND/OR).
+///
+/// Fields that are missing on a record never satisfy a comparison, `IN` or
+/// `BETWEEN`, so their negations (`!=`, `NOT IN`, `NOT BETWEEN`, `NOT ...`) do.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum WhereClause {
     /// A single condition.
@@ -139,6 +165,35 @@ pub enum WhereClause {
     },
     /// A grouped expression (parentheses).
     Grouped(Box<WhereClause>),
+    /// A negated clause (`NOT ...`).
+    Not(Box<WhereClause>),
+    /// Set membership (`field IN (...)`), true if the field equals any value.
+    In {
+        /// The field name to compare.
+        field: String,
+        /// The candidate values.
+        values: Vec<Value>,
+        /// `NOT IN`.
+        negated: bool,
+    },
+    /// Range check (`field BETWEEN low AND high`), inclusive on both ends.
+    Between {
+        /// The field name to compare.
+        field: String,
+        /// Lower bound.
+        low: Value,
+        /// Upper bound.
+        high: Value,
+        /// `NOT BETWEEN`.
+        negated: bool,
+    },
+    /// Presence check (`field IS NULL`). Missing fields and JSON `null` are null.
+    IsNull {
+        /// The field name to check.
+        field: String,
+        /// `IS NOT NULL`.
+        negated: bool,
+    },
 }
 
 impl std::fmt::Display for WhereClause {
@@ -151,6 +206,32 @@ impl std::fmt::Display for WhereClause {
                 right,
             } => write!(f, "{left} {operator} {right}"),
             Self::Grouped(inner) => write!(f, "({inner})"),
+            Self::Not(inner) => match **inner {
+                Self::Combined { .. } => write!(f, "NOT ({inner})"),
+                _ => write!(f, "NOT {inner}"),
+            },
+            Self::In {
+                field,
+                values,
+                negated,
+            } => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{field} {not}IN ({})", values.join(", "))
+            }
+            Self::Between {
+                field,
+                low,
+                high,
+                negated,
+            } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{field} {not}BETWEEN {low} AND {high}")
+            }
+            Self::IsNull { field, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{field} IS {not}NULL")
+            }
         }
     }
 }
@@ -174,7 +255,7 @@ impl std::fmt::Display for SortOrder {
     }
 }
 
-/// ORDER BY clause.
+/// A key of an ORDER BY clause.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OrderBy {
     /// The field to sort by.
@@ -189,15 +270,298 @@ impl std::fmt::Display for OrderBy {
     }
 }
 
+/// Unit of a [`DurationLiteral`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DurationUnit {
+    /// Seconds (`s`).
+    Seconds,
+    /// Minutes (`m`).
+    Minutes,
+    /// Hours (`h`).
+    Hours,
+    /// Days (`d`).
+    Days,
+}
+
+/// A duration literal such as `30s`, `5m`, `1h` or `7d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DurationLiteral {
+    /// Number of units.
+    pub amount: u64,
+    /// The unit.
+    pub unit: DurationUnit,
+}
+
+impl DurationLiteral {
+    /// Creates a duration literal.
+    #[must_use]
+    pub const fn new(amount: u64, unit: DurationUnit) -> Self {
+        Self { amount, unit }
+    }
+
+    /// Returns the duration in seconds.
+    #[must_use]
+    pub const fn as_secs(&self) -> u64 {
+        let unit = match self.unit {
+            DurationUnit::Seconds => 1,
+            DurationUnit::Minutes => 60,
+            DurationUnit::Hours => 3600,
+            DurationUnit::Days => 86_400,
+        };
+        self.amount.saturating_mul(unit)
+    }
+}
+
+impl std::fmt::Display for DurationLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            DurationUnit::Seconds => "s",
+            DurationUnit::Minutes => "m",
+            DurationUnit::Hours => "h",
+            DurationUnit::Days => "d",
+        };
+        write!(f, "{}{unit}", self.amount)
+    }
+}
+
+impl DurationLiteral {
+    /// Expresses a number of seconds in the largest unit that divides it.
+    #[must_use]
+    pub const fn from_secs(secs: u64) -> Self {
+        let unit = if secs == 0 {
+            DurationUnit::Seconds
+        } else if secs.is_multiple_of(86_400) {
+            DurationUnit::Days
+        } else if secs.is_multiple_of(3600) {
+            DurationUnit::Hours
+        } else if secs.is_multiple_of(60) {
+            DurationUnit::Minutes
+        } else {
+            DurationUnit::Seconds
+        };
+        let literal = Self::new(1, unit);
+        Self::new(secs / literal.as_secs(), unit)
+    }
+}
+
+/// A point in time relative to when the query runs: `now()`, `now() - 15m`, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelativeTime {
+    /// Offset from now in seconds. Negative offsets lie in the past.
+    pub offset_secs: i64,
+}
+
+impl RelativeTime {
+    /// `now()`.
+    #[must_use]
+    pub const fn now() -> Self {
+        Self { offset_secs: 0 }
+    }
+
+    /// `now() - duration`.
+    #[must_use]
+    pub fn ago(duration: DurationLiteral) -> Self {
+        Self {
+            offset_secs: -i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        }
+    }
+
+    /// `now() + duration`.
+    #[must_use]
+    pub fn ahead(duration: DurationLiteral) -> Self {
+        Self {
+            offset_secs: i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        }
+    }
+
+    /// Returns the absolute time relative to `now`.
+    #[must_use]
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        chrono::Duration::try_seconds(self.offset_secs)
+            .and_then(|offset| now.checked_add_signed(offset))
+            .unwrap_or(if self.offset_secs < 0 {
+                DateTime::<Utc>::MIN_UTC
+            } else {
+                DateTime::<Utc>::MAX_UTC
+            })
+    }
+}
+
+impl std::fmt::Display for RelativeTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offset = DurationLiteral::from_secs(self.offset_secs.unsigned_abs());
+        match self.offset_secs {
+            0 => write!(f, "now()"),
+            secs if secs < 0 => write!(f, "now() - {offset}"),
+            _ => write!(f, "now() + {offset}"),
+        }
+    }
+}
+
+/// Aggregate functions available in the SELECT list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    /// Number of records (`count(*)`) or of records where the field is present.
+    Count,
+    /// Sum of numeric values.
+    Sum,
+    /// Average of numeric values.
+    Avg,
+    /// Smallest numeric value.
+    Min,
+    /// Largest numeric value.
+    Max,
+    /// Number of distinct values.
+    CountDistinct,
+    /// The `k` most frequent values, most frequent first (`topk(10, name)`).
+    TopK(usize),
+}
+
+impl std::fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count => write!(f, "count"),
+            Self::Sum => write!(f, "sum"),
+            Self::Avg => write!(f, "avg"),
+            Self::Min => write!(f, "min"),
+            Self::Max => write!(f, "max"),
+            Self::CountDistinct => write!(f, "count_distinct"),
+            Self::TopK(_) => write!(f, "topk"),
+        }
+    }
+}
+
+/// An expression in the SELECT list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expr {
+    /// A field of the record (e.g. `service`, `attributes.user_id`).
+    Field(String),
+    /// The start of the time bucket a timestamp falls into (`bucket(timestamp, 5m)`).
+    Bucket {
+        /// The timestamp field.
+        field: String,
+        /// The bucket width.
+        interval: DurationLiteral,
+    },
+    /// An aggregate over a group of records (e.g. `count(*)`, `avg(duration_ms)`).
+    Aggregate {
+        /// The aggregate function.
+        function: AggregateFunction,
+        /// The aggregated field, or `None` for `*`.
+        field: Option<String>,
+    },
+}
+
+impl Expr {
+    /// Returns `true` if this is an aggregate expression.
+    #[must_use]
+    pub const fn is_aggregate(&self) -> bool {
+        matches!(self, Self::Aggregate { .. })
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Field(field) => write!(f, "{field}"),
+            Self::Bucket { field, interval } => write!(f, "bucket({field}, {interval})"),
+            Self::Aggregate {
+                function: AggregateFunction::TopK(k),
+                field,
+            } => write!(f, "topk({k}, {})", field.as_deref().unwrap_or("*")),
+            Self::Aggregate { function, field } => {
+                write!(f, "{function}({})", field.as_deref().unwrap_or("*"))
+            }
+        }
+    }
+}
+
+/// A column in the SELECT list (e.g. `attributes.user_id AS user`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectItem {
+    /// The projected expression.
+    pub expr: Expr,
+    /// Optional output column name (`AS alias`).
+    pub alias: Option<String>,
+}
+
+impl SelectItem {
+    /// Creates a projection of a field without an alias.
+    #[must_use]
+    pub fn new(field: impl Into<String>) -> Self {
+        Self {
+            expr: Expr::Field(field.into()),
+            alias: None,
+        }
+    }
+
+    /// Creates an aggregate column without an alias. A `field` of `None` means `*`.
+    #[must_use]
+    pub fn aggregate(function: AggregateFunction, field: Option<&str>) -> Self {
+        Self {
+            expr: Expr::Aggregate {
+                function,
+                field: field.map(ToString::to_string),
+            },
+            alias: None,
+        }
+    }
+
+    /// Sets the output column name.
+    #[must_use]
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    /// Returns the name of the output column: the alias, or the expression itself.
+    #[must_use]
+    pub fn column_name(&self) -> String {
+        self.alias.clone().unwrap_or_else(|| self.expr.to_string())
+    }
+}
+
+impl std::fmt::Display for SelectItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.alias {
+            Some(ref alias) => write!(f, "{} AS {alias}", self.expr),
+            None => write!(f, "{}", self.expr),
+        }
+    }
+}
+
 /// A parsed SQL-like query.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Query {
+    /// `SELECT DISTINCT`: each distinct combination of the projected fields is
+    /// returned once.
+    #[serde(default)]
+    pub distinct: bool,
+    /// Projected columns. Empty means `SELECT *` (whole records).
+    #[serde(default)]
+    pub projection: Vec<SelectItem>,
     /// The data source to query (logs, metrics, traces).
     pub source: Source,
     /// Optional WHERE clause with conditions.
     pub where_clause: Option<WhereClause>,
-    /// Optional ORDER BY clause.
-    pub order_by: Option<OrderBy>,
+    /// Optional inclusive start of the time range (`SINCE`).
+    #[serde(default)]
+    pub since: Option<Value>,
+    /// Optional exclusive end of the time range (`UNTIL`).
+    #[serde(default)]
+    pub until: Option<Value>,
+    /// GROUP BY keys: fields or time buckets.
+    #[serde(default)]
+    pub group_by: Vec<Expr>,
+    /// Optional HAVING clause, evaluated against aggregated rows.
+    #[serde(default)]
+    pub having: Option<WhereClause>,
+    /// ORDER BY keys, most significant first.
+    #[serde(default)]
+    pub order_by: Vec<OrderBy>,
     /// Optional LIMIT clause.
     pub limit: Option<usize>,
     /// Optional OFFSET clause.
@@ -209,14 +573,34 @@ impl Query {
     #[must_use]
     pub fn new(source: Source) -> Self {
         Self {
+            distinct: false,
+            projection: Vec::new(),
             source,
             where_clause: None,
-            order_by: None,
+            since: None,
+            until: None,
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
             limit: None,
             offset: None,
         }
     }
 
+    /// Sets the projected columns.
+    #[must_use]
+    pub fn with_projection(mut self, projection: Vec<SelectItem>) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// Returns each distinct projected row once (`SELECT DISTINCT`).
+    #[must_use]
+    pub fn with_distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
     /// Sets the WHERE clause.
     #[must_use]
     pub fn with_where(mut self, clause: WhereClause) -> Self {
@@ -224,10 +608,47 @@ impl Query {
         self
     }
 
-    /// Sets the ORDER BY clause.
+    /// Sets the inclusive start of the time range.
+    #[must_use]
+    pub fn with_since(mut self, since: Value) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Sets the exclusive end of the time range.
+    #[must_use]
+    pub fn with_until(mut self, until: Value) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Sets the GROUP BY keys.
+    #[must_use]
+    pub fn with_group_by(mut self, keys: Vec<Expr>) -> Self {
+        self.group_by = keys;
+        self
+    }
+
+    /// Sets the HAVING clause.
+    #[must_use]
+    pub fn with_having(mut self, clause: WhereClause) -> Self {
+        self.having = Some(clause);
+        self
+    }
+
+    /// Returns `true` if the query groups or aggregates records. `DISTINCT`
+    /// groups by the projected fields.
+    #[must_use]
+    pub fn is_aggregate(&self) -> bool {
+        self.distinct
+            || !self.group_by.is_empty()
+            || self.projection.iter().any(|item| item.expr.is_aggregate())
+    }
+
+    /// Adds an ORDER BY key, less significant than the keys already added.
     #[must_use]
     pub fn with_order_by(mut self, field: impl Into<String>, order: SortOrder) -> Self {
-        self.order_by = Some(OrderBy {
+        self.order_by.push(OrderBy {
             field: field.into(),
             order,
         });
@@ -251,14 +672,42 @@ impl Query {
 
 impl std::fmt::Display for Query {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "SELECT * FROM {}", self.source)?;
+        write!(f, "SELECT ")?;
+        if self.distinct {
+            write!(f, "DISTINCT ")?;
+        }
+        if self.projection.is_empty() {
+            write!(f, "*")?;
+        } else {
+            let columns: Vec<String> = self.projection.iter().map(ToString::to_string).collect();
+            write!(f, "{}", columns.join(", "))?;
+        }
+        write!(f, " FROM {}", self.source)?;
 
         if let Some(ref where_clause) = self.where_clause {
             write!(f, " WHERE {where_clause}")?;
         }
 
-        if let Some(ref order_by) = self.order_by {
-            write!(f, " ORDER BY {order_by}")?;
+        if let Some(ref since) = self.since {
+            write!(f, " SINCE {since}")?;
+        }
+
+        if let Some(ref until) = self.until {
+            write!(f, " UNTIL {until}")?;
+        }
+
+        if !self.group_by.is_empty() {
+            let keys: Vec<String> = self.group_by.iter().map(ToString::to_string).collect();
+            write!(f, " GROUP BY {}", keys.join(", "))?;
+        }
+
+        if let Some(ref having) = self.having {
+            write!(f, " HAVING {having}")?;
+        }
+
+        if !self.order_by.is_empty() {
+            let keys: Vec<String> = self.order_by.iter().map(ToString::to_string).collect();
+            write!(f, " ORDER BY {}", keys.join(", "))?;
         }
 
         if let Some(limit) = self.limit {
@@ -304,16 +753,129 @@ mod tests {
                 operator: ComparisonOp::Eq,
                 value: Value::String("error".to_string()),
             }))
+            .with_order_by("service", SortOrder::Asc)
             .with_order_by("timestamp", SortOrder::Desc)
             .with_limit(100)
             .with_offset(10);
 
         assert_eq!(
             query.to_string(),
-            "SELECT * FROM logs WHERE level = 'error' ORDER BY timestamp DESC LIMIT 100 OFFSET 10"
+            "SELECT * FROM logs WHERE level = 'error' ORDER BY service ASC, timestamp DESC LIMIT 100 OFFSET 10"
+        );
+    }
+
+    #[test]
+    fn test_query_display_with_projection() {
+        let query = Query::new(Source::Logs).with_projection(vec![
+            SelectItem::new("service"),
+            SelectItem::new("attributes.user_id").with_alias("user"),
+        ]);
+        assert_eq!(
+            query.to_string(),
+            "SELECT service, attributes.user_id AS user FROM logs"
         );
     }
 
+    #[test]
+    fn test_query_display_with_group_by() {
+        let query = Query::new(Source::Logs)
+            .with_projection(vec![
+                SelectItem::new("service"),
+                SelectItem::aggregate(AggregateFunction::Count, None).with_alias("errors"),
+                SelectItem::aggregate(AggregateFunction::Avg, Some("attributes.latency_ms")),
+            ])
+            .with_group_by(vec![Expr::Field("service".to_string())])
+            .with_having(WhereClause::Condition(Condition {
+                field: "count(*)".to_string(),
+                operator: ComparisonOp::Gt,
+                value: Value::Integer(10),
+            }));
+
+        assert!(query.is_aggregate());
+        assert_eq!(
+            query.to_string(),
+            "SELECT service, count(*) AS errors, avg(attributes.latency_ms) FROM logs \
+             GROUP BY service HAVING count(*) > 10"
+        );
+    }
+
+    #[test]
+    fn test_query_display_distinct_and_topk() {
+        let query = Query::new(Source::Logs)
+            .with_distinct()
+            .with_projection(vec![SelectItem::new("service")]);
+        assert!(query.is_aggregate());
+        assert_eq!(query.to_string(), "SELECT DISTINCT service FROM logs");
+
+        let query = Query::new(Source::Traces).with_projection(vec![SelectItem::aggregate(
+            AggregateFunction::TopK(10),
+            Some("name"),
+        )
+        .with_alias("names")]);
+        assert_eq!(
+            query.to_string(),
+            "SELECT topk(10, name) AS names FROM traces"
+        );
+    }
+
+    #[test]
+    fn test_bucket_display_and_duration() {
+        let interval = DurationLiteral::new(5, DurationUnit::Minutes);
+        assert_eq!(interval.as_secs(), 300);
+        assert_eq!(
+            DurationLiteral::new(2, DurationUnit::Days).as_secs(),
+            172_800
+        );
+
+        let bucket = Expr::Bucket {
+            field: "timestamp".to_string(),
+            interval,
+        };
+        assert_eq!(bucket.to_string(), "bucket(timestamp, 5m)");
+        assert!(!bucket.is_aggregate());
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let ago = RelativeTime::ago(DurationLiteral::new(15, DurationUnit::Minutes));
+        assert_eq!(ago.offset_secs, -900);
+        assert_eq!(ago.to_string(), "now() - 15m");
+        assert_eq!(ago.resolve(now).to_rfc3339(), "2024-01-01T11:45:00+00:00");
+        assert_eq!(RelativeTime::now().to_string(), "now()");
+        assert_eq!(
+            RelativeTime::ahead(DurationLiteral::new(48, DurationUnit::Hours)).to_string(),
+            "now() + 2d"
+        );
+        assert_eq!(DurationLiteral::from_secs(90).to_string(), "90s");
+
+        let query = Query::new(Source::Logs)
+            .with_since(Value::Time(ago))
+            .with_until(Value::String("2024-01-01T12:00:00Z".to_string()));
+        assert_eq!(
+            query.to_string(),
+            "SELECT * FROM logs SINCE now() - 15m UNTIL '2024-01-01T12:00:00Z'"
+        );
+    }
+
+    #[test]
+    fn test_select_item_column_name() {
+        assert_eq!(SelectItem::new("service").column_name(), "service");
+        assert_eq!(
+            SelectItem::aggregate(AggregateFunction::CountDistinct, Some("user_id")).column_name(),
+            "count_distinct(user_id)"
+        );
+        assert_eq!(
+            SelectItem::aggregate(AggregateFunction::Count, None)
+                .with_alias("n")
+                .column_name(),
+            "n"
+        );
+        assert!(!Query::new(Source::Logs).is_aggregate());
+    }
+
     #[test]
     fn test_condition_display() {
         let condition = Condition {
@@ -324,6 +886,40 @@ mod tests {
         assert_eq!(condition.to_string(), "service CONTAINS 'api'");
     }
 
+    #[test]
+    fn test_set_range_and_null_display() {
+        let clause = WhereClause::In {
+            field: "service".to_string(),
+            values: vec![Value::String("api".to_string()), Value::Integer(1)],
+            negated: true,
+        };
+        assert_eq!(clause.to_string(), "service NOT IN ('api', 1)");
+
+        let clause = WhereClause::Between {
+            field: "duration_ms".to_string(),
+            low: Value::Integer(10),
+            high: Value::Float(20.5),
+            negated: false,
+        };
+        assert_eq!(clause.to_string(), "duration_ms BETWEEN 10 AND 20.5");
+
+        let clause = WhereClause::Not(Box::new(WhereClause::Combined {
+            left: Box::new(WhereClause::IsNull {
+                field: "trace_id".to_string(),
+                negated: false,
+            }),
+            operator: LogicalOp::Or,
+            right: Box::new(WhereClause::IsNull {
+                field: "span_id".to_string(),
+                negated: true,
+            }),
+        }));
+        assert_eq!(
+            clause.to_string(),
+            "NOT (trace_id IS NULL OR span_id IS NOT NULL)"
+        );
+    }
+
     #[test]
     fn test_combined_where_clause() {
         let clause = WhereClause::Combined {
@@ -0,0 +1,790 @@
+//! In-process query result cache.
+//!
+//! Dashboards re-run the same queries every few seconds. [`QueryCache`] keeps
+//! their results for a short time, keyed by the normalized query with its
+//! relative times resolved against the current time rounded down to the TTL,
+//! so that identical queries made within the same TTL window share an entry.
+//!
+//! Time series (queries grouped by `bucket(timestamp, ...)` with a `SINCE`)
+//! are also cached per bucket: buckets that ended before the query ran are kept
+//! for the series TTL, and later queries over an overlapping range only
+//! aggregate the buckets that are not cached yet, such as the newest one.
+//!
+//! Entries are sized in cells, estimated as rows × columns. The cache holds at
+//! most a budget of cells in total, and results larger than the per-entry cap
+//! are not cached at all.
+
+use super::aggregate::{bucket_start, GroupQuery, GroupQueryResult};
+use super::ast::{
+    ComparisonOp, Condition, DurationLiteral, Expr, LogicalOp, Query, Source, Value, WhereClause,
+};
+use super::executor::{
+    bucket_key, ensure_bound, execute_query, execute_query_with_cursor, finish_grouped_query,
+    group_query, is_record_time, query_groups, resolve_times, store_group, timestamp_value,
+    ExecutionError, QueryData, QueryResult, QueryStores,
+};
+use super::fields::builtin_fields;
+use crate::storage::Cursor;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Maximum number of buckets kept for a single time series.
+const MAX_SERIES_BUCKETS: usize = 10_000;
+
+/// How many times longer than results completed buckets are cached by default.
+/// Only late records change a completed bucket.
+const SERIES_TTL_FACTOR: u32 = 10;
+
+/// Default maximum number of cells held by the cache.
+pub const DEFAULT_MAX_CELLS: usize = 10_000_000;
+
+/// Default maximum number of cells in a single cached result or time series.
+pub const DEFAULT_MAX_ENTRY_CELLS: usize = 100_000;
+
+/// How a result was obtained, reported in query responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    /// The whole result was served from the cache.
+    Hit,
+    /// Completed time buckets were served from the cache and the remaining
+    /// buckets were computed.
+    Partial,
+    /// The result was computed and cached.
+    Miss,
+    /// The result was computed without using the cache (e.g. cursor pages and
+    /// results too large to cache).
+    Bypass,
+}
+
+/// A cached result.
+struct ResultEntry {
+    value: Arc<dyn Any + Send + Sync>,
+    cells: usize,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+/// The cached rows of a completed bucket, possibly none.
+struct SeriesBucket {
+    rows: Vec<Vec<serde_json::Value>>,
+    cells: usize,
+    expires_at: Instant,
+}
+
+/// Cached buckets of a time series, by bucket start.
+struct SeriesEntry {
+    buckets: BTreeMap<DateTime<Utc>, SeriesBucket>,
+    cells: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    results: HashMap<String, ResultEntry>,
+    series: HashMap<String, SeriesEntry>,
+    /// Cells held by all results and series.
+    cells: usize,
+    /// Logical clock for least-recently-used eviction.
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn len(&self) -> usize {
+        self.results.len() + self.series.len()
+    }
+
+    /// Drops expired results and buckets and recounts the cells, then drops the
+    /// least recently used entries until at most `max_entries` entries and
+    /// `max_cells` cells remain.
+    fn evict(&mut self, max_entries: usize, max_cells: usize, now: Instant) {
+        self.results.retain(|_, entry| entry.expires_at > now);
+        self.series.retain(|_, entry| {
+            entry.buckets.retain(|_, bucket| bucket.expires_at > now);
+            entry.cells = entry.buckets.values().map(|bucket| bucket.cells).sum();
+            !entry.buckets.is_empty()
+        });
+        self.cells = self
+            .results
+            .values()
+            .map(|entry| entry.cells)
+            .sum::<usize>()
+            + self.series.values().map(|entry| entry.cells).sum::<usize>();
+        while self.len() > max_entries || self.cells > max_cells {
+            let result = self
+                .results
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, entry)| (entry.last_used, key.clone()));
+            let series = self
+                .series
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, entry)| (entry.last_used, key.clone()));
+            match (result, series) {
+                (Some(result), Some(series)) if series.0 < result.0 => {
+                    self.remove_series(&series.1);
+                }
+                (Some((_, key)), _) => {
+                    if let Some(entry) = self.results.remove(&key) {
+                        self.cells -= entry.cells;
+                    }
+                }
+                (None, Some((_, key))) => {
+                    self.remove_series(&key);
+                }
+                (None, None) => break,
+            }
+        }
+    }
+
+    fn remove_series(&mut self, key: &str) {
+        if let Some(entry) = self.series.remove(key) {
+            self.cells -= entry.cells;
+        }
+    }
+}
+
+/// An in-process cache of query results with a TTL and bounds on the number
+/// of entries and the cells they hold.
+///
+/// Results expire after the TTL, and the buckets of cached time series after the
+/// series TTL. Each time series counts as one entry; when the cache is full, the
+/// least recently used entries are evicted. Results with more than the
+/// per-entry cap of cells are not cached, and time series drop their oldest
+/// buckets to stay within it.
+pub struct QueryCache {
+    ttl: Duration,
+    series_ttl: Duration,
+    max_entries: usize,
+    max_cells: usize,
+    max_entry_cells: usize,
+    entries: Mutex<Entries>,
+}
+
+impl QueryCache {
+    /// Creates a cache whose results expire after `ttl`, holding at most
+    /// `max_entries` entries. Completed buckets expire after ten times `ttl`.
+    ///
+    /// The cache holds at most [`DEFAULT_MAX_CELLS`] cells, and at most
+    /// [`DEFAULT_MAX_ENTRY_CELLS`] cells per entry.
+    #[must_use]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            series_ttl: ttl.saturating_mul(SERIES_TTL_FACTOR),
+            max_entries,
+            max_cells: DEFAULT_MAX_CELLS,
+            max_entry_cells: DEFAULT_MAX_ENTRY_CELLS,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Sets the maximum number of cells held by the cache.
+    #[must_use]
+    pub fn with_max_cells(mut self, max_cells: usize) -> Self {
+        self.max_cells = max_cells;
+        self
+    }
+
+    /// Sets the maximum number of cells in a single cached result or time
+    /// series.
+    #[must_use]
+    pub fn with_max_entry_cells(mut self, max_entry_cells: usize) -> Self {
+        self.max_entry_cells = max_entry_cells;
+        self
+    }
+
+    /// Sets how long completed buckets of time series are cached.
+    #[must_use]
+    pub fn with_series_ttl(mut self, series_ttl: Duration) -> Self {
+        self.series_ttl = series_ttl;
+        self
+    }
+
+    /// Returns how long results are cached.
+    #[must_use]
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns how long completed buckets of time series are cached.
+    #[must_use]
+    pub fn series_ttl(&self) -> Duration {
+        self.series_ttl
+    }
+
+    /// Returns the maximum number of entries.
+    #[must_use]
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Returns the maximum number of cells held by the cache.
+    #[must_use]
+    pub fn max_cells(&self) -> usize {
+        self.max_cells
+    }
+
+    /// Returns the maximum number of cells in a single entry.
+    #[must_use]
+    pub fn max_entry_cells(&self) -> usize {
+        self.max_entry_cells
+    }
+
+    /// Returns the number of cells held by the cache.
+    #[must_use]
+    pub fn cells(&self) -> usize {
+        self.lock().cells
+    }
+
+    /// Returns the number of cached results and time series.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if nothing is cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        *self.lock() = Entries::default();
+    }
+
+    /// Returns the cached value for `key`, if it has not expired.
+    #[must_use]
+    pub fn get<T: Clone + Send + Sync + 'static>(&self, key: &str) -> Option<T> {
+        let mut entries = self.lock();
+        let clock = entries.tick();
+        let entry = entries.results.get_mut(key)?;
+        if entry.expires_at <= Instant::now() {
+            return None;
+        }
+        entry.last_used = clock;
+        entry.value.downcast_ref::<T>().cloned()
+    }
+
+    /// Caches `value`, estimated to hold `cells` cells, under `key` for the TTL.
+    ///
+    /// Returns `false` without caching it if `cells` is over the per-entry cap.
+    pub fn insert<T: Send + Sync + 'static>(
+        &self,
+        key: impl Into<String>,
+        value: T,
+        cells: usize,
+    ) -> bool {
+        if cells > self.max_entry_cells {
+            return false;
+        }
+        let now = Instant::now();
+        let mut entries = self.lock();
+        let last_used = entries.tick();
+        entries.results.insert(
+            key.into(),
+            ResultEntry {
+                value: Arc::new(value),
+                cells,
+                expires_at: now + self.ttl,
+                last_used,
+            },
+        );
+        entries.evict(self.max_entries, self.max_cells, now);
+        true
+    }
+
+    /// Rounds `now` down to a multiple of the TTL (at least one second), the
+    /// start of the window in which identical queries share a cached result.
+    #[must_use]
+    pub fn align(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let step = DurationLiteral::from_secs(self.ttl.as_secs().max(1));
+        bucket_start(now, &step)
+    }
+
+    /// Returns the unexpired cached buckets of a series in `start..end`.
+    fn series_buckets(
+        &self,
+        key: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BTreeMap<DateTime<Utc>, Vec<Vec<serde_json::Value>>> {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        let clock = entries.tick();
+        let Some(series) = entries.series.get_mut(key) else {
+            return BTreeMap::new();
+        };
+        series.last_used = clock;
+        series
+            .buckets
+            .range(start..end)
+            .filter(|(_, bucket)| bucket.expires_at > now)
+            .map(|(start, bucket)| (*start, bucket.rows.clone()))
+            .collect()
+    }
+
+    /// Adds completed buckets to a series for the series TTL, dropping its
+    /// oldest buckets beyond [`MAX_SERIES_BUCKETS`] or the per-entry cap of
+    /// cells.
+    fn insert_series_buckets(
+        &self,
+        key: &str,
+        buckets: BTreeMap<DateTime<Utc>, Vec<Vec<serde_json::Value>>>,
+    ) {
+        if buckets.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let expires_at = now + self.series_ttl;
+        let mut entries = self.lock();
+        let last_used = entries.tick();
+        let series = entries
+            .series
+            .entry(key.to_string())
+            .or_insert_with(|| SeriesEntry {
+                buckets: BTreeMap::new(),
+                cells: 0,
+                last_used,
+            });
+        series.last_used = last_used;
+        series
+            .buckets
+            .extend(buckets.into_iter().map(|(start, rows)| {
+                let cells = rows_cells(&rows);
+                (
+                    start,
+                    SeriesBucket {
+                        rows,
+                        cells,
+                        expires_at,
+                    },
+                )
+            }));
+        series.cells = series.buckets.values().map(|bucket| bucket.cells).sum();
+        while series.buckets.len() > MAX_SERIES_BUCKETS || series.cells > self.max_entry_cells {
+            let Some((_, bucket)) = series.buckets.pop_first() else {
+                break;
+            };
+            series.cells -= bucket.cells;
+        }
+        entries.evict(self.max_entries, self.max_cells, now);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // The entries are consistent after every operation, so a panic while
+        // holding the lock leaves nothing half-updated.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryCache")
+            .field("ttl", &self.ttl)
+            .field("series_ttl", &self.series_ttl)
+            .field("max_entries", &self.max_entries)
+            .field("max_cells", &self.max_cells)
+            .field("max_entry_cells", &self.max_entry_cells)
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Executes a query like [`execute_query_with_cursor`], serving it from `cache`
+/// when possible.
+///
+/// Relative times are resolved against the current time rounded down to the
+/// cache's TTL. Pages after a cursor are never cached.
+///
+/// # Errors
+///
+/// Returns an error if the query cannot be executed. Errors are not cached.
+pub fn execute_query_cached(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    stores: QueryStores<'_>,
+    cache: &QueryCache,
+) -> Result<(QueryResult, CacheStatus), ExecutionError> {
+    if cursor.is_some() {
+        let result = execute_query_with_cursor(query, cursor, stores)?;
+        return Ok((result, CacheStatus::Bypass));
+    }
+    ensure_bound(query)?;
+    let now = Utc::now();
+    let query = resolve_times(query, cache.align(now)).into_owned();
+    let key = serde_json::to_string(&query).unwrap_or_else(|_| query.to_string());
+    if let Some(result) = cache.get::<QueryResult>(&key) {
+        return Ok((result, CacheStatus::Hit));
+    }
+
+    let (result, status) = match series_range(&query, now) {
+        Some(range) => execute_series(&query, &range, stores, cache)?,
+        None => (execute_query(&query, stores)?, CacheStatus::Miss),
+    };
+    let cached = cache.insert(key, result.clone(), result_cells(&result));
+    let status = match status {
+        CacheStatus::Miss if !cached => CacheStatus::Bypass,
+        status => status,
+    };
+    Ok((result, status))
+}
+
+/// Estimates the cells of `count` records from `source`, one per built-in
+/// field.
+#[must_use]
+pub fn record_cells(source: &Source, count: usize) -> usize {
+    count.saturating_mul(builtin_fields(source).len())
+}
+
+/// Estimates the cells of a query result.
+fn result_cells(result: &QueryResult) -> usize {
+    match &result.data {
+        QueryData::Logs { logs } => record_cells(&Source::Logs, logs.len()),
+        QueryData::Metrics { metrics } => record_cells(&Source::Metrics, metrics.len()),
+        QueryData::Spans { spans } => record_cells(&Source::Traces, spans.len()),
+        QueryData::Rows { rows, .. } => rows_cells(rows),
+    }
+}
+
+/// Counts the cells of result rows.
+fn rows_cells(rows: &[Vec<serde_json::Value>]) -> usize {
+    rows.iter().map(Vec::len).sum()
+}
+
+/// The buckets of a time series query that can be cached.
+struct SeriesRange {
+    /// The bucketed field.
+    field: String,
+    /// The bucket width.
+    interval: DurationLiteral,
+    /// Start of the first bucket that lies entirely within the time range.
+    start: DateTime<Utc>,
+    /// End of the last bucket that lies entirely within the time range and
+    /// ended before the query ran.
+    end: DateTime<Utc>,
+}
+
+/// Returns the cacheable buckets of a query grouped by a bucket of the record
+/// time, or `None` if the query is not such a query or has no completed bucket.
+fn series_range(query: &Query, now: DateTime<Utc>) -> Option<SeriesRange> {
+    let (field, interval) = bucket_key(query)?;
+    if !is_record_time(&query.source, field) {
+        return None;
+    }
+    let since = query.since.as_ref().and_then(timestamp_value)?;
+    let until = query
+        .until
+        .as_ref()
+        .and_then(timestamp_value)
+        .map_or(now, |until| until.min(now));
+
+    let width = chrono::Duration::seconds(i64::try_from(interval.as_secs()).ok()?.max(1));
+    let mut start = bucket_start(since, &interval);
+    if start < since {
+        start += width;
+    }
+    let end = bucket_start(until, &interval);
+    (start < end).then(|| SeriesRange {
+        field: field.to_string(),
+        interval,
+        start,
+        end,
+    })
+}
+
+/// Executes a time series query, reusing the cached completed buckets and
+/// caching the buckets completed since.
+fn execute_series(
+    query: &Query,
+    range: &SeriesRange,
+    stores: QueryStores<'_>,
+    cache: &QueryCache,
+) -> Result<(QueryResult, CacheStatus), ExecutionError> {
+    let group = group_query(query)?;
+    let store_group = store_group(&group, true);
+    let series_key = series_key(query, &store_group);
+    let width = chrono::Duration::seconds(
+        i64::try_from(range.interval.as_secs())
+            .unwrap_or(i64::MAX)
+            .max(1),
+    );
+
+    // Use the first run of consecutive cached buckets, so that the remaining
+    // buckets can be read with a single store query
+    let cached = cache.series_buckets(&series_key, range.start, range.end);
+    let cached_start = cached.keys().next().copied().unwrap_or(range.start);
+    let mut cached_end = cached_start;
+    while cached.contains_key(&cached_end) {
+        cached_end += width;
+    }
+
+    let mut fresh_query = query.clone();
+    if cached_end > cached_start {
+        let excluded = WhereClause::Not(Box::new(WhereClause::Combined {
+            left: Box::new(time_condition(
+                &range.field,
+                ComparisonOp::GtEq,
+                cached_start,
+            )),
+            operator: LogicalOp::And,
+            right: Box::new(time_condition(&range.field, ComparisonOp::Lt, cached_end)),
+        }));
+        fresh_query.where_clause = Some(match fresh_query.where_clause.take() {
+            Some(clause) => WhereClause::Combined {
+                left: Box::new(WhereClause::Grouped(Box::new(clause))),
+                operator: LogicalOp::And,
+                right: Box::new(excluded),
+            },
+            None => excluded,
+        });
+    }
+    let fresh = query_groups(&fresh_query, &store_group, stores)?;
+
+    // Cache the completed buckets that were just aggregated, including empty ones
+    let bucket_index = store_group.columns.iter().position(|column| {
+        matches!(column.expr, Expr::Bucket { .. }) && query.group_by.contains(&column.expr)
+    });
+    let mut completed: BTreeMap<DateTime<Utc>, Vec<Vec<serde_json::Value>>> = BTreeMap::new();
+    let mut bucket = range.start;
+    while bucket < range.end {
+        if bucket < cached_start || bucket >= cached_end {
+            completed.insert(bucket, Vec::new());
+        }
+        bucket += width;
+    }
+    if let Some(index) = bucket_index {
+        for row in &fresh.rows {
+            let bucket = row[index]
+                .as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|t| t.with_timezone(&Utc));
+            if let Some(rows) = bucket.and_then(|bucket| completed.get_mut(&bucket)) {
+                rows.push(row.clone());
+            }
+        }
+    }
+    cache.insert_series_buckets(&series_key, completed);
+
+    let status = if cached_end > cached_start {
+        CacheStatus::Partial
+    } else {
+        CacheStatus::Miss
+    };
+    let mut rows = fresh.rows;
+    rows.extend(
+        cached
+            .range(cached_start..cached_end)
+            .flat_map(|(_, rows)| rows.iter().cloned()),
+    );
+    let result = GroupQueryResult {
+        total_count: rows.len(),
+        rows,
+    };
+    Ok((finish_grouped_query(query, &group, result)?, status))
+}
+
+/// Identifies a time series independently of its time range and of what is
+/// done with its rows after aggregation.
+fn series_key(query: &Query, group: &GroupQuery) -> String {
+    serde_json::json!({
+        "source": query.source,
+        "where": query.where_clause,
+        "columns": group.columns,
+        "group_by": group.group_by,
+    })
+    .to_string()
+}
+
+fn time_condition(field: &str, operator: ComparisonOp, time: DateTime<Utc>) -> WhereClause {
+    WhereClause::Condition(Condition::new(
+        field,
+        operator,
+        Value::String(time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LogEntry, LogLevel};
+    use crate::query::{parse_query, QueryData};
+    use crate::storage::{
+        InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore, LogStore, QueryLimits,
+    };
+
+    fn log_at(time: DateTime<Utc>) -> LogEntry {
+        let mut log = LogEntry::new(LogLevel::Error, "Request failed", "api");
+        log.timestamp = time;
+        log
+    }
+
+    fn total(result: &QueryResult) -> i64 {
+        let QueryData::Rows { rows, .. } = &result.data else {
+            panic!("expected rows");
+        };
+        rows.iter().map(|row| row[1].as_i64().unwrap()).sum()
+    }
+
+    #[test]
+    fn test_cached_result_hit() {
+        let logs = InMemoryLogStore::new();
+        let metrics = InMemoryMetricStore::new();
+        let traces = InMemoryTraceStore::new();
+        let limits = QueryLimits::default();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &limits,
+        };
+        let cache = QueryCache::new(Duration::from_mins(1), 10);
+        logs.insert(log_at(Utc::now())).unwrap();
+
+        let query =
+            parse_query("SELECT * FROM logs WHERE level = 'error' SINCE now() - 1h").unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!((result.total_count, status), (1, CacheStatus::Miss));
+
+        // New records are not seen until the result expires
+        logs.insert(log_at(Utc::now())).unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!((result.total_count, status), (1, CacheStatus::Hit));
+
+        let cursor = Cursor::new(0, "");
+        let (_, status) = execute_query_cached(&query, Some(&cursor), stores, &cache).unwrap();
+        assert_eq!(status, CacheStatus::Bypass);
+    }
+
+    #[test]
+    fn test_completed_buckets_cached() {
+        let logs = InMemoryLogStore::new();
+        let metrics = InMemoryMetricStore::new();
+        let traces = InMemoryTraceStore::new();
+        let limits = QueryLimits::default();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &limits,
+        };
+        // Results expire immediately, so only completed buckets are reused
+        let cache = QueryCache::new(Duration::ZERO, 10).with_series_ttl(Duration::from_mins(1));
+        let old = Utc::now() - chrono::Duration::minutes(30);
+        logs.insert(log_at(old)).unwrap();
+        logs.insert(log_at(Utc::now())).unwrap();
+
+        let query = parse_query(
+            "SELECT bucket(timestamp, 10m) AS time, count(*) FROM logs \
+             SINCE now() - 1h GROUP BY bucket(timestamp, 10m)",
+        )
+        .unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(total(&result), 2);
+        let uncached = execute_query(&query, stores).unwrap();
+        assert_eq!(result.data.len(), uncached.data.len());
+
+        // A late record in a completed bucket is not seen; the newest bucket is
+        // recomputed
+        logs.insert(log_at(old)).unwrap();
+        logs.insert(log_at(Utc::now())).unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!(status, CacheStatus::Partial);
+        assert_eq!(total(&result), 3);
+
+        // Once the buckets expire, the late record is seen
+        let cache = QueryCache::new(Duration::ZERO, 10);
+        assert_eq!(cache.series_ttl(), Duration::ZERO);
+        execute_query_cached(&query, None, stores, &cache).unwrap();
+        logs.insert(log_at(old)).unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(total(&result), 5);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = QueryCache::new(Duration::from_mins(1), 2);
+        cache.insert("a", 1, 1);
+        cache.insert("b", 2, 1);
+        assert_eq!(cache.get::<i32>("a"), Some(1));
+        cache.insert("c", 3, 1);
+
+        // "b" was used least recently
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get::<i32>("b"), None);
+        assert_eq!(cache.get::<i32>("a"), Some(1));
+        assert_eq!(cache.get::<String>("c"), None);
+
+        let cache = QueryCache::new(Duration::ZERO, 2);
+        cache.insert("a", 1, 1);
+        assert_eq!(cache.get::<i32>("a"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction_by_cells() {
+        let cache = QueryCache::new(Duration::from_mins(1), 10).with_max_cells(10);
+        assert!(cache.insert("a", 1, 4));
+        assert!(cache.insert("b", 2, 4));
+        assert_eq!(cache.cells(), 8);
+
+        // "a" is evicted to make room for "c"
+        assert!(cache.insert("c", 3, 4));
+        assert_eq!(cache.get::<i32>("a"), None);
+        assert_eq!(cache.get::<i32>("b"), Some(2));
+        assert_eq!(cache.cells(), 8);
+
+        // Replacing an entry does not count it twice
+        assert!(cache.insert("c", 4, 2));
+        assert_eq!(cache.cells(), 6);
+    }
+
+    #[test]
+    fn test_oversized_result_not_cached() {
+        let logs = InMemoryLogStore::new();
+        let metrics = InMemoryMetricStore::new();
+        let traces = InMemoryTraceStore::new();
+        let limits = QueryLimits::default();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &limits,
+        };
+        for _ in 0..3 {
+            logs.insert(log_at(Utc::now())).unwrap();
+        }
+
+        // Three logs are more cells than the cap, one log is not
+        let max_entry_cells = record_cells(&Source::Logs, 2);
+        let cache =
+            QueryCache::new(Duration::from_mins(1), 10).with_max_entry_cells(max_entry_cells);
+        assert!(!cache.insert("a", 1, max_entry_cells + 1));
+        assert!(cache.is_empty());
+
+        let query = parse_query("SELECT * FROM logs SINCE now() - 1h").unwrap();
+        let (result, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!((result.total_count, status), (3, CacheStatus::Bypass));
+        assert!(cache.is_empty());
+
+        let query = parse_query("SELECT * FROM logs SINCE now() - 1h LIMIT 1").unwrap();
+        let (_, status) = execute_query_cached(&query, None, stores, &cache).unwrap();
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.cells(), record_cells(&Source::Logs, 1));
+    }
+}
@@ -0,0 +1,1783 @@
+//! `ClickHouse` SQL generation for the query language.
+//!
+//! Compiles `WhereClause` and `OrderBy` nodes into SQL fragments, and grouped
+//! aggregations into complete statements, for the `ClickHouse` stores. User-supplied values are never interpolated into the SQL text; they are
+//! bound as server-side query parameters (`{p0:String}`) instead.
+//!
+//! The generated predicates mirror the semantics of the in-memory evaluator in
+//! [`super::executor`], which remains the reference implementation.
+
+use super::aggregate::{GroupQuery, GroupQueryResult};
+use super::ast::{
+    AggregateFunction, ComparisonOp, Condition, DurationLiteral, Expr, LogicalOp, OrderBy,
+    SortOrder, Source, Value, WhereClause,
+};
+use super::executor::{
+    attribute_key, attribute_paths, label_key, level_order_from_str, number_to_json,
+    timestamp_value, LEVELS,
+};
+use super::explain::{Backend, BoundParam, ReadEstimate, Statement, StorePlan};
+use super::fields::{
+    builtin_fields, dynamic_field_name, dynamic_kind, FieldInfo, FieldKind, FieldType,
+    MAX_DISCOVERED_FIELDS,
+};
+use crate::storage::QueryLimits;
+use serde::Serialize;
+
+/// A value bound to a server-side query parameter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum SqlParam {
+    /// A `String` parameter.
+    String(String),
+    /// An `Int64` parameter.
+    Int(i64),
+    /// A `Float64` parameter.
+    Float(f64),
+    /// A `Bool` parameter.
+    Bool(bool),
+}
+
+impl SqlParam {
+    /// Returns the `ClickHouse` type name used in the parameter placeholder.
+    #[must_use]
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "String",
+            Self::Int(_) => "Int64",
+            Self::Float(_) => "Float64",
+            Self::Bool(_) => "Bool",
+        }
+    }
+}
+
+/// Collects the parameters referenced by a generated SQL statement.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SqlParams {
+    params: Vec<(String, SqlParam)>,
+}
+
+impl SqlParams {
+    /// Creates an empty parameter list.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a parameter and returns its placeholder (e.g. `{p0:String}`).
+    pub fn push(&mut self, value: SqlParam) -> String {
+        let name = format!("p{}", self.params.len());
+        let placeholder = format!("{{{name}:{}}}", value.type_name());
+        self.params.push((name, value));
+        placeholder
+    }
+
+    /// Returns the registered parameters in order.
+    #[cfg(test)]
+    #[must_use]
+    pub fn as_slice(&self) -> &[(String, SqlParam)] {
+        &self.params
+    }
+
+    /// Returns the registered parameters for a [`StorePlan`].
+    #[must_use]
+    pub fn bound(&self) -> Vec<BoundParam> {
+        self.params
+            .iter()
+            .map(|(name, value)| BoundParam {
+                name: name.clone(),
+                param_type: value.type_name(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    /// Binds all registered parameters to a `ClickHouse` query.
+    pub fn bind(&self, mut query: clickhouse::query::Query) -> clickhouse::query::Query {
+        for (name, value) in &self.params {
+            query = query.param(name, value);
+        }
+        query
+    }
+}
+
+/// How a query field maps onto the table of its source.
+enum Column<'a> {
+    /// The `level` column of the `logs` table.
+    Level,
+    /// A `String` column.
+    Text(&'static str),
+    /// A `String` column where absent values are stored as `''`.
+    OptionalText(&'static str),
+    /// An `Int64` nanosecond timestamp column.
+    Timestamp(&'static str),
+    /// A numeric expression.
+    Number(&'static str),
+    /// A key in the `attributes` map (JSON-encoded values).
+    Attribute(&'a str),
+    /// A key in the `labels` map (plain string values).
+    Label(&'a str),
+}
+
+fn resolve_column<'a>(source: &Source, field: &'a str) -> Column<'a> {
+    let lower = field.to_lowercase();
+
+    match source {
+        Source::Logs => match lower.as_str() {
+            "level" => Column::Level,
+            "service" => Column::Text("service"),
+            "message" => Column::Text("message"),
+            "trace_id" => Column::OptionalText("trace_id"),
+            "span_id" => Column::OptionalText("span_id"),
+            "timestamp" => Column::Timestamp("timestamp"),
+            _ => Column::Attribute(attribute_key(field)),
+        },
+        Source::Metrics => match lower.as_str() {
+            "name" => Column::Text("name"),
+            "type" | "metric_type" => Column::Text("metric_type"),
+            "value" => Column::Number("value"),
+            "timestamp" => Column::Timestamp("timestamp"),
+            _ => Column::Label(label_key(field)),
+        },
+        Source::Traces => match lower.as_str() {
+            "trace_id" => Column::Text("trace_id"),
+            "span_id" => Column::Text("span_id"),
+            "parent_span_id" => Column::OptionalText("parent_span_id"),
+            "name" => Column::Text("name"),
+            "service" => Column::Text("service"),
+            "kind" => Column::Text("span_kind"),
+            "status" => Column::Text("status_code"),
+            "duration_ms" => Column::Number("intDiv(duration_ns, 1000000)"),
+            "timestamp" | "start_time" => Column::Timestamp("start_time"),
+            "end_time" => Column::Timestamp("end_time"),
+            _ => Column::Attribute(attribute_key(field)),
+        },
+    }
+}
+
+/// Compiles a WHERE clause over the table of `source` into a SQL predicate.
+#[must_use]
+pub fn compile_filter(source: &Source, clause: &WhereClause, params: &mut SqlParams) -> String {
+    match clause {
+        WhereClause::Condition(condition) => condition_sql(source, condition, params),
+        WhereClause::Combined {
+            left,
+            operator,
+            right,
+        } => {
+            let left = compile_filter(source, left, params);
+            let right = compile_filter(source, right, params);
+            let op = match operator {
+                LogicalOp::And => "AND",
+                LogicalOp::Or => "OR",
+            };
+            format!("({left} {op} {right})")
+        }
+        WhereClause::Grouped(inner) => format!("({})", compile_filter(source, inner, params)),
+        WhereClause::Not(inner) => format!("NOT ({})", compile_filter(source, inner, params)),
+        WhereClause::In {
+            field,
+            values,
+            negated,
+        } => {
+            let equals: Vec<String> = values
+                .iter()
+                .map(|value| {
+                    let condition = Condition::new(field.as_str(), ComparisonOp::Eq, value.clone());
+                    condition_sql(source, &condition, params)
+                })
+                .collect();
+            negate(&format!("({})", equals.join(" OR ")), *negated)
+        }
+        WhereClause::Between {
+            field,
+            low,
+            high,
+            negated,
+        } => {
+            let low = Condition::new(field.as_str(), ComparisonOp::GtEq, low.clone());
+            let high = Condition::new(field.as_str(), ComparisonOp::LtEq, high.clone());
+            let within = format!(
+                "({} AND {})",
+                condition_sql(source, &low, params),
+                condition_sql(source, &high, params)
+            );
+            negate(&within, *negated)
+        }
+        WhereClause::IsNull { field, negated } => {
+            negate(&null_sql(source, field, params), *negated)
+        }
+    }
+}
+
+fn negate(predicate: &str, negated: bool) -> String {
+    if negated {
+        format!("NOT {predicate}")
+    } else {
+        predicate.to_string()
+    }
+}
+
+/// Absent optional fields are stored as `''`, absent map keys are not stored at
+/// all, and attributes may hold a JSON `null`. Other columns are never null.
+fn null_sql(source: &Source, field: &str, params: &mut SqlParams) -> String {
+    match resolve_column(source, field) {
+        Column::OptionalText(column) => format!("({column} = '')"),
+        Column::Attribute(key) => {
+            let attr = attribute_sql(key, params);
+            format!("(NOT {} OR {} = 'null')", attr.present, attr.json)
+        }
+        Column::Label(key) => {
+            let k = params.push(SqlParam::String(key.to_string()));
+            format!("(NOT mapContains(labels, {k}))")
+        }
+        Column::Level | Column::Text(_) | Column::Timestamp(_) | Column::Number(_) => {
+            FALSE.to_string()
+        }
+    }
+}
+
+/// Compiles ORDER BY keys over the table of `source` into `ORDER BY` terms,
+/// registering attribute and label keys in `params`.
+///
+/// Mirrors the in-memory sort: attributes order numbers numerically before
+/// other values, which order by their text (strings unquoted); absent
+/// attributes and labels sort last in either direction. The caller appends the
+/// default order to break ties.
+#[must_use]
+pub fn compile_order(source: &Source, order_by: &[OrderBy], params: &mut SqlParams) -> Vec<String> {
+    let mut terms = Vec::new();
+    for key in order_by {
+        let direction = match key.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        match resolve_column(source, &key.field) {
+            Column::Level => terms.push(format!(
+                "indexOf([{}], level) {direction}",
+                level_list(&LEVELS)
+            )),
+            Column::Text(column)
+            | Column::OptionalText(column)
+            | Column::Timestamp(column)
+            | Column::Number(column) => terms.push(format!("{column} {direction}")),
+            Column::Attribute(key) => {
+                let AttributeSql { present, json } = attribute_sql(key, params);
+                terms.push(format!("toFloat64OrNull({json}) {direction} NULLS LAST"));
+                terms.push(format!(
+                    "if({present} AND {json} != 'null', \
+                     if(JSONType({json}) = 'String', JSONExtractString({json}), {json}), NULL) \
+                     {direction} NULLS LAST"
+                ));
+            }
+            Column::Label(key) => {
+                let k = params.push(SqlParam::String(key.to_string()));
+                terms.push(format!(
+                    "if(mapContains(labels, {k}), labels[{k}], NULL) {direction} NULLS LAST"
+                ));
+            }
+        }
+    }
+    terms
+}
+
+/// SQL literal for a predicate that never matches.
+const FALSE: &str = "0";
+
+fn condition_sql(source: &Source, condition: &Condition, params: &mut SqlParams) -> String {
+    match resolve_column(source, &condition.field) {
+        Column::Level => level_condition(condition, params),
+        Column::Text(column) => string_condition(column, condition, params),
+        Column::OptionalText(column) => optional_string_condition(column, condition, params),
+        Column::Timestamp(column) => timestamp_condition(column, condition, params),
+        Column::Number(expr) => number_condition(expr, condition, params),
+        Column::Attribute(key) => attribute_condition(key, condition, params),
+        Column::Label(key) => label_condition(key, condition, params),
+    }
+}
+
+fn level_list(levels: &[&str]) -> String {
+    levels
+        .iter()
+        .map(|l| format!("'{l}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn level_condition(condition: &Condition, params: &mut SqlParams) -> String {
+    let Value::String(s) = &condition.value else {
+        return FALSE.to_string();
+    };
+    let value_lower = s.to_lowercase();
+
+    match condition.operator {
+        ComparisonOp::Lt | ComparisonOp::LtEq | ComparisonOp::Gt | ComparisonOp::GtEq => {
+            let Some(val_ord) = level_order_from_str(&value_lower) else {
+                return FALSE.to_string();
+            };
+            let matching: Vec<&str> = LEVELS
+                .iter()
+                .zip(0u8..)
+                .filter(|(_, ord)| match condition.operator {
+                    ComparisonOp::Lt => *ord < val_ord,
+                    ComparisonOp::LtEq => *ord <= val_ord,
+                    ComparisonOp::Gt => *ord > val_ord,
+                    _ => *ord >= val_ord,
+                })
+                .map(|(level, _)| *level)
+                .collect();
+
+            if matching.is_empty() {
+                FALSE.to_string()
+            } else {
+                format!("level IN ({})", level_list(&matching))
+            }
+        }
+        ComparisonOp::Matches => {
+            let p = params.push(SqlParam::String(s.clone()));
+            format!("match(level, {p})")
+        }
+        _ => {
+            let p = params.push(SqlParam::String(value_lower));
+            string_op("level", &condition.operator, &p)
+        }
+    }
+}
+
+/// Builds a string comparison of a column expression with a placeholder.
+///
+/// Case-insensitive callers lower-case both the expression and the value first.
+fn string_op(column: &str, op: &ComparisonOp, placeholder: &str) -> String {
+    match op {
+        ComparisonOp::Eq => format!("{column} = {placeholder}"),
+        ComparisonOp::NotEq => format!("{column} != {placeholder}"),
+        ComparisonOp::Contains => format!("position({column}, {placeholder}) > 0"),
+        ComparisonOp::StartsWith => format!("startsWith({column}, {placeholder})"),
+        ComparisonOp::EndsWith => format!("endsWith({column}, {placeholder})"),
+        ComparisonOp::Matches => format!("match({column}, {placeholder})"),
+        ComparisonOp::Lt => format!("{column} < {placeholder}"),
+        ComparisonOp::LtEq => format!("{column} <= {placeholder}"),
+        ComparisonOp::Gt => format!("{column} > {placeholder}"),
+        ComparisonOp::GtEq => format!("{column} >= {placeholder}"),
+    }
+}
+
+fn string_condition(column: &str, condition: &Condition, params: &mut SqlParams) -> String {
+    let Value::String(s) = &condition.value else {
+        return FALSE.to_string();
+    };
+
+    match condition.operator {
+        // Ordering comparisons and patterns are case-sensitive, like the in-memory evaluator.
+        ComparisonOp::Lt
+        | ComparisonOp::LtEq
+        | ComparisonOp::Gt
+        | ComparisonOp::GtEq
+        | ComparisonOp::Matches => {
+            let p = params.push(SqlParam::String(s.clone()));
+            string_op(column, &condition.operator, &p)
+        }
+        _ => {
+            let p = params.push(SqlParam::String(s.to_lowercase()));
+            string_op(&format!("lowerUTF8({column})"), &condition.operator, &p)
+        }
+    }
+}
+
+/// Optional fields (e.g. `trace_id` on logs) are stored as empty strings when absent.
+fn optional_string_condition(
+    column: &str,
+    condition: &Condition,
+    params: &mut SqlParams,
+) -> String {
+    let matches_missing = matches!(&condition.value, Value::String(s) if s.is_empty())
+        || condition.operator == ComparisonOp::NotEq;
+    let expr = string_condition(column, condition, params);
+
+    if matches_missing {
+        format!("({column} = '' OR {expr})")
+    } else {
+        format!("({column} != '' AND {expr})")
+    }
+}
+
+fn timestamp_condition(column: &str, condition: &Condition, params: &mut SqlParams) -> String {
+    let Some(nanos) = timestamp_value(&condition.value).and_then(|t| t.timestamp_nanos_opt())
+    else {
+        return FALSE.to_string();
+    };
+
+    match numeric_op(&condition.operator) {
+        Some(op) => format!("{column} {op} {}", params.push(SqlParam::Int(nanos))),
+        None => FALSE.to_string(),
+    }
+}
+
+fn number_condition(expr: &str, condition: &Condition, params: &mut SqlParams) -> String {
+    match &condition.value {
+        Value::Integer(i) => match numeric_op(&condition.operator) {
+            Some(op) => format!("{expr} {op} {}", params.push(SqlParam::Int(*i))),
+            None => FALSE.to_string(),
+        },
+        Value::Float(f) => float_comparison(expr, &condition.operator, *f, params)
+            .unwrap_or_else(|| FALSE.to_string()),
+        _ => FALSE.to_string(),
+    }
+}
+
+/// Float equality uses the same epsilon as the in-memory evaluator.
+fn float_comparison(
+    expr: &str,
+    op: &ComparisonOp,
+    value: f64,
+    params: &mut SqlParams,
+) -> Option<String> {
+    let sql_op = numeric_op(op)?;
+    let p = params.push(SqlParam::Float(value));
+
+    Some(match op {
+        ComparisonOp::Eq => format!("abs({expr} - {p}) < {}", f64::EPSILON),
+        ComparisonOp::NotEq => format!("abs({expr} - {p}) >= {}", f64::EPSILON),
+        _ => format!("{expr} {sql_op} {p}"),
+    })
+}
+
+/// An attribute resolved to SQL expressions.
+struct AttributeSql {
+    /// Whether the attribute exists.
+    present: String,
+    /// The JSON text of the attribute value.
+    json: String,
+}
+
+/// Resolves an attribute key the same way as the in-memory evaluator.
+///
+/// Plain keys read the map entry directly. Dotted keys try each candidate from
+/// [`attribute_paths`] in order and extract the nested value from the first map
+/// key that exists; `JSONExtractRaw` yields `''` when the path does not resolve.
+fn attribute_sql(key: &str, params: &mut SqlParams) -> AttributeSql {
+    if !key.contains('.') {
+        let k = params.push(SqlParam::String(key.to_string()));
+        return AttributeSql {
+            present: format!("mapContains(attributes, {k})"),
+            json: format!("attributes[{k}]"),
+        };
+    }
+
+    let mut branches = Vec::new();
+    for (map_key, path) in attribute_paths(key) {
+        let k = params.push(SqlParam::String(map_key.to_string()));
+        let value = if path.is_empty() {
+            format!("attributes[{k}]")
+        } else {
+            let segments: Vec<String> = path
+                .into_iter()
+                .map(|segment| params.push(SqlParam::String(segment.to_string())))
+                .collect();
+            format!("JSONExtractRaw(attributes[{k}], {})", segments.join(", "))
+        };
+        branches.push(format!("mapContains(attributes, {k}), {value}"));
+    }
+
+    let json = format!("multiIf({}, '')", branches.join(", "));
+    AttributeSql {
+        present: format!("({json} != '')"),
+        json,
+    }
+}
+
+/// Attribute values are stored as JSON text in the `attributes` map, so the
+/// predicate checks the JSON type before extracting and comparing the value.
+fn attribute_condition(key: &str, condition: &Condition, params: &mut SqlParams) -> String {
+    let AttributeSql { present, json } = attribute_sql(key, params);
+    json_condition(&present, &json, condition, params)
+}
+
+/// Compares a JSON string value with `s` like the in-memory evaluator compares
+/// a string attribute.
+fn json_string_comparison(
+    extracted: &str,
+    op: &ComparisonOp,
+    s: &str,
+    params: &mut SqlParams,
+) -> String {
+    match op {
+        ComparisonOp::Lt | ComparisonOp::LtEq | ComparisonOp::Gt | ComparisonOp::GtEq => {
+            // Mirrors the in-memory evaluator, which treats these as exact matches.
+            let p = params.push(SqlParam::String(s.to_string()));
+            format!("{extracted} = {p}")
+        }
+        ComparisonOp::Matches => {
+            let p = params.push(SqlParam::String(s.to_string()));
+            format!("match({extracted}, {p})")
+        }
+        _ => {
+            let p = params.push(SqlParam::String(s.to_lowercase()));
+            string_op(&format!("lowerUTF8({extracted})"), op, &p)
+        }
+    }
+}
+
+/// Compares the JSON text `attr` with the condition's value, checking its JSON
+/// type first. `present` tells whether the value exists.
+fn json_condition(
+    present: &str,
+    attr: &str,
+    condition: &Condition,
+    params: &mut SqlParams,
+) -> String {
+    let expr = match &condition.value {
+        Value::String(s) => {
+            let extracted = format!("JSONExtractString({attr})");
+            let compare = json_string_comparison(&extracted, &condition.operator, s, params);
+            format!("JSONType({attr}) = 'String' AND {compare}")
+        }
+        Value::Integer(i) => match numeric_op(&condition.operator) {
+            Some(op) => format!(
+                "JSONType({attr}) IN ('Int64', 'UInt64') AND JSONExtractInt({attr}) {op} {}",
+                params.push(SqlParam::Int(*i))
+            ),
+            None => FALSE.to_string(),
+        },
+        Value::Float(f) => {
+            let extracted = format!("JSONExtractFloat({attr})");
+            match float_comparison(&extracted, &condition.operator, *f, params) {
+                Some(compare) => {
+                    format!("JSONType({attr}) IN ('Int64', 'UInt64', 'Double') AND {compare}")
+                }
+                None => FALSE.to_string(),
+            }
+        }
+        Value::Boolean(b) => {
+            let op = match condition.operator {
+                ComparisonOp::Eq => "=",
+                ComparisonOp::NotEq => "!=",
+                _ => return FALSE.to_string(),
+            };
+            format!(
+                "JSONType({attr}) = 'Bool' AND JSONExtractBool({attr}) {op} {}",
+                params.push(SqlParam::Bool(*b))
+            )
+        }
+        // Relative times are resolved and parameters bound before a query is compiled
+        Value::Time(_) | Value::Param(_) => FALSE.to_string(),
+    };
+
+    map_presence(present, &condition.operator, &expr)
+}
+
+/// Label values are plain strings in the `labels` map.
+fn label_condition(key: &str, condition: &Condition, params: &mut SqlParams) -> String {
+    let k = params.push(SqlParam::String(key.to_string()));
+    let expr = string_condition(&format!("labels[{k}]"), condition, params);
+
+    map_presence(
+        &format!("mapContains(labels, {k})"),
+        &condition.operator,
+        &expr,
+    )
+}
+
+/// Missing map keys only match `!=`, like missing fields in the in-memory evaluator.
+fn map_presence(present: &str, op: &ComparisonOp, expr: &str) -> String {
+    if *op == ComparisonOp::NotEq {
+        format!("(NOT {present} OR ({expr}))")
+    } else {
+        format!("({present} AND {expr})")
+    }
+}
+
+fn numeric_op(op: &ComparisonOp) -> Option<&'static str> {
+    match op {
+        ComparisonOp::Eq => Some("="),
+        ComparisonOp::NotEq => Some("!="),
+        ComparisonOp::Lt => Some("<"),
+        ComparisonOp::LtEq => Some("<="),
+        ComparisonOp::Gt => Some(">"),
+        ComparisonOp::GtEq => Some(">="),
+        _ => None,
+    }
+}
+
+/// SQL for a grouped aggregation, see [`compile_group_query`].
+#[derive(Debug, Clone)]
+pub struct GroupSql {
+    /// Returns the requested page of grouped rows.
+    pub select: String,
+    /// Returns the number of groups.
+    pub count: String,
+    decoders: Vec<Decode>,
+}
+
+/// How an output column is converted from `JSONCompactEachRow` output.
+#[derive(Debug, Clone)]
+enum Decode {
+    /// A plain string.
+    Text,
+    /// JSON text from the `attributes` map.
+    Json,
+    /// An `Int64` nanosecond timestamp.
+    Timestamp,
+    /// A number.
+    Number,
+    /// An array whose elements are decoded alike (`topK`).
+    List(Box<Decode>),
+}
+
+impl Decode {
+    fn decode(&self, value: serde_json::Value) -> serde_json::Value {
+        match (self, value) {
+            (_, serde_json::Value::Null) => serde_json::Value::Null,
+            (Self::List(element), serde_json::Value::Array(values)) => values
+                .into_iter()
+                .map(|value| element.decode(value))
+                .collect(),
+            (Self::Json, serde_json::Value::String(text)) => {
+                serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+            }
+            (Self::Timestamp, value) => value.as_i64().map_or(value, |nanos| {
+                serde_json::json!(chrono::DateTime::from_timestamp_nanos(nanos))
+            }),
+            (Self::Number, value) => value.as_f64().map_or(value, number_to_json),
+            (_, value) => value,
+        }
+    }
+}
+
+impl GroupSql {
+    /// Decodes the `JSONCompactEachRow` output of [`GroupSql::select`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is not a JSON array.
+    pub fn decode_rows(
+        &self,
+        body: &[u8],
+    ) -> Result<Vec<Vec<serde_json::Value>>, serde_json::Error> {
+        body.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let values: Vec<serde_json::Value> = serde_json::from_slice(line)?;
+                Ok(values
+                    .into_iter()
+                    .zip(&self.decoders)
+                    .map(|(value, decode)| decode.decode(value))
+                    .collect())
+            })
+            .collect()
+    }
+}
+
+/// Compiles a grouped aggregation over `table`.
+///
+/// `filter` is the store's `WHERE` clause (including the leading space). Output
+/// columns are aliased `c0`, `c1`, ... so they cannot shadow table columns; HAVING,
+/// ordering and pagination are applied in an outer query over those aliases.
+#[must_use]
+pub fn compile_group_query(
+    source: &Source,
+    table: &str,
+    filter: &str,
+    query: &GroupQuery,
+    params: &mut SqlParams,
+) -> GroupSql {
+    use std::fmt::Write as _;
+
+    let mut exprs = Vec::with_capacity(query.columns.len());
+    let mut decoders = Vec::with_capacity(query.columns.len());
+    for column in &query.columns {
+        let (expr, decode) = match column.expr {
+            Expr::Field(ref field) => key_expr(source, field, params),
+            Expr::Bucket {
+                ref field,
+                interval,
+            } => bucket_expr(source, field, interval),
+            Expr::Aggregate {
+                function,
+                ref field,
+            } => aggregate_expr(source, function, field.as_deref(), params),
+        };
+        exprs.push(expr);
+        decoders.push(decode);
+    }
+    let columns: Vec<String> = exprs
+        .iter()
+        .enumerate()
+        .map(|(i, expr)| format!("{expr} AS c{i}"))
+        .collect();
+
+    let keys: Vec<String> = query
+        .key_indexes()
+        .into_iter()
+        .map(|i| format!("c{i}"))
+        .collect();
+    let mut inner = format!("SELECT {} FROM {table}{filter}", columns.join(", "));
+    if !keys.is_empty() {
+        write!(&mut inner, " GROUP BY {}", keys.join(", ")).unwrap();
+    }
+
+    let having = query
+        .having
+        .as_ref()
+        .map(|clause| format!(" WHERE {}", having_sql(clause, query, &decoders, params)))
+        .unwrap_or_default();
+    // Groups of keys only (e.g. `SELECT DISTINCT`) are counted with `uniqExact`
+    // rather than by materializing every group
+    let key_only = !keys.is_empty()
+        && query.having.is_none()
+        && query
+            .columns
+            .iter()
+            .all(|column| !column.expr.is_aggregate());
+    let count = if key_only {
+        format!(
+            "SELECT uniqExact(tuple({})) FROM {table}{filter}",
+            exprs.join(", ")
+        )
+    } else {
+        format!("SELECT count() FROM ({inner}){having}")
+    };
+
+    let mut select = format!("SELECT * FROM ({inner}){having}");
+    let order: Vec<String> = query
+        .sort_keys()
+        .into_iter()
+        .map(|(i, order)| {
+            let direction = match order {
+                SortOrder::Asc => "ASC",
+                SortOrder::Desc => "DESC",
+            };
+            format!("c{i} {direction} NULLS LAST")
+        })
+        .collect();
+    if !order.is_empty() {
+        write!(&mut select, " ORDER BY {}", order.join(", ")).unwrap();
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    match query.limit {
+        Some(limit) => write!(&mut select, " LIMIT {limit} OFFSET {offset}").unwrap(),
+        None if offset > 0 => write!(&mut select, " OFFSET {offset} ROWS").unwrap(),
+        None => {}
+    }
+
+    GroupSql {
+        select,
+        count,
+        decoders,
+    }
+}
+
+/// Executes a compiled grouped aggregation within `limits`.
+///
+/// # Errors
+///
+/// Returns an error if a query fails or its output cannot be decoded.
+pub async fn fetch_groups(
+    client: &clickhouse::Client,
+    sql: &GroupSql,
+    params: &SqlParams,
+    limits: &QueryLimits,
+) -> Result<GroupQueryResult, clickhouse::error::Error> {
+    let total_count = limits
+        .apply(params.bind(client.query(&sql.count)))
+        .fetch_one::<u64>()
+        .await?;
+
+    let body = limits
+        .apply(params.bind(client.query(&sql.select)))
+        .with_option("output_format_json_quote_64bit_integers", "0")
+        .fetch_bytes("JSONCompactEachRow")?
+        .collect()
+        .await?;
+    let rows = sql
+        .decode_rows(&body)
+        .map_err(|e| clickhouse::error::Error::Custom(e.to_string()))?;
+
+    Ok(GroupQueryResult {
+        rows,
+        total_count: usize::try_from(total_count).unwrap_or(usize::MAX),
+    })
+}
+
+/// Statements listing the fields of a table, compiled by [`compile_fields_query`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldsSql {
+    /// Counts the records having each built-in field and its distinct values.
+    pub builtin: String,
+    /// Lists the attribute or label keys with their count, distinct values and
+    /// JSON types.
+    pub dynamic: String,
+    source: Source,
+}
+
+/// Compiles the statements listing the fields of the records of `source` in
+/// `table` that match `filter` (the store's `WHERE` clause).
+///
+/// Mirrors [`super::fields::collect_fields`]; distinct values are estimated
+/// with `uniq`.
+#[must_use]
+pub fn compile_fields_query(source: &Source, table: &str, filter: &str) -> FieldsSql {
+    let columns: Vec<String> = builtin_fields(source)
+        .iter()
+        .map(|(name, _)| {
+            let (expr, present) = match resolve_column(source, name) {
+                Column::Level => ("level".to_string(), None),
+                Column::Text(column) | Column::Timestamp(column) | Column::Number(column) => {
+                    (column.to_string(), None)
+                }
+                Column::OptionalText(column) => {
+                    (column.to_string(), Some(format!("{column} != ''")))
+                }
+                Column::Attribute(_) | Column::Label(_) => {
+                    ("NULL".to_string(), Some("0".to_string()))
+                }
+            };
+            match present {
+                Some(present) => format!("countIf({present}), uniqIf({expr}, {present})"),
+                None => format!("count(), uniq({expr})"),
+            }
+        })
+        .collect();
+    let builtin = format!("SELECT {} FROM {table}{filter}", columns.join(", "));
+
+    // Attribute values are JSON text; labels are plain strings
+    let (map, value_type) = match source {
+        Source::Metrics => ("labels", "'String'"),
+        Source::Logs | Source::Traces => (
+            "attributes",
+            "if(isValidJSON(field_value), toString(JSONType(field_value)), 'String')",
+        ),
+    };
+    let dynamic = format!(
+        "SELECT field_key, count(), uniq(field_value), groupUniqArray({value_type}) \
+         FROM {table} ARRAY JOIN mapKeys({map}) AS field_key, mapValues({map}) AS field_value\
+         {filter} GROUP BY field_key ORDER BY field_key LIMIT {MAX_DISCOVERED_FIELDS}"
+    );
+
+    FieldsSql {
+        builtin,
+        dynamic,
+        source: source.clone(),
+    }
+}
+
+/// Executes compiled field discovery statements within `limits`.
+///
+/// # Errors
+///
+/// Returns an error if a query fails or its output cannot be decoded.
+pub async fn fetch_fields(
+    client: &clickhouse::Client,
+    sql: &FieldsSql,
+    params: &SqlParams,
+    limits: &QueryLimits,
+) -> Result<Vec<FieldInfo>, clickhouse::error::Error> {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct KeyRow {
+        key: String,
+        count: u64,
+        cardinality: u64,
+        types: Vec<String>,
+    }
+
+    let body = limits
+        .apply(params.bind(client.query(&sql.builtin)))
+        .with_option("output_format_json_quote_64bit_integers", "0")
+        .fetch_bytes("JSONCompactEachRow")?
+        .collect()
+        .await?;
+    let counts: Vec<u64> = body
+        .split(|byte| *byte == b'\n')
+        .find(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .transpose()
+        .map_err(|e| clickhouse::error::Error::Custom(e.to_string()))?
+        .unwrap_or_default();
+
+    let mut fields: Vec<FieldInfo> = builtin_fields(&sql.source)
+        .iter()
+        .zip(counts.chunks(2))
+        .map(|((name, field_type), counts)| FieldInfo {
+            name: (*name).to_string(),
+            kind: FieldKind::Builtin,
+            field_type: *field_type,
+            count: counts[0],
+            cardinality: counts.get(1).copied().unwrap_or_default(),
+        })
+        .collect();
+
+    let rows = limits
+        .apply(params.bind(client.query(&sql.dynamic)))
+        .fetch_all::<KeyRow>()
+        .await?;
+    let kind = dynamic_kind(&sql.source);
+    fields.extend(rows.into_iter().map(|row| {
+        FieldInfo {
+            name: dynamic_field_name(&sql.source, &row.key),
+            kind,
+            field_type: row
+                .types
+                .iter()
+                .map(|name| FieldType::of_json_type(name))
+                .fold(FieldType::Null, FieldType::merge),
+            count: row.count,
+            cardinality: row.cardinality,
+        }
+    }));
+    Ok(fields)
+}
+
+/// Describes the execution of compiled statements, with `ClickHouse`'s own
+/// estimate of the data each one reads.
+///
+/// Estimates come from `EXPLAIN ESTIMATE`, run with the query's `limits`, and
+/// are left out if it fails.
+pub async fn explain_statements(
+    client: &clickhouse::Client,
+    statements: Vec<String>,
+    params: &SqlParams,
+    limits: &QueryLimits,
+) -> StorePlan {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct EstimateRow {
+        #[allow(dead_code)]
+        database: String,
+        #[allow(dead_code)]
+        table: String,
+        parts: u64,
+        rows: u64,
+        marks: u64,
+    }
+
+    let mut explained = Vec::with_capacity(statements.len());
+    for sql in statements {
+        let estimate = limits
+            .apply(params.bind(client.query(&format!("EXPLAIN ESTIMATE {sql}"))))
+            .fetch_all::<EstimateRow>()
+            .await;
+        let estimate = match estimate {
+            Ok(rows) => {
+                Some(
+                    rows.iter()
+                        .fold(ReadEstimate::default(), |total, row| ReadEstimate {
+                            parts: total.parts + row.parts,
+                            rows: total.rows + row.rows,
+                            granules: total.granules + row.marks,
+                        }),
+                )
+            }
+            Err(e) => {
+                tracing::debug!(error = %e, sql = %sql, "Failed to estimate statement");
+                None
+            }
+        };
+        explained.push(Statement { sql, estimate });
+    }
+
+    StorePlan {
+        backend: Backend::ClickHouse,
+        statements: explained,
+        params: params.bound(),
+    }
+}
+
+/// The value of a group key column. Absent values become `NULL`.
+fn key_expr(source: &Source, field: &str, params: &mut SqlParams) -> (String, Decode) {
+    match resolve_column(source, field) {
+        Column::Level => ("level".to_string(), Decode::Text),
+        Column::Text(column) => (column.to_string(), Decode::Text),
+        Column::OptionalText(column) => (format!("nullIf({column}, '')"), Decode::Text),
+        Column::Timestamp(column) => (column.to_string(), Decode::Timestamp),
+        Column::Number(expr) => (format!("toFloat64({expr})"), Decode::Number),
+        Column::Attribute(key) => {
+            let attr = attribute_sql(key, params);
+            (
+                format!("if({}, {}, NULL)", attr.present, attr.json),
+                Decode::Json,
+            )
+        }
+        Column::Label(key) => {
+            let k = params.push(SqlParam::String(key.to_string()));
+            (
+                format!("if(mapContains(labels, {k}), labels[{k}], NULL)"),
+                Decode::Text,
+            )
+        }
+    }
+}
+
+/// The start of the time bucket containing a timestamp column, in nanoseconds.
+///
+/// `toStartOfInterval` aligns buckets to the Unix epoch, like the in-memory
+/// implementation. Fields that are not timestamps have no bucket.
+fn bucket_expr(source: &Source, field: &str, interval: DurationLiteral) -> (String, Decode) {
+    match resolve_column(source, field) {
+        Column::Timestamp(column) => (
+            format!(
+                "toInt64(toUnixTimestamp(toStartOfInterval(toDateTime(intDiv({column}, \
+                 1000000000), 'UTC'), INTERVAL {} SECOND))) * 1000000000",
+                interval.as_secs()
+            ),
+            Decode::Timestamp,
+        ),
+        _ => ("NULL".to_string(), Decode::Timestamp),
+    }
+}
+
+/// The numeric value of a field, or `NULL` if it is absent or not a number.
+fn numeric_expr(source: &Source, field: &str, params: &mut SqlParams) -> String {
+    match resolve_column(source, field) {
+        Column::Number(expr) => format!("toFloat64({expr})"),
+        Column::Attribute(key) => {
+            let AttributeSql { present, json } = attribute_sql(key, params);
+            format!(
+                "if({present} AND JSONType({json}) IN ('Int64', 'UInt64', 'Double'), \
+                 JSONExtractFloat({json}), NULL)"
+            )
+        }
+        _ => "CAST(NULL AS Nullable(Float64))".to_string(),
+    }
+}
+
+fn aggregate_expr(
+    source: &Source,
+    function: AggregateFunction,
+    field: Option<&str>,
+    params: &mut SqlParams,
+) -> (String, Decode) {
+    let Some(field) = field else {
+        return ("count()".to_string(), Decode::Number);
+    };
+
+    let expr = match function {
+        AggregateFunction::Count => format!("count({})", key_expr(source, field, params).0),
+        AggregateFunction::CountDistinct => {
+            format!("uniqExact({})", key_expr(source, field, params).0)
+        }
+        AggregateFunction::Sum => format!("sumOrNull({})", numeric_expr(source, field, params)),
+        AggregateFunction::Avg => format!("avgOrNull({})", numeric_expr(source, field, params)),
+        AggregateFunction::Min => format!("minOrNull({})", numeric_expr(source, field, params)),
+        AggregateFunction::Max => format!("maxOrNull({})", numeric_expr(source, field, params)),
+        AggregateFunction::TopK(k) => {
+            // Approximate, unlike the exact in-memory counts
+            let (expr, decode) = key_expr(source, field, params);
+            return (format!("topK({k})({expr})"), Decode::List(Box::new(decode)));
+        }
+    };
+    (expr, Decode::Number)
+}
+
+/// Compiles HAVING over the aliased output columns. Aggregates are numeric;
+/// group keys are compared like attributes, as the in-memory evaluator sees
+/// them as JSON values. A `NULL` column only matches `!=`, like a missing field
+/// in a WHERE clause.
+fn having_sql(
+    clause: &WhereClause,
+    query: &GroupQuery,
+    decoders: &[Decode],
+    params: &mut SqlParams,
+) -> String {
+    match clause {
+        WhereClause::Condition(condition) => having_condition(condition, query, decoders, params),
+        WhereClause::Combined {
+            left,
+            operator,
+            right,
+        } => {
+            let left = having_sql(left, query, decoders, params);
+            let right = having_sql(right, query, decoders, params);
+            let op = match operator {
+                LogicalOp::And => "AND",
+                LogicalOp::Or => "OR",
+            };
+            format!("({left} {op} {right})")
+        }
+        WhereClause::Grouped(inner) => {
+            format!("({})", having_sql(inner, query, decoders, params))
+        }
+        WhereClause::Not(inner) => format!("NOT ({})", having_sql(inner, query, decoders, params)),
+        WhereClause::In {
+            field,
+            values,
+            negated,
+        } => {
+            let equals: Vec<String> = values
+                .iter()
+                .map(|value| {
+                    let condition = Condition::new(field.as_str(), ComparisonOp::Eq, value.clone());
+                    having_condition(&condition, query, decoders, params)
+                })
+                .collect();
+            negate(&format!("({})", equals.join(" OR ")), *negated)
+        }
+        WhereClause::Between {
+            field,
+            low,
+            high,
+            negated,
+        } => {
+            let low = Condition::new(field.as_str(), ComparisonOp::GtEq, low.clone());
+            let high = Condition::new(field.as_str(), ComparisonOp::LtEq, high.clone());
+            let within = format!(
+                "({} AND {})",
+                having_condition(&low, query, decoders, params),
+                having_condition(&high, query, decoders, params)
+            );
+            negate(&within, *negated)
+        }
+        WhereClause::IsNull { field, negated } => {
+            let null = query
+                .column_index(field)
+                .map_or_else(|| "1".to_string(), |index| format!("isNull(c{index})"));
+            negate(&null, *negated)
+        }
+    }
+}
+
+fn having_condition(
+    condition: &Condition,
+    query: &GroupQuery,
+    decoders: &[Decode],
+    params: &mut SqlParams,
+) -> String {
+    let Some(index) = query.column_index(&condition.field) else {
+        return FALSE.to_string();
+    };
+    let column = format!("c{index}");
+    let present = format!("isNotNull({column})");
+    let number = match (&decoders[index], &condition.value) {
+        (Decode::Number | Decode::Timestamp, _) => column.clone(),
+        // JSON numbers of any type compare as numbers, other values as attributes
+        (Decode::Json, Value::Integer(_) | Value::Float(_)) => {
+            format!("JSONExtractFloat({column})")
+        }
+        (Decode::Json, _) => return json_condition(&present, &column, condition, params),
+        (Decode::Text, value) => {
+            let expr = match value {
+                Value::String(s) => json_string_comparison(&column, &condition.operator, s, params),
+                _ => FALSE.to_string(),
+            };
+            return map_presence(&present, &condition.operator, &expr);
+        }
+        // `topK` arrays match no comparison
+        (Decode::List(_), _) => return FALSE.to_string(),
+    };
+    let compare = match condition.value {
+        Value::Integer(i) => numeric_op(&condition.operator)
+            .map(|op| format!("{number} {op} {}", params.push(SqlParam::Int(i)))),
+        Value::Float(f) => float_comparison(&number, &condition.operator, f, params),
+        Value::String(_) | Value::Boolean(_) | Value::Time(_) | Value::Param(_) => None,
+    };
+    let Some(mut compare) = compare else {
+        return FALSE.to_string();
+    };
+    if matches!(decoders[index], Decode::Json) {
+        compare = format!(
+            "multiIf(isNull({column}), NULL, \
+             JSONType({column}) IN ('Int64', 'UInt64', 'Double'), {compare}, 0)"
+        );
+    }
+    let if_null = u8::from(condition.operator == ComparisonOp::NotEq);
+    format!("ifNull({compare}, {if_null})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{parse_query, SelectItem};
+
+    fn compile(query: &str) -> (String, SqlParams) {
+        let query = parse_query(query).unwrap();
+        let mut params = SqlParams::new();
+        let sql = compile_filter(
+            &query.source,
+            query.where_clause.as_ref().unwrap(),
+            &mut params,
+        );
+        (sql, params)
+    }
+
+    #[test]
+    fn test_compile_level_eq() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE level = 'ERROR'");
+        assert_eq!(sql, "level = {p0:String}");
+        assert_eq!(
+            params.as_slice(),
+            &[("p0".to_string(), SqlParam::String("error".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_compile_level_ordering_uses_severity() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE level >= 'warn'");
+        assert_eq!(sql, "level IN ('warn', 'error', 'fatal')");
+        assert!(params.as_slice().is_empty());
+    }
+
+    #[test]
+    fn test_compile_service_case_insensitive() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE service = 'API'");
+        assert_eq!(sql, "lowerUTF8(service) = {p0:String}");
+        assert_eq!(params.as_slice()[0].1, SqlParam::String("api".to_string()));
+    }
+
+    #[test]
+    fn test_compile_message_contains() {
+        let (sql, _) = compile("SELECT * FROM logs WHERE message CONTAINS 'failed'");
+        assert_eq!(sql, "position(lowerUTF8(message), {p0:String}) > 0");
+    }
+
+    #[test]
+    fn test_compile_combined_and_grouped() {
+        let (sql, params) = compile(
+            "SELECT * FROM logs WHERE (level = 'error' OR level = 'warn') AND service = 'api'",
+        );
+        assert_eq!(
+            sql,
+            "(((level = {p0:String} OR level = {p1:String})) AND lowerUTF8(service) = {p2:String})"
+        );
+        assert_eq!(params.as_slice().len(), 3);
+    }
+
+    #[test]
+    fn test_compile_optional_field() {
+        let (sql, _) = compile("SELECT * FROM logs WHERE trace_id = 'abc'");
+        assert_eq!(
+            sql,
+            "(trace_id != '' AND lowerUTF8(trace_id) = {p0:String})"
+        );
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE trace_id != 'abc'");
+        assert_eq!(sql, "(trace_id = '' OR lowerUTF8(trace_id) != {p0:String})");
+    }
+
+    #[test]
+    fn test_compile_nested_attribute() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE attributes.http.status >= 500");
+        let attr = "multiIf(mapContains(attributes, {p0:String}), attributes[{p0:String}], \
+                    mapContains(attributes, {p1:String}), JSONExtractRaw(attributes[{p1:String}], \
+                    {p2:String}), '')";
+        assert_eq!(
+            sql,
+            format!(
+                "(({attr} != '') AND JSONType({attr}) IN ('Int64', 'UInt64') AND \
+                 JSONExtractInt({attr}) >= {{p3:Int64}})"
+            )
+        );
+        assert_eq!(
+            params.as_slice()[0].1,
+            SqlParam::String("http.status".to_string())
+        );
+        assert_eq!(params.as_slice()[1].1, SqlParam::String("http".to_string()));
+        assert_eq!(
+            params.as_slice()[2].1,
+            SqlParam::String("status".to_string())
+        );
+
+        let (sql, _) = compile("SELECT * FROM traces WHERE user.plan.tier IS NULL");
+        assert!(sql.starts_with(
+            "(NOT (multiIf(mapContains(attributes, {p0:String}), attributes[{p0:String}], \
+             mapContains(attributes, {p1:String}), JSONExtractRaw(attributes[{p1:String}], \
+             {p2:String}, {p3:String}), mapContains(attributes, {p4:String}), \
+             JSONExtractRaw(attributes[{p4:String}], {p5:String}), '') != '') OR "
+        ));
+    }
+
+    #[test]
+    fn test_compile_matches() {
+        let (sql, params) = compile(r"SELECT * FROM logs WHERE message MATCHES 'Timeout \d+'");
+        assert_eq!(sql, "match(message, {p0:String})");
+        assert_eq!(
+            params.as_slice()[0].1,
+            SqlParam::String(r"Timeout \d+".to_string())
+        );
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE level =~ '^err'");
+        assert_eq!(sql, "match(level, {p0:String})");
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE trace_id =~ '^a'");
+        assert_eq!(sql, "(trace_id != '' AND match(trace_id, {p0:String}))");
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE attributes.path =~ '^/api'");
+        assert_eq!(
+            sql,
+            "(mapContains(attributes, {p0:String}) AND JSONType(attributes[{p0:String}]) = \
+             'String' AND match(JSONExtractString(attributes[{p0:String}]), {p1:String}))"
+        );
+
+        let (sql, _) = compile("SELECT * FROM metrics WHERE labels.host MATCHES '^web-'");
+        assert_eq!(
+            sql,
+            "(mapContains(labels, {p0:String}) AND match(labels[{p0:String}], {p1:String}))"
+        );
+
+        let (sql, _) = compile("SELECT * FROM traces WHERE duration_ms =~ '1'");
+        assert_eq!(sql, "0");
+    }
+
+    #[test]
+    fn test_compile_in_and_between() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE service IN ('api', 'Auth')");
+        assert_eq!(
+            sql,
+            "(lowerUTF8(service) = {p0:String} OR lowerUTF8(service) = {p1:String})"
+        );
+        assert_eq!(params.as_slice()[1].1, SqlParam::String("auth".to_string()));
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE attributes.user_id NOT IN (1, 2)");
+        assert_eq!(
+            sql,
+            "NOT ((mapContains(attributes, {p0:String}) AND JSONType(attributes[{p0:String}]) IN \
+             ('Int64', 'UInt64') AND JSONExtractInt(attributes[{p0:String}]) = {p1:Int64}) OR \
+             (mapContains(attributes, {p2:String}) AND JSONType(attributes[{p2:String}]) IN \
+             ('Int64', 'UInt64') AND JSONExtractInt(attributes[{p2:String}]) = {p3:Int64}))"
+        );
+
+        let (sql, params) = compile("SELECT * FROM traces WHERE duration_ms BETWEEN 100 AND 500");
+        assert_eq!(
+            sql,
+            "(intDiv(duration_ns, 1000000) >= {p0:Int64} AND intDiv(duration_ns, 1000000) <= {p1:Int64})"
+        );
+        assert_eq!(params.as_slice()[1].1, SqlParam::Int(500));
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE level NOT BETWEEN 'debug' AND 'warn'");
+        assert_eq!(
+            sql,
+            "NOT (level IN ('debug', 'info', 'warn', 'error', 'fatal') AND \
+             level IN ('trace', 'debug', 'info', 'warn'))"
+        );
+    }
+
+    #[test]
+    fn test_compile_null_checks_and_not() {
+        let (sql, _) = compile("SELECT * FROM logs WHERE trace_id IS NULL");
+        assert_eq!(sql, "(trace_id = '')");
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE attributes.user_id IS NOT NULL");
+        assert_eq!(
+            sql,
+            "NOT (NOT mapContains(attributes, {p0:String}) OR attributes[{p0:String}] = 'null')"
+        );
+
+        let (sql, _) = compile("SELECT * FROM metrics WHERE labels.host IS NULL");
+        assert_eq!(sql, "(NOT mapContains(labels, {p0:String}))");
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE service IS NULL");
+        assert_eq!(sql, "0");
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE NOT (level = 'error' OR service = 'api')");
+        assert_eq!(
+            sql,
+            "NOT (((level = {p0:String} OR lowerUTF8(service) = {p1:String})))"
+        );
+    }
+
+    #[test]
+    fn test_compile_timestamp() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE timestamp >= '2024-01-01T00:00:00Z'");
+        assert_eq!(sql, "timestamp >= {p0:Int64}");
+        assert_eq!(
+            params.as_slice()[0].1,
+            SqlParam::Int(1_704_067_200_000_000_000)
+        );
+
+        let (sql, _) = compile("SELECT * FROM logs WHERE timestamp CONTAINS '2024'");
+        assert_eq!(sql, "0");
+    }
+
+    #[test]
+    fn test_compile_attribute_map_access() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE attributes.user_id = '123'");
+        assert_eq!(
+            sql,
+            "(mapContains(attributes, {p0:String}) AND JSONType(attributes[{p0:String}]) = 'String' \
+             AND lowerUTF8(JSONExtractString(attributes[{p0:String}])) = {p1:String})"
+        );
+        assert_eq!(
+            params.as_slice()[0].1,
+            SqlParam::String("user_id".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compile_attribute_numeric() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE status_code >= 500");
+        assert!(sql.contains("JSONExtractInt(attributes[{p0:String}]) >= {p1:Int64}"));
+        assert_eq!(params.as_slice()[1].1, SqlParam::Int(500));
+    }
+
+    #[test]
+    fn test_compile_attribute_not_eq_matches_missing() {
+        let (sql, _) = compile("SELECT * FROM logs WHERE region != 'eu'");
+        assert!(sql.starts_with("(NOT mapContains(attributes, {p0:String}) OR ("));
+    }
+
+    #[test]
+    fn test_compile_values_are_not_interpolated() {
+        let (sql, params) = compile("SELECT * FROM logs WHERE message = 'x\\' OR 1=1 --'");
+        assert!(!sql.contains("OR 1=1"));
+        assert_eq!(params.as_slice().len(), 1);
+    }
+
+    fn order(query: &str) -> (Vec<String>, SqlParams) {
+        let parsed = parse_query(query).unwrap();
+        let mut params = SqlParams::new();
+        let terms = compile_order(&parsed.source, &parsed.order_by, &mut params);
+        (terms, params)
+    }
+
+    #[test]
+    fn test_compile_order() {
+        let (terms, _) = order("SELECT * FROM logs ORDER BY timestamp ASC");
+        assert_eq!(terms, ["timestamp ASC"]);
+
+        let (terms, _) = order("SELECT * FROM logs ORDER BY level DESC, service ASC");
+        assert_eq!(
+            terms,
+            [
+                "indexOf(['trace', 'debug', 'info', 'warn', 'error', 'fatal'], level) DESC",
+                "service ASC"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_order_attributes_and_labels() {
+        let (terms, params) = order("SELECT * FROM logs ORDER BY attributes.user_id ASC");
+        assert_eq!(
+            terms,
+            [
+                "toFloat64OrNull(attributes[{p0:String}]) ASC NULLS LAST",
+                "if(mapContains(attributes, {p0:String}) AND attributes[{p0:String}] != 'null', \
+                 if(JSONType(attributes[{p0:String}]) = 'String', \
+                 JSONExtractString(attributes[{p0:String}]), attributes[{p0:String}]), NULL) \
+                 ASC NULLS LAST"
+            ]
+        );
+        assert_eq!(
+            params.as_slice()[0].1,
+            SqlParam::String("user_id".to_string())
+        );
+
+        let (terms, _) = order("SELECT * FROM metrics ORDER BY labels.host DESC");
+        assert_eq!(
+            terms,
+            ["if(mapContains(labels, {p0:String}), labels[{p0:String}], NULL) DESC NULLS LAST"]
+        );
+    }
+
+    #[test]
+    fn test_compile_metric_fields() {
+        let (sql, params) = compile(
+            "SELECT * FROM metrics WHERE name = 'cpu_usage' AND value > 90 AND labels.host = 'web-1'",
+        );
+        assert_eq!(
+            sql,
+            "((lowerUTF8(name) = {p0:String} AND value > {p1:Int64}) AND \
+             (mapContains(labels, {p2:String}) AND lowerUTF8(labels[{p2:String}]) = {p3:String}))"
+        );
+        assert_eq!(params.as_slice()[2].1, SqlParam::String("host".to_string()));
+    }
+
+    #[test]
+    fn test_compile_span_fields() {
+        let (sql, params) = compile(
+            "SELECT * FROM traces WHERE duration_ms >= 500 AND kind = 'server' AND status = 'error'",
+        );
+        assert_eq!(
+            sql,
+            "((intDiv(duration_ns, 1000000) >= {p0:Int64} AND lowerUTF8(span_kind) = {p1:String}) \
+             AND lowerUTF8(status_code) = {p2:String})"
+        );
+        assert_eq!(params.as_slice()[0].1, SqlParam::Int(500));
+
+        let (terms, _) = order("SELECT * FROM traces ORDER BY duration_ms DESC");
+        assert_eq!(terms, ["intDiv(duration_ns, 1000000) DESC"]);
+    }
+
+    fn group_by_service() -> GroupQuery {
+        GroupQuery {
+            columns: vec![
+                SelectItem::new("service"),
+                SelectItem::aggregate(AggregateFunction::Count, None).with_alias("errors"),
+                SelectItem::aggregate(AggregateFunction::Avg, Some("attributes.latency_ms")),
+            ],
+            group_by: vec![Expr::Field("service".to_string())],
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn test_compile_group_query() {
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(
+            &Source::Logs,
+            "logs",
+            " WHERE 1=1",
+            &group_by_service(),
+            &mut params,
+        );
+
+        let inner = "SELECT service AS c0, count() AS c1, avgOrNull(if(mapContains(attributes, \
+                     {p0:String}) AND JSONType(attributes[{p0:String}]) IN ('Int64', 'UInt64', \
+                     'Double'), JSONExtractFloat(attributes[{p0:String}]), NULL)) AS c2 \
+                     FROM logs WHERE 1=1 GROUP BY c0";
+        assert_eq!(sql.count, format!("SELECT count() FROM ({inner})"));
+        assert_eq!(
+            sql.select,
+            format!("SELECT * FROM ({inner}) ORDER BY c0 ASC NULLS LAST")
+        );
+        assert_eq!(
+            params.as_slice(),
+            &[("p0".to_string(), SqlParam::String("latency_ms".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_compile_distinct_and_topk() {
+        let mut query = group_by_service();
+        query.columns.truncate(1);
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", " WHERE 1=1", &query, &mut params);
+        assert_eq!(
+            sql.count,
+            "SELECT uniqExact(tuple(service)) FROM logs WHERE 1=1"
+        );
+
+        query.columns.push(SelectItem::aggregate(
+            AggregateFunction::TopK(3),
+            Some("attributes.user"),
+        ));
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", " WHERE 1=1", &query, &mut params);
+        assert!(sql.select.contains(
+            "topK(3)(if(mapContains(attributes, {p0:String}), attributes[{p0:String}], NULL)) AS c1"
+        ));
+        assert!(sql.count.starts_with("SELECT count() FROM ("));
+        assert_eq!(
+            sql.decode_rows(b"[\"api\",[\"\\\"alice\\\"\",\"42\"]]")
+                .unwrap(),
+            vec![vec![
+                serde_json::json!("api"),
+                serde_json::json!(["alice", 42])
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_compile_group_query_with_bucket() {
+        let bucket = Expr::Bucket {
+            field: "timestamp".to_string(),
+            interval: DurationLiteral::new(5, crate::query::DurationUnit::Minutes),
+        };
+        let query = GroupQuery {
+            columns: vec![
+                SelectItem {
+                    expr: bucket.clone(),
+                    alias: None,
+                },
+                SelectItem::aggregate(AggregateFunction::Count, None),
+            ],
+            group_by: vec![bucket],
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", " WHERE 1=1", &query, &mut params);
+
+        assert_eq!(
+            sql.select,
+            "SELECT * FROM (SELECT toInt64(toUnixTimestamp(toStartOfInterval(toDateTime(\
+             intDiv(timestamp, 1000000000), 'UTC'), INTERVAL 300 SECOND))) * 1000000000 AS c0, \
+             count() AS c1 FROM logs WHERE 1=1 GROUP BY c0) ORDER BY c0 ASC NULLS LAST"
+        );
+        assert_eq!(
+            sql.decode_rows(b"[1704103500000000000,3]").unwrap(),
+            vec![vec![
+                serde_json::json!("2024-01-01T10:05:00Z"),
+                serde_json::json!(3)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_compile_group_query_having_order_and_pagination() {
+        let mut query = group_by_service();
+        query.having = Some(
+            parse_query("SELECT * FROM logs WHERE errors > 10")
+                .unwrap()
+                .where_clause
+                .unwrap(),
+        );
+        query.order_by = vec![OrderBy {
+            field: "count(*)".to_string(),
+            order: SortOrder::Desc,
+        }];
+        query.limit = Some(5);
+        query.offset = Some(10);
+
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", "", &query, &mut params);
+
+        assert!(sql
+            .count
+            .ends_with(" GROUP BY c0) WHERE ifNull(c1 > {p1:Int64}, 0)"));
+        assert!(sql.select.ends_with(
+            " WHERE ifNull(c1 > {p1:Int64}, 0) ORDER BY c1 DESC NULLS LAST, c0 ASC NULLS LAST \
+             LIMIT 5 OFFSET 10"
+        ));
+        assert_eq!(params.as_slice()[1].1, SqlParam::Int(10));
+    }
+
+    #[test]
+    fn test_compile_having_with_set_range_and_null() {
+        let mut query = group_by_service();
+        query.having = Some(
+            parse_query(
+                "SELECT * FROM logs WHERE errors BETWEEN 2 AND 5 OR NOT errors IN (7) \
+                 OR errors IS NULL",
+            )
+            .unwrap()
+            .where_clause
+            .unwrap(),
+        );
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", "", &query, &mut params);
+
+        assert!(sql.count.ends_with(
+            " WHERE (((ifNull(c1 >= {p1:Int64}, 0) AND ifNull(c1 <= {p2:Int64}, 0)) OR \
+             NOT ((ifNull(c1 = {p3:Int64}, 0)))) OR isNull(c1))"
+        ));
+    }
+
+    #[test]
+    fn test_compile_having_on_group_keys() {
+        let having = |clause: &str| {
+            parse_query(&format!("SELECT * FROM logs WHERE {clause}"))
+                .unwrap()
+                .where_clause
+        };
+        let mut query = group_by_service();
+        query.having = having("service = 'API' AND service != true");
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", "", &query, &mut params);
+        assert!(sql.count.ends_with(
+            " WHERE ((isNotNull(c0) AND lowerUTF8(c0) = {p1:String}) AND \
+             (NOT isNotNull(c0) OR (0)))"
+        ));
+        assert_eq!(params.as_slice()[1].1, SqlParam::String("api".to_string()));
+
+        // Attribute keys are compared by their JSON type
+        query.columns[0] = SelectItem::new("attributes.region");
+        query.group_by = vec![Expr::Field("attributes.region".to_string())];
+        query.having = having("attributes.region = 'eu' OR attributes.region > 2");
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Logs, "logs", "", &query, &mut params);
+        assert!(sql.count.ends_with(
+            " WHERE ((isNotNull(c0) AND JSONType(c0) = 'String' AND \
+             lowerUTF8(JSONExtractString(c0)) = {p2:String}) OR \
+             ifNull(multiIf(isNull(c0), NULL, JSONType(c0) IN ('Int64', 'UInt64', 'Double'), \
+             JSONExtractFloat(c0) > {p3:Int64}, 0), 0))"
+        ));
+    }
+
+    #[test]
+    fn test_compile_global_aggregate_and_keys() {
+        let query = GroupQuery {
+            columns: vec![
+                SelectItem::aggregate(AggregateFunction::CountDistinct, Some("trace_id")),
+                SelectItem::aggregate(AggregateFunction::Max, Some("duration_ms")),
+                SelectItem::aggregate(AggregateFunction::Sum, Some("name")),
+            ],
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+        let mut params = SqlParams::new();
+        let sql = compile_group_query(&Source::Traces, "spans", "", &query, &mut params);
+
+        assert_eq!(
+            sql.select,
+            "SELECT * FROM (SELECT uniqExact(trace_id) AS c0, \
+             maxOrNull(toFloat64(intDiv(duration_ns, 1000000))) AS c1, \
+             sumOrNull(CAST(NULL AS Nullable(Float64))) AS c2 FROM spans)"
+        );
+
+        let mut params = SqlParams::new();
+        assert_eq!(
+            key_expr(&Source::Logs, "trace_id", &mut params).0,
+            "nullIf(trace_id, '')"
+        );
+        assert_eq!(
+            key_expr(&Source::Metrics, "labels.host", &mut params).0,
+            "if(mapContains(labels, {p0:String}), labels[{p0:String}], NULL)"
+        );
+    }
+
+    #[test]
+    fn test_decode_group_rows() {
+        let sql = GroupSql {
+            select: String::new(),
+            count: String::new(),
+            decoders: vec![
+                Decode::Text,
+                Decode::Json,
+                Decode::Timestamp,
+                Decode::Number,
+            ],
+        };
+        let body = b"[\"api\",\"42\",1700000000000000000,3]\n[null,null,null,2.5]\n";
+
+        let rows = sql.decode_rows(body).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    serde_json::json!("api"),
+                    serde_json::json!(42),
+                    serde_json::json!("2023-11-14T22:13:20Z"),
+                    serde_json::json!(3),
+                ],
+                vec![
+                    serde_json::Value::Null,
+                    serde_json::Value::Null,
+                    serde_json::Value::Null,
+                    serde_json::json!(2.5),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_fields_query() {
+        let sql = compile_fields_query(&Source::Traces, "spans", " WHERE 1=1");
+        assert!(sql.builtin.starts_with(
+            "SELECT count(), uniq(start_time), count(), uniq(end_time), count(), uniq(trace_id)"
+        ));
+        assert!(sql.builtin.contains(
+            "countIf(parent_span_id != ''), uniqIf(parent_span_id, parent_span_id != '')"
+        ));
+        assert!(sql
+            .builtin
+            .ends_with("uniq(intDiv(duration_ns, 1000000)) FROM spans WHERE 1=1"));
+        assert!(sql.dynamic.contains(
+            "FROM spans ARRAY JOIN mapKeys(attributes) AS field_key, \
+             mapValues(attributes) AS field_value WHERE 1=1 GROUP BY field_key"
+        ));
+        assert!(sql.dynamic.contains("JSONType(field_value)"));
+
+        let sql = compile_fields_query(&Source::Metrics, "metrics", "");
+        assert!(sql.builtin.contains("uniq(metric_type)"));
+        assert!(sql.dynamic.contains("groupUniqArray('String')"));
+        assert!(sql.dynamic.contains("mapKeys(labels)"));
+    }
+}
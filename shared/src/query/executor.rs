@@ -1,24 +1,43 @@
 //! Query execution engine.
 //!
-//! Executes parsed SQL-like queries against the log store.
+//! Executes parsed SQL-like queries against the log, metric and trace stores.
 
+use super::aggregate::{fill_buckets, finish_groups, key_value, GroupQuery, GroupQueryResult};
 use super::ast::{
-    ComparisonOp, Condition, LogicalOp, OrderBy, Query, SortOrder, Source, Value, WhereClause,
+    AggregateFunction, ComparisonOp, Condition, DurationLiteral, Expr, LogicalOp, OrderBy, Query,
+    SelectItem, SortOrder, Source, Value, WhereClause,
 };
-use crate::models::{LogEntry, LogLevel};
-use crate::storage::{LogQuery, LogQueryResult, LogStore, LogStoreError};
+use super::parser::{closest, parse_aggregate};
+use crate::models::{LogEntry, LogLevel, Metric, Span};
+use crate::storage::{
+    Cursor, LimitError, LogQuery, LogQueryResult, LogStore, LogStoreError, MetricQuery,
+    MetricQueryResult, MetricStore, MetricStoreError, QueryLimits, SpanQueryResult, TraceQuery,
+    TraceStore, TraceStoreError,
+};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
 use thiserror::Error;
 
 /// Errors that can occur during query execution.
 #[derive(Debug, Error)]
 pub enum ExecutionError {
     /// The query source is not supported.
-    #[error("Unsupported query source: {0}. Only 'logs' is currently supported.")]
+    #[error("Unsupported query source: {0}")]
     UnsupportedSource(String),
 
     /// The field is not recognized.
-    #[error("Unknown field: '{0}'")]
-    UnknownField(String),
+    #[error("Unknown field: '{field}'{}", did_you_mean(suggestion.as_deref()))]
+    UnknownField {
+        /// The unrecognized field.
+        field: String,
+        /// A field the query has that it most likely misspells.
+        suggestion: Option<String>,
+    },
 
     /// Type mismatch in comparison.
     #[error("Type mismatch: cannot compare {field} with {value_type}")]
@@ -29,21 +48,171 @@ pub enum ExecutionError {
         value_type: String,
     },
 
+    /// The query mixes grouped and ungrouped columns or misuses HAVING.
+    #[error("Invalid aggregation: {0}")]
+    InvalidAggregation(String),
+
+    /// A pagination cursor was used with a query that cannot be paginated by cursor.
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(String),
+
+    /// The query has a `$name` parameter that was not bound with [`Query::bind`].
+    #[error("Unbound parameter: '${0}'")]
+    UnboundParameter(String),
+
     /// Storage error during execution.
     #[error("Storage error: {0}")]
     StorageError(#[from] LogStoreError),
+
+    /// Metric storage error during execution.
+    #[error("Storage error: {0}")]
+    MetricStorageError(#[from] MetricStoreError),
+
+    /// Trace storage error during execution.
+    #[error("Storage error: {0}")]
+    TraceStorageError(#[from] TraceStoreError),
+}
+
+impl ExecutionError {
+    /// Returns the limit error if the query was stopped by its [`QueryLimits`].
+    #[must_use]
+    pub fn limit(&self) -> Option<&LimitError> {
+        match self {
+            Self::StorageError(LogStoreError::Limit(e))
+            | Self::MetricStorageError(MetricStoreError::Limit(e))
+            | Self::TraceStorageError(TraceStoreError::Limit(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The stores a query can be executed against.
+#[derive(Clone, Copy)]
+pub struct QueryStores<'a> {
+    /// Store used for `FROM logs`.
+    pub logs: &'a dyn LogStore,
+    /// Store used for `FROM metrics`.
+    pub metrics: &'a dyn MetricStore,
+    /// Store used for `FROM traces`.
+    pub traces: &'a dyn TraceStore,
+    /// Limits applied to every store query.
+    pub limits: &'a QueryLimits,
+}
+
+impl<'a> QueryStores<'a> {
+    /// Returns the same stores with different query limits.
+    #[must_use]
+    pub fn with_limits(self, limits: &'a QueryLimits) -> Self {
+        Self { limits, ..self }
+    }
+}
+
+/// Records returned by a query, tagged by data type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum QueryData {
+    /// Log entries (`FROM logs`).
+    Logs {
+        /// The matching logs.
+        logs: Vec<LogEntry>,
+    },
+    /// Metric data points (`FROM metrics`).
+    Metrics {
+        /// The matching metrics.
+        metrics: Vec<Metric>,
+    },
+    /// Spans (`FROM traces`).
+    Spans {
+        /// The matching spans.
+        spans: Vec<Span>,
+    },
+    /// Projected columns (`SELECT a, b AS c FROM ...`).
+    Rows {
+        /// Output column names, in SELECT order.
+        columns: Vec<String>,
+        /// One value per column for each matching record.
+        rows: Vec<Vec<serde_json::Value>>,
+    },
 }
 
-/// Executes a parsed query against a log store.
+impl QueryData {
+    /// Returns the number of records.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Logs { logs } => logs.len(),
+            Self::Metrics { metrics } => metrics.len(),
+            Self::Spans { spans } => spans.len(),
+            Self::Rows { rows, .. } => rows.len(),
+        }
+    }
+
+    /// Returns `true` if there are no records.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the data into individual records.
+    ///
+    /// Rows become objects keyed by column name.
+    #[must_use]
+    pub fn into_records(self) -> Vec<QueryRecord> {
+        match self {
+            Self::Logs { logs } => logs.into_iter().map(QueryRecord::Log).collect(),
+            Self::Metrics { metrics } => metrics.into_iter().map(QueryRecord::Metric).collect(),
+            Self::Spans { spans } => spans.into_iter().map(QueryRecord::Span).collect(),
+            Self::Rows { columns, rows } => rows
+                .into_iter()
+                .map(|row| QueryRecord::Row(columns.iter().cloned().zip(row).collect()))
+                .collect(),
+        }
+    }
+}
+
+/// A single record returned by a query, serialized without a type tag.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum QueryRecord {
+    /// A log entry (`SELECT * FROM logs`).
+    Log(LogEntry),
+    /// A metric data point (`SELECT * FROM metrics`).
+    Metric(Metric),
+    /// A span (`SELECT * FROM traces`).
+    Span(Span),
+    /// A projected or aggregated row, keyed by column name.
+    Row(serde_json::Map<String, serde_json::Value>),
+}
+
+/// Records streamed by [`stream_query`], one at a time.
 ///
-/// # Arguments
+/// Advancing the iterator may block on the store, so it should be consumed on a
+/// blocking thread (e.g. `tokio::task::spawn_blocking`).
+pub type QueryStream = Box<dyn Iterator<Item = Result<QueryRecord, ExecutionError>> + Send>;
+
+/// Result of executing a query.
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    /// The returned records.
+    pub data: QueryData,
+
+    /// Total count of matching records (before cursor/limit/offset applied).
+    pub total_count: usize,
+
+    /// Cursor for the next page of records, if there may be more.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Executes a parsed query against the store for its source.
 ///
-/// * `query` - The parsed query AST.
-/// * `store` - The log store to query.
+/// Queries with a projection list or `GROUP BY` return [`QueryData::Rows`];
+/// `SELECT *` returns whole records. Grouping and aggregation are performed by
+/// the stores.
 ///
-/// # Returns
+/// # Arguments
 ///
-/// Returns a `LogQueryResult` containing the matching logs.
+/// * `query` - The parsed query AST.
+/// * `stores` - The stores to query.
 ///
 /// # Errors
 ///
@@ -52,120 +221,962 @@ pub enum ExecutionError {
 /// # Example
 ///
 /// ```ignore
-/// use shared::query::{parse_query, execute_query};
-/// use shared::storage::InMemoryLogStore;
+/// use shared::query::{parse_query, execute_query, QueryStores};
+/// use shared::storage::{InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore, QueryLimits};
 ///
-/// let store = InMemoryLogStore::new();
-/// let query = parse_query("SELECT * FROM logs WHERE level = 'error'").unwrap();
-/// let result = execute_query(&query, &store).unwrap();
+/// let (logs, metrics, traces) = (
+///     InMemoryLogStore::new(),
+///     InMemoryMetricStore::new(),
+///     InMemoryTraceStore::new(),
+/// );
+/// let limits = QueryLimits::default();
+/// let stores = QueryStores { logs: &logs, metrics: &metrics, traces: &traces, limits: &limits };
+/// let query = parse_query("SELECT * FROM metrics WHERE name = 'cpu'").unwrap();
+/// let result = execute_query(&query, stores).unwrap();
 /// ```
 pub fn execute_query(
     query: &Query,
-    store: &dyn LogStore,
-) -> Result<LogQueryResult, ExecutionError> {
-    // Currently only logs are supported
-    if query.source != Source::Logs {
-        return Err(ExecutionError::UnsupportedSource(query.source.to_string()));
+    stores: QueryStores<'_>,
+) -> Result<QueryResult, ExecutionError> {
+    execute_query_with_cursor(query, None, stores)
+}
+
+/// Executes a parsed query, continuing after `cursor` if one is given.
+///
+/// Cursors are only supported for queries that return records in the default
+/// order (no `ORDER BY`, or `ORDER BY timestamp DESC`) and do not aggregate.
+/// For such queries with a `LIMIT`, [`QueryResult::next_cursor`] is set when the
+/// page is full.
+///
+/// # Errors
+///
+/// Returns an error if the query cannot be executed or cannot be paginated by cursor.
+pub fn execute_query_with_cursor(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    stores: QueryStores<'_>,
+) -> Result<QueryResult, ExecutionError> {
+    ensure_bound(query)?;
+    let query = &*resolve_times(query, Utc::now());
+    if query.is_aggregate() {
+        if cursor.is_some() {
+            return Err(ExecutionError::InvalidCursor(
+                "aggregate queries cannot be paginated with a cursor".to_string(),
+            ));
+        }
+        return execute_grouped_query(query, stores);
+    }
+    if query.having.is_some() {
+        return Err(ExecutionError::InvalidAggregation(
+            "HAVING requires GROUP BY or an aggregate function".to_string(),
+        ));
+    }
+
+    let projection = &query.projection;
+    match query.source {
+        Source::Logs => {
+            let result = stores
+                .logs
+                .query(build_log_query(query, cursor)?.with_limits(stores.limits.clone()))?;
+            let data = if projection.is_empty() {
+                QueryData::Logs { logs: result.logs }
+            } else {
+                project(projection, &result.logs)
+            };
+            Ok(QueryResult {
+                data,
+                total_count: result.total_count,
+                next_cursor: result.next_cursor,
+            })
+        }
+        Source::Metrics => {
+            let result = stores
+                .metrics
+                .query(build_metric_query(query, cursor)?.with_limits(stores.limits.clone()))?;
+            let data = if projection.is_empty() {
+                QueryData::Metrics {
+                    metrics: result.metrics,
+                }
+            } else {
+                project(projection, &result.metrics)
+            };
+            Ok(QueryResult {
+                data,
+                total_count: result.total_count,
+                next_cursor: result.next_cursor,
+            })
+        }
+        Source::Traces => {
+            let result = stores.traces.query_spans(
+                build_trace_query(query, cursor)?.with_limits(stores.limits.clone()),
+            )?;
+            let data = if projection.is_empty() {
+                QueryData::Spans {
+                    spans: result.spans,
+                }
+            } else {
+                project(projection, &result.spans)
+            };
+            Ok(QueryResult {
+                data,
+                total_count: result.total_count,
+                next_cursor: result.next_cursor,
+            })
+        }
     }
+}
 
-    // Get all logs first (we'll filter in-memory for complex conditions)
-    let all_logs = store.query(LogQuery::new())?;
+/// Executes a parsed query and streams its records as they are read.
+///
+/// Non-aggregate log queries are streamed from the log store without computing a
+/// total count, so results of any size can be exported. Other queries are
+/// executed as by [`execute_query_with_cursor`] and their records returned one
+/// by one. Cursors and errors are handled the same way.
+///
+/// # Errors
+///
+/// Returns an error if the query cannot be started; errors while reading are
+/// returned by the stream.
+pub fn stream_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    stores: QueryStores<'_>,
+) -> Result<QueryStream, ExecutionError> {
+    ensure_bound(query)?;
+    let query = resolve_times(query, Utc::now()).into_owned();
+    if query.source != Source::Logs || query.is_aggregate() || query.having.is_some() {
+        let records = execute_query_with_cursor(&query, cursor, stores)?
+            .data
+            .into_records();
+        return Ok(Box::new(records.into_iter().map(Ok)));
+    }
+
+    let logs = stores
+        .logs
+        .stream(build_log_query(&query, cursor)?.with_limits(stores.limits.clone()))?;
+    let projection = query.projection;
+    let columns: Vec<String> = projection.iter().map(SelectItem::column_name).collect();
+
+    Ok(Box::new(logs.map(move |log| {
+        let log = log?;
+        Ok(if projection.is_empty() {
+            QueryRecord::Log(log)
+        } else {
+            QueryRecord::Row(
+                columns
+                    .iter()
+                    .cloned()
+                    .zip(project_row(&projection, &log))
+                    .collect(),
+            )
+        })
+    })))
+}
+
+/// Projects records onto the selected columns.
+///
+/// Fields that do not exist on a record are returned as `null`.
+fn project<R: Record>(projection: &[SelectItem], records: &[R]) -> QueryData {
+    let columns = projection.iter().map(SelectItem::column_name).collect();
+    let rows = records
+        .iter()
+        .map(|record| project_row(projection, record))
+        .collect();
+
+    QueryData::Rows { columns, rows }
+}
+
+/// Projects a single record onto the selected columns.
+fn project_row<R: Record>(projection: &[SelectItem], record: &R) -> Vec<serde_json::Value> {
+    projection
+        .iter()
+        .map(|item| key_value(record, &item.expr))
+        .collect()
+}
 
-    // Apply WHERE clause filter
-    let filtered: Vec<LogEntry> = if let Some(ref where_clause) = query.where_clause {
-        all_logs
+/// Executes a query with `GROUP BY` or aggregate functions.
+fn execute_grouped_query(
+    query: &Query,
+    stores: QueryStores<'_>,
+) -> Result<QueryResult, ExecutionError> {
+    let group = group_query(query)?;
+    let bucketed = bucket_key(query).is_some();
+    let result = query_groups(query, &store_group(&group, bucketed), stores)?;
+    finish_grouped_query(query, &group, result)
+}
+
+/// Returns the field and interval of the query's `bucket()` group key.
+pub(crate) fn bucket_key(query: &Query) -> Option<(&str, DurationLiteral)> {
+    query.group_by.iter().find_map(|key| match key {
+        Expr::Bucket { field, interval } => Some((field.as_str(), *interval)),
+        _ => None,
+    })
+}
+
+/// Runs the aggregation `group` over the records matching `query` in the
+/// store for its source.
+pub(crate) fn query_groups(
+    query: &Query,
+    group: &GroupQuery,
+    stores: QueryStores<'_>,
+) -> Result<GroupQueryResult, ExecutionError> {
+    let limits = stores.limits.clone();
+    Ok(match query.source {
+        Source::Logs => stores
             .logs
-            .into_iter()
-            .filter(|log| evaluate_where_clause(where_clause, log))
-            .collect()
-    } else {
-        all_logs.logs
-    };
+            .query_groups(log_query(query).with_limits(limits), group)?,
+        Source::Metrics => stores
+            .metrics
+            .query_groups(metric_query(query).with_limits(limits), group)?,
+        Source::Traces => stores
+            .traces
+            .query_groups(trace_query(query).with_limits(limits), group)?,
+    })
+}
 
-    // Apply ORDER BY
-    let mut sorted = filtered;
-    if let Some(ref order_by) = query.order_by {
-        sort_logs(&mut sorted, order_by);
+/// Builds the result of a grouped query from the rows aggregated by the store.
+///
+/// For bucketed queries, fills in empty buckets and then applies HAVING,
+/// ordering and pagination, which the store left out (see [`store_group`]).
+pub(crate) fn finish_grouped_query(
+    query: &Query,
+    group: &GroupQuery,
+    mut result: GroupQueryResult,
+) -> Result<QueryResult, ExecutionError> {
+    if let Some((field, _)) = bucket_key(query) {
+        let (mut start, mut end) = query
+            .where_clause
+            .as_ref()
+            .map_or((None, None), |clause| time_range(clause, field));
+        if is_record_time(&query.source, field) {
+            let since = query.since.as_ref().and_then(timestamp_value);
+            let until = query.until.as_ref().and_then(timestamp_value);
+            start = start.max(since);
+            end = match (end, until) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        let mut rows = result.rows;
+        fill_buckets(&mut rows, group, start, end, MAX_BUCKETS).ok_or_else(|| {
+            ExecutionError::InvalidAggregation(format!(
+                "query would return more than {MAX_BUCKETS} buckets; \
+                 use a larger interval or a narrower time range"
+            ))
+        })?;
+        result = finish_groups(rows, group);
     }
 
-    let total_count = sorted.len();
-
-    // Apply OFFSET and LIMIT
-    let offset = query.offset.unwrap_or(0);
-    let result: Vec<LogEntry> = sorted
+    // Drop the hidden columns that were only needed for HAVING and ORDER BY
+    let visible = query.projection.len();
+    let rows = result
+        .rows
         .into_iter()
-        .skip(offset)
-        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|mut row| {
+            row.truncate(visible);
+            row
+        })
         .collect();
 
-    Ok(LogQueryResult {
-        logs: result,
-        total_count,
+    Ok(QueryResult {
+        data: QueryData::Rows {
+            columns: query
+                .projection
+                .iter()
+                .map(SelectItem::column_name)
+                .collect(),
+            rows,
+        },
+        total_count: result.total_count,
+        next_cursor: None,
     })
 }
 
-/// Evaluates a WHERE clause against a log entry.
-fn evaluate_where_clause(clause: &WhereClause, log: &LogEntry) -> bool {
+/// Returns the aggregation performed by the store for a grouped query.
+///
+/// Empty buckets are filled in before HAVING, ordering and pagination, so for
+/// bucketed queries the store only aggregates.
+pub(crate) fn store_group(group: &GroupQuery, bucketed: bool) -> GroupQuery {
+    if bucketed {
+        GroupQuery {
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            ..group.clone()
+        }
+    } else {
+        group.clone()
+    }
+}
+
+/// Upper bound on the number of rows a bucketed query may produce after
+/// empty buckets have been filled in.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Upper bound on `k` in `topk(k, field)`.
+const MAX_TOP_K: usize = 1_000;
+
+/// Extracts the time range `[start, end)` that a WHERE clause places on `field`.
+///
+/// Only conditions that must hold for every record are considered, i.e. those
+/// that are not below an `OR`.
+fn time_range(clause: &WhereClause, field: &str) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
     match clause {
-        WhereClause::Condition(condition) => evaluate_condition(condition, log),
+        WhereClause::Condition(condition) if condition.field == field => {
+            let Some(time) = timestamp_value(&condition.value) else {
+                return (None, None);
+            };
+            match condition.operator {
+                ComparisonOp::Gt | ComparisonOp::GtEq => (Some(time), None),
+                ComparisonOp::Lt => (None, Some(time)),
+                // The bucket containing an inclusive end is still part of the range
+                ComparisonOp::LtEq => (None, Some(time + chrono::Duration::nanoseconds(1))),
+                _ => (None, None),
+            }
+        }
         WhereClause::Combined {
             left,
-            operator,
+            operator: LogicalOp::And,
             right,
         } => {
-            let left_result = evaluate_where_clause(left, log);
-            let right_result = evaluate_where_clause(right, log);
+            let (left_start, left_end) = time_range(left, field);
+            let (right_start, right_end) = time_range(right, field);
+            (
+                left_start.max(right_start),
+                match (left_end, right_end) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                },
+            )
+        }
+        WhereClause::Grouped(inner) => time_range(inner, field),
+        WhereClause::Between {
+            field: name,
+            low,
+            high,
+            negated: false,
+        } if name == field => (
+            timestamp_value(low),
+            timestamp_value(high).map(|t| t + chrono::Duration::nanoseconds(1)),
+        ),
+        _ => (None, None),
+    }
+}
 
-            match operator {
-                LogicalOp::And => left_result && right_result,
-                LogicalOp::Or => left_result || right_result,
+/// Whether `field` is the time that `SINCE` and `UNTIL` apply to.
+pub(crate) fn is_record_time(source: &Source, field: &str) -> bool {
+    field == "timestamp" || (*source == Source::Traces && field == "start_time")
+}
+
+/// Builds the store-level aggregation for a grouped query.
+///
+/// Every selected field must be grouped, HAVING may only filter on group keys
+/// and aggregates, and group keys or aggregates referenced only by HAVING / ORDER BY are added
+/// as hidden columns after the projection. `SELECT DISTINCT` groups by the
+/// projected fields.
+pub(crate) fn group_query(query: &Query) -> Result<GroupQuery, ExecutionError> {
+    if query.projection.is_empty() {
+        let clause = if query.distinct {
+            "DISTINCT"
+        } else {
+            "GROUP BY"
+        };
+        return Err(ExecutionError::InvalidAggregation(format!(
+            "SELECT * cannot be used with {clause}"
+        )));
+    }
+    let group_by = if query.distinct {
+        if !query.group_by.is_empty() {
+            return Err(ExecutionError::InvalidAggregation(
+                "DISTINCT cannot be combined with GROUP BY".to_string(),
+            ));
+        }
+        if let Some(item) = query
+            .projection
+            .iter()
+            .find(|item| item.expr.is_aggregate())
+        {
+            return Err(ExecutionError::InvalidAggregation(format!(
+                "DISTINCT cannot be combined with aggregates such as '{}'",
+                item.expr
+            )));
+        }
+        query
+            .projection
+            .iter()
+            .map(|item| item.expr.clone())
+            .collect()
+    } else {
+        query.group_by.clone()
+    };
+    for item in &query.projection {
+        if let Expr::Aggregate {
+            function: AggregateFunction::TopK(k),
+            ..
+        } = item.expr
+        {
+            if k == 0 || k > MAX_TOP_K {
+                return Err(ExecutionError::InvalidAggregation(format!(
+                    "topk() takes between 1 and {MAX_TOP_K} values, got {k}"
+                )));
             }
         }
-        WhereClause::Grouped(inner) => evaluate_where_clause(inner, log),
+        if !item.expr.is_aggregate() && !group_by.contains(&item.expr) {
+            let keys: Vec<String> = group_by.iter().map(ToString::to_string).collect();
+            let suggestion = closest(&item.expr.to_string(), keys.iter().map(String::as_str));
+            return Err(ExecutionError::InvalidAggregation(format!(
+                "'{}' must appear in GROUP BY or be used in an aggregate function{}",
+                item.expr,
+                did_you_mean(suggestion)
+            )));
+        }
+    }
+    let buckets = query
+        .group_by
+        .iter()
+        .filter(|key| matches!(key, Expr::Bucket { .. }))
+        .count();
+    if buckets > 1 {
+        return Err(ExecutionError::InvalidAggregation(
+            "only one bucket() may be used in GROUP BY".to_string(),
+        ));
+    }
+
+    let mut group = GroupQuery {
+        columns: query.projection.clone(),
+        group_by,
+        having: query.having.clone(),
+        order_by: query.order_by.clone(),
+        limit: query.limit,
+        offset: query.offset,
+    };
+    for key in &query.group_by {
+        if !group.columns.iter().any(|column| column.expr == *key) {
+            group.columns.push(SelectItem {
+                expr: key.clone(),
+                alias: None,
+            });
+        }
     }
+
+    let mut having_fields = Vec::new();
+    if let Some(ref having) = query.having {
+        collect_fields(having, &mut having_fields);
+    }
+    for name in having_fields {
+        // Selected fields are grouped, so any other column is a key or an aggregate
+        if group.column_index(name).is_none() && parse_aggregate(name).is_none() {
+            return Err(ExecutionError::InvalidAggregation(format!(
+                "HAVING can only filter on group keys and aggregates; \
+                 filter '{name}' in WHERE instead"
+            )));
+        }
+        resolve_output_column(&mut group, name)?;
+    }
+    for order_by in &query.order_by {
+        resolve_output_column(&mut group, &order_by.field)?;
+    }
+
+    Ok(group)
 }
 
-/// Evaluates a single condition against a log entry.
-fn evaluate_condition(condition: &Condition, log: &LogEntry) -> bool {
-    let field = condition.field.to_lowercase();
+/// Returns the column referenced by `name`, adding a hidden column for an
+/// aggregate that is not selected.
+fn resolve_output_column(group: &mut GroupQuery, name: &str) -> Result<usize, ExecutionError> {
+    if let Some(index) = group.column_index(name) {
+        return Ok(index);
+    }
+    let Some(expr) = parse_aggregate(name) else {
+        let names: Vec<String> = group
+            .columns
+            .iter()
+            .map(|column| {
+                column
+                    .alias
+                    .clone()
+                    .unwrap_or_else(|| column.expr.to_string())
+            })
+            .collect();
+        return Err(ExecutionError::UnknownField {
+            field: name.to_string(),
+            suggestion: closest(name, names.iter().map(String::as_str)).map(ToString::to_string),
+        });
+    };
+    group.columns.push(SelectItem { expr, alias: None });
+    Ok(group.columns.len() - 1)
+}
 
-    match field.as_str() {
-        "level" => evaluate_level_condition(condition, log),
-        "service" => evaluate_string_field(&log.service, condition),
-        "message" => evaluate_string_field(&log.message, condition),
-        "trace_id" => {
-            if let Some(ref trace_id) = log.trace_id {
-                evaluate_string_field(trace_id, condition)
-            } else {
-                // If trace_id is None, only match if comparing with empty string or not-equal
-                matches!(&condition.value, Value::String(s) if s.is_empty())
-                    || condition.operator == ComparisonOp::NotEq
-            }
+/// Formats the "did you mean" hint appended to errors about a misspelled
+/// field, or nothing without a suggestion.
+fn did_you_mean(suggestion: Option<&str>) -> String {
+    suggestion.map_or_else(String::new, |field| format!("; did you mean '{field}'?"))
+}
+
+/// Collects the field names referenced by a clause.
+fn collect_fields<'a>(clause: &'a WhereClause, fields: &mut Vec<&'a str>) {
+    match clause {
+        WhereClause::Condition(condition) => fields.push(&condition.field),
+        WhereClause::Combined { left, right, .. } => {
+            collect_fields(left, fields);
+            collect_fields(right, fields);
         }
-        "span_id" => {
-            if let Some(ref span_id) = log.span_id {
-                evaluate_string_field(span_id, condition)
-            } else {
-                matches!(&condition.value, Value::String(s) if s.is_empty())
-                    || condition.operator == ComparisonOp::NotEq
+        WhereClause::Grouped(inner) | WhereClause::Not(inner) => collect_fields(inner, fields),
+        WhereClause::In { field, .. }
+        | WhereClause::Between { field, .. }
+        | WhereClause::IsNull { field, .. } => fields.push(field),
+    }
+}
+
+/// Builds the log store query for the WHERE clause and time range of `query`.
+pub(crate) fn log_query(query: &Query) -> LogQuery {
+    let mut log_query = LogQuery::new();
+    if let Some(ref where_clause) = query.where_clause {
+        log_query = log_query.with_filter(where_clause.clone());
+    }
+    if let Some(start) = query.since.as_ref().and_then(timestamp_value) {
+        log_query = log_query.with_start_time(start);
+    }
+    if let Some(end) = query.until.as_ref().and_then(timestamp_value) {
+        log_query = log_query.with_end_time(end);
+    }
+    log_query
+}
+
+/// Builds the metric store query for the WHERE clause and time range of `query`.
+pub(crate) fn metric_query(query: &Query) -> MetricQuery {
+    let mut metric_query = MetricQuery::new();
+    if let Some(ref where_clause) = query.where_clause {
+        metric_query = metric_query.with_filter(where_clause.clone());
+    }
+    if let Some(start) = query.since.as_ref().and_then(timestamp_value) {
+        metric_query = metric_query.with_start_time(start);
+    }
+    if let Some(end) = query.until.as_ref().and_then(timestamp_value) {
+        metric_query = metric_query.with_end_time(end);
+    }
+    metric_query
+}
+
+/// Builds the trace store query for the WHERE clause and time range of `query`.
+pub(crate) fn trace_query(query: &Query) -> TraceQuery {
+    let mut trace_query = TraceQuery::new();
+    if let Some(ref where_clause) = query.where_clause {
+        trace_query = trace_query.with_filter(where_clause.clone());
+    }
+    if let Some(start) = query.since.as_ref().and_then(timestamp_value) {
+        trace_query = trace_query.with_start_time(start);
+    }
+    if let Some(end) = query.until.as_ref().and_then(timestamp_value) {
+        trace_query = trace_query.with_end_time(end);
+    }
+    trace_query
+}
+
+/// Executes a `FROM logs` query against a log store.
+///
+/// # Errors
+///
+/// Returns an error if the query targets another source or the store fails.
+pub fn execute_log_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    store: &dyn LogStore,
+) -> Result<LogQueryResult, ExecutionError> {
+    if query.source != Source::Logs {
+        return Err(ExecutionError::UnsupportedSource(query.source.to_string()));
+    }
+    let query = &*resolve_times(query, Utc::now());
+
+    Ok(store.query(build_log_query(query, cursor)?)?)
+}
+
+/// Builds the log store query for `query`, pushing filtering, ordering and
+/// pagination down to the store.
+pub(crate) fn build_log_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+) -> Result<LogQuery, ExecutionError> {
+    let mut log_query = log_query(query);
+    if !is_default_order(query) {
+        if cursor.is_some() {
+            return Err(ExecutionError::InvalidCursor(
+                "cursor pagination requires the default order (ORDER BY timestamp DESC)"
+                    .to_string(),
+            ));
+        }
+        log_query = log_query.with_order_by(store_order(query));
+    }
+    if let Some(cursor) = cursor {
+        log_query = log_query.with_cursor(cursor.clone());
+    }
+    if let Some(limit) = query.limit {
+        log_query = log_query.with_limit(limit);
+    }
+    if let Some(offset) = query.offset {
+        log_query = log_query.with_offset(offset);
+    }
+
+    Ok(log_query)
+}
+
+/// Executes a `FROM metrics` query against a metric store.
+///
+/// # Errors
+///
+/// Returns an error if the query targets another source or the store fails.
+pub fn execute_metric_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    store: &dyn MetricStore,
+) -> Result<MetricQueryResult, ExecutionError> {
+    if query.source != Source::Metrics {
+        return Err(ExecutionError::UnsupportedSource(query.source.to_string()));
+    }
+    let query = &*resolve_times(query, Utc::now());
+
+    Ok(store.query(build_metric_query(query, cursor)?)?)
+}
+
+/// Builds the metric store query for `query`, pushing filtering, ordering and
+/// pagination down to the store.
+pub(crate) fn build_metric_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+) -> Result<MetricQuery, ExecutionError> {
+    let mut metric_query = metric_query(query);
+    if !is_default_order(query) {
+        if cursor.is_some() {
+            return Err(ExecutionError::InvalidCursor(
+                "cursor pagination requires the default order (ORDER BY timestamp DESC)"
+                    .to_string(),
+            ));
+        }
+        metric_query = metric_query.with_order_by(store_order(query));
+    }
+    if let Some(cursor) = cursor {
+        metric_query = metric_query.with_cursor(cursor.clone());
+    }
+    if let Some(limit) = query.limit {
+        metric_query = metric_query.with_limit(limit);
+    }
+    if let Some(offset) = query.offset {
+        metric_query = metric_query.with_offset(offset);
+    }
+
+    Ok(metric_query)
+}
+
+/// Executes a `FROM traces` query against a trace store, returning matching spans.
+///
+/// # Errors
+///
+/// Returns an error if the query targets another source or the store fails.
+pub fn execute_trace_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    store: &dyn TraceStore,
+) -> Result<SpanQueryResult, ExecutionError> {
+    if query.source != Source::Traces {
+        return Err(ExecutionError::UnsupportedSource(query.source.to_string()));
+    }
+    let query = &*resolve_times(query, Utc::now());
+
+    Ok(store.query_spans(build_trace_query(query, cursor)?)?)
+}
+
+/// Builds the trace store query for `query`, pushing filtering, ordering and
+/// pagination down to the store.
+pub(crate) fn build_trace_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+) -> Result<TraceQuery, ExecutionError> {
+    let mut trace_query = trace_query(query);
+    if !is_default_order(query) {
+        if cursor.is_some() {
+            return Err(ExecutionError::InvalidCursor(
+                "cursor pagination requires the default order (ORDER BY timestamp DESC)"
+                    .to_string(),
+            ));
+        }
+        trace_query = trace_query.with_order_by(store_order(query));
+    }
+    if let Some(cursor) = cursor {
+        trace_query = trace_query.with_cursor(cursor.clone());
+    }
+    if let Some(limit) = query.limit {
+        trace_query = trace_query.with_limit(limit);
+    }
+    if let Some(offset) = query.offset {
+        trace_query = trace_query.with_offset(offset);
+    }
+
+    Ok(trace_query)
+}
+
+/// Returns `true` if the query returns records in the stores' default order:
+/// newest first, with ties broken by the cursor tiebreaker.
+fn is_default_order(query: &Query) -> bool {
+    match query.order_by.as_slice() {
+        [] => true,
+        [order_by] => {
+            order_by.order == SortOrder::Desc
+                && is_record_time(&query.source, &order_by.field.to_lowercase())
+        }
+        _ => false,
+    }
+}
+
+/// Returns the ORDER BY keys of a record query for the store, with keys naming
+/// a projection alias replaced by the aliased field.
+fn store_order(query: &Query) -> Vec<OrderBy> {
+    query
+        .order_by
+        .iter()
+        .map(|key| {
+            let aliased = query.projection.iter().find_map(|item| match item.expr {
+                Expr::Field(ref field) if item.alias.as_deref() == Some(key.field.as_str()) => {
+                    Some(field.clone())
+                }
+                _ => None,
+            });
+            OrderBy {
+                field: aliased.unwrap_or_else(|| key.field.clone()),
+                order: key.order.clone(),
             }
+        })
+        .collect()
+}
+
+/// Severity levels in ascending order, as stored in the `level` column.
+pub(crate) const LEVELS: [&str; 6] = ["trace", "debug", "info", "warn", "error", "fatal"];
+
+/// Returns the attribute key referenced by a field name.
+///
+/// Attributes can be addressed either directly (`user_id`) or with an explicit
+/// `attributes.` prefix (`attributes.user_id`).
+pub(crate) fn attribute_key(field: &str) -> &str {
+    field.strip_prefix("attributes.").unwrap_or(field)
+}
+
+/// Returns the candidate map keys for an attribute key, with the path to follow
+/// inside each candidate's JSON value.
+///
+/// The whole key comes first, so flat dotted keys (`http.status_code`) take
+/// precedence. Otherwise the key is split at each `.` from the left, so
+/// `http.status_code` can also address `{"http": {"status_code": 500}}`.
+pub(crate) fn attribute_paths(key: &str) -> impl Iterator<Item = (&str, Vec<&str>)> {
+    std::iter::once((key, Vec::new())).chain(
+        key.match_indices('.')
+            .map(move |(i, _)| (&key[..i], key[i + 1..].split('.').collect())),
+    )
+}
+
+/// Looks up an attribute, following nested objects for dotted keys.
+///
+/// The first candidate from [`attribute_paths`] that exists in the map is used.
+pub(crate) fn attribute_value<'a>(
+    attributes: &'a HashMap<String, serde_json::Value>,
+    key: &str,
+) -> Option<&'a serde_json::Value> {
+    let (map_key, path) = attribute_paths(key).find(|(k, _)| attributes.contains_key(*k))?;
+
+    path.iter()
+        .try_fold(&attributes[map_key], |value, segment| value.get(segment))
+}
+
+/// Returns the metric label referenced by a field name (`labels.host` or `host`).
+pub(crate) fn label_key(field: &str) -> &str {
+    field.strip_prefix("labels.").unwrap_or(field)
+}
+
+/// A field value resolved from a record.
+pub(crate) enum FieldValue<'a> {
+    /// A log level, compared by severity.
+    Level(LogLevel),
+    /// A text value.
+    Text(Cow<'a, str>),
+    /// A text value that may be absent (e.g. `trace_id` on a log).
+    OptionalText(Option<&'a str>),
+    /// A point in time.
+    Timestamp(DateTime<Utc>),
+    /// A numeric value.
+    Number(f64),
+    /// A JSON attribute value.
+    Json(&'a serde_json::Value),
+    /// The field does not exist on this record.
+    Missing,
+}
+
+impl FieldValue<'_> {
+    /// Converts the value to JSON for a projected column.
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Level(level) => serde_json::Value::String(level.to_string()),
+            Self::Text(text) => serde_json::Value::String(text.to_string()),
+            Self::OptionalText(Some(text)) => serde_json::Value::String((*text).to_string()),
+            Self::Timestamp(timestamp) => serde_json::json!(timestamp),
+            Self::Number(number) => number_to_json(*number),
+            Self::Json(value) => (*value).clone(),
+            Self::OptionalText(None) | Self::Missing => serde_json::Value::Null,
         }
-        "timestamp" => evaluate_timestamp_condition(condition, log),
-        _ => {
-            // Check in attributes
-            if let Some(attr_value) = log.attributes.get(&condition.field) {
-                evaluate_attribute_condition(attr_value, condition)
-            } else {
-                // Field not found - return false for equality, true for not-equal
-                condition.operator == ComparisonOp::NotEq
+    }
+}
+
+/// Converts a number to JSON, keeping whole numbers (e.g. `duration_ms`) as integers.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub(crate) fn number_to_json(number: f64) -> serde_json::Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        serde_json::json!(number as i64)
+    } else {
+        serde_json::json!(number)
+    }
+}
+
+/// A record that query conditions can be evaluated against.
+pub(crate) trait Record {
+    /// Resolves a field name to its value on this record.
+    fn field(&self, name: &str) -> FieldValue<'_>;
+}
+
+impl Record for LogEntry {
+    fn field(&self, name: &str) -> FieldValue<'_> {
+        match name.to_lowercase().as_str() {
+            "level" => FieldValue::Level(self.level),
+            "service" => FieldValue::Text(Cow::Borrowed(&self.service)),
+            "message" => FieldValue::Text(Cow::Borrowed(&self.message)),
+            "trace_id" => FieldValue::OptionalText(self.trace_id.as_deref()),
+            "span_id" => FieldValue::OptionalText(self.span_id.as_deref()),
+            "timestamp" => FieldValue::Timestamp(self.timestamp),
+            _ => attribute_value(&self.attributes, attribute_key(name))
+                .map_or(FieldValue::Missing, FieldValue::Json),
+        }
+    }
+}
+
+impl Record for Metric {
+    fn field(&self, name: &str) -> FieldValue<'_> {
+        match name.to_lowercase().as_str() {
+            "name" => FieldValue::Text(Cow::Borrowed(&self.name)),
+            "type" | "metric_type" => FieldValue::Text(Cow::Owned(self.metric_type.to_string())),
+            "value" => self
+                .value
+                .as_simple()
+                .or_else(|| self.value.as_histogram().map(|h| h.sum))
+                .map_or(FieldValue::Missing, FieldValue::Number),
+            "timestamp" => FieldValue::Timestamp(self.timestamp),
+            _ => self
+                .labels
+                .get(label_key(name))
+                .map_or(FieldValue::Missing, |v| FieldValue::Text(Cow::Borrowed(v))),
+        }
+    }
+}
+
+impl Record for Span {
+    fn field(&self, name: &str) -> FieldValue<'_> {
+        match name.to_lowercase().as_str() {
+            "trace_id" => FieldValue::Text(Cow::Borrowed(&self.trace_id)),
+            "span_id" => FieldValue::Text(Cow::Borrowed(&self.span_id)),
+            "parent_span_id" => FieldValue::OptionalText(self.parent_span_id.as_deref()),
+            "name" => FieldValue::Text(Cow::Borrowed(&self.name)),
+            "service" => FieldValue::Text(Cow::Borrowed(&self.service)),
+            "kind" => FieldValue::Text(Cow::Owned(self.kind.to_string())),
+            "status" => FieldValue::Text(Cow::Owned(self.status.to_string())),
+            #[allow(clippy::cast_precision_loss)]
+            "duration_ms" => FieldValue::Number(self.duration_ms() as f64),
+            "timestamp" | "start_time" => FieldValue::Timestamp(self.start_time),
+            "end_time" => FieldValue::Timestamp(self.end_time),
+            _ => attribute_value(&self.attributes, attribute_key(name))
+                .map_or(FieldValue::Missing, FieldValue::Json),
+        }
+    }
+}
+
+/// Returns `true` if the record matches the WHERE clause.
+///
+/// This is the reference implementation of the query semantics; the `ClickHouse`
+/// SQL generator mirrors it.
+pub(crate) fn matches_filter<R: Record>(clause: &WhereClause, record: &R) -> bool {
+    match clause {
+        WhereClause::Condition(condition) => evaluate_condition(condition, record),
+        WhereClause::Combined {
+            left,
+            operator,
+            right,
+        } => {
+            let left_result = matches_filter(left, record);
+            let right_result = matches_filter(right, record);
+
+            match operator {
+                LogicalOp::And => left_result && right_result,
+                LogicalOp::Or => left_result || right_result,
             }
         }
+        WhereClause::Grouped(inner) => matches_filter(inner, record),
+        WhereClause::Not(inner) => !matches_filter(inner, record),
+        WhereClause::In {
+            field,
+            values,
+            negated,
+        } => {
+            let found = values.iter().any(|value| {
+                evaluate_condition(
+                    &Condition::new(field.as_str(), ComparisonOp::Eq, value.clone()),
+                    record,
+                )
+            });
+            found != *negated
+        }
+        WhereClause::Between {
+            field,
+            low,
+            high,
+            negated,
+        } => {
+            let within = evaluate_condition(
+                &Condition::new(field.as_str(), ComparisonOp::GtEq, low.clone()),
+                record,
+            ) && evaluate_condition(
+                &Condition::new(field.as_str(), ComparisonOp::LtEq, high.clone()),
+                record,
+            );
+            within != *negated
+        }
+        WhereClause::IsNull { field, negated } => {
+            let null = matches!(
+                record.field(field),
+                FieldValue::Missing
+                    | FieldValue::OptionalText(None)
+                    | FieldValue::Json(serde_json::Value::Null)
+            );
+            null != *negated
+        }
+    }
+}
+
+/// Evaluates a single condition against a record.
+fn evaluate_condition<R: Record>(condition: &Condition, record: &R) -> bool {
+    match record.field(&condition.field) {
+        FieldValue::Level(level) => evaluate_level_condition(condition, level),
+        FieldValue::Text(text) => evaluate_string_field(&text, condition),
+        FieldValue::OptionalText(Some(text)) => evaluate_string_field(text, condition),
+        FieldValue::OptionalText(None) => {
+            // If the field is absent, only match if comparing with empty string or not-equal
+            matches!(&condition.value, Value::String(s) if s.is_empty())
+                || condition.operator == ComparisonOp::NotEq
+        }
+        FieldValue::Timestamp(timestamp) => evaluate_timestamp_condition(condition, timestamp),
+        FieldValue::Number(number) => evaluate_number_condition(condition, number),
+        FieldValue::Json(attr_value) => evaluate_attribute_condition(attr_value, condition),
+        // Field not found - return false for equality, true for not-equal
+        FieldValue::Missing => condition.operator == ComparisonOp::NotEq,
     }
 }
 
 /// Evaluates a condition against the log level.
-fn evaluate_level_condition(condition: &Condition, log: &LogEntry) -> bool {
-    let log_level_str = log.level.to_string();
+fn evaluate_level_condition(condition: &Condition, level: LogLevel) -> bool {
+    let log_level_str = level.to_string();
 
     match &condition.value {
         Value::String(s) => {
@@ -176,10 +1187,11 @@ fn evaluate_level_condition(condition: &Condition, log: &LogEntry) -> bool {
                 ComparisonOp::Contains => log_level_str.contains(&value_lower),
                 ComparisonOp::StartsWith => log_level_str.starts_with(&value_lower),
                 ComparisonOp::EndsWith => log_level_str.ends_with(&value_lower),
+                ComparisonOp::Matches => regex_matches(s, &log_level_str),
                 // Comparison operators for levels (using severity order)
                 ComparisonOp::Lt | ComparisonOp::LtEq | ComparisonOp::Gt | ComparisonOp::GtEq => {
                     if let Some(val_ord) = level_order_from_str(&value_lower) {
-                        let log_ord = level_order(log.level);
+                        let log_ord = level_order(level);
                         match condition.operator {
                             ComparisonOp::Lt => log_ord < val_ord,
                             ComparisonOp::LtEq => log_ord <= val_ord,
@@ -210,16 +1222,11 @@ fn level_order(level: LogLevel) -> u8 {
 }
 
 /// Returns the severity order from a level string.
-fn level_order_from_str(s: &str) -> Option<u8> {
-    match s {
-        "trace" => Some(0),
-        "debug" => Some(1),
-        "info" => Some(2),
-        "warn" => Some(3),
-        "error" => Some(4),
-        "fatal" => Some(5),
-        _ => None,
-    }
+pub(crate) fn level_order_from_str(s: &str) -> Option<u8> {
+    LEVELS
+        .iter()
+        .position(|level| *level == s)
+        .and_then(|pos| u8::try_from(pos).ok())
 }
 
 /// Evaluates a string field condition.
@@ -235,6 +1242,7 @@ fn evaluate_string_field(field_value: &str, condition: &Condition) -> bool {
                 ComparisonOp::Contains => field_lower.contains(&value_lower),
                 ComparisonOp::StartsWith => field_lower.starts_with(&value_lower),
                 ComparisonOp::EndsWith => field_lower.ends_with(&value_lower),
+                ComparisonOp::Matches => regex_matches(s, field_value),
                 ComparisonOp::Lt => field_value < s.as_str(),
                 ComparisonOp::LtEq => field_value <= s.as_str(),
                 ComparisonOp::Gt => field_value > s.as_str(),
@@ -245,41 +1253,185 @@ fn evaluate_string_field(field_value: &str, condition: &Condition) -> bool {
     }
 }
 
-/// Evaluates a timestamp condition.
-fn evaluate_timestamp_condition(condition: &Condition, log: &LogEntry) -> bool {
+/// Maximum number of compiled patterns kept by [`regex_matches`].
+const REGEX_CACHE_SIZE: usize = 256;
+
+/// Compiled `MATCHES` patterns, so a pattern is compiled once rather than per record.
+static REGEX_CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+/// Returns `true` if `text` matches the regular expression `pattern`.
+///
+/// Patterns are validated by the parser; an invalid pattern never matches.
+fn regex_matches(pattern: &str, text: &str) -> bool {
+    let cache = REGEX_CACHE.get_or_init(Mutex::default);
+    let cached = cache
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(pattern).cloned());
+    if let Some(regex) = cached {
+        return regex.is_match(text);
+    }
+
+    let Ok(regex) = Regex::new(pattern) else {
+        return false;
+    };
+    if let Ok(mut cache) = cache.lock() {
+        if cache.len() >= REGEX_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), regex.clone());
+    }
+    regex.is_match(text)
+}
+
+/// Evaluates a timestamp condition.
+fn evaluate_timestamp_condition(condition: &Condition, timestamp: DateTime<Utc>) -> bool {
+    let Some(parsed) = timestamp_value(&condition.value) else {
+        return false;
+    };
+
+    match condition.operator {
+        ComparisonOp::Eq => timestamp == parsed,
+        ComparisonOp::NotEq => timestamp != parsed,
+        ComparisonOp::Lt => timestamp < parsed,
+        ComparisonOp::LtEq => timestamp <= parsed,
+        ComparisonOp::Gt => timestamp > parsed,
+        ComparisonOp::GtEq => timestamp >= parsed,
+        _ => false,
+    }
+}
+
+/// Rejects queries with parameters that have not been bound.
+pub(crate) fn ensure_bound(query: &Query) -> Result<(), ExecutionError> {
+    match query.params().first() {
+        Some(name) => Err(ExecutionError::UnboundParameter((*name).to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Replaces relative times such as `now() - 15m` with absolute timestamps.
+///
+/// Every relative time in a query is resolved against the same `now`, so the
+/// stores only ever see absolute times.
+pub(crate) fn resolve_times(query: &Query, now: DateTime<Utc>) -> Cow<'_, Query> {
+    let relative = matches!(query.since, Some(Value::Time(_)))
+        || matches!(query.until, Some(Value::Time(_)))
+        || query.where_clause.as_ref().is_some_and(has_relative_time);
+    if !relative {
+        return Cow::Borrowed(query);
+    }
+
+    let resolve = |value: &Value| match value {
+        Value::Time(time) => Value::String(
+            time.resolve(now)
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+        ),
+        other => other.clone(),
+    };
+    let mut resolved = query.clone();
+    resolved.since = query.since.as_ref().map(resolve);
+    resolved.until = query.until.as_ref().map(resolve);
+    resolved.where_clause = query
+        .where_clause
+        .as_ref()
+        .map(|clause| map_values(clause, &resolve));
+    Cow::Owned(resolved)
+}
+
+fn has_relative_time(clause: &WhereClause) -> bool {
+    match clause {
+        WhereClause::Condition(condition) => matches!(condition.value, Value::Time(_)),
+        WhereClause::Combined { left, right, .. } => {
+            has_relative_time(left) || has_relative_time(right)
+        }
+        WhereClause::Grouped(inner) | WhereClause::Not(inner) => has_relative_time(inner),
+        WhereClause::In { values, .. } => values.iter().any(|v| matches!(v, Value::Time(_))),
+        WhereClause::Between { low, high, .. } => {
+            matches!(low, Value::Time(_)) || matches!(high, Value::Time(_))
+        }
+        WhereClause::IsNull { .. } => false,
+    }
+}
+
+/// Rewrites the value of every condition in a clause.
+fn map_values(clause: &WhereClause, f: &impl Fn(&Value) -> Value) -> WhereClause {
+    match clause {
+        WhereClause::Condition(condition) => WhereClause::Condition(Condition {
+            field: condition.field.clone(),
+            operator: condition.operator.clone(),
+            value: f(&condition.value),
+        }),
+        WhereClause::Combined {
+            left,
+            operator,
+            right,
+        } => WhereClause::Combined {
+            left: Box::new(map_values(left, f)),
+            operator: operator.clone(),
+            right: Box::new(map_values(right, f)),
+        },
+        WhereClause::Grouped(inner) => WhereClause::Grouped(Box::new(map_values(inner, f))),
+        WhereClause::Not(inner) => WhereClause::Not(Box::new(map_values(inner, f))),
+        WhereClause::In {
+            field,
+            values,
+            negated,
+        } => WhereClause::In {
+            field: field.clone(),
+            values: values.iter().map(f).collect(),
+            negated: *negated,
+        },
+        WhereClause::Between {
+            field,
+            low,
+            high,
+            negated,
+        } => WhereClause::Between {
+            field: field.clone(),
+            low: f(low),
+            high: f(high),
+            negated: *negated,
+        },
+        WhereClause::IsNull { .. } => clause.clone(),
+    }
+}
+
+/// Interprets a query value as a timestamp: an RFC 3339 string or epoch seconds.
+pub(crate) fn timestamp_value(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        Value::Integer(epoch_secs) => chrono::DateTime::from_timestamp(*epoch_secs, 0),
+        _ => None,
+    }
+}
+
+/// Evaluates a condition against a numeric column such as `value` or `duration_ms`.
+fn evaluate_number_condition(condition: &Condition, number: f64) -> bool {
     match &condition.value {
-        Value::String(s) => {
-            // Try to parse as ISO 8601 timestamp
-            if let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(s) {
-                let parsed_utc = parsed.with_timezone(&chrono::Utc);
-                match condition.operator {
-                    ComparisonOp::Eq => log.timestamp == parsed_utc,
-                    ComparisonOp::NotEq => log.timestamp != parsed_utc,
-                    ComparisonOp::Lt => log.timestamp < parsed_utc,
-                    ComparisonOp::LtEq => log.timestamp <= parsed_utc,
-                    ComparisonOp::Gt => log.timestamp > parsed_utc,
-                    ComparisonOp::GtEq => log.timestamp >= parsed_utc,
-                    _ => false,
-                }
-            } else {
-                false
-            }
-        }
-        Value::Integer(epoch_secs) => {
-            if let Some(parsed) = chrono::DateTime::from_timestamp(*epoch_secs, 0) {
-                match condition.operator {
-                    ComparisonOp::Eq => log.timestamp == parsed,
-                    ComparisonOp::NotEq => log.timestamp != parsed,
-                    ComparisonOp::Lt => log.timestamp < parsed,
-                    ComparisonOp::LtEq => log.timestamp <= parsed,
-                    ComparisonOp::Gt => log.timestamp > parsed,
-                    ComparisonOp::GtEq => log.timestamp >= parsed,
-                    _ => false,
-                }
-            } else {
-                false
-            }
+        #[allow(clippy::cast_precision_loss)]
+        Value::Integer(query_val) => {
+            compare_numbers(number, *query_val as f64, &condition.operator)
         }
+        Value::Float(query_val) => match condition.operator {
+            ComparisonOp::Eq => (number - query_val).abs() < f64::EPSILON,
+            ComparisonOp::NotEq => (number - query_val).abs() >= f64::EPSILON,
+            ref op => compare_numbers(number, *query_val, op),
+        },
+        _ => false,
+    }
+}
+
+/// Compares two numbers with an ordering operator.
+fn compare_numbers(left: f64, right: f64, op: &ComparisonOp) -> bool {
+    match op {
+        ComparisonOp::Eq => left.total_cmp(&right) == Ordering::Equal,
+        ComparisonOp::NotEq => left.total_cmp(&right) != Ordering::Equal,
+        ComparisonOp::Lt => left < right,
+        ComparisonOp::LtEq => left <= right,
+        ComparisonOp::Gt => left > right,
+        ComparisonOp::GtEq => left >= right,
         _ => false,
     }
 }
@@ -297,6 +1449,7 @@ fn evaluate_attribute_condition(attr_value: &serde_json::Value, condition: &Cond
                 ComparisonOp::Contains => attr_lower.contains(&query_lower),
                 ComparisonOp::StartsWith => attr_lower.starts_with(&query_lower),
                 ComparisonOp::EndsWith => attr_lower.ends_with(&query_lower),
+                ComparisonOp::Matches => regex_matches(query_val, attr_str),
                 _ => attr_str.as_str().cmp(query_val.as_str()) == std::cmp::Ordering::Equal,
             }
         }
@@ -341,30 +1494,81 @@ fn evaluate_attribute_condition(attr_value: &serde_json::Value, condition: &Cond
     }
 }
 
-/// Sorts logs by the specified field and order.
-fn sort_logs(logs: &mut [LogEntry], order_by: &OrderBy) {
-    let field = order_by.field.to_lowercase();
+/// Sorts records by the ORDER BY keys, most significant first.
+///
+/// The sort is stable, so records that tie on every key keep their order; the
+/// stores sort newest first beforehand. `compile_order` mirrors this ordering.
+pub(crate) fn sort_records<R: Record>(records: &mut [R], order_by: &[OrderBy]) {
+    if order_by.is_empty() {
+        return;
+    }
+    records.sort_by(|a, b| {
+        order_by
+            .iter()
+            .map(|key| compare_key(&a.field(&key.field), &b.field(&key.field), &key.order))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
 
-    logs.sort_by(|a, b| {
-        let cmp = match field.as_str() {
-            "timestamp" => a.timestamp.cmp(&b.timestamp),
-            "level" => level_order(a.level).cmp(&level_order(b.level)),
-            "service" => a.service.cmp(&b.service),
-            "message" => a.message.cmp(&b.message),
-            _ => std::cmp::Ordering::Equal,
-        };
+/// Compares two values of a sort key in the given direction.
+///
+/// Absent attributes and labels (and JSON `null`) sort last in either
+/// direction. Attribute numbers sort before other attribute values, which
+/// compare by their text.
+fn compare_key(a: &FieldValue<'_>, b: &FieldValue<'_>, order: &SortOrder) -> Ordering {
+    let directed = |ordering: Ordering| match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    };
+    let is_missing = |value: &FieldValue<'_>| {
+        matches!(
+            value,
+            FieldValue::Missing | FieldValue::Json(serde_json::Value::Null)
+        )
+    };
 
-        match order_by.order {
-            SortOrder::Asc => cmp,
-            SortOrder::Desc => cmp.reverse(),
-        }
-    });
+    match (a, b) {
+        _ if is_missing(a) || is_missing(b) => is_missing(a).cmp(&is_missing(b)),
+        (FieldValue::Json(a), FieldValue::Json(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => directed(
+                x.total_cmp(&y)
+                    .then_with(|| json_text(a).cmp(&json_text(b))),
+            ),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => directed(json_text(a).cmp(&json_text(b))),
+        },
+        _ => directed(compare_fields(a, b)),
+    }
+}
+
+/// Returns the text an attribute value sorts by: strings unquoted, other values
+/// as JSON.
+fn json_text(value: &serde_json::Value) -> Cow<'_, str> {
+    match value {
+        serde_json::Value::String(text) => Cow::Borrowed(text),
+        other => Cow::Owned(other.to_string()),
+    }
+}
+
+/// Compares two resolved built-in field values.
+fn compare_fields(a: &FieldValue<'_>, b: &FieldValue<'_>) -> Ordering {
+    match (a, b) {
+        (FieldValue::Level(a), FieldValue::Level(b)) => level_order(*a).cmp(&level_order(*b)),
+        (FieldValue::Text(a), FieldValue::Text(b)) => a.cmp(b),
+        (FieldValue::OptionalText(a), FieldValue::OptionalText(b)) => a.cmp(b),
+        (FieldValue::Timestamp(a), FieldValue::Timestamp(b)) => a.cmp(b),
+        (FieldValue::Number(a), FieldValue::Number(b)) => a.total_cmp(b),
+        _ => Ordering::Equal,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::InMemoryLogStore;
+    use crate::models::{SpanKind, SpanStatus};
+    use crate::storage::{InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore};
 
     fn create_test_store() -> InMemoryLogStore {
         let store = InMemoryLogStore::new();
@@ -397,219 +1601,1086 @@ mod tests {
     }
 
     #[test]
-    fn test_execute_simple_select() {
+    fn test_execute_simple_select() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 5);
+        assert_eq!(result.logs.len(), 5);
+    }
+
+    #[test]
+    fn test_execute_where_level_eq() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs WHERE level = 'error'").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 2);
+        assert!(result.logs.iter().all(|l| l.level == LogLevel::Error));
+    }
+
+    #[test]
+    fn test_execute_where_service_eq() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs WHERE service = 'api'").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 3);
+        assert!(result.logs.iter().all(|l| l.service == "api"));
+    }
+
+    #[test]
+    fn test_execute_where_message_contains() {
+        let store = create_test_store();
+        let query =
+            super::super::parse_query("SELECT * FROM logs WHERE message CONTAINS 'message'")
+                .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 2); // "Info message" and "Debug message"
+    }
+
+    #[test]
+    fn test_execute_where_and() {
+        let store = create_test_store();
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE level = 'error' AND service = 'api'",
+        )
+        .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 1);
+        assert_eq!(result.logs[0].message, "Error occurred");
+    }
+
+    #[test]
+    fn test_execute_where_or() {
+        let store = create_test_store();
+        let query =
+            super::super::parse_query("SELECT * FROM logs WHERE level = 'error' OR level = 'warn'")
+                .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 3); // 2 errors + 1 warn
+    }
+
+    #[test]
+    fn test_execute_where_grouped() {
+        let store = create_test_store();
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE (level = 'error' OR level = 'warn') AND service = 'api'",
+        )
+        .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.total_count, 2); // "Error occurred" and "High memory usage"
+    }
+
+    #[test]
+    fn test_execute_order_by_timestamp_desc() {
+        let store = create_test_store();
+        let query =
+            super::super::parse_query("SELECT * FROM logs ORDER BY timestamp DESC").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        // Check that timestamps are in descending order
+        for i in 1..result.logs.len() {
+            assert!(result.logs[i - 1].timestamp >= result.logs[i].timestamp);
+        }
+    }
+
+    #[test]
+    fn test_execute_order_by_timestamp_asc() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs ORDER BY timestamp ASC").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        // Check that timestamps are in ascending order
+        for i in 1..result.logs.len() {
+            assert!(result.logs[i - 1].timestamp <= result.logs[i].timestamp);
+        }
+    }
+
+    #[test]
+    fn test_execute_order_by_level() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs ORDER BY level DESC").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        // Check that levels are in descending severity order
+        let level_orders: Vec<u8> = result.logs.iter().map(|l| level_order(l.level)).collect();
+
+        for i in 1..level_orders.len() {
+            assert!(level_orders[i - 1] >= level_orders[i]);
+        }
+    }
+
+    #[test]
+    fn test_execute_order_by_multiple_keys() {
+        let store = create_test_store();
+        let query = super::super::parse_query(
+            "SELECT * FROM logs ORDER BY service ASC, level DESC, message ASC",
+        )
+        .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        let messages: Vec<&str> = result.logs.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Error occurred",
+                "High memory usage",
+                "Info message",
+                "Debug message",
+                "Database connection failed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_execute_order_by_attribute() {
+        let store = InMemoryLogStore::new();
+        for (message, user) in [
+            ("a", serde_json::json!("bob")),
+            ("b", serde_json::json!(10)),
+            ("c", serde_json::json!(9)),
+            ("d", serde_json::Value::Null),
+            ("e", serde_json::json!("alice")),
+        ] {
+            store
+                .insert(LogEntry::new(LogLevel::Info, message, "api").with_attribute("user", user))
+                .unwrap();
+        }
+        store
+            .insert(LogEntry::new(LogLevel::Info, "f", "api"))
+            .unwrap();
+
+        let order = |query: &str| -> Vec<String> {
+            let query = super::super::parse_query(query).unwrap();
+            let result = execute_log_query(&query, None, &store).unwrap();
+            result.logs.into_iter().map(|l| l.message).collect()
+        };
+
+        // Numbers first, then text; missing values last in either direction
+        let asc = order("SELECT * FROM logs ORDER BY attributes.user ASC");
+        assert_eq!(&asc[..4], ["c", "b", "e", "a"]);
+        let desc = order("SELECT * FROM logs ORDER BY user DESC");
+        assert_eq!(&desc[..4], ["b", "c", "a", "e"]);
+        for messages in [asc, desc] {
+            let mut missing = messages[4..].to_vec();
+            missing.sort();
+            assert_eq!(missing, ["d", "f"]);
+        }
+    }
+
+    #[test]
+    fn test_execute_order_by_projection_alias() {
+        let store = create_test_store();
+        let query =
+            super::super::parse_query("SELECT message AS text FROM logs ORDER BY text ASC LIMIT 2")
+                .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        let messages: Vec<&str> = result.logs.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(messages, ["Database connection failed", "Debug message"]);
+    }
+
+    #[test]
+    fn test_execute_limit() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs LIMIT 2").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.logs.len(), 2);
+        assert_eq!(result.total_count, 5);
+    }
+
+    #[test]
+    fn test_execute_offset() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs LIMIT 2 OFFSET 2").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.logs.len(), 2);
+        assert_eq!(result.total_count, 5);
+    }
+
+    #[test]
+    fn test_execute_full_query() {
+        let store = create_test_store();
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE service = 'api' ORDER BY timestamp DESC LIMIT 2",
+        )
+        .unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        assert_eq!(result.logs.len(), 2);
+        assert_eq!(result.total_count, 3); // 3 api logs total
+        assert!(result.logs.iter().all(|l| l.service == "api"));
+    }
+
+    #[test]
+    fn test_execute_unsupported_source() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM metrics").unwrap();
+
+        let result = execute_log_query(&query, None, &store);
+
+        assert!(matches!(result, Err(ExecutionError::UnsupportedSource(_))));
+    }
+
+    #[test]
+    fn test_execute_level_comparison() {
+        let store = create_test_store();
+        let query = super::super::parse_query("SELECT * FROM logs WHERE level >= 'warn'").unwrap();
+
+        let result = execute_log_query(&query, None, &store).unwrap();
+
+        // Should include warn, error, fatal
+        assert_eq!(result.total_count, 3); // 1 warn + 2 errors
+    }
+
+    #[test]
+    fn test_execute_with_attributes() {
+        let store = InMemoryLogStore::new();
+
+        let log = LogEntry::new(LogLevel::Info, "Test", "api")
+            .with_attribute("user_id", "123")
+            .with_attribute("count", 42);
+        store.insert(log).unwrap();
+
+        let query = super::super::parse_query("SELECT * FROM logs WHERE user_id = '123'").unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 1);
+
+        let query = super::super::parse_query("SELECT * FROM logs WHERE count = 42").unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 1);
+    }
+
+    #[test]
+    fn test_execute_case_insensitive() {
         let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs").unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
+        // Level should be case-insensitive
+        let query = super::super::parse_query("SELECT * FROM logs WHERE level = 'ERROR'").unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 2);
 
-        assert_eq!(result.total_count, 5);
-        assert_eq!(result.logs.len(), 5);
+        // Service should be case-insensitive
+        let query = super::super::parse_query("SELECT * FROM logs WHERE service = 'API'").unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 3);
     }
 
     #[test]
-    fn test_execute_where_level_eq() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs WHERE level = 'error'").unwrap();
+    fn test_execute_with_attribute_path() {
+        let store = InMemoryLogStore::new();
 
-        let result = execute_query(&query, &store).unwrap();
+        let log = LogEntry::new(LogLevel::Info, "Test", "api").with_attribute("user_id", "123");
+        store.insert(log).unwrap();
+        store
+            .insert(LogEntry::new(LogLevel::Info, "Other", "api"))
+            .unwrap();
 
-        assert_eq!(result.total_count, 2);
-        assert!(result.logs.iter().all(|l| l.level == LogLevel::Error));
+        let query =
+            super::super::parse_query("SELECT * FROM logs WHERE attributes.user_id = '123'")
+                .unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 1);
+        assert_eq!(result.logs[0].message, "Test");
     }
 
     #[test]
-    fn test_execute_where_service_eq() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs WHERE service = 'api'").unwrap();
+    fn test_execute_without_limit_returns_all_rows() {
+        let store = InMemoryLogStore::new();
+        let logs: Vec<LogEntry> = (0..1500)
+            .map(|i| LogEntry::new(LogLevel::Info, format!("Message {i}"), "api"))
+            .collect();
+        store.insert_batch(logs).unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
+        let query = super::super::parse_query("SELECT * FROM logs").unwrap();
+        let result = execute_log_query(&query, None, &store).unwrap();
+        assert_eq!(result.total_count, 1500);
+        assert_eq!(result.logs.len(), 1500);
+    }
 
-        assert_eq!(result.total_count, 3);
-        assert!(result.logs.iter().all(|l| l.service == "api"));
+    fn create_test_stores() -> (InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore) {
+        let metrics = InMemoryMetricStore::new();
+        metrics
+            .insert_batch(vec![
+                Metric::gauge("cpu_usage", 91.5).with_label("host", "web-1"),
+                Metric::gauge("cpu_usage", 20.0).with_label("host", "web-2"),
+                Metric::counter("requests_total", 1200.0).with_label("host", "web-1"),
+            ])
+            .unwrap();
+
+        let start = Utc::now();
+        let traces = InMemoryTraceStore::new();
+        traces
+            .insert_spans(vec![
+                Span::new("trace-1", "span-1", "GET /checkout", "api")
+                    .with_kind(SpanKind::Server)
+                    .with_status(SpanStatus::Error)
+                    .with_start_time(start)
+                    .with_end_time(start + chrono::Duration::milliseconds(900)),
+                Span::new("trace-1", "span-2", "charge", "payments")
+                    .with_parent("span-1")
+                    .with_kind(SpanKind::Client)
+                    .with_start_time(start)
+                    .with_end_time(start + chrono::Duration::milliseconds(40)),
+            ])
+            .unwrap();
+
+        (create_test_store(), metrics, traces)
     }
 
     #[test]
-    fn test_execute_where_message_contains() {
-        let store = create_test_store();
+    fn test_execute_metrics_source() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+
+        let query = super::super::parse_query(
+            "SELECT * FROM metrics WHERE type = 'gauge' AND labels.host = 'web-1'",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
+
+        assert_eq!(result.total_count, 1);
+        match result.data {
+            QueryData::Metrics { metrics } => assert_eq!(metrics[0].name, "cpu_usage"),
+            other => panic!("Expected metrics, got {other:?}"),
+        }
+
         let query =
-            super::super::parse_query("SELECT * FROM logs WHERE message CONTAINS 'message'")
+            super::super::parse_query("SELECT * FROM metrics WHERE value >= 90 ORDER BY value ASC")
                 .unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
-
-        assert_eq!(result.total_count, 2); // "Info message" and "Debug message"
+        assert_eq!(result.total_count, 2);
+        match result.data {
+            QueryData::Metrics { metrics } => {
+                assert_eq!(metrics[0].name, "cpu_usage");
+                assert_eq!(metrics[1].name, "requests_total");
+            }
+            other => panic!("Expected metrics, got {other:?}"),
+        }
     }
 
     #[test]
-    fn test_execute_where_and() {
-        let store = create_test_store();
+    fn test_execute_traces_source() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+
         let query = super::super::parse_query(
-            "SELECT * FROM logs WHERE level = 'error' AND service = 'api'",
+            "SELECT * FROM traces WHERE kind = 'server' AND status = 'error' AND duration_ms > 500",
         )
         .unwrap();
-
-        let result = execute_query(&query, &store).unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
         assert_eq!(result.total_count, 1);
-        assert_eq!(result.logs[0].message, "Error occurred");
-    }
+        match result.data {
+            QueryData::Spans { spans } => assert_eq!(spans[0].span_id, "span-1"),
+            other => panic!("Expected spans, got {other:?}"),
+        }
 
-    #[test]
-    fn test_execute_where_or() {
-        let store = create_test_store();
         let query =
-            super::super::parse_query("SELECT * FROM logs WHERE level = 'error' OR level = 'warn'")
+            super::super::parse_query("SELECT * FROM traces ORDER BY duration_ms ASC LIMIT 1")
                 .unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
-
-        assert_eq!(result.total_count, 3); // 2 errors + 1 warn
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.data.len(), 1);
+        match result.data {
+            QueryData::Spans { spans } => assert_eq!(spans[0].span_id, "span-2"),
+            other => panic!("Expected spans, got {other:?}"),
+        }
     }
 
     #[test]
-    fn test_execute_where_grouped() {
-        let store = create_test_store();
+    fn test_execute_projection() {
+        let (logs, metrics, traces) = create_test_stores();
+        logs.insert(
+            LogEntry::new(LogLevel::Error, "Payment declined", "payments")
+                .with_attribute("user_id", 42),
+        )
+        .unwrap();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+
         let query = super::super::parse_query(
-            "SELECT * FROM logs WHERE (level = 'error' OR level = 'warn') AND service = 'api'",
+            "SELECT service, level AS severity, attributes.user_id, trace_id FROM logs \
+             WHERE level = 'error' ORDER BY service ASC",
         )
         .unwrap();
+        let result = execute_query(&query, stores).unwrap();
+
+        assert_eq!(result.total_count, 3);
+        match result.data {
+            QueryData::Rows { columns, rows } => {
+                assert_eq!(
+                    columns,
+                    vec!["service", "severity", "attributes.user_id", "trace_id"]
+                );
+                assert_eq!(rows.len(), 3);
+                assert_eq!(
+                    rows[2],
+                    vec![
+                        serde_json::json!("payments"),
+                        serde_json::json!("error"),
+                        serde_json::json!(42),
+                        serde_json::Value::Null,
+                    ]
+                );
+                assert_eq!(rows[0][2], serde_json::Value::Null);
+            }
+            other => panic!("Expected rows, got {other:?}"),
+        }
+    }
 
-        let result = execute_query(&query, &store).unwrap();
+    #[test]
+    fn test_execute_projection_on_spans() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        assert_eq!(result.total_count, 2); // "Error occurred" and "High memory usage"
+        let query = super::super::parse_query(
+            "SELECT span_id, duration_ms AS ms, kind FROM traces ORDER BY duration_ms DESC",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
+
+        match result.data {
+            QueryData::Rows { columns, rows } => {
+                assert_eq!(columns, vec!["span_id", "ms", "kind"]);
+                assert_eq!(
+                    rows[0],
+                    vec![
+                        serde_json::json!("span-1"),
+                        serde_json::json!(900),
+                        serde_json::json!("server"),
+                    ]
+                );
+            }
+            other => panic!("Expected rows, got {other:?}"),
+        }
     }
 
     #[test]
-    fn test_execute_order_by_timestamp_desc() {
-        let store = create_test_store();
-        let query =
-            super::super::parse_query("SELECT * FROM logs ORDER BY timestamp DESC").unwrap();
+    fn test_stream_query() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        let stream = |sql: &str| -> Vec<serde_json::Value> {
+            let query = super::super::parse_query(sql).unwrap();
+            stream_query(&query, None, stores)
+                .unwrap()
+                .map(|record| serde_json::to_value(record.unwrap()).unwrap())
+                .collect()
+        };
 
-        let result = execute_query(&query, &store).unwrap();
+        // Whole log records, as returned by execute_query
+        let query = super::super::parse_query("SELECT * FROM logs ORDER BY message ASC").unwrap();
+        let expected = serde_json::to_value(match execute_query(&query, stores).unwrap().data {
+            QueryData::Logs { logs } => logs,
+            other => panic!("Expected logs, got {other:?}"),
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::Value::Array(stream("SELECT * FROM logs ORDER BY message ASC")),
+            expected
+        );
+
+        // Projections and aggregates become objects keyed by column name
+        let rows = stream(
+            "SELECT service, level AS severity FROM logs WHERE level = 'error' \
+             ORDER BY service ASC LIMIT 1",
+        );
+        assert_eq!(
+            rows,
+            [serde_json::json!({"service": "api", "severity": "error"})]
+        );
+        let rows = stream("SELECT count(*) AS n FROM traces");
+        assert_eq!(rows, [serde_json::json!({"n": 2})]);
+
+        // Errors are reported before streaming starts
+        let query = super::super::parse_query("SELECT * FROM logs HAVING count(*) > 1").unwrap();
+        assert!(stream_query(&query, None, stores).is_err());
+    }
 
-        // Check that timestamps are in descending order
-        for i in 1..result.logs.len() {
-            assert!(result.logs[i - 1].timestamp >= result.logs[i].timestamp);
+    fn into_rows(data: QueryData) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
+        match data {
+            QueryData::Rows { columns, rows } => (columns, rows),
+            other => panic!("Expected rows, got {other:?}"),
         }
     }
 
     #[test]
-    fn test_execute_order_by_timestamp_asc() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs ORDER BY timestamp ASC").unwrap();
+    fn test_execute_group_by() {
+        let (logs, metrics, traces) = create_test_stores();
+        logs.insert(
+            LogEntry::new(LogLevel::Error, "Slow checkout", "api")
+                .with_attribute("latency_ms", 300),
+        )
+        .unwrap();
+        logs.insert(
+            LogEntry::new(LogLevel::Error, "Slow payment", "api").with_attribute("latency_ms", 100),
+        )
+        .unwrap();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        let result = execute_query(&query, &store).unwrap();
+        let query = super::super::parse_query(
+            "SELECT service, count(*) AS errors, avg(attributes.latency_ms) FROM logs \
+             WHERE level = 'error' GROUP BY service",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
-        // Check that timestamps are in ascending order
-        for i in 1..result.logs.len() {
-            assert!(result.logs[i - 1].timestamp <= result.logs[i].timestamp);
-        }
+        assert_eq!(result.total_count, 2);
+        let (columns, rows) = into_rows(result.data);
+        assert_eq!(
+            columns,
+            vec!["service", "errors", "avg(attributes.latency_ms)"]
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    serde_json::json!("api"),
+                    serde_json::json!(3),
+                    serde_json::json!(200)
+                ],
+                vec![
+                    serde_json::json!("db-service"),
+                    serde_json::json!(1),
+                    serde_json::Value::Null
+                ],
+            ]
+        );
     }
 
     #[test]
-    fn test_execute_order_by_level() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs ORDER BY level DESC").unwrap();
+    fn test_execute_having_on_unselected_aggregate() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        let result = execute_query(&query, &store).unwrap();
+        let query = super::super::parse_query(
+            "SELECT service FROM logs GROUP BY service HAVING count(*) > 1 ORDER BY service DESC",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
-        // Check that levels are in descending severity order
-        let level_orders: Vec<u8> = result.logs.iter().map(|l| level_order(l.level)).collect();
+        assert_eq!(result.total_count, 1);
+        let (columns, rows) = into_rows(result.data);
+        assert_eq!(columns, vec!["service"]);
+        assert_eq!(rows, vec![vec![serde_json::json!("api")]]);
+    }
 
-        for i in 1..level_orders.len() {
-            assert!(level_orders[i - 1] >= level_orders[i]);
+    #[test]
+    fn test_execute_aggregates_on_metrics_and_traces() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+
+        let query = super::super::parse_query(
+            "SELECT name, max(value), count_distinct(labels.host) FROM metrics \
+             GROUP BY name ORDER BY max(value) DESC",
+        )
+        .unwrap();
+        let (_, rows) = into_rows(execute_query(&query, stores).unwrap().data);
+        assert_eq!(rows[0][0], serde_json::json!("requests_total"));
+        assert_eq!(rows[1][1], serde_json::json!(91.5));
+        assert_eq!(rows[1][2], serde_json::json!(2));
+
+        let query = super::super::parse_query(
+            "SELECT count(*), sum(duration_ms), min(duration_ms) FROM traces",
+        )
+        .unwrap();
+        let (_, rows) = into_rows(execute_query(&query, stores).unwrap().data);
+        assert_eq!(
+            rows,
+            vec![vec![
+                serde_json::json!(2),
+                serde_json::json!(940),
+                serde_json::json!(40)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_execute_in_between_null_and_not() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        logs.insert(
+            LogEntry::new(LogLevel::Warn, "Slow", "checkout")
+                .with_attribute("latency_ms", 250)
+                .with_attribute("user", serde_json::Value::Null)
+                .with_trace_id("t1"),
+        )
+        .unwrap();
+        logs.insert(
+            LogEntry::new(LogLevel::Warn, "Fast", "checkout").with_attribute("latency_ms", 20),
+        )
+        .unwrap();
+
+        for (query, expected) in [
+            ("service = 'checkout' AND level IN ('warn', 'fatal')", 2),
+            (
+                "service = 'checkout' AND attributes.latency_ms IN (20, 30)",
+                1,
+            ),
+            ("service = 'checkout' AND latency_ms BETWEEN 100 AND 250", 1),
+            (
+                "service = 'checkout' AND latency_ms NOT BETWEEN 100 AND 250",
+                1,
+            ),
+            ("service = 'checkout' AND trace_id IS NOT NULL", 1),
+            ("service = 'checkout' AND trace_id IS NULL", 1),
+            // JSON null and absent attributes are both null
+            ("service = 'checkout' AND user IS NULL", 2),
+            ("service = 'checkout' AND NOT latency_ms > 100", 1),
+            // Missing fields never match IN, so they match NOT IN
+            ("service = 'checkout' AND region IN ('eu')", 0),
+            ("service = 'checkout' AND region NOT IN ('eu')", 2),
+            ("service = 'checkout' AND NOT region = 'eu'", 2),
+        ] {
+            let parsed =
+                super::super::parse_query(&format!("SELECT * FROM logs WHERE {query}")).unwrap();
+            let result = execute_query(&parsed, stores).unwrap();
+            assert_eq!(result.total_count, expected, "{query}");
         }
+
+        let query = super::super::parse_query(
+            "SELECT service, count(*) AS n FROM logs GROUP BY service HAVING n BETWEEN 2 AND 2",
+        )
+        .unwrap();
+        let (_, rows) = into_rows(execute_query(&query, stores).unwrap().data);
+        assert_eq!(
+            rows,
+            vec![vec![serde_json::json!("checkout"), serde_json::json!(2)]]
+        );
     }
 
     #[test]
-    fn test_execute_limit() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs LIMIT 2").unwrap();
+    fn test_execute_matches() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        logs.insert(
+            LogEntry::new(LogLevel::Error, "Request timeout after 350ms", "gateway")
+                .with_attribute("path", "/api/v2/orders"),
+        )
+        .unwrap();
+        logs.insert(LogEntry::new(
+            LogLevel::Error,
+            "Request timeout after ms",
+            "gateway",
+        ))
+        .unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
+        for (filter, expected) in [
+            (r"message MATCHES 'timeout after \d+ms'", 1),
+            // Patterns are case-sensitive unless they opt out
+            ("message =~ 'request timeout'", 0),
+            ("message =~ '(?i)request timeout'", 2),
+            (r"attributes.path =~ '^/api/v\d+/'", 1),
+            ("level MATCHES '^(error|fatal)$' AND service = 'gateway'", 2),
+            ("service = 'gateway' AND NOT trace_id =~ '.'", 2),
+        ] {
+            let query =
+                super::super::parse_query(&format!("SELECT * FROM logs WHERE {filter}")).unwrap();
+            // Run twice so the second evaluation uses the cached pattern
+            for _ in 0..2 {
+                let result = execute_query(&query, stores).unwrap();
+                assert_eq!(result.total_count, expected, "{filter}");
+            }
+        }
 
-        assert_eq!(result.logs.len(), 2);
-        assert_eq!(result.total_count, 5);
+        assert!(!regex_matches("(", "("));
     }
 
     #[test]
-    fn test_execute_offset() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs LIMIT 2 OFFSET 2").unwrap();
+    fn test_execute_typed_and_nested_attributes() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        logs.insert(
+            LogEntry::new(LogLevel::Error, "a", "typed")
+                .with_attribute("http.status_code", 503)
+                .with_attribute("retry", true)
+                .with_attribute(
+                    "user",
+                    serde_json::json!({"id": 7, "plan": {"tier": "pro"}}),
+                ),
+        )
+        .unwrap();
+        logs.insert(
+            LogEntry::new(LogLevel::Info, "b", "typed")
+                .with_attribute("http", serde_json::json!({"status_code": 200}))
+                .with_attribute("retry", false)
+                .with_attribute("user", serde_json::json!({"id": "7"})),
+        )
+        .unwrap();
 
-        let result = execute_query(&query, &store).unwrap();
+        for (filter, expected) in [
+            ("http.status_code >= 500", 1),
+            ("attributes.http.status_code = 200", 1),
+            ("http.status_code > 100", 2),
+            ("retry = true", 1),
+            // Types are not coerced: the string "7" is not the number 7
+            ("user.id = 7", 1),
+            ("user.id = '7'", 1),
+            ("user.plan.tier = 'pro'", 1),
+            ("user.plan.tier IS NULL", 1),
+            ("user.plan IS NOT NULL", 1),
+        ] {
+            let query = super::super::parse_query(&format!(
+                "SELECT * FROM logs WHERE service = 'typed' AND {filter}"
+            ))
+            .unwrap();
+            let result = execute_query(&query, stores).unwrap();
+            assert_eq!(result.total_count, expected, "{filter}");
+        }
+    }
 
-        assert_eq!(result.logs.len(), 2);
-        assert_eq!(result.total_count, 5);
+    #[test]
+    fn test_attribute_value() {
+        let attributes: HashMap<String, serde_json::Value> = [
+            ("a.b".to_string(), serde_json::json!(1)),
+            ("a".to_string(), serde_json::json!({"b": 2, "c": {"d": 3}})),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            attribute_value(&attributes, "a.b"),
+            Some(&serde_json::json!(1))
+        );
+        assert_eq!(
+            attribute_value(&attributes, "a.c.d"),
+            Some(&serde_json::json!(3))
+        );
+        assert_eq!(attribute_value(&attributes, "a.x"), None);
+        assert_eq!(attribute_value(&attributes, "b"), None);
     }
 
     #[test]
-    fn test_execute_full_query() {
-        let store = create_test_store();
+    fn test_execute_bucketed_series() {
+        let (logs, metrics, traces) = create_test_stores();
+        for (time, level) in [
+            ("2024-01-01T10:01:00Z", LogLevel::Error),
+            ("2024-01-01T10:03:00Z", LogLevel::Error),
+            ("2024-01-01T10:12:00Z", LogLevel::Error),
+            ("2024-01-01T10:13:00Z", LogLevel::Info),
+        ] {
+            let mut log = LogEntry::new(level, "Request failed", "api");
+            log.timestamp = DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .with_timezone(&Utc);
+            logs.insert(log).unwrap();
+        }
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+
         let query = super::super::parse_query(
-            "SELECT * FROM logs WHERE service = 'api' ORDER BY timestamp DESC LIMIT 2",
+            "SELECT bucket(timestamp, 5m) AS time, count(*) FROM logs \
+             WHERE level = 'error' AND timestamp >= '2024-01-01T10:00:00Z' \
+             AND timestamp < '2024-01-01T10:20:00Z' GROUP BY bucket(timestamp, 5m)",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
+
+        // The empty 10:05 and 10:15 buckets are filled with zero counts
+        assert_eq!(result.total_count, 4);
+        let (columns, rows) = into_rows(result.data);
+        assert_eq!(columns, vec!["time", "count(*)"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    serde_json::json!("2024-01-01T10:00:00Z"),
+                    serde_json::json!(2)
+                ],
+                vec![
+                    serde_json::json!("2024-01-01T10:05:00Z"),
+                    serde_json::json!(0)
+                ],
+                vec![
+                    serde_json::json!("2024-01-01T10:10:00Z"),
+                    serde_json::json!(1)
+                ],
+                vec![
+                    serde_json::json!("2024-01-01T10:15:00Z"),
+                    serde_json::json!(0)
+                ],
+            ]
+        );
+
+        // HAVING, ordering and pagination apply to the filled series
+        let query = super::super::parse_query(
+            "SELECT bucket(timestamp, 5m) AS time, count(*) AS errors FROM logs \
+             WHERE level = 'error' AND timestamp >= '2024-01-01T10:00:00Z' \
+             AND timestamp <= '2024-01-01T10:15:00Z' GROUP BY bucket(timestamp, 5m) \
+             HAVING errors < 1 ORDER BY time DESC LIMIT 1",
         )
         .unwrap();
+        let result = execute_query(&query, stores).unwrap();
+        assert_eq!(result.total_count, 2);
+        let (_, rows) = into_rows(result.data);
+        assert_eq!(
+            rows,
+            vec![vec![
+                serde_json::json!("2024-01-01T10:15:00Z"),
+                serde_json::json!(0)
+            ]]
+        );
+    }
 
-        let result = execute_query(&query, &store).unwrap();
+    #[test]
+    fn test_execute_since_until() {
+        let (logs, metrics, traces) = create_test_stores();
+        let now = Utc::now();
+        for minutes_ago in [120, 30, 5] {
+            let mut log = LogEntry::new(LogLevel::Info, "Tick", "clock");
+            log.timestamp = now - chrono::Duration::minutes(minutes_ago);
+            logs.insert(log).unwrap();
+        }
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        assert_eq!(result.logs.len(), 2);
-        assert_eq!(result.total_count, 3); // 3 api logs total
-        assert!(result.logs.iter().all(|l| l.service == "api"));
+        for (query, expected) in [
+            ("SELECT * FROM logs WHERE service = 'clock' SINCE 1h", 2),
+            (
+                "SELECT * FROM logs WHERE service = 'clock' SINCE now() - 3h UNTIL 1h",
+                1,
+            ),
+            (
+                "SELECT * FROM logs WHERE service = 'clock' AND timestamp < now() - 10m SINCE 1h",
+                1,
+            ),
+            ("SELECT * FROM logs WHERE service = 'clock' SINCE now()", 0),
+        ] {
+            let parsed = super::super::parse_query(query).unwrap();
+            let result = execute_query(&parsed, stores).unwrap();
+            assert_eq!(result.total_count, expected, "{query}");
+        }
     }
 
     #[test]
-    fn test_execute_unsupported_source() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM metrics").unwrap();
+    fn test_resolve_times() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE level = 'error' AND (timestamp > now() - 90s) SINCE 1d",
+        )
+        .unwrap();
 
-        let result = execute_query(&query, &store);
+        let resolved = resolve_times(&query, now);
+        assert_eq!(
+            resolved.to_string(),
+            "SELECT * FROM logs WHERE level = 'error' AND (timestamp > '2024-01-01T11:58:30Z') \
+             SINCE '2023-12-31T12:00:00Z'"
+        );
 
-        assert!(matches!(result, Err(ExecutionError::UnsupportedSource(_))));
+        let query = super::super::parse_query("SELECT * FROM logs SINCE 1704067200").unwrap();
+        assert!(matches!(resolve_times(&query, now), Cow::Borrowed(_)));
     }
 
     #[test]
-    fn test_execute_level_comparison() {
-        let store = create_test_store();
-        let query = super::super::parse_query("SELECT * FROM logs WHERE level >= 'warn'").unwrap();
-
-        let result = execute_query(&query, &store).unwrap();
+    fn test_time_range() {
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE timestamp >= 100 AND (service = 'api' AND timestamp <= 200) \
+             AND timestamp < 300",
+        )
+        .unwrap();
+        let (start, end) = time_range(query.where_clause.as_ref().unwrap(), "timestamp");
+        assert_eq!(start, DateTime::from_timestamp(100, 0));
+        assert_eq!(end, DateTime::from_timestamp(200, 1));
 
-        // Should include warn, error, fatal
-        assert_eq!(result.total_count, 3); // 1 warn + 2 errors
+        let query = super::super::parse_query(
+            "SELECT * FROM logs WHERE timestamp >= 100 OR level = 'error'",
+        )
+        .unwrap();
+        assert_eq!(
+            time_range(query.where_clause.as_ref().unwrap(), "timestamp"),
+            (None, None)
+        );
     }
 
     #[test]
-    fn test_execute_with_attributes() {
-        let store = InMemoryLogStore::new();
+    fn test_execute_distinct_and_topk() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        let log = LogEntry::new(LogLevel::Info, "Test", "api")
-            .with_attribute("user_id", "123")
-            .with_attribute("count", 42);
-        store.insert(log).unwrap();
+        let query = super::super::parse_query(
+            "SELECT DISTINCT service FROM logs WHERE level >= 'info' ORDER BY service DESC LIMIT 2",
+        )
+        .unwrap();
+        let result = execute_query(&query, stores).unwrap();
 
-        let query = super::super::parse_query("SELECT * FROM logs WHERE user_id = '123'").unwrap();
-        let result = execute_query(&query, &store).unwrap();
-        assert_eq!(result.total_count, 1);
+        assert_eq!(result.total_count, 2);
+        let (columns, rows) = into_rows(result.data);
+        assert_eq!(columns, vec!["service"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![serde_json::json!("db-service")],
+                vec![serde_json::json!("api")]
+            ]
+        );
 
-        let query = super::super::parse_query("SELECT * FROM logs WHERE count = 42").unwrap();
-        let result = execute_query(&query, &store).unwrap();
-        assert_eq!(result.total_count, 1);
+        let query =
+            super::super::parse_query("SELECT topk(2, service) AS services FROM logs").unwrap();
+        let (columns, rows) = into_rows(execute_query(&query, stores).unwrap().data);
+        assert_eq!(columns, vec!["services"]);
+        assert_eq!(rows, vec![vec![serde_json::json!(["api", "auth-service"])]]);
     }
 
     #[test]
-    fn test_execute_case_insensitive() {
-        let store = create_test_store();
+    fn test_execute_invalid_aggregations() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
 
-        // Level should be case-insensitive
-        let query = super::super::parse_query("SELECT * FROM logs WHERE level = 'ERROR'").unwrap();
-        let result = execute_query(&query, &store).unwrap();
-        assert_eq!(result.total_count, 2);
+        for query in [
+            "SELECT * FROM logs GROUP BY service",
+            "SELECT service, level FROM logs GROUP BY service",
+            "SELECT service, count(*) FROM logs GROUP BY service HAVING level = 'error'",
+            "SELECT * FROM logs HAVING count(*) > 1",
+            "SELECT bucket(timestamp, 1m), count(*) FROM logs GROUP BY service",
+            "SELECT count(*) FROM logs GROUP BY bucket(timestamp, 1m), bucket(timestamp, 1h)",
+            "SELECT count(*) FROM logs WHERE timestamp >= '2024-01-01T00:00:00Z' \
+             AND timestamp < '2024-01-02T00:00:00Z' GROUP BY bucket(timestamp, 1s)",
+            "SELECT DISTINCT * FROM logs",
+            "SELECT DISTINCT service, count(*) FROM logs",
+            "SELECT DISTINCT service FROM logs GROUP BY service",
+            "SELECT topk(0, service) FROM logs",
+        ] {
+            let query = super::super::parse_query(query).unwrap();
+            assert!(matches!(
+                execute_query(&query, stores),
+                Err(ExecutionError::InvalidAggregation(_))
+            ));
+        }
 
-        // Service should be case-insensitive
-        let query = super::super::parse_query("SELECT * FROM logs WHERE service = 'API'").unwrap();
-        let result = execute_query(&query, &store).unwrap();
-        assert_eq!(result.total_count, 3);
+        let query = super::super::parse_query(
+            "SELECT service, count(*) FROM logs GROUP BY service ORDER BY latency",
+        )
+        .unwrap();
+        assert!(matches!(
+            execute_query(&query, stores),
+            Err(ExecutionError::UnknownField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_misspelled_field_suggestions() {
+        let (logs, metrics, traces) = create_test_stores();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        let error = |sql: &str| {
+            let query = super::super::parse_query(sql).unwrap();
+            execute_query(&query, stores).unwrap_err().to_string()
+        };
+
+        let message = error("SELECT servce, count(*) FROM logs GROUP BY service");
+        assert!(message.ends_with("did you mean 'service'?"), "{message}");
+
+        let message = error("SELECT service, count(*) AS n FROM logs GROUP BY service ORDER BY m");
+        assert!(message.ends_with("did you mean 'n'?"), "{message}");
+
+        let message = error("SELECT service, count(*) FROM logs GROUP BY service ORDER BY latency");
+        assert_eq!(message, "Unknown field: 'latency'");
+
+        // Fields that run are not second-guessed
+        let query = super::super::parse_query("SELECT * FROM logs WHERE levle = 'error'").unwrap();
+        assert_eq!(execute_query(&query, stores).unwrap().total_count, 0);
     }
 }
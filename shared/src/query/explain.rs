@@ -0,0 +1,337 @@
+//! Query plans (`EXPLAIN`).
+//!
+//! Describes how a query would be executed without running it: the query after
+//! relative times are resolved, which operations the store's backend performs
+//! and which are evaluated in memory, and the statements sent to `ClickHouse`.
+
+use super::aggregate::GroupQuery;
+use super::ast::{LogicalOp, Query, Source, WhereClause};
+use super::clickhouse::SqlParam;
+use super::executor::{
+    bucket_key, build_log_query, build_metric_query, build_trace_query, ensure_bound, group_query,
+    log_query, metric_query, resolve_times, store_group, trace_query, ExecutionError, QueryStores,
+};
+use crate::storage::Cursor;
+use chrono::Utc;
+use serde::Serialize;
+
+/// The backend that executes a store's part of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Records are filtered, sorted and aggregated in memory.
+    Memory,
+    /// The query is compiled into `ClickHouse` SQL.
+    ClickHouse,
+}
+
+/// `ClickHouse`'s estimate of the data a statement reads (`EXPLAIN ESTIMATE`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReadEstimate {
+    /// Number of data parts read.
+    pub parts: u64,
+    /// Number of rows read.
+    pub rows: u64,
+    /// Number of index granules (marks) read.
+    pub granules: u64,
+}
+
+/// A statement sent to the backend.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    /// The SQL text, with `{name:Type}` parameter placeholders.
+    pub sql: String,
+    /// The backend's estimate of the data read, if it could be obtained.
+    pub estimate: Option<ReadEstimate>,
+}
+
+/// A value bound to a statement parameter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoundParam {
+    /// The parameter name (`p0`, `p1`, ...).
+    pub name: String,
+    /// The `ClickHouse` type of the parameter.
+    #[serde(rename = "type")]
+    pub param_type: &'static str,
+    /// The bound value.
+    pub value: SqlParam,
+}
+
+/// How a store executes its part of a query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StorePlan {
+    /// The backend that executes the query.
+    pub backend: Backend,
+    /// Statements sent to the backend, in execution order.
+    pub statements: Vec<Statement>,
+    /// Values bound to the statement parameters.
+    pub params: Vec<BoundParam>,
+}
+
+impl StorePlan {
+    /// The plan of a store that evaluates queries in memory.
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self {
+            backend: Backend::Memory,
+            statements: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+}
+
+/// How a query would be executed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryPlan {
+    /// The query as executed, with relative times resolved.
+    pub normalized_query: String,
+    /// Predicates and operations compiled into backend statements.
+    pub pushed_down: Vec<String>,
+    /// Predicates and operations evaluated in memory.
+    pub in_memory: Vec<String>,
+    /// How the store executes its part of the query.
+    #[serde(flatten)]
+    pub store: StorePlan,
+}
+
+/// Explains how a query would be executed, continuing after `cursor` if one is
+/// given, without running it.
+///
+/// Operations are listed as query clauses, with the WHERE clause split into its
+/// `AND`-ed predicates. The query is validated the same way as by
+/// [`super::execute_query_with_cursor`], and `ClickHouse` statements are
+/// estimated within the stores' limits.
+///
+/// # Errors
+///
+/// Returns an error if the query could not be executed, the store fails, or the
+/// query is cancelled or times out.
+pub fn explain_query(
+    query: &Query,
+    cursor: Option<&Cursor>,
+    stores: QueryStores<'_>,
+) -> Result<QueryPlan, ExecutionError> {
+    ensure_bound(query)?;
+    let query = &*resolve_times(query, Utc::now());
+    let limits = stores.limits.clone();
+
+    let mut store_ops = Vec::new();
+    if let Some(ref clause) = query.where_clause {
+        let mut predicates = Vec::new();
+        conjuncts(clause, &mut predicates);
+        store_ops.extend(predicates.into_iter().map(|p| format!("WHERE {p}")));
+    }
+    if let Some(ref since) = query.since {
+        store_ops.push(format!("SINCE {since}"));
+    }
+    if let Some(ref until) = query.until {
+        store_ops.push(format!("UNTIL {until}"));
+    }
+
+    let mut engine_ops = Vec::new();
+    let store = if query.is_aggregate() {
+        if cursor.is_some() {
+            return Err(ExecutionError::InvalidCursor(
+                "aggregate queries cannot be paginated with a cursor".to_string(),
+            ));
+        }
+        let group = group_query(query)?;
+        let bucketed = bucket_key(query).is_some();
+
+        if !group.group_by.is_empty() {
+            store_ops.push(format!("GROUP BY {}", join(&group.group_by)));
+        }
+        store_ops.push(format!("SELECT {}", join(&group.columns)));
+        // Empty buckets are filled in memory, before the rest of the query
+        let rest = if bucketed {
+            engine_ops.push("fill empty buckets".to_string());
+            &mut engine_ops
+        } else {
+            &mut store_ops
+        };
+        paging_ops(&group, rest);
+
+        let store_group = store_group(&group, bucketed);
+        match query.source {
+            Source::Logs => stores
+                .logs
+                .explain(log_query(query).with_limits(limits), Some(&store_group))?,
+            Source::Metrics => stores
+                .metrics
+                .explain(metric_query(query).with_limits(limits), Some(&store_group))?,
+            Source::Traces => stores
+                .traces
+                .explain(trace_query(query).with_limits(limits), Some(&store_group))?,
+        }
+    } else {
+        if query.having.is_some() {
+            return Err(ExecutionError::InvalidAggregation(
+                "HAVING requires GROUP BY or an aggregate function".to_string(),
+            ));
+        }
+        if cursor.is_some() {
+            store_ops.push("CURSOR".to_string());
+        }
+        if !query.order_by.is_empty() {
+            store_ops.push(format!("ORDER BY {}", join(&query.order_by)));
+        }
+        if let Some(limit) = query.limit {
+            store_ops.push(format!("LIMIT {limit}"));
+        }
+        if let Some(offset) = query.offset {
+            store_ops.push(format!("OFFSET {offset}"));
+        }
+        if !query.projection.is_empty() {
+            engine_ops.push(format!("SELECT {}", join(&query.projection)));
+        }
+
+        match query.source {
+            Source::Logs => stores
+                .logs
+                .explain(build_log_query(query, cursor)?.with_limits(limits), None)?,
+            Source::Metrics => stores
+                .metrics
+                .explain(build_metric_query(query, cursor)?.with_limits(limits), None)?,
+            Source::Traces => stores
+                .traces
+                .explain(build_trace_query(query, cursor)?.with_limits(limits), None)?,
+        }
+    };
+
+    let (pushed_down, in_memory) = match store.backend {
+        Backend::Memory => (Vec::new(), [store_ops, engine_ops].concat()),
+        Backend::ClickHouse => (store_ops, engine_ops),
+    };
+
+    Ok(QueryPlan {
+        normalized_query: query.to_string(),
+        pushed_down,
+        in_memory,
+        store,
+    })
+}
+
+/// Appends the HAVING, ORDER BY, LIMIT and OFFSET clauses of a grouped query.
+fn paging_ops(group: &GroupQuery, ops: &mut Vec<String>) {
+    if let Some(ref having) = group.having {
+        ops.push(format!("HAVING {having}"));
+    }
+    if !group.order_by.is_empty() {
+        ops.push(format!("ORDER BY {}", join(&group.order_by)));
+    }
+    if let Some(limit) = group.limit {
+        ops.push(format!("LIMIT {limit}"));
+    }
+    if let Some(offset) = group.offset {
+        ops.push(format!("OFFSET {offset}"));
+    }
+}
+
+/// Splits a clause into the predicates that are combined with `AND`.
+fn conjuncts<'a>(clause: &'a WhereClause, predicates: &mut Vec<&'a WhereClause>) {
+    match clause {
+        WhereClause::Combined {
+            left,
+            operator: LogicalOp::And,
+            right,
+        } => {
+            conjuncts(left, predicates);
+            conjuncts(right, predicates);
+        }
+        other => predicates.push(other),
+    }
+}
+
+fn join<T: std::fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LogEntry, LogLevel};
+    use crate::query::parse_query;
+    use crate::storage::{
+        InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore, LogStore, QueryLimits,
+    };
+
+    fn explain(sql: &str) -> Result<QueryPlan, ExecutionError> {
+        let logs = InMemoryLogStore::new();
+        logs.insert(LogEntry::new(LogLevel::Error, "failed", "api"))
+            .unwrap();
+        let (metrics, traces) = (InMemoryMetricStore::new(), InMemoryTraceStore::new());
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &QueryLimits::default(),
+        };
+        explain_query(&parse_query(sql).unwrap(), None, stores)
+    }
+
+    #[test]
+    fn test_explain_in_memory_query() {
+        let plan = explain(
+            "SELECT service FROM logs WHERE level = 'error' AND (service = 'api' OR service = 'web') \
+             ORDER BY service ASC LIMIT 10",
+        )
+        .unwrap();
+
+        assert_eq!(plan.store, StorePlan::in_memory());
+        assert!(plan.pushed_down.is_empty());
+        assert_eq!(
+            plan.in_memory,
+            [
+                "WHERE level = 'error'",
+                "WHERE (service = 'api' OR service = 'web')",
+                "ORDER BY service ASC",
+                "LIMIT 10",
+                "SELECT service",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_resolves_relative_times() {
+        let plan = explain("SELECT * FROM logs SINCE now() - 1h").unwrap();
+        assert!(!plan.normalized_query.contains("now()"));
+        assert!(plan
+            .normalized_query
+            .starts_with("SELECT * FROM logs SINCE '"));
+        assert_eq!(plan.in_memory.len(), 1);
+    }
+
+    #[test]
+    fn test_explain_grouped_query() {
+        let plan = explain(
+            "SELECT bucket(timestamp, 1m) AS t, count(*) FROM logs \
+             GROUP BY bucket(timestamp, 1m) HAVING count(*) > 1",
+        )
+        .unwrap();
+        assert_eq!(
+            plan.in_memory,
+            [
+                "GROUP BY bucket(timestamp, 1m)",
+                "SELECT bucket(timestamp, 1m) AS t, count(*)",
+                "fill empty buckets",
+                "HAVING count(*) > 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_validates_query() {
+        assert!(matches!(
+            explain("SELECT * FROM logs HAVING count(*) > 1"),
+            Err(ExecutionError::InvalidAggregation(_))
+        ));
+        assert!(matches!(
+            explain("SELECT service, message FROM logs GROUP BY service"),
+            Err(ExecutionError::InvalidAggregation(_))
+        ));
+    }
+}
@@ -0,0 +1,578 @@
+//! Field discovery.
+//!
+//! Lists the fields of a source with their inferred types and cardinality, and
+//! the most frequent values of a field, to help write queries. Built-in fields
+//! are always listed; attributes (logs and traces) and labels (metrics) are
+//! discovered from the stored records.
+
+use super::ast::{
+    AggregateFunction, ComparisonOp, Condition, Expr, LogicalOp, Query, SelectItem, SortOrder,
+    Source, Value, WhereClause,
+};
+use super::executor::{
+    execute_query, log_query, metric_query, trace_query, ExecutionError, QueryData, QueryStores,
+    Record,
+};
+use crate::models::{LogEntry, Metric, Span};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Maximum number of attributes or labels listed for a source.
+pub const MAX_DISCOVERED_FIELDS: usize = 1000;
+
+/// Where a field comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    /// A field every record of the source has, such as `service`.
+    Builtin,
+    /// A key in the `attributes` of logs and spans.
+    Attribute,
+    /// A metric label.
+    Label,
+}
+
+/// The type of the values seen for a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// Strings.
+    String,
+    /// Integers or floating point numbers.
+    Number,
+    /// `true` or `false`.
+    Boolean,
+    /// Points in time.
+    Timestamp,
+    /// JSON objects, whose members can be queried with dotted names.
+    Object,
+    /// JSON arrays.
+    Array,
+    /// Only `null` values were seen.
+    Null,
+    /// Values of more than one type were seen.
+    Mixed,
+}
+
+impl FieldType {
+    /// Returns the type of a JSON value.
+    fn of_json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(_) => Self::String,
+            serde_json::Value::Number(_) => Self::Number,
+            serde_json::Value::Bool(_) => Self::Boolean,
+            serde_json::Value::Object(_) => Self::Object,
+            serde_json::Value::Array(_) => Self::Array,
+            serde_json::Value::Null => Self::Null,
+        }
+    }
+
+    /// Returns the type for a `ClickHouse` `JSONType` name.
+    pub(crate) fn of_json_type(name: &str) -> Self {
+        match name {
+            "String" => Self::String,
+            "Int64" | "UInt64" | "Double" => Self::Number,
+            "Bool" => Self::Boolean,
+            "Object" => Self::Object,
+            "Array" => Self::Array,
+            _ => Self::Null,
+        }
+    }
+
+    /// Combines the types of two values of the same field. `null` values do not
+    /// change the type.
+    #[must_use]
+    pub(crate) fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Self::Null, other) | (other, Self::Null) => other,
+            _ => Self::Mixed,
+        }
+    }
+}
+
+/// A field found in the records of a source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldInfo {
+    /// The name to use in queries.
+    pub name: String,
+    /// Where the field comes from.
+    pub kind: FieldKind,
+    /// The type of its values.
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// Number of records that have the field.
+    pub count: u64,
+    /// Number of distinct values. Estimated by `ClickHouse` stores.
+    pub cardinality: u64,
+}
+
+/// A value of a field and the number of records that have it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldValueCount {
+    /// The value.
+    pub value: serde_json::Value,
+    /// Number of records with this value.
+    pub count: u64,
+}
+
+/// The records to discover fields in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldFilter {
+    /// The source to discover fields of.
+    pub source: Source,
+    /// Only records of this service (for metrics, the `service` label).
+    pub service: Option<String>,
+    /// Only records at or after this time.
+    pub start_time: Option<DateTime<Utc>>,
+    /// Only records before this time.
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl FieldFilter {
+    /// Creates a filter matching all records of `source`.
+    #[must_use]
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            service: None,
+            start_time: None,
+            end_time: None,
+        }
+    }
+
+    /// Only matches records of this service.
+    #[must_use]
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Only matches records at or after this time.
+    #[must_use]
+    pub fn with_start_time(mut self, start: DateTime<Utc>) -> Self {
+        self.start_time = Some(start);
+        self
+    }
+
+    /// Only matches records before this time.
+    #[must_use]
+    pub fn with_end_time(mut self, end: DateTime<Utc>) -> Self {
+        self.end_time = Some(end);
+        self
+    }
+
+    /// Returns a query over the matching records.
+    fn to_query(&self) -> Query {
+        let mut query = Query::new(self.source.clone());
+        if let Some(ref service) = self.service {
+            query = query.with_where(WhereClause::Condition(Condition::new(
+                "service",
+                ComparisonOp::Eq,
+                Value::String(service.clone()),
+            )));
+        }
+        if let Some(start) = self.start_time {
+            query = query.with_since(Value::String(start.to_rfc3339()));
+        }
+        if let Some(end) = self.end_time {
+            query = query.with_until(Value::String(end.to_rfc3339()));
+        }
+        query
+    }
+}
+
+/// Lists the fields of the records matching `filter`.
+///
+/// Built-in fields come first, then attributes or labels ordered by name. An
+/// attribute or label named like a built-in field is listed with its prefix
+/// (`attributes.service`, `labels.name`), which is how queries refer to it.
+///
+/// # Errors
+///
+/// Returns an error if the store fails.
+pub fn discover_fields(
+    filter: &FieldFilter,
+    stores: QueryStores<'_>,
+) -> Result<Vec<FieldInfo>, ExecutionError> {
+    let query = filter.to_query();
+    let limits = stores.limits.clone();
+    Ok(match filter.source {
+        Source::Logs => stores.logs.fields(log_query(&query).with_limits(limits))?,
+        Source::Metrics => stores
+            .metrics
+            .fields(metric_query(&query).with_limits(limits))?,
+        Source::Traces => stores
+            .traces
+            .fields(trace_query(&query).with_limits(limits))?,
+    })
+}
+
+/// Returns the most frequent values of `field` in the records matching
+/// `filter`, most frequent first.
+///
+/// Records without the field are not counted. The values are counted with a
+/// grouped query, so the `ClickHouse` stores count them in the database.
+///
+/// # Errors
+///
+/// Returns an error if the store fails.
+pub fn top_values(
+    filter: &FieldFilter,
+    field: &str,
+    limit: usize,
+    stores: QueryStores<'_>,
+) -> Result<Vec<FieldValueCount>, ExecutionError> {
+    let mut query = filter.to_query();
+    let present = WhereClause::IsNull {
+        field: field.to_string(),
+        negated: true,
+    };
+    query.where_clause = Some(match query.where_clause.take() {
+        Some(clause) => WhereClause::Combined {
+            left: Box::new(clause),
+            operator: LogicalOp::And,
+            right: Box::new(present),
+        },
+        None => present,
+    });
+    let query = query
+        .with_projection(vec![
+            SelectItem::new(field),
+            SelectItem::aggregate(AggregateFunction::Count, None),
+        ])
+        .with_group_by(vec![Expr::Field(field.to_string())])
+        .with_order_by("count(*)", SortOrder::Desc)
+        .with_limit(limit);
+
+    let QueryData::Rows { rows, .. } = execute_query(&query, stores)?.data else {
+        return Ok(Vec::new());
+    };
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let mut row = row.into_iter();
+            let value = row.next()?;
+            let count = row.next()?.as_f64()?;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Some(FieldValueCount {
+                value,
+                count: count as u64,
+            })
+        })
+        .collect())
+}
+
+/// Returns the built-in fields of a source with their types, in listing order.
+pub(crate) fn builtin_fields(source: &Source) -> &'static [(&'static str, FieldType)] {
+    match source {
+        Source::Logs => &[
+            ("timestamp", FieldType::Timestamp),
+            ("level", FieldType::String),
+            ("service", FieldType::String),
+            ("message", FieldType::String),
+            ("trace_id", FieldType::String),
+            ("span_id", FieldType::String),
+        ],
+        Source::Metrics => &[
+            ("timestamp", FieldType::Timestamp),
+            ("name", FieldType::String),
+            ("type", FieldType::String),
+            ("value", FieldType::Number),
+        ],
+        Source::Traces => &[
+            ("start_time", FieldType::Timestamp),
+            ("end_time", FieldType::Timestamp),
+            ("trace_id", FieldType::String),
+            ("span_id", FieldType::String),
+            ("parent_span_id", FieldType::String),
+            ("name", FieldType::String),
+            ("service", FieldType::String),
+            ("kind", FieldType::String),
+            ("status", FieldType::String),
+            ("duration_ms", FieldType::Number),
+        ],
+    }
+}
+
+/// Returns the query name of an attribute or label key, prefixed if the key
+/// would otherwise resolve to a built-in field.
+pub(crate) fn dynamic_field_name(source: &Source, key: &str) -> String {
+    let lower = key.to_lowercase();
+    let shadowed = builtin_fields(source)
+        .iter()
+        .any(|(name, _)| *name == lower)
+        || matches!(
+            (source, lower.as_str()),
+            (Source::Metrics, "metric_type") | (Source::Traces, "timestamp")
+        );
+    match (shadowed, source) {
+        (false, _) => key.to_string(),
+        (true, Source::Metrics) => format!("labels.{key}"),
+        (true, Source::Logs | Source::Traces) => format!("attributes.{key}"),
+    }
+}
+
+/// The kind of the fields a record has besides the built-in ones.
+pub(crate) fn dynamic_kind(source: &Source) -> FieldKind {
+    match source {
+        Source::Metrics => FieldKind::Label,
+        Source::Logs | Source::Traces => FieldKind::Attribute,
+    }
+}
+
+/// A record whose attributes or labels can be listed.
+pub(crate) trait Discoverable: Record {
+    /// The source the record belongs to.
+    const SOURCE: Source;
+
+    /// Calls `f` with each attribute or label key and value.
+    fn for_each_dynamic(&self, f: &mut dyn FnMut(&str, serde_json::Value));
+}
+
+impl Discoverable for LogEntry {
+    const SOURCE: Source = Source::Logs;
+
+    fn for_each_dynamic(&self, f: &mut dyn FnMut(&str, serde_json::Value)) {
+        for (key, value) in &self.attributes {
+            f(key, value.clone());
+        }
+    }
+}
+
+impl Discoverable for Metric {
+    const SOURCE: Source = Source::Metrics;
+
+    fn for_each_dynamic(&self, f: &mut dyn FnMut(&str, serde_json::Value)) {
+        for (key, value) in &self.labels {
+            f(key, serde_json::Value::String(value.clone()));
+        }
+    }
+}
+
+impl Discoverable for Span {
+    const SOURCE: Source = Source::Traces;
+
+    fn for_each_dynamic(&self, f: &mut dyn FnMut(&str, serde_json::Value)) {
+        for (key, value) in &self.attributes {
+            f(key, value.clone());
+        }
+    }
+}
+
+/// Counts the values of one field.
+struct FieldStats {
+    field_type: FieldType,
+    count: u64,
+    distinct: HashSet<String>,
+}
+
+impl FieldStats {
+    fn new(field_type: FieldType) -> Self {
+        Self {
+            field_type,
+            count: 0,
+            distinct: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, value: &serde_json::Value) {
+        self.count += 1;
+        self.distinct.insert(value.to_string());
+    }
+
+    fn into_info(self, name: String, kind: FieldKind) -> FieldInfo {
+        FieldInfo {
+            name,
+            kind,
+            field_type: self.field_type,
+            count: self.count,
+            cardinality: self.distinct.len() as u64,
+        }
+    }
+}
+
+/// Lists the fields of records in memory, as [`discover_fields`] does.
+///
+/// This is the reference for the `ClickHouse` implementation in
+/// [`super::clickhouse`].
+pub(crate) fn collect_fields<R: Discoverable>(records: &[R]) -> Vec<FieldInfo> {
+    let source = R::SOURCE;
+    let mut fields: Vec<FieldInfo> = builtin_fields(&source)
+        .iter()
+        .map(|(name, field_type)| {
+            let mut stats = FieldStats::new(*field_type);
+            for record in records {
+                let value = record.field(name).to_json();
+                if !value.is_null() {
+                    stats.add(&value);
+                }
+            }
+            stats.into_info((*name).to_string(), FieldKind::Builtin)
+        })
+        .collect();
+
+    let mut dynamic: BTreeMap<String, FieldStats> = BTreeMap::new();
+    for record in records {
+        record.for_each_dynamic(&mut |key, value| {
+            let stats = dynamic
+                .entry(key.to_string())
+                .or_insert_with(|| FieldStats::new(FieldType::Null));
+            stats.field_type = stats.field_type.merge(FieldType::of_json(&value));
+            stats.add(&value);
+        });
+    }
+    let kind = dynamic_kind(&source);
+    fields.extend(
+        dynamic
+            .into_iter()
+            .take(MAX_DISCOVERED_FIELDS)
+            .map(|(key, stats)| stats.into_info(dynamic_field_name(&source, &key), kind)),
+    );
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LogLevel;
+    use crate::storage::{InMemoryLogStore, InMemoryMetricStore, InMemoryTraceStore};
+    use crate::storage::{LogStore, MetricStore, QueryLimits};
+    use serde_json::json;
+
+    fn field<'a>(fields: &'a [FieldInfo], name: &str) -> &'a FieldInfo {
+        fields
+            .iter()
+            .find(|field| field.name == name)
+            .unwrap_or_else(|| panic!("no field {name} in {fields:?}"))
+    }
+
+    #[test]
+    fn test_field_type_merge() {
+        assert_eq!(
+            FieldType::Number.merge(FieldType::Number),
+            FieldType::Number
+        );
+        assert_eq!(FieldType::Null.merge(FieldType::String), FieldType::String);
+        assert_eq!(
+            FieldType::Boolean.merge(FieldType::Null),
+            FieldType::Boolean
+        );
+        assert_eq!(FieldType::String.merge(FieldType::Number), FieldType::Mixed);
+        assert_eq!(FieldType::of_json_type("UInt64"), FieldType::Number);
+    }
+
+    #[test]
+    fn test_dynamic_field_name() {
+        assert_eq!(dynamic_field_name(&Source::Logs, "user_id"), "user_id");
+        assert_eq!(
+            dynamic_field_name(&Source::Logs, "service"),
+            "attributes.service"
+        );
+        assert_eq!(dynamic_field_name(&Source::Metrics, "Name"), "labels.Name");
+        assert_eq!(dynamic_field_name(&Source::Metrics, "host"), "host");
+        assert_eq!(
+            dynamic_field_name(&Source::Traces, "timestamp"),
+            "attributes.timestamp"
+        );
+    }
+
+    #[test]
+    fn test_collect_log_fields() {
+        let logs = vec![
+            LogEntry::new(LogLevel::Error, "Boom", "api")
+                .with_attribute("status", json!(500))
+                .with_attribute("user", json!("alice")),
+            LogEntry::new(LogLevel::Info, "OK", "api")
+                .with_attribute("status", json!(200))
+                .with_attribute("user", json!(42)),
+            LogEntry::new(LogLevel::Info, "OK", "web").with_attribute("status", json!(200)),
+        ];
+
+        let fields = collect_fields(&logs);
+        let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "timestamp",
+                "level",
+                "service",
+                "message",
+                "trace_id",
+                "span_id",
+                "status",
+                "user"
+            ]
+        );
+
+        let service = field(&fields, "service");
+        assert_eq!(service.kind, FieldKind::Builtin);
+        assert_eq!((service.count, service.cardinality), (3, 2));
+        assert_eq!(field(&fields, "trace_id").count, 0);
+
+        let status = field(&fields, "status");
+        assert_eq!(status.kind, FieldKind::Attribute);
+        assert_eq!(status.field_type, FieldType::Number);
+        assert_eq!((status.count, status.cardinality), (3, 2));
+        assert_eq!(field(&fields, "user").field_type, FieldType::Mixed);
+    }
+
+    #[test]
+    fn test_discover_and_top_values() {
+        let logs = InMemoryLogStore::new();
+        let metrics = InMemoryMetricStore::new();
+        let traces = InMemoryTraceStore::new();
+        let limits = QueryLimits::default();
+        let stores = QueryStores {
+            logs: &logs,
+            metrics: &metrics,
+            traces: &traces,
+            limits: &limits,
+        };
+
+        for (service, path) in [
+            ("api", "/users"),
+            ("api", "/users"),
+            ("api", "/orders"),
+            ("web", "/"),
+        ] {
+            logs.insert(
+                LogEntry::new(LogLevel::Info, "Request", service)
+                    .with_attribute("path", json!(path)),
+            )
+            .unwrap();
+        }
+        logs.insert(LogEntry::new(LogLevel::Warn, "No path", "api"))
+            .unwrap();
+        metrics
+            .insert(Metric::gauge("cpu", 0.5).with_label("host", "a"))
+            .unwrap();
+
+        let filter = FieldFilter::new(Source::Logs).with_service("api");
+        let fields = discover_fields(&filter, stores).unwrap();
+        assert_eq!(field(&fields, "path").count, 3);
+        assert_eq!(field(&fields, "service").cardinality, 1);
+
+        let values = top_values(&filter, "path", 10, stores).unwrap();
+        assert_eq!(
+            values,
+            [
+                FieldValueCount {
+                    value: json!("/users"),
+                    count: 2
+                },
+                FieldValueCount {
+                    value: json!("/orders"),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(top_values(&filter, "level", 1, stores).unwrap().len(), 1);
+
+        let fields = discover_fields(&FieldFilter::new(Source::Metrics), stores).unwrap();
+        let host = field(&fields, "host");
+        assert_eq!(host.kind, FieldKind::Label);
+        assert_eq!(host.field_type, FieldType::String);
+    }
+}
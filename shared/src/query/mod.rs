@@ -9,8 +9,30 @@
 //! SELECT * FROM logs WHERE level = 'error' AND service = 'api'
 //! SELECT * FROM logs WHERE message CONTAINS 'failed' LIMIT 100
 //! SELECT * FROM logs WHERE level = 'error' ORDER BY timestamp DESC LIMIT 50
+//! SELECT * FROM logs WHERE attributes.user_id = '123'
+//! SELECT * FROM metrics WHERE name = 'cpu_usage' AND labels.host = 'web-1'
+//! SELECT * FROM traces WHERE service = 'api' AND duration_ms > 500 ORDER BY duration_ms DESC
+//! SELECT service, attributes.user_id AS user FROM logs
+//! SELECT service, count(*), avg(attributes.latency_ms) FROM logs GROUP BY service HAVING count(*) > 10
+//! SELECT bucket(timestamp, 5m) AS time, count(*) FROM logs GROUP BY bucket(timestamp, 5m)
+//! SELECT * FROM logs WHERE level = 'error' SINCE now() - 15m UNTIL now()
+//! SELECT * FROM logs WHERE service IN ('api', 'auth') AND trace_id IS NOT NULL AND NOT attributes.latency_ms BETWEEN 0 AND 100
+//! SELECT * FROM logs WHERE message =~ 'timeout after \d+ms' OR attributes.path MATCHES '^/api/v[12]/'
+//! SELECT * FROM traces WHERE http.status_code >= 500 AND attributes.user.plan.tier = 'pro'
+//! SELECT * FROM logs WHERE service IN ($services) AND level = $level SINCE $since
 //! ```
 //!
+//! Filters, ordering, pagination and aggregation are pushed down to the store for the source.
+//! The `ClickHouse` stores compile them into parameterized SQL; the in-memory stores
+//! evaluate them directly.
+//!
+//! `$name` parameters are bound to values with [`Query::bind`] before a query runs.
+//!
+//! [`execute_query_cached`] serves repeated queries from a [`QueryCache`].
+//!
+//! [`discover_fields`] lists the fields that can be queried in a source, and
+//! [`top_values`] their most frequent values.
+//!
 //! # Example
 //!
 //! ```
@@ -21,10 +43,41 @@
 //! assert_eq!(query.limit, Some(10));
 //! ```
 
+mod aggregate;
 mod ast;
+mod cache;
+mod clickhouse;
 mod executor;
+mod explain;
+mod fields;
+mod params;
 mod parser;
 
+pub(crate) use aggregate::group_records;
+pub use aggregate::{GroupQuery, GroupQueryResult};
 pub use ast::*;
-pub use executor::{execute_query, ExecutionError};
-pub use parser::{parse_query, ParseError};
+pub use cache::{
+    execute_query_cached, record_cells, CacheStatus, QueryCache, DEFAULT_MAX_CELLS,
+    DEFAULT_MAX_ENTRY_CELLS,
+};
+pub use clickhouse::SqlParam;
+pub(crate) use clickhouse::{
+    compile_fields_query, compile_filter, compile_group_query, compile_order, explain_statements,
+    fetch_fields, fetch_groups, SqlParams,
+};
+pub use executor::{
+    execute_log_query, execute_metric_query, execute_query, execute_query_with_cursor,
+    execute_trace_query, stream_query, ExecutionError, QueryData, QueryRecord, QueryResult,
+    QueryStores, QueryStream,
+};
+pub(crate) use executor::{matches_filter, sort_records};
+pub use explain::{
+    explain_query, Backend, BoundParam, QueryPlan, ReadEstimate, Statement, StorePlan,
+};
+pub(crate) use fields::collect_fields;
+pub use fields::{
+    discover_fields, top_values, FieldFilter, FieldInfo, FieldKind, FieldType, FieldValueCount,
+    MAX_DISCOVERED_FIELDS,
+};
+pub use params::{BindError, QueryParams};
+pub use parser::{parse_duration, parse_query, ParseError, SyntaxError};
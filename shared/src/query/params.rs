@@ -0,0 +1,642 @@
+//! Bind parameters.
+//!
+//! Queries may use `$name` placeholders wherever a value is expected:
+//!
+//! ```sql
+//! SELECT * FROM logs WHERE service = $service AND level IN ($levels) SINCE $since
+//! ```
+//!
+//! [`Query::bind`] replaces them with JSON values, checking each value against
+//! the type of the field it is compared with. Bound values are ordinary query
+//! values, so the `ClickHouse` stores send them as query parameters rather than
+//! splicing them into the SQL text.
+
+use super::ast::{ComparisonOp, Query, RelativeTime, Source, Value, WhereClause};
+use super::parser::{parse_aggregate, parse_duration};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Parameter values by name, without the leading `$`.
+pub type QueryParams = BTreeMap<String, serde_json::Value>;
+
+/// Errors that can occur when binding parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BindError {
+    /// The query uses a parameter that has no value.
+    #[error("Missing value for parameter '${0}'")]
+    Missing(String),
+
+    /// A value was given for a parameter the query does not use.
+    #[error("Unknown parameter '${0}': the query does not use it")]
+    Unknown(String),
+
+    /// The value has the wrong type for the field it is compared with.
+    #[error("Type mismatch for parameter '${name}': {field} expects {expected}, got {found}")]
+    TypeMismatch {
+        /// The parameter name.
+        name: String,
+        /// The field or clause the parameter is used with.
+        field: String,
+        /// The expected type.
+        expected: &'static str,
+        /// The type of the given value.
+        found: &'static str,
+    },
+
+    /// The value cannot be used where the parameter appears.
+    #[error("Invalid value for parameter '${name}': {message}")]
+    InvalidValue {
+        /// The parameter name.
+        name: String,
+        /// Why the value was rejected.
+        message: String,
+    },
+}
+
+impl Query {
+    /// Returns the names of the parameters used by the query, in order of first use.
+    #[must_use]
+    pub fn params(&self) -> Vec<&str> {
+        let mut values = Vec::new();
+        for clause in self.where_clause.iter().chain(&self.having) {
+            visit_values(clause, &mut |value| values.push(value));
+        }
+        values.extend(self.since.iter().chain(&self.until));
+
+        let mut names = Vec::new();
+        for value in values {
+            if let Value::Param(name) = value {
+                if !names.contains(&name.as_str()) {
+                    names.push(name.as_str());
+                }
+            }
+        }
+        names
+    }
+
+    /// Returns the query with its parameters replaced by `params`.
+    ///
+    /// String values may be compared with text fields and attributes, numbers
+    /// with numeric fields and attributes, and booleans with attributes. Time
+    /// fields take RFC 3339 timestamps or epoch seconds; `SINCE` and `UNTIL` also
+    /// take durations such as `15m`, meaning that long ago. A list bound to a
+    /// parameter inside `IN (...)` expands to its elements.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a parameter has no value, a value is given for a
+    /// parameter the query does not use, or a value has the wrong type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shared::query::{parse_query, QueryParams};
+    ///
+    /// let query = parse_query("SELECT * FROM logs WHERE service IN ($services)").unwrap();
+    /// let params = QueryParams::from([("services".to_string(), serde_json::json!(["api", "web"]))]);
+    /// let bound = query.bind(&params).unwrap();
+    /// assert_eq!(bound.to_string(), "SELECT * FROM logs WHERE service IN ('api', 'web')");
+    /// ```
+    pub fn bind(&self, params: &QueryParams) -> Result<Self, BindError> {
+        Binder::new(&self.source, params, true).bind(self)
+    }
+
+    /// Checks the types of the given parameter values without requiring a value
+    /// for every parameter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a value is given for a parameter the query does not
+    /// use or has the wrong type, as for [`Query::bind`].
+    pub fn check_params(&self, params: &QueryParams) -> Result<(), BindError> {
+        Binder::new(&self.source, params, false)
+            .bind(self)
+            .map(|_| ())
+    }
+}
+
+/// Calls `f` with every value in the clause.
+fn visit_values<'a>(clause: &'a WhereClause, f: &mut impl FnMut(&'a Value)) {
+    match clause {
+        WhereClause::Condition(condition) => f(&condition.value),
+        WhereClause::Combined { left, right, .. } => {
+            visit_values(left, f);
+            visit_values(right, f);
+        }
+        WhereClause::Grouped(inner) | WhereClause::Not(inner) => visit_values(inner, f),
+        WhereClause::In { values, .. } => values.iter().for_each(f),
+        WhereClause::Between { low, high, .. } => {
+            f(low);
+            f(high);
+        }
+        WhereClause::IsNull { .. } => {}
+    }
+}
+
+/// The type of values a field can be compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    /// Text fields, such as `service`.
+    Text,
+    /// Numeric fields and aggregates.
+    Number,
+    /// Timestamps, compared with RFC 3339 strings or epoch seconds.
+    Time,
+    /// Attributes, which may hold any JSON scalar.
+    Any,
+}
+
+impl FieldType {
+    /// Returns the type of a record field, mirroring the executor's `Record` impls.
+    fn of_field(source: &Source, field: &str) -> Self {
+        match (source, field.to_lowercase().as_str()) {
+            (_, "timestamp") | (Source::Traces, "start_time" | "end_time") => Self::Time,
+            (Source::Metrics, "value") | (Source::Traces, "duration_ms") => Self::Number,
+            // Metric labels are strings
+            (Source::Logs, "level" | "service" | "message" | "trace_id" | "span_id")
+            | (
+                Source::Traces,
+                "trace_id" | "span_id" | "parent_span_id" | "name" | "service" | "kind" | "status",
+            )
+            | (Source::Metrics, _) => Self::Text,
+            _ => Self::Any,
+        }
+    }
+
+    /// Returns the type of an output column of a grouped query.
+    fn of_column(source: &Source, column: &str) -> Self {
+        if parse_aggregate(column).is_some() {
+            Self::Number
+        } else if column.to_lowercase().starts_with("bucket(") {
+            Self::Time
+        } else {
+            Self::of_field(source, column)
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Text => "a string",
+            Self::Number => "a number",
+            Self::Time => "a timestamp or epoch seconds",
+            Self::Any => "a string, number or boolean",
+        }
+    }
+}
+
+/// Returns the name of a JSON value's type, for error messages.
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "a list",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Replaces parameters with values, collecting the names it sees.
+struct Binder<'a> {
+    source: &'a Source,
+    params: &'a QueryParams,
+    /// Whether every parameter must have a value.
+    require_all: bool,
+    used: Vec<String>,
+}
+
+impl<'a> Binder<'a> {
+    fn new(source: &'a Source, params: &'a QueryParams, require_all: bool) -> Self {
+        Self {
+            source,
+            params,
+            require_all,
+            used: Vec::new(),
+        }
+    }
+
+    fn bind(mut self, query: &Query) -> Result<Query, BindError> {
+        let mut bound = query.clone();
+        if let Some(clause) = &mut bound.where_clause {
+            self.clause(clause, false)?;
+        }
+        if let Some(clause) = &mut bound.having {
+            self.clause(clause, true)?;
+        }
+        if let Some(since) = &mut bound.since {
+            self.time_bound(since, "SINCE")?;
+        }
+        if let Some(until) = &mut bound.until {
+            self.time_bound(until, "UNTIL")?;
+        }
+
+        if let Some(name) = self.params.keys().find(|name| !self.used.contains(name)) {
+            return Err(BindError::Unknown(name.clone()));
+        }
+        Ok(bound)
+    }
+
+    /// Returns the value of a parameter, or `None` if it has none and values are
+    /// not required.
+    fn lookup(&mut self, name: &str) -> Result<Option<&'a serde_json::Value>, BindError> {
+        if !self.used.iter().any(|used| used == name) {
+            self.used.push(name.to_string());
+        }
+        match self.params.get(name) {
+            Some(value) => Ok(Some(value)),
+            None if self.require_all => Err(BindError::Missing(name.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn clause(&mut self, clause: &mut WhereClause, having: bool) -> Result<(), BindError> {
+        let source = self.source;
+        let field_type = |field: &str| {
+            if having {
+                FieldType::of_column(source, field)
+            } else {
+                FieldType::of_field(source, field)
+            }
+        };
+        match clause {
+            WhereClause::Condition(condition) => {
+                let ty = field_type(&condition.field);
+                self.value(
+                    &mut condition.value,
+                    &condition.field,
+                    ty,
+                    &condition.operator,
+                )
+            }
+            WhereClause::Combined { left, right, .. } => {
+                self.clause(left, having)?;
+                self.clause(right, having)
+            }
+            WhereClause::Grouped(inner) | WhereClause::Not(inner) => self.clause(inner, having),
+            WhereClause::In { field, values, .. } => {
+                let ty = field_type(field);
+                self.list(values, field, ty)
+            }
+            WhereClause::Between {
+                field, low, high, ..
+            } => {
+                let ty = field_type(field);
+                self.value(low, field, ty, &ComparisonOp::GtEq)?;
+                self.value(high, field, ty, &ComparisonOp::LtEq)
+            }
+            WhereClause::IsNull { .. } => Ok(()),
+        }
+    }
+
+    fn value(
+        &mut self,
+        value: &mut Value,
+        field: &str,
+        ty: FieldType,
+        operator: &ComparisonOp,
+    ) -> Result<(), BindError> {
+        let Value::Param(name) = value else {
+            return Ok(());
+        };
+        let name = name.clone();
+        if let Some(json) = self.lookup(&name)? {
+            *value = convert(&name, json, field, ty, operator)?;
+        }
+        Ok(())
+    }
+
+    /// Binds the values of an `IN` list, expanding parameters bound to lists.
+    fn list(
+        &mut self,
+        values: &mut Vec<Value>,
+        field: &str,
+        ty: FieldType,
+    ) -> Result<(), BindError> {
+        let mut bound = Vec::with_capacity(values.len());
+        for value in values.drain(..) {
+            let Value::Param(name) = value else {
+                bound.push(value);
+                continue;
+            };
+            match self.lookup(&name)? {
+                Some(serde_json::Value::Array(items)) if items.is_empty() => {
+                    return Err(BindError::InvalidValue {
+                        name,
+                        message: "lists cannot be empty".to_string(),
+                    });
+                }
+                Some(serde_json::Value::Array(items)) => {
+                    for item in items {
+                        bound.push(convert(&name, item, field, ty, &ComparisonOp::Eq)?);
+                    }
+                }
+                Some(json) => bound.push(convert(&name, json, field, ty, &ComparisonOp::Eq)?),
+                None => bound.push(Value::Param(name)),
+            }
+        }
+        *values = bound;
+        Ok(())
+    }
+
+    /// Binds a `SINCE` or `UNTIL` bound.
+    fn time_bound(&mut self, value: &mut Value, clause: &str) -> Result<(), BindError> {
+        let Value::Param(name) = value else {
+            return Ok(());
+        };
+        let name = name.clone();
+        let Some(json) = self.lookup(&name)? else {
+            return Ok(());
+        };
+        *value = match json {
+            serde_json::Value::String(s) => match parse_duration(s) {
+                Ok(duration) => Value::Time(RelativeTime::ago(duration)),
+                Err(_) if chrono::DateTime::parse_from_rfc3339(s).is_ok() => {
+                    Value::String(s.clone())
+                }
+                Err(_) => {
+                    return Err(BindError::InvalidValue {
+                        name,
+                        message: format!(
+                            "'{s}' is neither a duration (e.g. 15m) nor an RFC 3339 timestamp"
+                        ),
+                    })
+                }
+            },
+            serde_json::Value::Number(n) if n.is_i64() => Value::Integer(n.as_i64().unwrap_or(0)),
+            other => {
+                return Err(BindError::TypeMismatch {
+                    name,
+                    field: clause.to_string(),
+                    expected: "a duration, timestamp or epoch seconds",
+                    found: json_type(other),
+                })
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Converts a JSON value to a query value for comparison with `field`.
+fn convert(
+    name: &str,
+    json: &serde_json::Value,
+    field: &str,
+    ty: FieldType,
+    operator: &ComparisonOp,
+) -> Result<Value, BindError> {
+    let mismatch = |expected: &'static str| BindError::TypeMismatch {
+        name: name.to_string(),
+        field: field.to_string(),
+        expected,
+        found: json_type(json),
+    };
+    let invalid = |message: &str| BindError::InvalidValue {
+        name: name.to_string(),
+        message: message.to_string(),
+    };
+
+    let text_operator = matches!(
+        operator,
+        ComparisonOp::Contains
+            | ComparisonOp::StartsWith
+            | ComparisonOp::EndsWith
+            | ComparisonOp::Matches
+    );
+    if text_operator {
+        let serde_json::Value::String(s) = json else {
+            return Err(mismatch("a string"));
+        };
+        if *operator == ComparisonOp::Matches {
+            regex::Regex::new(s)
+                .map_err(|e| invalid(&format!("invalid regular expression: {e}")))?;
+        }
+        return Ok(Value::String(s.clone()));
+    }
+
+    match (ty, json) {
+        (_, serde_json::Value::Null) => Err(invalid("null is not a value; use IS NULL")),
+        (_, serde_json::Value::Array(_)) => Err(invalid("lists are only allowed in IN (...)")),
+        (FieldType::Text | FieldType::Any, serde_json::Value::String(s)) => {
+            Ok(Value::String(s.clone()))
+        }
+        (FieldType::Time, serde_json::Value::String(s)) => {
+            if chrono::DateTime::parse_from_rfc3339(s).is_ok() {
+                Ok(Value::String(s.clone()))
+            } else {
+                Err(invalid(&format!("'{s}' is not an RFC 3339 timestamp")))
+            }
+        }
+        (FieldType::Time, serde_json::Value::Number(n)) => n
+            .as_i64()
+            .map(Value::Integer)
+            .ok_or_else(|| mismatch(FieldType::Time.description())),
+        (FieldType::Number | FieldType::Any, serde_json::Value::Number(n)) => {
+            Ok(n.as_i64().map_or_else(
+                || Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+                Value::Integer,
+            ))
+        }
+        (FieldType::Any, serde_json::Value::Bool(b)) => Ok(Value::Boolean(*b)),
+        (ty, _) => Err(mismatch(ty.description())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query;
+    use serde_json::json;
+
+    fn params(pairs: &[(&str, serde_json::Value)]) -> QueryParams {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), v.clone()))
+            .collect()
+    }
+
+    fn bind(query: &str, pairs: &[(&str, serde_json::Value)]) -> Result<String, BindError> {
+        parse_query(query)
+            .unwrap()
+            .bind(&params(pairs))
+            .map(|q| q.to_string())
+    }
+
+    #[test]
+    fn test_params_in_order_of_use() {
+        let query = parse_query(
+            "SELECT * FROM logs WHERE service = $service AND (level IN ('error', $level) \
+             OR attributes.n BETWEEN $low AND $high) AND service != $service SINCE $since",
+        )
+        .unwrap();
+        assert_eq!(
+            query.params(),
+            vec!["service", "level", "low", "high", "since"]
+        );
+        assert_eq!(
+            query.to_string(),
+            "SELECT * FROM logs WHERE service = $service AND (level IN ('error', $level) \
+             OR attributes.n BETWEEN $low AND $high) AND service != $service SINCE $since"
+        );
+    }
+
+    #[test]
+    fn test_bind_values() {
+        assert_eq!(
+            bind(
+                "SELECT * FROM logs WHERE service = $s AND message CONTAINS $text",
+                &[("s", json!("it's")), ("text", json!("say \"hi\""))],
+            )
+            .unwrap(),
+            "SELECT * FROM logs WHERE service = 'it's' AND message CONTAINS 'say \"hi\"'"
+        );
+        assert_eq!(
+            bind(
+                "SELECT * FROM traces WHERE duration_ms > $ms AND attributes.retry = $retry",
+                &[("ms", json!(250)), ("retry", json!(true))],
+            )
+            .unwrap(),
+            "SELECT * FROM traces WHERE duration_ms > 250 AND attributes.retry = true"
+        );
+        assert_eq!(
+            bind(
+                "SELECT service, count(*) FROM logs GROUP BY service HAVING count(*) > $min",
+                &[("min", json!(1.5))],
+            )
+            .unwrap(),
+            "SELECT service, count(*) FROM logs GROUP BY service HAVING count(*) > 1.5"
+        );
+    }
+
+    #[test]
+    fn test_bind_lists() {
+        assert_eq!(
+            bind(
+                "SELECT * FROM logs WHERE level NOT IN ($levels, 'debug')",
+                &[("levels", json!(["trace", "info"]))],
+            )
+            .unwrap(),
+            "SELECT * FROM logs WHERE level NOT IN ('trace', 'info', 'debug')"
+        );
+        assert_eq!(
+            bind(
+                "SELECT * FROM logs WHERE service IN ($s)",
+                &[("s", json!("api"))]
+            )
+            .unwrap(),
+            "SELECT * FROM logs WHERE service IN ('api')"
+        );
+        assert!(matches!(
+            bind(
+                "SELECT * FROM logs WHERE service IN ($s)",
+                &[("s", json!([]))]
+            ),
+            Err(BindError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            bind(
+                "SELECT * FROM logs WHERE service = $s",
+                &[("s", json!(["a"]))]
+            ),
+            Err(BindError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bind_time_bounds() {
+        let query = parse_query("SELECT * FROM logs SINCE $since UNTIL $until").unwrap();
+        let bound = query
+            .bind(&params(&[
+                ("since", json!("15m")),
+                ("until", json!("2024-01-01T00:00:00Z")),
+            ]))
+            .unwrap();
+        assert_eq!(
+            bound.since,
+            Some(Value::Time(RelativeTime { offset_secs: -900 }))
+        );
+        assert_eq!(
+            bound.until,
+            Some(Value::String("2024-01-01T00:00:00Z".to_string()))
+        );
+
+        let bound = query
+            .bind(&params(&[
+                ("since", json!(1_704_067_200)),
+                ("until", json!("1h")),
+            ]))
+            .unwrap();
+        assert_eq!(bound.since, Some(Value::Integer(1_704_067_200)));
+
+        for since in [json!("soon"), json!(true), json!(1.5)] {
+            assert!(query
+                .bind(&params(&[("since", since), ("until", json!("1h"))]))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_bind_type_checks() {
+        let cases = [
+            ("SELECT * FROM logs WHERE service = $v", json!(1)),
+            ("SELECT * FROM traces WHERE duration_ms > $v", json!("500")),
+            ("SELECT * FROM metrics WHERE labels.host = $v", json!(true)),
+            ("SELECT * FROM logs WHERE timestamp > $v", json!(false)),
+            (
+                "SELECT * FROM logs WHERE attributes.n = $v",
+                json!({"a": 1}),
+            ),
+            ("SELECT * FROM logs WHERE message CONTAINS $v", json!(5)),
+        ];
+        for (query, value) in cases {
+            assert!(
+                matches!(
+                    bind(query, &[("v", value.clone())]),
+                    Err(BindError::TypeMismatch { .. } | BindError::InvalidValue { .. })
+                ),
+                "{query} with {value}"
+            );
+        }
+
+        assert!(matches!(
+            bind(
+                "SELECT * FROM logs WHERE timestamp > $v",
+                &[("v", json!("yesterday"))]
+            ),
+            Err(BindError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            bind(
+                "SELECT * FROM logs WHERE message MATCHES $re",
+                &[("re", json!("a(b"))]
+            ),
+            Err(BindError::InvalidValue { .. })
+        ));
+        assert_eq!(
+            bind(
+                "SELECT * FROM logs WHERE message =~ $re",
+                &[("re", json!(r"\d+ms"))]
+            )
+            .unwrap(),
+            r"SELECT * FROM logs WHERE message MATCHES '\d+ms'"
+        );
+    }
+
+    #[test]
+    fn test_missing_and_unknown_params() {
+        let query = parse_query("SELECT * FROM logs WHERE service = $service").unwrap();
+        assert_eq!(
+            query.bind(&QueryParams::new()),
+            Err(BindError::Missing("service".to_string()))
+        );
+        assert_eq!(
+            query.bind(&params(&[("service", json!("api")), ("typo", json!(1))])),
+            Err(BindError::Unknown("typo".to_string()))
+        );
+
+        assert!(query.check_params(&QueryParams::new()).is_ok());
+        assert!(query
+            .check_params(&params(&[("service", json!(1))]))
+            .is_err());
+        assert!(query.check_params(&params(&[("other", json!(1))])).is_err());
+    }
+}
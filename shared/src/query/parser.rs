@@ -5,27 +5,37 @@
 //! - `SELECT * FROM logs WHERE level = 'error'`
 //! - `SELECT * FROM logs WHERE level = 'error' AND service = 'api'`
 //! - `SELECT * FROM logs WHERE message CONTAINS 'failed' LIMIT 100`
+//! - `SELECT service, attributes.user_id AS user FROM logs`
+//! - `SELECT service, count(*) FROM logs GROUP BY service HAVING count(*) > 10`
+//! - `SELECT bucket(timestamp, 1m) AS minute, count(*) FROM logs GROUP BY bucket(timestamp, 1m)`
+//! - `SELECT * FROM logs WHERE level = 'error' SINCE now() - 15m`
+//! - `SELECT * FROM logs WHERE service IN ('api', 'auth') AND NOT trace_id IS NULL`
+//! - `SELECT * FROM logs WHERE message =~ 'timeout after \d+ms'`
+//! - `SELECT * FROM logs WHERE service = $service SINCE $since`
 
 use super::ast::{
-    ComparisonOp, Condition, LogicalOp, OrderBy, Query, SortOrder, Source, Value, WhereClause,
+    AggregateFunction, ComparisonOp, Condition, DurationLiteral, DurationUnit, Expr, LogicalOp,
+    OrderBy, Query, RelativeTime, SelectItem, SortOrder, Source, Value, WhereClause,
 };
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag, tag_no_case, take_while1},
-    character::complete::{char, digit1, multispace0, multispace1, none_of},
-    combinator::{map, map_res, opt, recognize, value},
-    multi::many0,
+    bytes::complete::{escaped, tag, tag_no_case, take_while, take_while1},
+    character::complete::{anychar, char, digit1, multispace0, multispace1, none_of, satisfy},
+    combinator::{map, map_res, not, opt, peek, recognize, value, verify},
+    multi::{many0, separated_list1},
     sequence::{delimited, pair, preceded},
     IResult, Parser,
 };
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use thiserror::Error;
 
 /// Errors that can occur during query parsing.
 #[derive(Debug, Error)]
 pub enum ParseError {
-    /// The query syntax is invalid.
-    #[error("Invalid query syntax: {0}")]
-    SyntaxError(String),
+    /// The query syntax is invalid, or it names a field that looks misspelled.
+    #[error("Invalid query syntax at {0}")]
+    SyntaxError(Box<SyntaxError>),
 
     /// An unexpected token was encountered.
     #[error("Unexpected token: expected {expected}, found '{found}'")]
@@ -43,6 +53,122 @@ pub enum ParseError {
     /// Unknown data source.
     #[error("Unknown data source: '{0}'. Expected 'logs', 'metrics', or 'traces'")]
     UnknownSource(String),
+
+    /// A `MATCHES` pattern is not a valid regular expression.
+    #[error("Invalid regular expression '{pattern}': {message}")]
+    InvalidPattern {
+        /// The pattern as written in the query.
+        pattern: String,
+        /// Why the pattern was rejected.
+        message: String,
+    },
+
+    /// A duration is not a duration literal.
+    #[error("Invalid duration: '{0}' (expected e.g. 30s, 5m, 1h or 7d)")]
+    InvalidDuration(String),
+}
+
+impl ParseError {
+    /// Returns where the query is invalid, for syntax errors.
+    #[must_use]
+    pub fn syntax(&self) -> Option<&SyntaxError> {
+        match self {
+            Self::SyntaxError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Where a query is invalid, what was found there and what was expected.
+///
+/// Lines and columns are 1-based; columns count characters. `offset` is the
+/// byte offset of the offending token in the query as given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyntaxError {
+    /// What is wrong, e.g. `unexpected 'FORM', expected 'FROM'`.
+    pub message: String,
+    /// Line of the offending token.
+    pub line: usize,
+    /// Column of the offending token.
+    pub column: usize,
+    /// Byte offset of the offending token.
+    pub offset: usize,
+    /// The offending token, or an empty string at the end of the query.
+    pub found: String,
+    /// The tokens that could have appeared instead: keywords and symbols as
+    /// written (`FROM`, `)`), or descriptions such as `a value`.
+    pub expected: Vec<String>,
+    /// A likely correction for a misspelled keyword, source or field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+    /// The offending line with a caret under the token.
+    pub snippet: String,
+}
+
+impl SyntaxError {
+    /// Creates an error for the token at `offset` in `input`.
+    fn at(input: &str, offset: usize, expected: &[&'static str]) -> Self {
+        let found = token_at(&input[offset..]).to_string();
+        let found_text = if found.is_empty() {
+            "end of query".to_string()
+        } else {
+            format!("'{found}'")
+        };
+        let message = if expected.is_empty() {
+            format!("unexpected {found_text}")
+        } else {
+            format!("unexpected {found_text}, expected {}", describe(expected))
+        };
+        let suggestion = closest(&found, expected.iter().copied()).map(ToString::to_string);
+        Self::new(input, offset, found, message, expected, suggestion)
+    }
+
+    fn new(
+        input: &str,
+        offset: usize,
+        found: String,
+        message: String,
+        expected: &[&str],
+        suggestion: Option<String>,
+    ) -> Self {
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+        let line = input[..offset].matches('\n').count() + 1;
+        let column = input[line_start..offset].chars().count() + 1;
+        let snippet = format!(
+            "{}\n{}{}",
+            &input[line_start..line_end],
+            " ".repeat(column - 1),
+            "^".repeat(found.chars().count().max(1))
+        );
+
+        Self {
+            message,
+            line,
+            column,
+            offset,
+            found,
+            expected: expected.iter().map(ToString::to_string).collect(),
+            suggestion,
+            snippet,
+        }
+    }
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, ". Did you mean '{suggestion}'?")?;
+        }
+        write!(f, "\n{}", self.snippet)
+    }
 }
 
 /// Parses a SQL-like query string into a Query AST.
@@ -62,6 +188,11 @@ pub enum ParseError {
 /// - The syntax is invalid
 /// - There is unexpected trailing content
 ///
+/// Syntax errors carry the position of the offending token, the expected
+/// alternatives and a suggestion for misspelled keywords and sources. Fields
+/// that are not built-in are attributes or labels, so misspelled fields are not
+/// syntax errors.
+///
 /// # Examples
 ///
 /// ```
@@ -69,26 +200,234 @@ pub enum ParseError {
 ///
 /// let query = parse_query("SELECT * FROM logs WHERE level = 'error'").unwrap();
 /// assert_eq!(query.source, Source::Logs);
+///
+/// let err = parse_query("SELECT * FORM logs").unwrap_err();
+/// let syntax = err.syntax().unwrap();
+/// assert_eq!((syntax.line, syntax.column), (1, 10));
+/// assert_eq!(syntax.suggestion.as_deref(), Some("FROM"));
 /// ```
-pub fn parse_query(input: &str) -> Result<Query, ParseError> {
-    let input = input.trim();
+pub fn parse_query(original: &str) -> Result<Query, ParseError> {
+    let input = original.trim();
     if input.is_empty() {
         return Err(ParseError::EmptyQuery);
     }
+    let leading = original.len() - original.trim_start().len();
+    let offset_of = |remaining: usize| leading + input.len() - remaining;
+
+    DIAGNOSTICS.with_borrow_mut(|diagnostics| *diagnostics = Diagnostics::default());
+    let result = query(input);
+    let diagnostics = DIAGNOSTICS.take();
+
+    let remaining = match result {
+        Ok((remaining, query)) if remaining.trim().is_empty() => {
+            for clause in query.where_clause.iter().chain(&query.having) {
+                validate_patterns(clause)?;
+            }
+            return Ok(query);
+        }
+        Ok((remaining, _)) => remaining.len(),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e.input.len(),
+        Err(nom::Err::Incomplete(_)) => 0,
+    };
+
+    // Report the furthest point the parsers got to, which is where the query
+    // stops making sense
+    let error = match diagnostics.furthest {
+        Some(furthest) if furthest <= remaining => {
+            SyntaxError::at(original, offset_of(furthest), &diagnostics.expected)
+        }
+        _ => SyntaxError::at(original, offset_of(remaining), &[]),
+    };
+    Err(ParseError::SyntaxError(Box::new(error)))
+}
+
+/// Parses a duration literal such as `30s`, `5m`, `1h` or `7d` on its own.
+///
+/// # Errors
+///
+/// Returns a `ParseError` if the input is not a duration literal.
+///
+/// # Examples
+///
+/// ```
+/// use shared::query::parse_duration;
+///
+/// assert_eq!(parse_duration("15m").unwrap().as_secs(), 900);
+/// assert!(parse_duration("15 minutes").is_err());
+/// ```
+pub fn parse_duration(input: &str) -> Result<DurationLiteral, ParseError> {
+    match duration_literal(input.trim()) {
+        Ok(("", duration)) => Ok(duration),
+        _ => Err(ParseError::InvalidDuration(input.to_string())),
+    }
+}
 
-    match query(input) {
-        Ok((remaining, query)) => {
-            let remaining = remaining.trim();
-            if remaining.is_empty() {
-                Ok(query)
+/// Parses an aggregate call such as `count(*)` on its own.
+///
+/// Used to resolve aggregates that are referenced by HAVING or ORDER BY but not selected.
+pub(crate) fn parse_aggregate(input: &str) -> Option<Expr> {
+    match aggregate_call(input.trim()) {
+        Ok(("", expr)) => Some(expr),
+        _ => None,
+    }
+}
+
+// ============================================================================
+// Error reporting
+// ============================================================================
+
+/// What the parsers of one [`parse_query`] call found out about the query,
+/// for reporting errors.
+///
+/// nom discards the errors of alternatives and optional clauses, so the
+/// labelled parsers record their failures here instead. The furthest failure
+/// is where the query stops making sense, and the labels recorded there are
+/// what could have appeared instead. Positions are lengths of the remaining
+/// input, so a smaller value is further into the query.
+#[derive(Debug, Default)]
+struct Diagnostics {
+    /// Position of the furthest failure.
+    furthest: Option<usize>,
+    /// Labels of the parsers that failed at the furthest position.
+    expected: Vec<&'static str>,
+}
+
+thread_local! {
+    static DIAGNOSTICS: RefCell<Diagnostics> = RefCell::default();
+}
+
+/// Records that `label` was expected at the start of `input`.
+fn record_expected(input: &str, label: &'static str) {
+    DIAGNOSTICS.with_borrow_mut(|diagnostics| match diagnostics.furthest {
+        Some(furthest) if furthest < input.len() => {}
+        Some(furthest) if furthest == input.len() => {
+            if !diagnostics.expected.contains(&label) {
+                diagnostics.expected.push(label);
+            }
+        }
+        _ => {
+            diagnostics.furthest = Some(input.len());
+            diagnostics.expected = vec![label];
+        }
+    });
+}
+
+/// Wraps a parser so that its failures are reported as expecting `label`.
+///
+/// Labels of keywords and symbols are written as in a query (`FROM`, `)`);
+/// other labels describe what was expected and start with "a" or "an".
+fn expect<'a, O>(
+    label: &'static str,
+    mut parser: impl Parser<&'a str, Output = O, Error = nom::error::Error<&'a str>>,
+) -> impl Parser<&'a str, Output = O, Error = nom::error::Error<&'a str>> {
+    move |input: &'a str| {
+        let result = parser.parse(input);
+        if result.is_err() {
+            record_expected(input, label);
+        }
+        result
+    }
+}
+
+/// Parses a keyword, case-insensitively.
+fn keyword<'a>(
+    keyword: &'static str,
+) -> impl Parser<&'a str, Output = &'a str, Error = nom::error::Error<&'a str>> {
+    expect(keyword, tag_no_case(keyword))
+}
+
+/// Parses a symbol such as `(` or `,`.
+fn symbol<'a>(
+    symbol: &'static str,
+) -> impl Parser<&'a str, Output = &'a str, Error = nom::error::Error<&'a str>> {
+    expect(symbol, tag(symbol))
+}
+
+/// Parses a field name.
+fn field_name(input: &str) -> IResult<&str, &str> {
+    expect("a field", identifier).parse(input)
+}
+
+/// Returns the token at the start of `input`: a word, a quoted string, an
+/// operator or a single character. Empty at the end of the query.
+fn token_at(input: &str) -> &str {
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '$');
+    let is_operator = |c: char| matches!(c, '=' | '!' | '<' | '>' | '~');
+    let Some(first) = input.chars().next() else {
+        return "";
+    };
+    let end = if first == '\'' || first == '"' {
+        input[1..].find(first).map_or(input.len(), |i| i + 2)
+    } else if is_word(first) {
+        input.find(|c: char| !is_word(c)).unwrap_or(input.len())
+    } else if is_operator(first) {
+        input.find(|c: char| !is_operator(c)).unwrap_or(input.len())
+    } else {
+        first.len_utf8()
+    };
+    &input[..end]
+}
+
+/// Lists the expected labels: `'FROM'`, `',' or 'AS'`, `a field, ')' or 'AS'`.
+fn describe(expected: &[&str]) -> String {
+    let quoted: Vec<String> = expected
+        .iter()
+        .map(|label| {
+            if label.starts_with("a ") || label.starts_with("an ") {
+                (*label).to_string()
             } else {
-                Err(ParseError::SyntaxError(format!(
-                    "Unexpected trailing content: '{remaining}'"
-                )))
+                format!("'{label}'")
+            }
+        })
+        .collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
+
+/// Returns the candidate that `found` most likely misspells: one that differs
+/// by a single edit, or by two for words of more than four characters.
+/// Multi-word candidates such as `GROUP BY` are compared by their first word.
+pub(crate) fn closest<'c>(
+    found: &str,
+    candidates: impl Iterator<Item = &'c str>,
+) -> Option<&'c str> {
+    let found = found.to_lowercase();
+    let max_distance = if found.chars().count() > 4 { 2 } else { 1 };
+    candidates
+        .filter(|candidate| !candidate.starts_with("a ") && !candidate.starts_with("an "))
+        .filter(|candidate| candidate.chars().any(char::is_alphabetic))
+        .map(|candidate| {
+            let word = candidate.split(' ').next().unwrap_or(candidate);
+            (edit_distance(&found, &word.to_lowercase()), candidate)
+        })
+        .filter(|(distance, _)| (1..=max_distance).contains(distance))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Number of single-character insertions, deletions, substitutions and
+/// transpositions of adjacent characters needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
             }
         }
-        Err(e) => Err(ParseError::SyntaxError(format!("{e}"))),
+        rows.push(row);
     }
+    rows[a.len()][b.len()]
 }
 
 // ============================================================================
@@ -97,11 +436,12 @@ pub fn parse_query(input: &str) -> Result<Query, ParseError> {
 
 fn query(input: &str) -> IResult<&str, Query> {
     let (input, _) = multispace0(input)?;
-    let (input, _) = tag_no_case("SELECT")(input)?;
+    let (input, _) = keyword("SELECT").parse(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, _) = char('*')(input)?;
+    let (input, distinct) = opt((keyword("DISTINCT"), multispace1)).parse(input)?;
+    let (input, projection) = projection(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, _) = tag_no_case("FROM")(input)?;
+    let (input, _) = keyword("FROM").parse(input)?;
     let (input, _) = multispace1(input)?;
     let (input, source) = source(input)?;
     let (input, _) = multispace0(input)?;
@@ -109,6 +449,18 @@ fn query(input: &str) -> IResult<&str, Query> {
     let (input, where_clause) = opt(where_clause).parse(input)?;
     let (input, _) = multispace0(input)?;
 
+    let (input, since) = opt(since_clause).parse(input)?;
+    let (input, _) = multispace0(input)?;
+
+    let (input, until) = opt(until_clause).parse(input)?;
+    let (input, _) = multispace0(input)?;
+
+    let (input, group_by) = opt(group_by).parse(input)?;
+    let (input, _) = multispace0(input)?;
+
+    let (input, having) = opt(having_clause).parse(input)?;
+    let (input, _) = multispace0(input)?;
+
     let (input, order_by) = opt(order_by).parse(input)?;
     let (input, _) = multispace0(input)?;
 
@@ -121,24 +473,182 @@ fn query(input: &str) -> IResult<&str, Query> {
     Ok((
         input,
         Query {
+            distinct: distinct.is_some(),
+            projection,
             source,
             where_clause,
-            order_by,
+            since,
+            until,
+            group_by: group_by.unwrap_or_default(),
+            having,
+            order_by: order_by.unwrap_or_default(),
             limit,
             offset,
         },
     ))
 }
 
+/// Rejects `MATCHES` conditions whose pattern does not compile.
+fn validate_patterns(clause: &WhereClause) -> Result<(), ParseError> {
+    match clause {
+        WhereClause::Condition(Condition {
+            operator: ComparisonOp::Matches,
+            value: Value::String(pattern),
+            ..
+        }) => regex::Regex::new(pattern)
+            .map(|_| ())
+            .map_err(|e| ParseError::InvalidPattern {
+                pattern: pattern.clone(),
+                message: match e {
+                    regex::Error::Syntax(message) => message
+                        .lines()
+                        .last()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                        .to_string(),
+                    other => other.to_string(),
+                },
+            }),
+        WhereClause::Combined { left, right, .. } => {
+            validate_patterns(left)?;
+            validate_patterns(right)
+        }
+        WhereClause::Grouped(inner) | WhereClause::Not(inner) => validate_patterns(inner),
+        _ => Ok(()),
+    }
+}
+
+// ============================================================================
+// Projection parser
+// ============================================================================
+
+fn projection(input: &str) -> IResult<&str, Vec<SelectItem>> {
+    alt((
+        value(Vec::new(), symbol("*")),
+        separated_list1((multispace0, symbol(","), multispace0), select_item),
+    ))
+    .parse(input)
+}
+
+fn select_item(input: &str) -> IResult<&str, SelectItem> {
+    let (input, expr) = alt((aggregate_call, key_expr)).parse(input)?;
+    let (input, alias) = opt(preceded(
+        (multispace1, keyword("AS"), multispace1),
+        expect("an alias", identifier),
+    ))
+    .parse(input)?;
+
+    Ok((
+        input,
+        SelectItem {
+            expr,
+            alias: alias.map(ToString::to_string),
+        },
+    ))
+}
+
+/// Parses a grouping key: a time bucket or a field.
+fn key_expr(input: &str) -> IResult<&str, Expr> {
+    alt((bucket_call, map(field_name, |f| Expr::Field(f.to_string())))).parse(input)
+}
+
+/// Parses a time bucket such as `bucket(timestamp, 5m)`.
+fn bucket_call(input: &str) -> IResult<&str, Expr> {
+    let (input, _) = (tag_no_case("bucket"), multispace0, symbol("("), multispace0).parse(input)?;
+    let (input, field) = field_name(input)?;
+    let (input, _) = (multispace0, symbol(","), multispace0).parse(input)?;
+    let (input, interval) = expect("a duration", duration_literal).parse(input)?;
+    let (input, _) = (multispace0, symbol(")")).parse(input)?;
+
+    Ok((
+        input,
+        Expr::Bucket {
+            field: field.to_string(),
+            interval,
+        },
+    ))
+}
+
+/// Parses a duration literal such as `30s`, `5m`, `1h` or `7d`.
+fn duration_literal(input: &str) -> IResult<&str, DurationLiteral> {
+    let (input, amount) = map_res(digit1, |s: &str| s.parse::<u64>()).parse(input)?;
+    let (input, unit) = alt((
+        value(DurationUnit::Seconds, tag_no_case("s")),
+        value(DurationUnit::Minutes, tag_no_case("m")),
+        value(DurationUnit::Hours, tag_no_case("h")),
+        value(DurationUnit::Days, tag_no_case("d")),
+    ))
+    .parse(input)?;
+    // A unit must not run into an identifier (e.g. `5min`)
+    let (input, ()) = not(satisfy(|c: char| c.is_alphanumeric() || c == '_')).parse(input)?;
+
+    Ok((input, DurationLiteral::new(amount, unit)))
+}
+
+/// Parses an aggregate call such as `count(*)`, `avg(attributes.latency_ms)`
+/// or `topk(10, name)`.
+fn aggregate_call(input: &str) -> IResult<&str, Expr> {
+    alt((topk_call, function_call)).parse(input)
+}
+
+/// Parses `topk(k, field)`.
+fn topk_call(input: &str) -> IResult<&str, Expr> {
+    let (input, _) = (tag_no_case("topk"), multispace0, symbol("("), multispace0).parse(input)?;
+    let (input, k) =
+        expect("a number", map_res(digit1, |s: &str| s.parse::<usize>())).parse(input)?;
+    let (input, _) = (multispace0, symbol(","), multispace0).parse(input)?;
+    let (input, field) = field_name(input)?;
+    let (input, _) = (multispace0, symbol(")")).parse(input)?;
+
+    Ok((
+        input,
+        Expr::Aggregate {
+            function: AggregateFunction::TopK(k),
+            field: Some(field.to_string()),
+        },
+    ))
+}
+
+/// Parses a single-argument aggregate call such as `sum(value)`.
+fn function_call(input: &str) -> IResult<&str, Expr> {
+    let (input, function) = alt((
+        value(
+            AggregateFunction::CountDistinct,
+            tag_no_case("count_distinct"),
+        ),
+        value(AggregateFunction::Count, tag_no_case("count")),
+        value(AggregateFunction::Sum, tag_no_case("sum")),
+        value(AggregateFunction::Avg, tag_no_case("avg")),
+        value(AggregateFunction::Min, tag_no_case("min")),
+        value(AggregateFunction::Max, tag_no_case("max")),
+    ))
+    .parse(input)?;
+    let (input, _) = (multispace0, symbol("("), multispace0).parse(input)?;
+    let (input, field) = if function == AggregateFunction::Count {
+        alt((value(None, symbol("*")), map(field_name, Some))).parse(input)?
+    } else {
+        map(field_name, Some).parse(input)?
+    };
+    let (input, _) = (multispace0, symbol(")")).parse(input)?;
+
+    Ok((
+        input,
+        Expr::Aggregate {
+            function,
+            field: field.map(ToString::to_string),
+        },
+    ))
+}
+
 // ============================================================================
 // Source parser
 // ============================================================================
 
 fn source(input: &str) -> IResult<&str, Source> {
     alt((
-        value(Source::Logs, tag_no_case("logs")),
-        value(Source::Metrics, tag_no_case("metrics")),
-        value(Source::Traces, tag_no_case("traces")),
+        value(Source::Logs, keyword("logs")),
+        value(Source::Metrics, keyword("metrics")),
+        value(Source::Traces, keyword("traces")),
     ))
     .parse(input)
 }
@@ -147,22 +657,24 @@ fn source(input: &str) -> IResult<&str, Source> {
 // WHERE clause parser
 // ============================================================================
 
+/// Parses the field on the left-hand side of a condition.
+type FieldParser = fn(&str) -> IResult<&str, String>;
+
 fn where_clause(input: &str) -> IResult<&str, WhereClause> {
-    let (input, _) = tag_no_case("WHERE")(input)?;
+    let (input, _) = keyword("WHERE").parse(input)?;
     let (input, _) = multispace1(input)?;
-    where_expression(input)
+    or_expression(input, where_field)
 }
 
-fn where_expression(input: &str) -> IResult<&str, WhereClause> {
-    or_expression(input)
+fn where_field(input: &str) -> IResult<&str, String> {
+    map(field_name, ToString::to_string).parse(input)
 }
 
-fn or_expression(input: &str) -> IResult<&str, WhereClause> {
-    let (input, first) = and_expression(input)?;
-    let (input, rest) = many0(preceded(
-        (multispace1, tag_no_case("OR"), multispace1),
-        and_expression,
-    ))
+fn or_expression(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, first) = and_expression(input, field)?;
+    let (input, rest) = many0(preceded((multispace1, keyword("OR"), multispace1), |i| {
+        and_expression(i, field)
+    }))
     .parse(input)?;
 
     let result = rest
@@ -176,12 +688,11 @@ fn or_expression(input: &str) -> IResult<&str, WhereClause> {
     Ok((input, result))
 }
 
-fn and_expression(input: &str) -> IResult<&str, WhereClause> {
-    let (input, first) = primary_condition(input)?;
-    let (input, rest) = many0(preceded(
-        (multispace1, tag_no_case("AND"), multispace1),
-        primary_condition,
-    ))
+fn and_expression(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, first) = primary_condition(input, field)?;
+    let (input, rest) = many0(preceded((multispace1, keyword("AND"), multispace1), |i| {
+        primary_condition(i, field)
+    }))
     .parse(input)?;
 
     let result = rest
@@ -195,37 +706,146 @@ fn and_expression(input: &str) -> IResult<&str, WhereClause> {
     Ok((input, result))
 }
 
-fn primary_condition(input: &str) -> IResult<&str, WhereClause> {
-    alt((grouped_condition, map(condition, WhereClause::Condition))).parse(input)
+fn primary_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    alt((
+        |i| not_condition(i, field),
+        |i| grouped_condition(i, field),
+        |i| in_condition(i, field),
+        |i| between_condition(i, field),
+        |i| null_condition(i, field),
+        map(|i| condition(i, field), WhereClause::Condition),
+    ))
+    .parse(input)
+}
+
+/// Parses `NOT <condition>`. `NOT` binds tighter than `AND` and `OR`.
+fn not_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, _) = (keyword("NOT"), alt((multispace1, peek(tag("("))))).parse(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, inner) = primary_condition(input, field)?;
+
+    Ok((input, WhereClause::Not(Box::new(inner))))
+}
+
+/// Parses an optional `NOT` before `IN` or `BETWEEN`.
+fn negation(input: &str) -> IResult<&str, bool> {
+    map(opt((keyword("NOT"), multispace1)), |not| not.is_some()).parse(input)
+}
+
+/// Parses `field [NOT] IN (value, ...)`.
+fn in_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, field) = field(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, negated) = negation(input)?;
+    let (input, _) = (keyword("IN"), multispace0, symbol("("), multispace0).parse(input)?;
+    let (input, values) =
+        separated_list1((multispace0, symbol(","), multispace0), query_value).parse(input)?;
+    let (input, _) = (multispace0, symbol(")")).parse(input)?;
+
+    Ok((
+        input,
+        WhereClause::In {
+            field,
+            values,
+            negated,
+        },
+    ))
+}
+
+/// Parses `field [NOT] BETWEEN low AND high`.
+fn between_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, field) = field(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, negated) = negation(input)?;
+    let (input, _) = (keyword("BETWEEN"), multispace1).parse(input)?;
+    let (input, low) = query_value(input)?;
+    let (input, _) = (multispace1, keyword("AND"), multispace1).parse(input)?;
+    let (input, high) = query_value(input)?;
+
+    Ok((
+        input,
+        WhereClause::Between {
+            field,
+            low,
+            high,
+            negated,
+        },
+    ))
+}
+
+/// Parses `field IS [NOT] NULL`.
+fn null_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, field) = field(input)?;
+    let (input, _) = (multispace1, keyword("IS"), multispace1).parse(input)?;
+    let (input, negated) = negation(input)?;
+    let (input, _) = keyword("NULL").parse(input)?;
+
+    Ok((input, WhereClause::IsNull { field, negated }))
 }
 
-fn grouped_condition(input: &str) -> IResult<&str, WhereClause> {
-    let (input, _) = char('(')(input)?;
+fn grouped_condition(input: &str, field: FieldParser) -> IResult<&str, WhereClause> {
+    let (input, _) = symbol("(").parse(input)?;
     let (input, _) = multispace0(input)?;
-    let (input, expr) = where_expression(input)?;
+    let (input, expr) = or_expression(input, field)?;
     let (input, _) = multispace0(input)?;
-    let (input, _) = char(')')(input)?;
+    let (input, _) = symbol(")").parse(input)?;
 
     Ok((input, WhereClause::Grouped(Box::new(expr))))
 }
 
-fn condition(input: &str) -> IResult<&str, Condition> {
-    let (input, field) = identifier(input)?;
+fn condition(input: &str, field: FieldParser) -> IResult<&str, Condition> {
+    let (input, field) = field(input)?;
     let (input, _) = multispace0(input)?;
-    let (input, operator) = comparison_op(input)?;
+    let (input, operator) = expect("a comparison operator", comparison_op).parse(input)?;
     let (input, _) = multispace0(input)?;
-    let (input, value) = query_value(input)?;
+    // Patterns are always strings, so a pattern cannot be mistaken for a number
+    let (input, value) = if operator == ComparisonOp::Matches {
+        expect("a string", alt((string_value, param_value))).parse(input)?
+    } else {
+        query_value(input)?
+    };
 
     Ok((
         input,
         Condition {
-            field: field.to_string(),
+            field,
             operator,
             value,
         },
     ))
 }
 
+// ============================================================================
+// GROUP BY and HAVING clauses
+// ============================================================================
+
+fn group_by(input: &str) -> IResult<&str, Vec<Expr>> {
+    let (input, _) = (
+        expect("GROUP BY", tag_no_case("GROUP")),
+        multispace1,
+        keyword("BY"),
+        multispace1,
+    )
+        .parse(input)?;
+    separated_list1((multispace0, char(','), multispace0), key_expr).parse(input)
+}
+
+fn having_clause(input: &str) -> IResult<&str, WhereClause> {
+    let (input, _) = keyword("HAVING").parse(input)?;
+    let (input, _) = multispace1(input)?;
+    or_expression(input, output_column)
+}
+
+/// Parses a reference to an output column of a grouped query: an aggregate call
+/// or time bucket (normalized to e.g. `count(*)`) or a field name / alias.
+fn output_column(input: &str) -> IResult<&str, String> {
+    alt((
+        map(alt((aggregate_call, bucket_call)), |expr| expr.to_string()),
+        where_field,
+    ))
+    .parse(input)
+}
+
 // ============================================================================
 // Comparison operators
 // ============================================================================
@@ -233,6 +853,10 @@ fn condition(input: &str) -> IResult<&str, Condition> {
 fn comparison_op(input: &str) -> IResult<&str, ComparisonOp> {
     alt((
         value(ComparisonOp::NotEq, alt((tag("!="), tag("<>")))),
+        value(
+            ComparisonOp::Matches,
+            alt((tag("=~"), tag_no_case("MATCHES"))),
+        ),
         value(ComparisonOp::LtEq, tag("<=")),
         value(ComparisonOp::GtEq, tag(">=")),
         value(ComparisonOp::Eq, char('=')),
@@ -256,17 +880,69 @@ fn comparison_op(input: &str) -> IResult<&str, ComparisonOp> {
 // ============================================================================
 
 fn query_value(input: &str) -> IResult<&str, Value> {
-    alt((boolean_value, float_value, integer_value, string_value)).parse(input)
+    expect(
+        "a value",
+        alt((
+            param_value,
+            time_value,
+            boolean_value,
+            float_value,
+            integer_value,
+            string_value,
+        )),
+    )
+    .parse(input)
+}
+
+/// Parses `now()`, optionally shifted by a duration: `now() - 15m`, `now() + 1h`.
+fn time_value(input: &str) -> IResult<&str, Value> {
+    let (input, _) = (
+        tag_no_case("now"),
+        multispace0,
+        symbol("("),
+        multispace0,
+        symbol(")"),
+    )
+        .parse(input)?;
+    let (input, offset) = opt((
+        multispace0,
+        alt((char('-'), char('+'))),
+        multispace0,
+        expect("a duration", duration_literal),
+    ))
+    .parse(input)?;
+
+    let time = match offset {
+        None => RelativeTime::now(),
+        Some((_, '-', _, duration)) => RelativeTime::ago(duration),
+        Some((_, _, _, duration)) => RelativeTime::ahead(duration),
+    };
+    Ok((input, Value::Time(time)))
+}
+
+/// Parses a bind parameter such as `$service`.
+fn param_value(input: &str) -> IResult<&str, Value> {
+    let (input, name) = preceded(
+        char('$'),
+        recognize(pair(
+            satisfy(|c: char| c.is_ascii_alphabetic() || c == '_'),
+            take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        )),
+    )
+    .parse(input)?;
+    Ok((input, Value::Param(name.to_string())))
 }
 
 fn string_value(input: &str) -> IResult<&str, Value> {
     alt((single_quoted_string, double_quoted_string)).parse(input)
 }
 
+/// Backslash escapes are kept verbatim, so patterns such as `'\d+'` reach the
+/// regex engine unchanged.
 fn single_quoted_string(input: &str) -> IResult<&str, Value> {
     let (input, s) = delimited(
         char('\''),
-        alt((escaped(none_of("'\\"), '\\', char('\'')), tag(""))),
+        alt((escaped(none_of("'\\"), '\\', anychar), tag(""))),
         char('\''),
     )
     .parse(input)?;
@@ -276,7 +952,7 @@ fn single_quoted_string(input: &str) -> IResult<&str, Value> {
 fn double_quoted_string(input: &str) -> IResult<&str, Value> {
     let (input, s) = delimited(
         char('"'),
-        alt((escaped(none_of("\"\\"), '\\', char('"')), tag(""))),
+        alt((escaped(none_of("\"\\"), '\\', anychar), tag(""))),
         char('"'),
     )
     .parse(input)?;
@@ -308,23 +984,57 @@ fn boolean_value(input: &str) -> IResult<&str, Value> {
     .parse(input)
 }
 
+// ============================================================================
+// SINCE / UNTIL clauses
+// ============================================================================
+
+fn since_clause(input: &str) -> IResult<&str, Value> {
+    preceded((keyword("SINCE"), multispace1), time_bound).parse(input)
+}
+
+fn until_clause(input: &str) -> IResult<&str, Value> {
+    preceded((keyword("UNTIL"), multispace1), time_bound).parse(input)
+}
+
+/// Parses a time range bound: `now()` expressions, a bare duration meaning that
+/// long ago (`SINCE 1h`), an RFC 3339 timestamp, epoch seconds or a parameter.
+fn time_bound(input: &str) -> IResult<&str, Value> {
+    expect("a time", alt((
+        param_value,
+        time_value,
+        map(duration_literal, |duration| {
+            Value::Time(RelativeTime::ago(duration))
+        }),
+        verify(string_value, |value| {
+            matches!(value, Value::String(s) if chrono::DateTime::parse_from_rfc3339(s).is_ok())
+        }),
+        integer_value,
+    )))
+    .parse(input)
+}
+
 // ============================================================================
 // ORDER BY clause
 // ============================================================================
 
-fn order_by(input: &str) -> IResult<&str, OrderBy> {
-    let (input, _) = tag_no_case("ORDER")(input)?;
+fn order_by(input: &str) -> IResult<&str, Vec<OrderBy>> {
+    let (input, _) = expect("ORDER BY", tag_no_case("ORDER")).parse(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, _) = tag_no_case("BY")(input)?;
+    let (input, _) = keyword("BY").parse(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, field) = identifier(input)?;
+    separated_list1((multispace0, symbol(","), multispace0), order_key).parse(input)
+}
+
+/// A sort key: `service`, `attributes.user_id ASC`, `count(*) DESC`.
+fn order_key(input: &str) -> IResult<&str, OrderBy> {
+    let (input, field) = output_column(input)?;
     let (input, _) = multispace0(input)?;
     let (input, order) = opt(sort_order).parse(input)?;
 
     Ok((
         input,
         OrderBy {
-            field: field.to_string(),
+            field,
             order: order.unwrap_or_default(),
         },
     ))
@@ -332,8 +1042,8 @@ fn order_by(input: &str) -> IResult<&str, OrderBy> {
 
 fn sort_order(input: &str) -> IResult<&str, SortOrder> {
     alt((
-        value(SortOrder::Asc, tag_no_case("ASC")),
-        value(SortOrder::Desc, tag_no_case("DESC")),
+        value(SortOrder::Asc, keyword("ASC")),
+        value(SortOrder::Desc, keyword("DESC")),
     ))
     .parse(input)
 }
@@ -343,16 +1053,18 @@ fn sort_order(input: &str) -> IResult<&str, SortOrder> {
 // ============================================================================
 
 fn limit_clause(input: &str) -> IResult<&str, usize> {
-    let (input, _) = tag_no_case("LIMIT")(input)?;
+    let (input, _) = keyword("LIMIT").parse(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, n) = map_res(digit1, |s: &str| s.parse::<usize>()).parse(input)?;
+    let (input, n) =
+        expect("a number", map_res(digit1, |s: &str| s.parse::<usize>())).parse(input)?;
     Ok((input, n))
 }
 
 fn offset_clause(input: &str) -> IResult<&str, usize> {
-    let (input, _) = tag_no_case("OFFSET")(input)?;
+    let (input, _) = keyword("OFFSET").parse(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, n) = map_res(digit1, |s: &str| s.parse::<usize>()).parse(input)?;
+    let (input, n) =
+        expect("a number", map_res(digit1, |s: &str| s.parse::<usize>())).parse(input)?;
     Ok((input, n))
 }
 
@@ -360,8 +1072,9 @@ fn offset_clause(input: &str) -> IResult<&str, usize> {
 // Identifier parser
 // ============================================================================
 
+/// Parses a field name. Dots are allowed for nested paths such as `attributes.user_id`.
 fn identifier(input: &str) -> IResult<&str, &str> {
-    take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '.')(input)
 }
 
 // ============================================================================
@@ -377,7 +1090,7 @@ mod tests {
         let query = parse_query("SELECT * FROM logs").unwrap();
         assert_eq!(query.source, Source::Logs);
         assert!(query.where_clause.is_none());
-        assert!(query.order_by.is_none());
+        assert!(query.order_by.is_empty());
         assert!(query.limit.is_none());
     }
 
@@ -628,37 +1341,49 @@ mod tests {
     fn test_parse_order_by() {
         let query = parse_query("SELECT * FROM logs ORDER BY timestamp DESC").unwrap();
 
-        match query.order_by {
-            Some(ob) => {
-                assert_eq!(ob.field, "timestamp");
-                assert_eq!(ob.order, SortOrder::Desc);
-            }
-            None => panic!("Expected ORDER BY"),
-        }
+        assert_eq!(query.order_by.len(), 1);
+        assert_eq!(query.order_by[0].field, "timestamp");
+        assert_eq!(query.order_by[0].order, SortOrder::Desc);
     }
 
     #[test]
     fn test_parse_order_by_asc() {
         let query = parse_query("SELECT * FROM logs ORDER BY timestamp ASC").unwrap();
 
-        match query.order_by {
-            Some(ob) => {
-                assert_eq!(ob.order, SortOrder::Asc);
-            }
-            None => panic!("Expected ORDER BY"),
-        }
+        assert_eq!(query.order_by[0].order, SortOrder::Asc);
     }
 
     #[test]
     fn test_parse_order_by_default_desc() {
         let query = parse_query("SELECT * FROM logs ORDER BY timestamp").unwrap();
 
-        match query.order_by {
-            Some(ob) => {
-                assert_eq!(ob.order, SortOrder::Desc); // Default is DESC
-            }
-            None => panic!("Expected ORDER BY"),
-        }
+        assert_eq!(query.order_by[0].order, SortOrder::Desc); // Default is DESC
+    }
+
+    #[test]
+    fn test_parse_order_by_multiple_keys() {
+        let query = parse_query(
+            "SELECT * FROM logs ORDER BY service ASC,timestamp , attributes.user_id ASC LIMIT 5",
+        )
+        .unwrap();
+
+        let keys: Vec<(&str, SortOrder)> = query
+            .order_by
+            .iter()
+            .map(|key| (key.field.as_str(), key.order.clone()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("service", SortOrder::Asc),
+                ("timestamp", SortOrder::Desc),
+                ("attributes.user_id", SortOrder::Asc),
+            ]
+        );
+        assert_eq!(query.limit, Some(5));
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        assert!(parse_query("SELECT * FROM logs ORDER BY service,").is_err());
     }
 
     #[test]
@@ -682,7 +1407,7 @@ mod tests {
 
         assert_eq!(query.source, Source::Logs);
         assert!(query.where_clause.is_some());
-        assert!(query.order_by.is_some());
+        assert_eq!(query.order_by.len(), 1);
         assert_eq!(query.limit, Some(100));
         assert_eq!(query.offset, Some(10));
     }
@@ -729,4 +1454,497 @@ mod tests {
             _ => panic!("Expected single condition"),
         }
     }
+
+    #[test]
+    fn test_parse_attribute_path() {
+        let query = parse_query("SELECT * FROM logs WHERE attributes.user_id = '123'").unwrap();
+
+        match query.where_clause {
+            Some(WhereClause::Condition(c)) => {
+                assert_eq!(c.field, "attributes.user_id");
+                assert_eq!(c.value, Value::String("123".to_string()));
+            }
+            _ => panic!("Expected single condition"),
+        }
+    }
+
+    #[test]
+    fn test_parse_projection() {
+        let query =
+            parse_query("SELECT service, level,message FROM logs WHERE level = 'error'").unwrap();
+
+        assert_eq!(
+            query.projection,
+            vec![
+                SelectItem::new("service"),
+                SelectItem::new("level"),
+                SelectItem::new("message"),
+            ]
+        );
+        assert!(query.where_clause.is_some());
+    }
+
+    #[test]
+    fn test_parse_projection_with_alias() {
+        let query = parse_query("select attributes.user_id as user, service FROM logs").unwrap();
+
+        assert_eq!(
+            query.projection,
+            vec![
+                SelectItem::new("attributes.user_id").with_alias("user"),
+                SelectItem::new("service"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_select_star_has_no_projection() {
+        let query = parse_query("SELECT * FROM logs").unwrap();
+        assert!(query.projection.is_empty());
+    }
+
+    #[test]
+    fn test_parse_projection_errors() {
+        assert!(parse_query("SELECT service, FROM logs").is_err());
+        assert!(parse_query("SELECT service AS FROM logs").is_err());
+        assert!(parse_query("SELECT *, service FROM logs").is_err());
+    }
+
+    #[test]
+    fn test_parse_group_by_with_aggregates() {
+        let query = parse_query(
+            "SELECT service, COUNT(*), avg(attributes.latency_ms) AS latency FROM logs \
+             WHERE level = 'error' GROUP BY service",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.projection,
+            vec![
+                SelectItem::new("service"),
+                SelectItem::aggregate(AggregateFunction::Count, None),
+                SelectItem::aggregate(AggregateFunction::Avg, Some("attributes.latency_ms"))
+                    .with_alias("latency"),
+            ]
+        );
+        assert_eq!(query.group_by, vec![Expr::Field("service".to_string())]);
+        assert!(query.where_clause.is_some());
+        assert!(query.having.is_none());
+    }
+
+    #[test]
+    fn test_parse_all_aggregate_functions() {
+        let query = parse_query(
+            "SELECT count(service), sum(value), min(value), max(value), count_distinct(host) \
+             FROM metrics",
+        )
+        .unwrap();
+
+        let functions: Vec<String> = query.projection.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            functions,
+            vec![
+                "count(service)",
+                "sum(value)",
+                "min(value)",
+                "max(value)",
+                "count_distinct(host)"
+            ]
+        );
+        assert!(query.group_by.is_empty());
+        assert!(query.is_aggregate());
+    }
+
+    #[test]
+    fn test_parse_having_and_order_by_aggregate() {
+        let query = parse_query(
+            "SELECT service, count(*) FROM logs GROUP BY service, level \
+             HAVING COUNT(*) > 10 AND avg(duration_ms) >= 2.5 ORDER BY count(*) DESC LIMIT 5",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.group_by,
+            vec![
+                Expr::Field("service".to_string()),
+                Expr::Field("level".to_string())
+            ]
+        );
+        assert_eq!(
+            query.having.unwrap().to_string(),
+            "count(*) > 10 AND avg(duration_ms) >= 2.5"
+        );
+        assert_eq!(query.order_by[0].field, "count(*)");
+        assert_eq!(query.order_by[0].order, SortOrder::Desc);
+        assert_eq!(query.limit, Some(5));
+    }
+
+    #[test]
+    fn test_parse_aggregate_errors() {
+        assert!(parse_query("SELECT sum(*) FROM metrics").is_err());
+        assert!(parse_query("SELECT count( FROM logs").is_err());
+        assert!(parse_query("SELECT * FROM logs GROUP BY").is_err());
+        assert!(parse_query("SELECT * FROM logs WHERE count(*) > 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_field_named_like_function() {
+        let query = parse_query("SELECT counter, min_value FROM metrics").unwrap();
+        assert_eq!(
+            query.projection,
+            vec![SelectItem::new("counter"), SelectItem::new("min_value")]
+        );
+    }
+
+    #[test]
+    fn test_parse_distinct_and_topk() {
+        let query =
+            parse_query("SELECT DISTINCT service, level FROM logs WHERE level = 'error' SINCE 1h")
+                .unwrap();
+        assert!(query.distinct);
+        assert_eq!(
+            query.projection,
+            vec![SelectItem::new("service"), SelectItem::new("level")]
+        );
+        assert!(query.is_aggregate());
+
+        let query =
+            parse_query("SELECT service, TOPK( 5 , name ) AS names FROM traces GROUP BY service")
+                .unwrap();
+        assert!(!query.distinct);
+        assert_eq!(
+            query.projection[1],
+            SelectItem::aggregate(AggregateFunction::TopK(5), Some("name")).with_alias("names")
+        );
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        for invalid in [
+            "SELECT topk(name) FROM traces",
+            "SELECT topk(5, *) FROM traces",
+            "SELECT DISTINCT FROM logs",
+        ] {
+            assert!(parse_query(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_parse_bucket() {
+        let query = parse_query(
+            "SELECT bucket(timestamp, 5m) AS time, service, count(*) FROM logs \
+             GROUP BY BUCKET( timestamp , 5m ), service ORDER BY bucket(timestamp, 5m) ASC",
+        )
+        .unwrap();
+
+        let bucket = Expr::Bucket {
+            field: "timestamp".to_string(),
+            interval: DurationLiteral::new(5, DurationUnit::Minutes),
+        };
+        assert_eq!(query.projection[0].expr, bucket);
+        assert_eq!(query.projection[0].column_name(), "time");
+        assert_eq!(
+            query.group_by,
+            vec![bucket, Expr::Field("service".to_string())]
+        );
+        assert_eq!(query.order_by[0].field, "bucket(timestamp, 5m)");
+    }
+
+    #[test]
+    fn test_parse_duration_literals() {
+        for (text, secs) in [("30s", 30), ("5m", 300), ("1h", 3600), ("7d", 604_800)] {
+            let (rest, duration) = duration_literal(text).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(duration.as_secs(), secs);
+            assert_eq!(duration.to_string(), text);
+        }
+
+        assert!(duration_literal("5min").is_err());
+        assert!(duration_literal("m").is_err());
+        assert!(parse_query("SELECT count(*) FROM logs GROUP BY bucket(timestamp)").is_err());
+    }
+
+    #[test]
+    fn test_parse_now() {
+        let query = parse_query("SELECT * FROM logs WHERE timestamp >= now() - 15m").unwrap();
+        let Some(WhereClause::Condition(condition)) = query.where_clause else {
+            panic!("Expected a condition");
+        };
+        assert_eq!(
+            condition.value,
+            Value::Time(RelativeTime { offset_secs: -900 })
+        );
+
+        for (text, offset_secs) in [("now()", 0), ("NOW ( ) + 1h", 3600), ("now()-7d", -604_800)] {
+            let (rest, value) = time_value(text).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(value, Value::Time(RelativeTime { offset_secs }));
+        }
+    }
+
+    #[test]
+    fn test_parse_since_until() {
+        let query = parse_query(
+            "SELECT service, count(*) FROM logs WHERE level = 'error' \
+             SINCE now() - 1h UNTIL '2024-01-01T12:00:00Z' GROUP BY service",
+        )
+        .unwrap();
+        assert_eq!(
+            query.since,
+            Some(Value::Time(RelativeTime { offset_secs: -3600 }))
+        );
+        assert_eq!(
+            query.until,
+            Some(Value::String("2024-01-01T12:00:00Z".to_string()))
+        );
+        assert_eq!(query.group_by.len(), 1);
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        // A bare duration means that long ago
+        let query = parse_query("SELECT * FROM logs SINCE 7d UNTIL 1704067200 LIMIT 5").unwrap();
+        assert_eq!(
+            query.since,
+            Some(Value::Time(RelativeTime {
+                offset_secs: -604_800
+            }))
+        );
+        assert_eq!(query.until, Some(Value::Integer(1_704_067_200)));
+        assert_eq!(query.limit, Some(5));
+
+        assert!(parse_query("SELECT * FROM logs SINCE 'yesterday'").is_err());
+        assert!(parse_query("SELECT * FROM logs SINCE").is_err());
+    }
+
+    #[test]
+    fn test_parse_in_between_and_null() {
+        let query = parse_query(
+            "SELECT * FROM logs WHERE service IN ('api', \"auth\") AND duration_ms NOT BETWEEN 1 AND 2.5 \
+             AND trace_id IS NOT NULL AND span_id is null AND level not in('debug')",
+        )
+        .unwrap();
+        assert_eq!(
+            query.where_clause.as_ref().unwrap().to_string(),
+            "service IN ('api', 'auth') AND duration_ms NOT BETWEEN 1 AND 2.5 AND \
+             trace_id IS NOT NULL AND span_id IS NULL AND level NOT IN ('debug')"
+        );
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        let Some(WhereClause::Combined { left, .. }) = query.where_clause else {
+            panic!("Expected a combined clause");
+        };
+        let Some(WhereClause::Combined { left, .. }) = Some(*left) else {
+            panic!("Expected a combined clause");
+        };
+        let Some(WhereClause::Combined { left, .. }) = Some(*left) else {
+            panic!("Expected a combined clause");
+        };
+        let Some(WhereClause::Combined { left, right, .. }) = Some(*left) else {
+            panic!("Expected a combined clause");
+        };
+        assert_eq!(
+            *left,
+            WhereClause::In {
+                field: "service".to_string(),
+                values: vec![
+                    Value::String("api".to_string()),
+                    Value::String("auth".to_string())
+                ],
+                negated: false,
+            }
+        );
+        assert_eq!(
+            *right,
+            WhereClause::Between {
+                field: "duration_ms".to_string(),
+                low: Value::Integer(1),
+                high: Value::Float(2.5),
+                negated: true,
+            }
+        );
+
+        assert!(parse_query("SELECT * FROM logs WHERE service IN ()").is_err());
+        assert!(parse_query("SELECT * FROM logs WHERE duration_ms BETWEEN 1").is_err());
+        assert!(parse_query("SELECT * FROM logs WHERE trace_id IS 'x'").is_err());
+    }
+
+    #[test]
+    fn test_parse_not() {
+        let query = parse_query("SELECT * FROM logs WHERE NOT level = 'error' AND service = 'api'")
+            .unwrap();
+        let Some(WhereClause::Combined { left, operator, .. }) = query.where_clause else {
+            panic!("Expected a combined clause");
+        };
+        assert_eq!(operator, LogicalOp::And);
+        assert!(matches!(*left, WhereClause::Not(_)));
+
+        let query = parse_query("SELECT * FROM logs WHERE NOT(level = 'error' OR service = 'api')")
+            .unwrap();
+        assert_eq!(
+            query.where_clause.unwrap().to_string(),
+            "NOT (level = 'error' OR service = 'api')"
+        );
+
+        // Identifiers starting with a keyword are still fields
+        let query =
+            parse_query("SELECT * FROM logs WHERE notice = 'x' AND island IS NULL").unwrap();
+        assert_eq!(
+            query.where_clause.unwrap().to_string(),
+            "notice = 'x' AND island IS NULL"
+        );
+    }
+
+    #[test]
+    fn test_parse_matches() {
+        let query =
+            parse_query(r"SELECT * FROM logs WHERE message MATCHES 'timeout after \d+ms'").unwrap();
+        let Some(WhereClause::Condition(ref condition)) = query.where_clause else {
+            panic!("Expected a condition");
+        };
+        assert_eq!(condition.operator, ComparisonOp::Matches);
+        assert_eq!(
+            condition.value,
+            Value::String(r"timeout after \d+ms".to_string())
+        );
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        let query =
+            parse_query("SELECT * FROM logs WHERE service=~'^api-' AND level = 'error'").unwrap();
+        assert_eq!(
+            query.where_clause.unwrap().to_string(),
+            "service MATCHES '^api-' AND level = 'error'"
+        );
+
+        // Patterns must be quoted
+        assert!(matches!(
+            parse_query("SELECT * FROM logs WHERE message MATCHES 42"),
+            Err(ParseError::SyntaxError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid_pattern() {
+        let err = parse_query("SELECT * FROM logs WHERE level = 'error' AND (message =~ 'a(b')")
+            .unwrap_err();
+        let ParseError::InvalidPattern {
+            ref pattern,
+            ref message,
+        } = err
+        else {
+            panic!("Expected an invalid pattern error, got {err:?}");
+        };
+        assert_eq!(pattern, "a(b");
+        assert_eq!(message, "unclosed group");
+        assert_eq!(
+            err.to_string(),
+            "Invalid regular expression 'a(b': unclosed group"
+        );
+
+        assert!(matches!(
+            parse_query(
+                "SELECT service, count(*) FROM logs GROUP BY service HAVING NOT service MATCHES '['"
+            ),
+            Err(ParseError::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let query = parse_query(
+            "SELECT * FROM logs WHERE service = $service AND level IN ($levels, 'fatal') \
+             AND message MATCHES $pattern AND message CONTAINS '$literal' SINCE $since",
+        )
+        .unwrap();
+        assert_eq!(
+            query.params(),
+            vec!["service", "levels", "pattern", "since"]
+        );
+        assert_eq!(query.since, Some(Value::Param("since".to_string())));
+        assert_eq!(parse_query(&query.to_string()).unwrap(), query);
+
+        for invalid in [
+            "SELECT * FROM logs WHERE service = $",
+            "SELECT * FROM logs WHERE service = $1x",
+            "SELECT * FROM logs LIMIT $n",
+        ] {
+            assert!(parse_query(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    fn syntax_error(input: &str) -> SyntaxError {
+        match parse_query(input) {
+            Err(e) => e
+                .syntax()
+                .cloned()
+                .unwrap_or_else(|| panic!("{input}: {e}")),
+            Ok(query) => panic!("{input} parsed as {query:?}"),
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_location() {
+        let error = syntax_error("SELECT *\nFROM logs\nWHERE level = 'error' LIMT 10");
+        assert_eq!((error.line, error.column, error.offset), (3, 23, 41));
+        assert_eq!(error.found, "LIMT");
+        assert_eq!(error.suggestion.as_deref(), Some("LIMIT"));
+        assert!(error.expected.contains(&"LIMIT".to_string()));
+        assert_eq!(
+            error.snippet,
+            "WHERE level = 'error' LIMT 10\n                      ^^^^"
+        );
+
+        // Leading whitespace is trimmed before parsing but still counted.
+        let error = syntax_error("  SELECT * FORM logs");
+        assert_eq!((error.column, error.offset), (12, 11));
+    }
+
+    #[test]
+    fn test_syntax_error_suggestions() {
+        let error = syntax_error("SELECT * FORM logs");
+        assert_eq!(error.found, "FORM");
+        assert_eq!(error.expected, vec!["FROM"]);
+        assert_eq!(error.suggestion.as_deref(), Some("FROM"));
+
+        let error = syntax_error("SELECT * FROM logz");
+        assert_eq!(error.suggestion.as_deref(), Some("logs"));
+        assert_eq!(error.expected, vec!["logs", "metrics", "traces"]);
+
+        let error = syntax_error("SELECT * FROM logs WHERE level = 'error' xyzzy");
+        assert_eq!(error.found, "xyzzy");
+        assert_eq!(error.suggestion, None);
+    }
+
+    #[test]
+    fn test_syntax_error_expected_tokens() {
+        let error = syntax_error("SELECT * FROM logs WHERE level");
+        assert_eq!(error.found, "");
+        assert!(error.message.contains("end of query"), "{}", error.message);
+        assert!(error
+            .expected
+            .contains(&"a comparison operator".to_string()));
+
+        let error = syntax_error("SELECT * FROM logs WHERE level IN ('error'");
+        assert!(
+            error.expected.contains(&")".to_string()),
+            "{:?}",
+            error.expected
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_allowed() {
+        // Bare names close to a built-in field are attributes and labels too
+        for query in [
+            "SELECT * FROM logs WHERE levle = 'error'",
+            "SELECT * FROM logs WHERE services = 'api' ORDER BY levels",
+            "SELECT names, count(*) FROM metrics GROUP BY names",
+            "SELECT * FROM logs WHERE level = 'error' ORDER BY timestmp",
+        ] {
+            assert!(parse_query(query).is_ok(), "{query}");
+        }
+        assert!(parse_query("SELECT * FROM logs WHERE attributes.levle = 'x'").is_ok());
+        assert!(parse_query("SELECT * FROM metrics WHERE labels.host = 'a'").is_ok());
+        assert!(
+            parse_query("SELECT service, count(*) AS n FROM logs GROUP BY service ORDER BY n")
+                .is_ok()
+        );
+        assert!(parse_query("SELECT * FROM traces ORDER BY timestamp").is_ok());
+    }
 }
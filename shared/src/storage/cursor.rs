@@ -0,0 +1,381 @@
+//! Keyset pagination cursors.
+//!
+//! In the default order (newest first) records are sorted by timestamp and then by
+//! a tiebreaker, both descending. A [`Cursor`] holds that sort key for the last
+//! record of a page, and the next page starts with the records that sort after
+//! it. Unlike an offset, this does not skip or repeat records when new data is
+//! ingested between requests, and `ClickHouse` can seek to it instead of scanning
+//! all preceding rows.
+//!
+//! Identical records share a sort key, so the cursor also counts how many
+//! records with its key were returned, and the next page skips only those.
+//!
+//! Tiebreakers:
+//! - Logs: a hash of the service, level, message, trace and span IDs, and
+//!   attributes, so that cursors have a fixed size
+//! - Metrics: name and labels
+//! - Spans: trace ID and span ID
+//! - Traces: trace ID (the timestamp is the start of the earliest span)
+
+use crate::models::{LogEntry, Metric, Span, Trace};
+use crate::query::{SqlParam, SqlParams};
+use crate::storage::encode_attributes;
+use base64::Engine;
+use std::cmp::Reverse;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use thiserror::Error;
+
+/// Error returned when decoding a malformed cursor.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invalid cursor: {0}")]
+pub struct CursorError(String);
+
+/// The position of a record in the default (newest first) order.
+///
+/// Cursors are exchanged with clients in their opaque [`Cursor::encode`] form.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    /// Timestamp of the record in nanoseconds since the Unix epoch.
+    pub timestamp: i64,
+
+    /// Tiebreaker between records with the same timestamp.
+    pub tiebreaker: String,
+
+    /// Number of records with this timestamp and tiebreaker up to the cursor.
+    pub seen: usize,
+}
+
+impl Cursor {
+    /// Creates a cursor after the first record with a timestamp in nanoseconds
+    /// and a tiebreaker.
+    #[must_use]
+    pub fn new(timestamp: i64, tiebreaker: impl Into<String>) -> Self {
+        Self {
+            timestamp,
+            tiebreaker: tiebreaker.into(),
+            seen: 1,
+        }
+    }
+
+    /// Encodes the cursor as an opaque, URL-safe string.
+    #[must_use]
+    pub fn encode(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}:{}",
+            self.timestamp, self.seen, self.tiebreaker
+        ))
+    }
+
+    /// Decodes a cursor produced by [`Cursor::encode`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string is not a valid cursor.
+    pub fn decode(cursor: &str) -> Result<Self, CursorError> {
+        let invalid = || CursorError(cursor.to_string());
+
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| invalid())?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (timestamp, rest) = text.split_once(':').ok_or_else(invalid)?;
+        let (seen, tiebreaker) = rest.split_once(':').ok_or_else(invalid)?;
+        let timestamp = timestamp.parse().map_err(|_| invalid())?;
+        let seen = seen.parse().map_err(|_| invalid())?;
+
+        Ok(Self {
+            seen,
+            ..Self::new(timestamp, tiebreaker)
+        })
+    }
+
+    /// Returns whether `key` is the sort key of the records the cursor counts.
+    fn is_at(&self, key: &Self) -> bool {
+        key.timestamp == self.timestamp && key.tiebreaker == self.tiebreaker
+    }
+
+    /// Returns whether a record with the given key sorts after the cursor's
+    /// key, or shares it.
+    fn reaches(&self, key: &Self) -> bool {
+        (key.timestamp, &key.tiebreaker) <= (self.timestamp, &self.tiebreaker)
+    }
+
+    /// Removes the records up to the cursor from `records`, sorted in the
+    /// default order.
+    pub(crate) fn skip<R: CursorKey>(&self, records: &mut Vec<R>) {
+        records.retain(|record| self.reaches(&record.cursor_key()));
+        let seen = records
+            .iter()
+            .take(self.seen)
+            .take_while(|record| self.is_at(&record.cursor_key()))
+            .count();
+        records.drain(..seen);
+    }
+
+    /// SQL predicate selecting the rows after the cursor and those sharing its
+    /// key, given the timestamp column and the tiebreaker expression of the
+    /// table. The [`Cursor::seen`] rows sharing the key come first in the
+    /// default order and are skipped with an `OFFSET`.
+    ///
+    /// The separate bound on the timestamp lets `ClickHouse` prune partitions.
+    pub(crate) fn to_sql(&self, column: &str, tiebreaker: &str, params: &mut SqlParams) -> String {
+        let t = params.push(SqlParam::Int(self.timestamp));
+        let k = params.push(SqlParam::String(self.tiebreaker.clone()));
+        format!("{column} <= {t} AND ({column}, {tiebreaker}) <= ({t}, {k})")
+    }
+}
+
+/// Returns the `OFFSET` of a page: the rows sharing the cursor's key that were
+/// already returned, followed by `offset`.
+pub(crate) fn page_offset(cursor: Option<&Cursor>, offset: Option<usize>) -> usize {
+    cursor.map_or(0, |cursor| cursor.seen) + offset.unwrap_or(0)
+}
+
+/// SQL tiebreaker for the `logs` table: a hash of the row as 16 hex digits.
+///
+/// It differs from the hash of [`CursorKey`] for [`LogEntry`], so `ClickHouse`
+/// selects it with each row and cursors are built from the value it computed.
+pub(crate) const LOG_TIEBREAKER: &str =
+    "leftPad(hex(cityHash64(service, level, message, trace_id, span_id, attributes)), 16, '0')";
+
+/// SQL tiebreaker for the `metrics` table, matching [`CursorKey`] for [`Metric`].
+pub(crate) const METRIC_TIEBREAKER: &str = "concat(name, '\\0', arrayStringConcat(\
+     arraySort(arrayMap((k, v) -> concat(k, '=', v), mapKeys(labels), mapValues(labels))), ','))";
+
+/// SQL tiebreaker for the `spans` table, matching [`CursorKey`] for [`Span`].
+pub(crate) const SPAN_TIEBREAKER: &str = "concat(trace_id, '\\0', span_id)";
+
+/// Records that can be paginated with a [`Cursor`].
+pub trait CursorKey {
+    /// Returns the sort key of the record in the default order.
+    fn cursor_key(&self) -> Cursor;
+}
+
+impl CursorKey for Cursor {
+    fn cursor_key(&self) -> Cursor {
+        self.clone()
+    }
+}
+
+impl CursorKey for LogEntry {
+    fn cursor_key(&self) -> Cursor {
+        // Attribute values as stored in `ClickHouse`, in a stable order
+        let mut attributes: Vec<(String, String)> =
+            encode_attributes(&self.attributes).into_iter().collect();
+        attributes.sort();
+
+        let mut hasher = DefaultHasher::new();
+        self.service.hash(&mut hasher);
+        self.level.to_string().hash(&mut hasher);
+        self.message.hash(&mut hasher);
+        self.trace_id
+            .as_deref()
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        self.span_id
+            .as_deref()
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        attributes.hash(&mut hasher);
+
+        Cursor::new(
+            self.timestamp.timestamp_nanos_opt().unwrap_or(0),
+            format!("{:016X}", hasher.finish()),
+        )
+    }
+}
+
+impl CursorKey for Metric {
+    fn cursor_key(&self) -> Cursor {
+        let mut labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect();
+        labels.sort();
+
+        Cursor::new(
+            self.timestamp.timestamp_nanos_opt().unwrap_or(0),
+            format!("{}\0{}", self.name, labels.join(",")),
+        )
+    }
+}
+
+impl CursorKey for Span {
+    fn cursor_key(&self) -> Cursor {
+        Cursor::new(
+            self.start_time.timestamp_nanos_opt().unwrap_or(0),
+            format!("{}\0{}", self.trace_id, self.span_id),
+        )
+    }
+}
+
+impl CursorKey for Trace {
+    fn cursor_key(&self) -> Cursor {
+        let start = self
+            .spans
+            .iter()
+            .map(|span| span.start_time.timestamp_nanos_opt().unwrap_or(0))
+            .min()
+            .unwrap_or(0);
+
+        Cursor::new(start, self.trace_id.clone())
+    }
+}
+
+/// Sorts records in the default order: newest first, then by tiebreaker.
+pub(crate) fn sort_newest_first<R: CursorKey>(records: &mut [R]) {
+    records.sort_by_cached_key(|record| Reverse(record.cursor_key()));
+}
+
+/// Returns the cursor for the page after `records`, or `None` if the page is
+/// not full and therefore the last one.
+///
+/// `previous` is the cursor the page started after, whose records count
+/// towards the new cursor when the whole page shares its key.
+pub(crate) fn next_cursor<R: CursorKey>(
+    records: &[R],
+    limit: Option<usize>,
+    previous: Option<&Cursor>,
+) -> Option<Cursor> {
+    match limit {
+        Some(limit) if limit > 0 && records.len() >= limit => {
+            let mut cursor = records.last()?.cursor_key();
+            cursor.seen = records
+                .iter()
+                .rev()
+                .take_while(|record| cursor.is_at(&record.cursor_key()))
+                .count();
+            if let Some(previous) = previous.filter(|previous| previous.is_at(&cursor)) {
+                if cursor.seen == records.len() {
+                    cursor.seen += previous.seen;
+                }
+            }
+            Some(cursor)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LogLevel;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor::new(1_704_067_200_000_000_000, "api\0GET /users: 200");
+        let encoded = cursor.encode();
+
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Cursor::decode(&encoded), Ok(cursor));
+    }
+
+    #[test]
+    fn test_cursor_decode_invalid() {
+        assert!(Cursor::decode("not a cursor!").is_err());
+        assert!(
+            Cursor::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode("x:y"))
+                .is_err()
+        );
+        assert!(Cursor::decode("").is_err());
+    }
+
+    #[test]
+    fn test_sort_newest_first_and_next_cursor() {
+        let base = chrono::Utc::now();
+        let mut logs = vec![
+            LogEntry::new(LogLevel::Info, "b", "api"),
+            LogEntry::new(LogLevel::Info, "a", "api"),
+            LogEntry::new(LogLevel::Info, "c", "api"),
+        ];
+        logs[0].timestamp = base;
+        logs[1].timestamp = base;
+        logs[2].timestamp = base - chrono::Duration::seconds(1);
+
+        sort_newest_first(&mut logs);
+        let mut messages: Vec<&str> = logs.iter().map(|log| log.message.as_str()).collect();
+        messages[..2].sort_unstable();
+        assert_eq!(messages, ["a", "b", "c"]);
+        assert!(logs[0].cursor_key() > logs[1].cursor_key());
+
+        let cursor = next_cursor(&logs[..2], Some(2), None).unwrap();
+        assert_eq!(cursor, logs[1].cursor_key());
+        let mut rest = logs.clone();
+        cursor.skip(&mut rest);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].message, "c");
+
+        assert_eq!(next_cursor(&logs, Some(5), None), None);
+        assert_eq!(next_cursor(&logs, None, None), None);
+    }
+
+    #[test]
+    fn test_cursor_counts_duplicate_keys() {
+        let timestamp = chrono::Utc::now();
+        let log = |message| {
+            let mut log = LogEntry::new(LogLevel::Info, message, "api");
+            log.timestamp = timestamp;
+            log
+        };
+        let logs = vec![log("b"), log("b"), log("b"), log("a")];
+
+        // Each page of two straddles the duplicates
+        let first = next_cursor(&logs[..2], Some(2), None).unwrap();
+        assert_eq!(first.seen, 2);
+        let mut rest = logs.clone();
+        first.skip(&mut rest);
+        let messages: Vec<&str> = rest.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(messages, ["b", "a"]);
+
+        // A page made of the same key adds to the previous cursor
+        let second = next_cursor(&logs[2..3], Some(1), Some(&first)).unwrap();
+        assert_eq!(second.seen, 3);
+        let mut rest = logs.clone();
+        second.skip(&mut rest);
+        assert_eq!(rest.len(), 1);
+
+        // Records differing only by level or attributes have distinct keys
+        let mut other = log("b");
+        other.level = LogLevel::Warn;
+        assert_ne!(other.cursor_key(), logs[0].cursor_key());
+        let other = log("b").with_attribute("user", 42);
+        assert_ne!(other.cursor_key(), logs[0].cursor_key());
+
+        let decoded = Cursor::decode(&second.encode()).unwrap();
+        assert_eq!(decoded, second);
+    }
+
+    #[test]
+    fn test_log_cursor_has_fixed_size() {
+        let log = |message: String| {
+            let mut log = LogEntry::new(LogLevel::Info, message, "api")
+                .with_attribute("user", 42)
+                .with_trace_id("trace-1")
+                .with_span_id("span-1");
+            log.timestamp = chrono::Utc::now();
+            log
+        };
+        let small = log("a".to_string()).cursor_key().encode();
+        let large = log("a".repeat(100_000)).cursor_key().encode();
+
+        assert_eq!(small.len(), large.len());
+        assert!(large.len() < 64);
+    }
+
+    #[test]
+    fn test_cursor_to_sql() {
+        let mut params = SqlParams::new();
+        let sql = Cursor::new(10, "x").to_sql("timestamp", SPAN_TIEBREAKER, &mut params);
+
+        assert_eq!(
+            sql,
+            "timestamp <= {p0:Int64} AND (timestamp, concat(trace_id, '\\0', span_id)) <= \
+             ({p0:Int64}, {p1:String})"
+        );
+        assert_eq!(page_offset(Some(&Cursor::new(10, "x")), Some(5)), 6);
+        assert_eq!(page_offset(None, None), 0);
+    }
+}
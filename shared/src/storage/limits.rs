@@ -0,0 +1,565 @@
+//! Query guardrails.
+//!
+//! [`QueryLimits`] bounds the time and resources a single read query may use.
+//! The `ClickHouse` stores pass the limits to the server as query settings and
+//! stop waiting for the response when the query is cancelled or its deadline
+//! passes; dropping the response makes the server cancel the query. The
+//! in-memory stores count rows with a [`Scan`] as they filter them and check
+//! the size of their results with [`QueryLimits::check_result`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// `ClickHouse` error code for a query that ran longer than `max_execution_time`.
+const TIMEOUT_EXCEEDED: u32 = 159;
+/// `ClickHouse` error code for a query that read more than `max_rows_to_read` rows.
+const TOO_MANY_ROWS: u32 = 158;
+/// `ClickHouse` error code for a query that read too many bytes.
+const TOO_MANY_BYTES: u32 = 307;
+/// `ClickHouse` error code for a result larger than `max_result_bytes`.
+const TOO_MANY_ROWS_OR_BYTES: u32 = 396;
+/// `ClickHouse` error code for a cancelled query.
+const QUERY_WAS_CANCELLED: u32 = 394;
+
+/// Number of rows a [`Scan`] reads between checks of the deadline and the
+/// cancellation token.
+const SCAN_CHECK_INTERVAL: u64 = 1024;
+
+/// A limit that a query can exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Limit {
+    /// The query ran longer than its timeout.
+    #[serde(rename = "timeout")]
+    Timeout,
+    /// The query scanned more rows than allowed.
+    #[serde(rename = "max_rows_scanned")]
+    RowsScanned,
+    /// The query result was larger than allowed.
+    #[serde(rename = "max_result_bytes")]
+    ResultBytes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Timeout => "timeout",
+            Self::RowsScanned => "max_rows_scanned",
+            Self::ResultBytes => "max_result_bytes",
+        })
+    }
+}
+
+/// Errors raised when a query is stopped by its [`QueryLimits`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LimitError {
+    /// The query exceeded one of its limits.
+    #[error("Query limit exceeded ({limit}): {message}")]
+    Exceeded {
+        /// The limit that was exceeded.
+        limit: Limit,
+        /// Details of the violation.
+        message: String,
+    },
+
+    /// The query was cancelled, e.g. because the client disconnected.
+    #[error("Query was cancelled")]
+    Cancelled,
+}
+
+impl LimitError {
+    /// Classifies a `ClickHouse` error caused by a query setting set by
+    /// [`QueryLimits`], returning `None` for other errors.
+    pub(crate) fn from_clickhouse(error: &clickhouse::error::Error) -> Option<Self> {
+        let clickhouse::error::Error::BadResponse(response) = error else {
+            return None;
+        };
+        let code = response
+            .split_once("Code: ")
+            .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|code| code.parse::<u32>().ok())?;
+        let limit = match code {
+            TIMEOUT_EXCEEDED => Limit::Timeout,
+            TOO_MANY_ROWS | TOO_MANY_BYTES => Limit::RowsScanned,
+            TOO_MANY_ROWS_OR_BYTES => Limit::ResultBytes,
+            QUERY_WAS_CANCELLED => return Some(Self::Cancelled),
+            _ => return None,
+        };
+        Some(Self::Exceeded {
+            limit,
+            message: response.trim().to_string(),
+        })
+    }
+}
+
+/// Limits on the execution of a read query.
+///
+/// All limits are unset by default. Limits configured for the server can be
+/// tightened for a single request with [`QueryLimits::restrict`].
+///
+/// # Example
+///
+/// ```
+/// use shared::storage::QueryLimits;
+/// use std::time::Duration;
+///
+/// let server = QueryLimits::new()
+///     .with_timeout(Duration::from_secs(30))
+///     .with_max_rows_scanned(100_000_000);
+/// let request = QueryLimits::new().with_timeout(Duration::from_secs(5));
+///
+/// let limits = server.restrict(&request);
+/// assert_eq!(limits.timeout, Some(Duration::from_secs(5)));
+/// assert_eq!(limits.max_rows_scanned, Some(100_000_000));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QueryLimits {
+    /// Maximum time the query may run.
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of rows the query may scan.
+    pub max_rows_scanned: Option<u64>,
+
+    /// Maximum size of the query result, in bytes.
+    pub max_result_bytes: Option<u64>,
+
+    /// Token that aborts the query when cancelled.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl QueryLimits {
+    /// Creates limits that do not restrict queries.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum time the query may run.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of rows the query may scan.
+    #[must_use]
+    pub fn with_max_rows_scanned(mut self, rows: u64) -> Self {
+        self.max_rows_scanned = Some(rows);
+        self
+    }
+
+    /// Sets the maximum size of the query result, in bytes.
+    #[must_use]
+    pub fn with_max_result_bytes(mut self, bytes: u64) -> Self {
+        self.max_result_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets the token that aborts the query when cancelled.
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Combines these limits with `other`, keeping the stricter value of each.
+    ///
+    /// The cancellation token of `other` is used if it has one.
+    #[must_use]
+    pub fn restrict(&self, other: &Self) -> Self {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Self {
+            timeout: min(self.timeout, other.timeout),
+            max_rows_scanned: min(self.max_rows_scanned, other.max_rows_scanned),
+            max_result_bytes: min(self.max_result_bytes, other.max_result_bytes),
+            cancellation: other
+                .cancellation
+                .clone()
+                .or_else(|| self.cancellation.clone()),
+        }
+    }
+
+    /// Returns an error if `records` are larger than `max_result_bytes` when
+    /// serialized as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LimitError::Exceeded`] for [`Limit::ResultBytes`].
+    pub(crate) fn check_result<T: Serialize>(&self, records: &[T]) -> Result<(), LimitError> {
+        let Some(max) = self.max_result_bytes else {
+            return Ok(());
+        };
+        let mut size = ByteCount(0);
+        for record in records {
+            // Writing to a byte counter only fails for unserializable values,
+            // which are not counted
+            let _ = serde_json::to_writer(&mut size, record);
+            if size.0 > max {
+                return Err(LimitError::Exceeded {
+                    limit: Limit::ResultBytes,
+                    message: format!("query result is larger than {max} bytes"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns these limits without `max_result_bytes`, for reading the
+    /// records of a result computed from them.
+    pub(crate) fn without_result_bytes(&self) -> Self {
+        Self {
+            max_result_bytes: None,
+            ..self.clone()
+        }
+    }
+
+    /// Adds the limits to a `ClickHouse` query as settings.
+    pub(crate) fn apply(&self, mut query: clickhouse::query::Query) -> clickhouse::query::Query {
+        if let Some(timeout) = self.timeout {
+            query = query
+                .with_option("max_execution_time", timeout.as_secs_f64().to_string())
+                .with_option("timeout_overflow_mode", "throw");
+        }
+        if let Some(rows) = self.max_rows_scanned {
+            query = query
+                .with_option("max_rows_to_read", rows.to_string())
+                .with_option("read_overflow_mode", "throw");
+        }
+        if let Some(bytes) = self.max_result_bytes {
+            query = query
+                .with_option("max_result_bytes", bytes.to_string())
+                .with_option("result_overflow_mode", "throw");
+        }
+        // Stop the query when its response is dropped
+        query.with_option("cancel_http_readonly_queries_on_client_close", "1")
+    }
+
+    /// Returns the time by which a query started at `start` must finish.
+    pub(crate) fn deadline(&self, start: Instant) -> Option<Instant> {
+        self.timeout.map(|timeout| start + timeout)
+    }
+
+    /// Runs `future` until it completes, the query is cancelled or `deadline`
+    /// passes, whichever happens first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query is cancelled or times out; the future is
+    /// dropped.
+    pub(crate) async fn run<F: Future>(
+        &self,
+        deadline: Option<Instant>,
+        future: F,
+    ) -> Result<F::Output, LimitError> {
+        let cancelled = async {
+            match self.cancellation {
+                Some(ref token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let expired = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            output = future => Ok(output),
+            () = cancelled => Err(LimitError::Cancelled),
+            () = expired => Err(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                message: format!(
+                    "query did not finish within {:?}",
+                    self.timeout.unwrap_or_default()
+                ),
+            }),
+        }
+    }
+}
+
+/// Rows scanned by a query on an in-memory store.
+///
+/// [`Scan::rows`] counts rows as the store reads them and ends the scan once
+/// the query is cancelled, passes its deadline or reads more than
+/// `max_rows_scanned` rows; [`Scan::finish`] then returns the reason.
+pub(crate) struct Scan {
+    limits: QueryLimits,
+    deadline: Option<Instant>,
+    rows: u64,
+    next_check: u64,
+    error: Option<LimitError>,
+}
+
+impl Scan {
+    /// Starts a scan bounded by `limits`; its deadline starts now.
+    pub(crate) fn new(limits: &QueryLimits) -> Self {
+        Self {
+            limits: limits.clone(),
+            deadline: limits.deadline(Instant::now()),
+            rows: 0,
+            next_check: 0,
+            error: None,
+        }
+    }
+
+    /// Counts each row of `rows` as it is read, ending early when the query is
+    /// stopped by its limits.
+    pub(crate) fn rows<'s, I: Iterator + 's>(
+        &'s mut self,
+        rows: I,
+    ) -> impl Iterator<Item = I::Item> + 's {
+        self.groups(rows, |_| 1)
+    }
+
+    /// Like [`Scan::rows`], for items that each hold `len(item)` rows, such as
+    /// the spans of a trace.
+    pub(crate) fn groups<'s, I: Iterator + 's>(
+        &'s mut self,
+        groups: I,
+        len: impl Fn(&I::Item) -> usize + 's,
+    ) -> impl Iterator<Item = I::Item> + 's {
+        groups.map_while(move |group| match self.read(len(&group)) {
+            Ok(()) => Some(group),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        })
+    }
+
+    /// Ends the scan.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the scan, or an error if the query was
+    /// cancelled or timed out after the last row was read.
+    pub(crate) fn finish(self) -> Result<(), LimitError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.check(),
+        }
+    }
+
+    fn read(&mut self, rows: usize) -> Result<(), LimitError> {
+        if self.rows >= self.next_check {
+            self.check()?;
+            self.next_check = self.rows + SCAN_CHECK_INTERVAL;
+        }
+        self.rows += u64::try_from(rows).unwrap_or(u64::MAX);
+        match self.limits.max_rows_scanned {
+            Some(max) if self.rows > max => Err(LimitError::Exceeded {
+                limit: Limit::RowsScanned,
+                message: format!("query scanned more than {max} rows"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check(&self) -> Result<(), LimitError> {
+        if self
+            .limits
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(LimitError::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                message: format!(
+                    "query did not finish within {:?}",
+                    self.limits.timeout.unwrap_or_default()
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Counts the bytes written to it.
+struct ByteCount(u64);
+
+impl io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += u64::try_from(buf.len()).unwrap_or(u64::MAX);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restrict_keeps_stricter_limits() {
+        let server = QueryLimits::new()
+            .with_timeout(Duration::from_secs(30))
+            .with_max_result_bytes(1_000);
+        let request = QueryLimits::new()
+            .with_timeout(Duration::from_secs(90))
+            .with_max_rows_scanned(10)
+            .with_cancellation(CancellationToken::new());
+
+        let limits = server.restrict(&request);
+        assert_eq!(limits.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(limits.max_rows_scanned, Some(10));
+        assert_eq!(limits.max_result_bytes, Some(1_000));
+        assert!(limits.cancellation.is_some());
+    }
+
+    #[test]
+    fn test_scan_counts_rows_read() {
+        let limits = QueryLimits::new().with_max_rows_scanned(2);
+
+        let mut scan = Scan::new(&limits);
+        let even: Vec<u32> = scan.rows(0..4).filter(|n| n % 2 == 0).collect();
+        // The third row is over the limit and ends the scan
+        assert_eq!(even, vec![0]);
+        assert!(matches!(
+            scan.finish(),
+            Err(LimitError::Exceeded {
+                limit: Limit::RowsScanned,
+                ..
+            })
+        ));
+
+        // Rows filtered out before the scan are not counted
+        let mut scan = Scan::new(&limits);
+        let read = scan.rows((0..10).filter(|n| *n >= 8)).count();
+        assert_eq!(read, 2);
+        assert!(scan.finish().is_ok());
+
+        // Groups count all of their rows
+        let mut scan = Scan::new(&limits);
+        let groups = [vec![1], vec![2, 3]];
+        assert_eq!(scan.groups(groups.iter(), |group| group.len()).count(), 1);
+        assert!(scan.finish().is_err());
+    }
+
+    #[test]
+    fn test_scan_stops_at_deadline_and_cancellation() {
+        let limits = QueryLimits::new().with_timeout(Duration::ZERO);
+        let mut scan = Scan::new(&limits);
+        assert_eq!(scan.rows(0..10).count(), 0);
+        assert!(matches!(
+            scan.finish(),
+            Err(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                ..
+            })
+        ));
+
+        let token = CancellationToken::new();
+        let limits = QueryLimits::new().with_cancellation(token.clone());
+        let mut scan = Scan::new(&limits);
+        assert_eq!(scan.rows(0..10).count(), 10);
+        token.cancel();
+        assert_eq!(scan.finish(), Err(LimitError::Cancelled));
+    }
+
+    #[test]
+    fn test_check_result() {
+        let records = vec!["a".repeat(10); 3];
+        // Each record serializes to 12 bytes
+        assert!(QueryLimits::new()
+            .with_max_result_bytes(36)
+            .check_result(&records)
+            .is_ok());
+        assert!(matches!(
+            QueryLimits::new()
+                .with_max_result_bytes(35)
+                .check_result(&records),
+            Err(LimitError::Exceeded {
+                limit: Limit::ResultBytes,
+                ..
+            })
+        ));
+        assert!(QueryLimits::new().check_result(&records).is_ok());
+    }
+
+    #[test]
+    fn test_classify_clickhouse_errors() {
+        let error = |message: &str| clickhouse::error::Error::BadResponse(message.to_string());
+
+        assert!(matches!(
+            LimitError::from_clickhouse(&error(
+                "Code: 159. DB::Exception: Timeout exceeded: elapsed 1.0 seconds, maximum: 1. (TIMEOUT_EXCEEDED)"
+            )),
+            Some(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                ..
+            })
+        ));
+        assert!(matches!(
+            LimitError::from_clickhouse(&error(
+                "Code: 158. DB::Exception: Limit for rows (controlled by 'max_rows_to_read' setting) exceeded. (TOO_MANY_ROWS)"
+            )),
+            Some(LimitError::Exceeded {
+                limit: Limit::RowsScanned,
+                ..
+            })
+        ));
+        assert!(matches!(
+            LimitError::from_clickhouse(&error(
+                "Code: 396. DB::Exception: Limit for result exceeded, max bytes: 1.00 B. (TOO_MANY_ROWS_OR_BYTES)"
+            )),
+            Some(LimitError::Exceeded {
+                limit: Limit::ResultBytes,
+                ..
+            })
+        ));
+        assert_eq!(
+            LimitError::from_clickhouse(&error("Code: 394. DB::Exception: Query was cancelled.")),
+            Some(LimitError::Cancelled)
+        );
+        assert_eq!(
+            LimitError::from_clickhouse(&error(
+                "Code: 60. DB::Exception: Unknown table. (UNKNOWN_TABLE)"
+            )),
+            None
+        );
+        assert_eq!(
+            LimitError::from_clickhouse(&clickhouse::error::Error::TimedOut),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_deadline_and_cancellation() {
+        let limits = QueryLimits::new().with_timeout(Duration::from_millis(10));
+        let deadline = limits.deadline(Instant::now());
+        let result = limits.run(deadline, std::future::pending::<()>()).await;
+        assert!(matches!(
+            result,
+            Err(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                ..
+            })
+        ));
+
+        let token = CancellationToken::new();
+        let limits = QueryLimits::new().with_cancellation(token.clone());
+        token.cancel();
+        let result = limits.run(None, std::future::pending::<()>()).await;
+        assert_eq!(result, Err(LimitError::Cancelled));
+
+        assert_eq!(QueryLimits::new().run(None, async { 1 }).await, Ok(1));
+    }
+}
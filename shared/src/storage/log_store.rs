@@ -3,10 +3,21 @@
 //! Provides the `LogStore` trait for abstracting log storage operations
 //! and an `InMemoryLogStore` implementation for development and testing.
 
+use super::cursor::{next_cursor, page_offset, sort_newest_first, CursorKey, LOG_TIEBREAKER};
+use super::rollup::{fetch_log_counts, log_counts, LogCount, RollupQuery};
+use super::{decode_attributes, encode_attributes, Cursor, LimitError, QueryLimits, Scan};
+use crate::config::RetentionPolicy;
 use crate::models::{LogEntry, LogLevel};
+use crate::query::{
+    collect_fields, compile_fields_query, compile_filter, compile_group_query, compile_order,
+    explain_statements, fetch_fields, fetch_groups, group_records, matches_filter, sort_records,
+    FieldInfo, GroupQuery, GroupQueryResult, OrderBy, Source, SqlParam, SqlParams, StorePlan,
+    WhereClause,
+};
 use chrono::{DateTime, Utc};
 use std::sync::{Arc, RwLock};
 use thiserror::Error;
+use tokio::time::Instant;
 
 /// Errors that can occur during log store operations.
 #[derive(Debug, Error)]
@@ -18,6 +29,9 @@ pub enum LogStoreError {
     /// Generic storage error.
     #[error("Storage error: {0}")]
     StorageError(String),
+    /// The query was stopped by its limits.
+    #[error("{0}")]
+    Limit(#[from] LimitError),
 }
 
 /// Query parameters for retrieving logs.
@@ -43,6 +57,19 @@ pub struct LogQuery {
 
     /// Number of logs to skip (for pagination).
     pub offset: Option<usize>,
+
+    /// Additional filter expressed in the query language.
+    pub filter: Option<WhereClause>,
+
+    /// Sort keys, most significant first. Ties, and queries without keys,
+    /// are ordered newest first.
+    pub order_by: Vec<OrderBy>,
+
+    /// Return only logs after this position in the default order (keyset pagination).
+    pub cursor: Option<Cursor>,
+
+    /// Limits on the execution of the query.
+    pub limits: QueryLimits,
 }
 
 impl LogQuery {
@@ -100,6 +127,34 @@ impl LogQuery {
         self.message_contains = Some(pattern.into());
         self
     }
+
+    /// Sets an additional filter expressed in the query language.
+    #[must_use]
+    pub fn with_filter(mut self, filter: WhereClause) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets the sort keys.
+    #[must_use]
+    pub fn with_order_by(mut self, order_by: Vec<OrderBy>) -> Self {
+        self.order_by = order_by;
+        self
+    }
+
+    /// Continues after the given cursor.
+    #[must_use]
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Sets the limits on the execution of the query.
+    #[must_use]
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
 /// Result of a log query operation.
@@ -108,10 +163,19 @@ pub struct LogQueryResult {
     /// The logs matching the query.
     pub logs: Vec<LogEntry>,
 
-    /// Total count of matching logs (before limit/offset applied).
+    /// Total count of matching logs (before cursor/limit/offset applied).
     pub total_count: usize,
+
+    /// Cursor for the next page, if the page is full and in the default order.
+    pub next_cursor: Option<Cursor>,
 }
 
+/// Logs streamed from a store, one at a time.
+///
+/// Advancing the iterator may block on the store, so it should be consumed on a
+/// blocking thread (e.g. `tokio::task::spawn_blocking`).
+pub type LogStream = Box<dyn Iterator<Item = Result<LogEntry, LogStoreError>> + Send>;
+
 /// Trait for log storage implementations.
 ///
 /// This trait defines the interface for storing and querying logs.
@@ -138,6 +202,71 @@ pub trait LogStore: Send + Sync {
     /// Returns an error if the query operation fails.
     fn query(&self, query: LogQuery) -> Result<LogQueryResult, LogStoreError>;
 
+    /// Streams the logs matching the query, in the same order as [`LogStore::query`].
+    ///
+    /// Unlike [`LogStore::query`], no total count is computed and the logs do not
+    /// have to be held in memory at once. The default implementation runs
+    /// [`LogStore::query`] and iterates over its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query cannot be started; errors while reading are
+    /// returned by the stream.
+    fn stream(&self, query: LogQuery) -> Result<LogStream, LogStoreError> {
+        let logs = self.query(query)?.logs;
+        Ok(Box::new(logs.into_iter().map(Ok)))
+    }
+
+    /// Groups and aggregates the logs matching the query.
+    ///
+    /// Ordering and pagination of `query` are ignored; `group` carries its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn query_groups(
+        &self,
+        query: LogQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, LogStoreError>;
+
+    /// Lists the fields of the logs matching the query, with their types and
+    /// cardinality.
+    ///
+    /// Ordering and pagination of `query` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn fields(&self, query: LogQuery) -> Result<Vec<FieldInfo>, LogStoreError>;
+
+    /// Reads the log counts of the tier at `query.interval`, ordered by
+    /// service, level and message pattern, then time. Logs only have hourly
+    /// and daily tiers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn counts(&self, query: RollupQuery) -> Result<Vec<LogCount>, LogStoreError>;
+
+    /// Describes how the store would execute `query`, or the aggregation `group`
+    /// over the logs matching `query` if one is given, without running it.
+    ///
+    /// The default implementation describes a store that evaluates queries in
+    /// memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails.
+    fn explain(
+        &self,
+        query: LogQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, LogStoreError> {
+        let _ = (query, group);
+        Ok(StorePlan::in_memory())
+    }
+
     /// Returns the total number of logs in the store.
     ///
     /// # Errors
@@ -165,6 +294,17 @@ pub trait LogStore: Send + Sync {
     ///
     /// Returns an error if the operation fails.
     fn get_newest_timestamp(&self) -> Result<Option<DateTime<Utc>>, LogStoreError>;
+
+    /// Deletes the logs that have outlived `policy` at `now`, returning how
+    /// many were deleted.
+    ///
+    /// Stores whose database expires data itself, like `ClickHouse` with its
+    /// table TTLs, delete nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails.
+    fn expire(&self, policy: &RetentionPolicy, now: DateTime<Utc>) -> Result<usize, LogStoreError>;
 }
 
 /// In-memory log store implementation.
@@ -233,22 +373,15 @@ impl LogStore for InMemoryLogStore {
         // Prepare case-insensitive message search pattern
         let message_pattern = query.message_contains.as_ref().map(|s| s.to_lowercase());
 
-        // Apply all filters
-        let filtered: Vec<LogEntry> = logs
-            .iter()
+        // Apply all filters. Like the partitions `ClickHouse` skips, logs
+        // outside the time range are not counted as scanned.
+        let mut scan = Scan::new(&query.limits);
+        let filtered: Vec<LogEntry> = scan
+            .rows(logs.iter().filter(|log| {
+                query.start_time.is_none_or(|start| log.timestamp >= start)
+                    && query.end_time.is_none_or(|end| log.timestamp < end)
+            }))
             .filter(|log| {
-                // Time range filter
-                if let Some(start) = query.start_time {
-                    if log.timestamp < start {
-                        return false;
-                    }
-                }
-                if let Some(end) = query.end_time {
-                    if log.timestamp >= end {
-                        return false;
-                    }
-                }
-
                 // Level filter
                 if let Some(ref level) = query.level {
                     if &log.level != level {
@@ -270,27 +403,95 @@ impl LogStore for InMemoryLogStore {
                     }
                 }
 
+                // Query language filter
+                if let Some(ref filter) = query.filter {
+                    if !matches_filter(filter, *log) {
+                        return false;
+                    }
+                }
+
                 true
             })
             .cloned()
             .collect();
+        scan.finish()?;
+
+        let mut filtered = filtered;
+        // Sort newest first, then by the requested order
+        sort_newest_first(&mut filtered);
+        sort_records(&mut filtered, &query.order_by);
 
         let total_count = filtered.len();
 
-        // Apply offset and limit
+        // Apply cursor, offset and limit
+        if let Some(ref cursor) = query.cursor {
+            cursor.skip(&mut filtered);
+        }
         let offset = query.offset.unwrap_or(0);
         let result: Vec<LogEntry> = filtered
             .into_iter()
             .skip(offset)
             .take(query.limit.unwrap_or(usize::MAX))
             .collect();
+        query.limits.check_result(&result)?;
 
         Ok(LogQueryResult {
+            next_cursor: query
+                .order_by
+                .is_empty()
+                .then(|| next_cursor(&result, query.limit, query.cursor.as_ref()))
+                .flatten(),
             logs: result,
             total_count,
         })
     }
 
+    fn query_groups(
+        &self,
+        query: LogQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, LogStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query(LogQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let groups = group_records(&result.logs, group);
+        limits.check_result(&groups.rows)?;
+        Ok(groups)
+    }
+
+    fn fields(&self, query: LogQuery) -> Result<Vec<FieldInfo>, LogStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query(LogQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let fields = collect_fields(&result.logs);
+        limits.check_result(&fields)?;
+        Ok(fields)
+    }
+
+    fn counts(&self, query: RollupQuery) -> Result<Vec<LogCount>, LogStoreError> {
+        let logs = self.logs.read().map_err(|_| LogStoreError::LockError)?;
+        let mut scan = Scan::new(&query.limits);
+        let counts = log_counts(
+            scan.rows(logs.iter().filter(|log| query.contains_time(log.timestamp))),
+            &query,
+        );
+        scan.finish()?;
+        query.limits.check_result(&counts)?;
+        Ok(counts)
+    }
+
     fn count(&self) -> Result<usize, LogStoreError> {
         let logs = self.logs.read().map_err(|_| LogStoreError::LockError)?;
         Ok(logs.len())
@@ -311,6 +512,55 @@ impl LogStore for InMemoryLogStore {
         let logs = self.logs.read().map_err(|_| LogStoreError::LockError)?;
         Ok(logs.iter().map(|log| log.timestamp).max())
     }
+
+    fn expire(&self, policy: &RetentionPolicy, now: DateTime<Utc>) -> Result<usize, LogStoreError> {
+        let mut logs = self.logs.write().map_err(|_| LogStoreError::LockError)?;
+        let before = logs.len();
+        logs.retain(|log| !policy.is_expired(log, log.timestamp, now));
+        Ok(before - logs.len())
+    }
+}
+
+/// A row of the `logs` table as read from `ClickHouse`.
+#[derive(clickhouse::Row, serde::Deserialize)]
+struct LogRow {
+    timestamp: i64,
+    trace_id: String,
+    span_id: String,
+    level: String,
+    message: String,
+    service: String,
+    attributes: std::collections::HashMap<String, String>,
+    /// The row's [`LOG_TIEBREAKER`].
+    tiebreaker: String,
+}
+
+impl CursorKey for LogRow {
+    fn cursor_key(&self) -> Cursor {
+        Cursor::new(self.timestamp, self.tiebreaker.clone())
+    }
+}
+
+impl From<LogRow> for LogEntry {
+    fn from(row: LogRow) -> Self {
+        let level = match row.level.as_str() {
+            "trace" => LogLevel::Trace,
+            "debug" => LogLevel::Debug,
+            "warn" => LogLevel::Warn,
+            "error" => LogLevel::Error,
+            "fatal" => LogLevel::Fatal,
+            _ => LogLevel::Info,
+        };
+        Self {
+            timestamp: DateTime::from_timestamp_nanos(row.timestamp),
+            level,
+            message: row.message,
+            service: row.service,
+            attributes: decode_attributes(row.attributes),
+            trace_id: (!row.trace_id.is_empty()).then_some(row.trace_id),
+            span_id: (!row.span_id.is_empty()).then_some(row.span_id),
+        }
+    }
 }
 
 /// `ClickHouse`-backed log store implementation.
@@ -346,6 +596,108 @@ impl ClickHouseLogStore {
                 .map_err(|e| LogStoreError::StorageError(e.to_string()))
         })
     }
+
+    /// Like [`Self::block_on`], but stops waiting when the query is cancelled or
+    /// `deadline` passes, and reports queries stopped by `limits`.
+    fn block_on_limited<F, T>(
+        limits: &QueryLimits,
+        deadline: Option<Instant>,
+        future: F,
+    ) -> Result<T, LogStoreError>
+    where
+        F: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(limits.run(deadline, future))
+        })?
+        .map_err(|e| {
+            LimitError::from_clickhouse(&e).map_or_else(
+                || LogStoreError::StorageError(e.to_string()),
+                LogStoreError::Limit,
+            )
+        })
+    }
+
+    /// Builds the count and `SELECT` statements executed by [`LogStore::query`].
+    fn build_statements(query: &LogQuery, params: &mut SqlParams) -> (String, String) {
+        let filter = Self::build_filter(query, params);
+        let count_sql = format!("SELECT count() FROM logs{filter}");
+        let sql = Self::build_select(query, &filter, params);
+        (count_sql, sql)
+    }
+
+    /// Builds the `SELECT` statement for the logs matching `query`, including the
+    /// cursor, ordering and pagination.
+    fn build_select(query: &LogQuery, filter: &str, params: &mut SqlParams) -> String {
+        use std::fmt::Write as _;
+
+        let mut sql = format!(
+            "SELECT timestamp, trace_id, span_id, level, message, service, attributes, \
+             {LOG_TIEBREAKER} AS tiebreaker FROM logs{filter}"
+        );
+        if let Some(ref cursor) = query.cursor {
+            let predicate = cursor.to_sql("timestamp", LOG_TIEBREAKER, params);
+            write!(&mut sql, " AND {predicate}").unwrap();
+        }
+
+        // Add ordering
+        let mut order = compile_order(&Source::Logs, &query.order_by, params);
+        order.push(format!("timestamp DESC, {LOG_TIEBREAKER} DESC"));
+        write!(&mut sql, " ORDER BY {}", order.join(", ")).unwrap();
+
+        // Add limit and offset
+        let offset = page_offset(query.cursor.as_ref(), query.offset);
+        match query.limit {
+            Some(limit) => write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap(),
+            None if offset > 0 => write!(&mut sql, " OFFSET {offset} ROWS").unwrap(),
+            None => {}
+        }
+
+        sql
+    }
+
+    /// Builds the `WHERE` clause for a log query, registering values in `params`.
+    fn build_filter(query: &LogQuery, params: &mut SqlParams) -> String {
+        use std::fmt::Write as _;
+
+        let mut filter = String::from(" WHERE 1=1");
+
+        // Add time range filters
+        if let Some(start) = query.start_time {
+            let p = params.push(SqlParam::Int(start.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND timestamp >= {p}").unwrap();
+        }
+        if let Some(end) = query.end_time {
+            let p = params.push(SqlParam::Int(end.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND timestamp < {p}").unwrap();
+        }
+
+        // Add level filter
+        if let Some(ref level) = query.level {
+            let p = params.push(SqlParam::String(level.to_string()));
+            write!(&mut filter, " AND level = {p}").unwrap();
+        }
+
+        // Add service filter
+        if let Some(ref service) = query.service {
+            let p = params.push(SqlParam::String(service.clone()));
+            write!(&mut filter, " AND service = {p}").unwrap();
+        }
+
+        // Add message search filter
+        if let Some(ref pattern) = query.message_contains {
+            let p = params.push(SqlParam::String(pattern.to_lowercase()));
+            write!(&mut filter, " AND position(lowerUTF8(message), {p}) > 0").unwrap();
+        }
+
+        // Add query language filter
+        if let Some(ref clause) = query.filter {
+            let predicate = compile_filter(&Source::Logs, clause, params);
+            write!(&mut filter, " AND {predicate}").unwrap();
+        }
+
+        filter
+    }
 }
 
 impl LogStore for ClickHouseLogStore {
@@ -374,13 +726,6 @@ impl LogStore for ClickHouseLogStore {
             let mut inserter = client.insert::<LogRow>("logs").await?;
 
             for entry in entries {
-                // Convert attributes HashMap<String, serde_json::Value> to Map(String, String)
-                let attributes: std::collections::HashMap<String, String> = entry
-                    .attributes
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.to_string()))
-                    .collect();
-
                 let row = LogRow {
                     timestamp: entry.timestamp.timestamp_nanos_opt().unwrap_or(0),
                     trace_id: entry.trace_id.unwrap_or_default(),
@@ -388,7 +733,7 @@ impl LogStore for ClickHouseLogStore {
                     level: entry.level.to_string(),
                     message: entry.message,
                     service: entry.service,
-                    attributes,
+                    attributes: encode_attributes(&entry.attributes),
                 };
 
                 inserter.write(&row).await?;
@@ -400,132 +745,126 @@ impl LogStore for ClickHouseLogStore {
     }
 
     fn query(&self, query: LogQuery) -> Result<LogQueryResult, LogStoreError> {
-        use std::fmt::Write as _;
-
-        // Define row structure for deserialization
-        #[derive(clickhouse::Row, serde::Deserialize)]
-        struct LogRow {
-            timestamp: i64,
-            trace_id: String,
-            span_id: String,
-            level: String,
-            message: String,
-            service: String,
-            attributes: std::collections::HashMap<String, String>,
-        }
-
-        // Build SQL query
-        let mut sql = String::from("SELECT timestamp, trace_id, span_id, level, message, service, attributes FROM logs WHERE 1=1");
-
-        // Add time range filters
-        if let Some(start) = query.start_time {
-            write!(
-                &mut sql,
-                " AND timestamp >= {}",
-                start.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-        if let Some(end) = query.end_time {
-            write!(
-                &mut sql,
-                " AND timestamp < {}",
-                end.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-
-        // Add level filter
-        if let Some(ref level) = query.level {
-            write!(&mut sql, " AND level = '{level}'").unwrap();
-        }
-
-        // Add service filter
-        if let Some(ref service) = query.service {
-            write!(&mut sql, " AND service = '{}'", service.replace('\'', "''")).unwrap();
-        }
-
-        // Add message search filter
-        if let Some(ref pattern) = query.message_contains {
-            write!(
-                &mut sql,
-                " AND position(lower(message), '{}') > 0",
-                pattern.to_lowercase().replace('\'', "''")
-            )
-            .unwrap();
-        }
-
-        // Add ordering
-        sql.push_str(" ORDER BY timestamp DESC");
-
-        // Calculate total count query
-        let count_sql = sql.replace(
-            "SELECT timestamp, trace_id, span_id, level, message, service, attributes FROM logs",
-            "SELECT count() FROM logs",
-        );
-
-        // Add limit and offset
-        let offset = query.offset.unwrap_or(0);
-        let limit = query.limit.unwrap_or(1000);
-        write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap();
+        // All user-supplied values are bound as query parameters
+        let mut params = SqlParams::new();
+        let (count_sql, sql) = Self::build_statements(&query, &mut params);
 
+        let limit = query.limit;
+        let default_order = query.order_by.is_empty();
+        let cursor = query.cursor.clone();
         let client = Arc::clone(&self.client);
-        let count_sql_clone = count_sql.clone();
 
         // Execute queries
-        Self::block_on(async move {
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
             // Execute count query
-            let total_count: u64 = client.query(&count_sql_clone).fetch_one::<u64>().await?;
+            let total_count: u64 = limits
+                .apply(params.bind(client.query(&count_sql)))
+                .fetch_one::<u64>()
+                .await?;
 
             // Execute main query
-            let rows: Vec<LogRow> = client.query(&sql).fetch_all::<LogRow>().await?;
+            let rows: Vec<LogRow> = limits
+                .apply(params.bind(client.query(&sql)))
+                .fetch_all::<LogRow>()
+                .await?;
+
+            // The cursor uses the tiebreaker computed by ClickHouse
+            let next_cursor = default_order
+                .then(|| next_cursor(&rows, limit, cursor.as_ref()))
+                .flatten();
 
             // Convert rows to LogEntry
-            let logs: Vec<LogEntry> = rows
-                .into_iter()
-                .map(|row| {
-                    let timestamp = DateTime::from_timestamp_nanos(row.timestamp);
-                    let level = match row.level.as_str() {
-                        "trace" => LogLevel::Trace,
-                        "debug" => LogLevel::Debug,
-                        "warn" => LogLevel::Warn,
-                        "error" => LogLevel::Error,
-                        "fatal" => LogLevel::Fatal,
-                        _ => LogLevel::Info,
-                    };
-                    let attributes: std::collections::HashMap<String, serde_json::Value> = row
-                        .attributes
-                        .into_iter()
-                        .map(|(k, v)| (k, serde_json::Value::String(v)))
-                        .collect();
-
-                    LogEntry {
-                        timestamp,
-                        level,
-                        message: row.message,
-                        service: row.service,
-                        attributes,
-                        trace_id: if row.trace_id.is_empty() {
-                            None
-                        } else {
-                            Some(row.trace_id)
-                        },
-                        span_id: if row.span_id.is_empty() {
-                            None
-                        } else {
-                            Some(row.span_id)
-                        },
-                    }
-                })
-                .collect();
+            let logs: Vec<LogEntry> = rows.into_iter().map(LogEntry::from).collect();
 
             Ok(LogQueryResult {
+                next_cursor,
                 logs,
                 total_count: usize::try_from(total_count).unwrap_or(usize::MAX),
             })
         })
     }
 
+    fn stream(&self, query: LogQuery) -> Result<LogStream, LogStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = Self::build_select(&query, &filter, &mut params);
+
+        // The request is sent on the first read; rows are then decoded as the
+        // response body arrives, so a slow consumer slows down the read.
+        let limits = query.limits;
+        let mut rows = limits
+            .apply(params.bind(self.client.query(&sql)))
+            .fetch::<LogRow>()
+            .map_err(|e| LogStoreError::StorageError(e.to_string()))?;
+
+        // The deadline covers the whole stream, not each read
+        let deadline = limits.deadline(Instant::now());
+        Ok(Box::new(std::iter::from_fn(move || {
+            Self::block_on_limited(&limits, deadline, rows.next())
+                .transpose()
+                .map(|row| row.map(LogEntry::from))
+        })))
+    }
+
+    fn query_groups(
+        &self,
+        query: LogQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, LogStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_group_query(&Source::Logs, "logs", &filter, group, &mut params);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_groups(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn fields(&self, query: LogQuery) -> Result<Vec<FieldInfo>, LogStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_fields_query(&Source::Logs, "logs", &filter);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_fields(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn counts(&self, query: RollupQuery) -> Result<Vec<LogCount>, LogStoreError> {
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            fetch_log_counts(&client, &query).await
+        })
+    }
+
+    fn explain(
+        &self,
+        query: LogQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, LogStoreError> {
+        let mut params = SqlParams::new();
+        let statements = if let Some(group) = group {
+            let filter = Self::build_filter(&query, &mut params);
+            let sql = compile_group_query(&Source::Logs, "logs", &filter, group, &mut params);
+            vec![sql.count, sql.select]
+        } else {
+            let (count_sql, sql) = Self::build_statements(&query, &mut params);
+            vec![count_sql, sql]
+        };
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            Ok(explain_statements(&client, statements, &params, limits).await)
+        })
+    }
+
     fn count(&self) -> Result<usize, LogStoreError> {
         let client = Arc::clone(&self.client);
         let count: u64 = Self::block_on(async move {
@@ -560,12 +899,22 @@ impl LogStore for ClickHouseLogStore {
             Ok(result.map(DateTime::from_timestamp_nanos))
         })
     }
+
+    fn expire(
+        &self,
+        _policy: &RetentionPolicy,
+        _now: DateTime<Utc>,
+    ) -> Result<usize, LogStoreError> {
+        // The table TTL deletes expired rows
+        Ok(0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::LogLevel;
+    use crate::storage::Limit;
     use chrono::Duration;
 
     fn create_test_log(message: &str) -> LogEntry {
@@ -650,7 +999,8 @@ mod tests {
 
         assert_eq!(result.logs.len(), 5);
         assert_eq!(result.total_count, 10);
-        assert_eq!(result.logs[0].message, "Log 5");
+        // Newest first
+        assert_eq!(result.logs[0].message, "Log 4");
     }
 
     #[test]
@@ -666,8 +1016,94 @@ mod tests {
 
         assert_eq!(result.logs.len(), 3);
         assert_eq!(result.total_count, 10);
-        assert_eq!(result.logs[0].message, "Log 3");
-        assert_eq!(result.logs[2].message, "Log 5");
+        assert_eq!(result.logs[0].message, "Log 6");
+        assert_eq!(result.logs[2].message, "Log 4");
+    }
+
+    #[test]
+    fn test_query_with_cursor() {
+        let store = InMemoryLogStore::new();
+        let base = Utc::now() - Duration::hours(1);
+        for i in 0..5 {
+            // Two logs per timestamp, so pages have to break ties
+            let timestamp = base + Duration::seconds(i / 2);
+            store
+                .insert(create_test_log_with_timestamp(
+                    &format!("Log {i}"),
+                    timestamp,
+                ))
+                .unwrap();
+        }
+
+        // Logs sharing a timestamp are ordered by their hash
+        let expected: Vec<String> = store
+            .query(LogQuery::new())
+            .unwrap()
+            .logs
+            .into_iter()
+            .map(|log| log.message)
+            .collect();
+        assert_eq!((expected.len(), &*expected[0]), (5, "Log 4"));
+
+        let mut messages = Vec::new();
+        let mut query = LogQuery::new().with_limit(2);
+        loop {
+            let result = store.query(query.clone()).unwrap();
+            messages.extend(result.logs.into_iter().map(|log| log.message));
+
+            let Some(cursor) = result.next_cursor else {
+                break;
+            };
+            // Logs ingested while paging do not shift the following pages
+            store.insert(create_test_log("New")).unwrap();
+            query = query.with_cursor(cursor);
+        }
+
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn test_stream_matches_query() {
+        let store = InMemoryLogStore::new();
+        for i in 0..5 {
+            store.insert(create_test_log(&format!("Log {i}"))).unwrap();
+        }
+
+        let query = LogQuery::new().with_limit(3).with_offset(1);
+        let streamed: Vec<String> = store
+            .stream(query.clone())
+            .unwrap()
+            .map(|log| log.unwrap().message)
+            .collect();
+        let queried: Vec<String> = store
+            .query(query)
+            .unwrap()
+            .logs
+            .into_iter()
+            .map(|log| log.message)
+            .collect();
+
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, queried);
+    }
+
+    #[test]
+    fn test_query_cursor_only_in_default_order() {
+        let store = InMemoryLogStore::new();
+        for i in 0..3 {
+            store.insert(create_test_log(&format!("Log {i}"))).unwrap();
+        }
+
+        let result = store.query(LogQuery::new().with_limit(2)).unwrap();
+        assert!(result.next_cursor.is_some());
+
+        let order_by = crate::query::parse_query("SELECT * FROM logs ORDER BY message ASC")
+            .unwrap()
+            .order_by;
+        let result = store
+            .query(LogQuery::new().with_limit(2).with_order_by(order_by))
+            .unwrap();
+        assert!(result.next_cursor.is_none());
     }
 
     #[test]
@@ -738,6 +1174,57 @@ mod tests {
         assert_eq!(result.total_count, 0);
     }
 
+    #[test]
+    fn test_query_limits() {
+        let store = InMemoryLogStore::new();
+        let now = Utc::now();
+        for hours in 0..4 {
+            store
+                .insert(create_test_log_with_timestamp(
+                    &format!("Log {hours}"),
+                    now - Duration::hours(hours),
+                ))
+                .unwrap();
+        }
+        let last_hour = || LogQuery::new().with_start_time(now - Duration::minutes(90));
+
+        // Logs outside the time range are not scanned
+        let result = store
+            .query(last_hour().with_limits(QueryLimits::new().with_max_rows_scanned(2)))
+            .unwrap();
+        assert_eq!(result.total_count, 2);
+        assert!(matches!(
+            store.query(LogQuery::new().with_limits(QueryLimits::new().with_max_rows_scanned(2))),
+            Err(LogStoreError::Limit(LimitError::Exceeded {
+                limit: Limit::RowsScanned,
+                ..
+            }))
+        ));
+
+        // The result size is checked on the returned page
+        let size = serde_json::to_vec(&result.logs[0]).unwrap().len() as u64;
+        let limits = QueryLimits::new().with_max_result_bytes(size + size / 2);
+        assert!(store
+            .query(last_hour().with_limit(1).with_limits(limits.clone()))
+            .is_ok());
+        assert!(matches!(
+            store.query(last_hour().with_limits(limits)),
+            Err(LogStoreError::Limit(LimitError::Exceeded {
+                limit: Limit::ResultBytes,
+                ..
+            }))
+        ));
+
+        let limits = QueryLimits::new().with_timeout(std::time::Duration::ZERO);
+        assert!(matches!(
+            store.query(LogQuery::new().with_limits(limits)),
+            Err(LogStoreError::Limit(LimitError::Exceeded {
+                limit: Limit::Timeout,
+                ..
+            }))
+        ));
+    }
+
     #[test]
     fn test_clear_store() {
         let store = InMemoryLogStore::new();
@@ -976,7 +1463,7 @@ mod tests {
 
         assert_eq!(result.total_count, 10); // Total errors before pagination
         assert_eq!(result.logs.len(), 3); // After limit
-        assert_eq!(result.logs[0].message, "Error 2"); // After offset
+        assert_eq!(result.logs[0].message, "Error 7"); // Newest first, after offset
     }
 
     #[test]
@@ -1034,4 +1521,33 @@ mod tests {
         assert_eq!(query.limit, Some(100));
         assert_eq!(query.offset, Some(10));
     }
+
+    #[test]
+    fn test_query_with_filter_and_order() {
+        use crate::query::parse_query;
+
+        let store = InMemoryLogStore::new();
+        store
+            .insert(create_test_log_with_level("Warning", LogLevel::Warn))
+            .unwrap();
+        store
+            .insert(create_test_log_with_level("Info", LogLevel::Info))
+            .unwrap();
+        store
+            .insert(create_test_log_with_level("Failure", LogLevel::Error))
+            .unwrap();
+
+        let parsed =
+            parse_query("SELECT * FROM logs WHERE level >= 'warn' ORDER BY level ASC").unwrap();
+        let query = LogQuery::new()
+            .with_filter(parsed.where_clause.unwrap())
+            .with_order_by(parsed.order_by)
+            .with_limit(1);
+
+        let result = store.query(query).unwrap();
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.logs.len(), 1);
+        assert_eq!(result.logs[0].level, LogLevel::Warn);
+    }
 }
@@ -3,11 +3,26 @@
 //! Provides the `MetricStore` trait for abstracting metric storage operations
 //! and an `InMemoryMetricStore` implementation for development and testing.
 
+use super::cursor::{next_cursor, page_offset, sort_newest_first, METRIC_TIEBREAKER};
+use super::rollup::{
+    fetch_metric_rollups, metric_rollups, metric_series, MetricRollup, RollupQuery, SeriesBucket,
+    SeriesPoint, SeriesQuery, Tier,
+};
+use super::{Cursor, LimitError, QueryLimits, Scan};
+use crate::config::RetentionPolicy;
 use crate::models::{Metric, MetricType};
+use crate::query::{
+    collect_fields, compile_fields_query, compile_filter, compile_group_query, compile_order,
+    explain_statements, fetch_fields, fetch_groups, group_records, matches_filter, sort_records,
+    FieldInfo, GroupQuery, GroupQueryResult, OrderBy, Source, SqlParam, SqlParams, StorePlan,
+    WhereClause,
+};
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, RwLock};
 use thiserror::Error;
+use tokio::time::Instant;
 
 /// Errors that can occur during metric store operations.
 #[derive(Debug, Error)]
@@ -19,6 +34,9 @@ pub enum MetricStoreError {
     /// Generic storage error.
     #[error("Storage error: {0}")]
     StorageError(String),
+    /// The query was stopped by its limits.
+    #[error("{0}")]
+    Limit(#[from] LimitError),
 }
 
 /// Query parameters for retrieving metrics.
@@ -44,6 +62,19 @@ pub struct MetricQuery {
 
     /// Number of metrics to skip (for pagination).
     pub offset: Option<usize>,
+
+    /// Additional filter expressed in the query language.
+    pub filter: Option<WhereClause>,
+
+    /// Sort keys, most significant first. Ties, and queries without keys,
+    /// are ordered newest first.
+    pub order_by: Vec<OrderBy>,
+
+    /// Return only metrics after this position in the default order (keyset pagination).
+    pub cursor: Option<Cursor>,
+
+    /// Limits on the execution of the query.
+    pub limits: QueryLimits,
 }
 
 impl MetricQuery {
@@ -101,6 +132,34 @@ impl MetricQuery {
         self.offset = Some(offset);
         self
     }
+
+    /// Sets an additional filter expressed in the query language.
+    #[must_use]
+    pub fn with_filter(mut self, filter: WhereClause) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets the sort keys.
+    #[must_use]
+    pub fn with_order_by(mut self, order_by: Vec<OrderBy>) -> Self {
+        self.order_by = order_by;
+        self
+    }
+
+    /// Continues after the given cursor.
+    #[must_use]
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Sets the limits on the execution of the query.
+    #[must_use]
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
 /// Result of a metric query operation.
@@ -109,12 +168,16 @@ pub struct MetricQueryResult {
     /// The metrics matching the query.
     pub metrics: Vec<Metric>,
 
-    /// Total count of matching metrics (before limit/offset applied).
+    /// Total count of matching metrics (before cursor/limit/offset applied).
     pub total_count: usize,
+
+    /// Cursor for the next page, if the page is full and in the default order.
+    pub next_cursor: Option<Cursor>,
 }
 
 /// Aggregation function for metrics.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AggregationFunction {
     /// Sum of values.
     Sum,
@@ -163,6 +226,63 @@ pub trait MetricStore: Send + Sync {
     /// Returns an error if the query operation fails.
     fn query(&self, query: MetricQuery) -> Result<MetricQueryResult, MetricStoreError>;
 
+    /// Groups and aggregates the metrics matching the query.
+    ///
+    /// Ordering and pagination of `query` are ignored; `group` carries its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn query_groups(
+        &self,
+        query: MetricQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, MetricStoreError>;
+
+    /// Lists the fields of the metrics matching the query, with their types and
+    /// cardinality.
+    ///
+    /// Ordering and pagination of `query` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn fields(&self, query: MetricQuery) -> Result<Vec<FieldInfo>, MetricStoreError>;
+
+    /// Reads the rollups of the metric tier at `query.interval`, ordered by
+    /// series, then time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn rollups(&self, query: RollupQuery) -> Result<Vec<MetricRollup>, MetricStoreError>;
+
+    /// Aggregates the metrics matching the query into steps, reading each
+    /// segment of `query` from its tier. Steps without values are omitted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn series(&self, query: SeriesQuery) -> Result<Vec<SeriesPoint>, MetricStoreError>;
+
+    /// Describes how the store would execute `query`, or the aggregation `group`
+    /// over the metrics matching `query` if one is given, without running it.
+    ///
+    /// The default implementation describes a store that evaluates queries in
+    /// memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails.
+    fn explain(
+        &self,
+        query: MetricQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, MetricStoreError> {
+        let _ = (query, group);
+        Ok(StorePlan::in_memory())
+    }
+
     /// Returns the total number of metrics in the store.
     ///
     /// # Errors
@@ -201,6 +321,21 @@ pub trait MetricStore: Send + Sync {
     ///
     /// Returns an error if the operation fails.
     fn get_newest_timestamp(&self) -> Result<Option<DateTime<Utc>>, MetricStoreError>;
+
+    /// Deletes the metrics that have outlived `policy` at `now`, returning how
+    /// many were deleted.
+    ///
+    /// Stores whose database expires data itself, like `ClickHouse` with its
+    /// table TTLs, delete nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails.
+    fn expire(
+        &self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<usize, MetricStoreError>;
 }
 
 /// In-memory metric store implementation.
@@ -250,8 +385,14 @@ impl MetricStore for InMemoryMetricStore {
             .read()
             .map_err(|_| MetricStoreError::LockError)?;
 
-        let filtered: Vec<Metric> = metrics
-            .iter()
+        // Like the partitions `ClickHouse` skips, metrics outside the time
+        // range are not counted as scanned
+        let mut scan = Scan::new(&query.limits);
+        let filtered: Vec<Metric> = scan
+            .rows(metrics.iter().filter(|m| {
+                query.start_time.is_none_or(|start| m.timestamp >= start)
+                    && query.end_time.is_none_or(|end| m.timestamp < end)
+            }))
             .filter(|m| {
                 // Name filter
                 if let Some(ref name) = query.name {
@@ -267,18 +408,6 @@ impl MetricStore for InMemoryMetricStore {
                     }
                 }
 
-                // Time range filter
-                if let Some(start) = query.start_time {
-                    if m.timestamp < start {
-                        return false;
-                    }
-                }
-                if let Some(end) = query.end_time {
-                    if m.timestamp >= end {
-                        return false;
-                    }
-                }
-
                 // Label filters (all must match)
                 for (key, value) in &query.labels {
                     match m.labels.get(key) {
@@ -287,27 +416,119 @@ impl MetricStore for InMemoryMetricStore {
                     }
                 }
 
+                // Query language filter
+                if let Some(ref filter) = query.filter {
+                    if !matches_filter(filter, *m) {
+                        return false;
+                    }
+                }
+
                 true
             })
             .cloned()
             .collect();
+        scan.finish()?;
+
+        let mut filtered = filtered;
+        // Sort newest first, then by the requested order
+        sort_newest_first(&mut filtered);
+        sort_records(&mut filtered, &query.order_by);
 
         let total_count = filtered.len();
 
-        // Apply offset and limit
+        // Apply cursor, offset and limit
+        if let Some(ref cursor) = query.cursor {
+            cursor.skip(&mut filtered);
+        }
         let offset = query.offset.unwrap_or(0);
         let result: Vec<Metric> = filtered
             .into_iter()
             .skip(offset)
             .take(query.limit.unwrap_or(usize::MAX))
             .collect();
+        query.limits.check_result(&result)?;
 
         Ok(MetricQueryResult {
+            next_cursor: query
+                .order_by
+                .is_empty()
+                .then(|| next_cursor(&result, query.limit, query.cursor.as_ref()))
+                .flatten(),
             metrics: result,
             total_count,
         })
     }
 
+    fn query_groups(
+        &self,
+        query: MetricQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, MetricStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query(MetricQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let groups = group_records(&result.metrics, group);
+        limits.check_result(&groups.rows)?;
+        Ok(groups)
+    }
+
+    fn fields(&self, query: MetricQuery) -> Result<Vec<FieldInfo>, MetricStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query(MetricQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let fields = collect_fields(&result.metrics);
+        limits.check_result(&fields)?;
+        Ok(fields)
+    }
+
+    fn rollups(&self, query: RollupQuery) -> Result<Vec<MetricRollup>, MetricStoreError> {
+        let metrics = self
+            .metrics
+            .read()
+            .map_err(|_| MetricStoreError::LockError)?;
+        let mut scan = Scan::new(&query.limits);
+        let rollups = metric_rollups(
+            scan.rows(metrics.iter().filter(|m| query.contains_time(m.timestamp))),
+            &query,
+        );
+        scan.finish()?;
+        query.limits.check_result(&rollups)?;
+        Ok(rollups)
+    }
+
+    fn series(&self, query: SeriesQuery) -> Result<Vec<SeriesPoint>, MetricStoreError> {
+        let metrics = self
+            .metrics
+            .read()
+            .map_err(|_| MetricStoreError::LockError)?;
+        // Each segment reads the metrics again, so the metrics in the time
+        // range are scanned once up front
+        let mut scan = Scan::new(&query.limits);
+        let scanned: Vec<&Metric> = scan
+            .rows(
+                metrics
+                    .iter()
+                    .filter(|m| query.start_time <= m.timestamp && m.timestamp < query.end_time),
+            )
+            .collect();
+        scan.finish()?;
+        let points = metric_series(&scanned, &query);
+        query.limits.check_result(&points)?;
+        Ok(points)
+    }
+
     fn count(&self) -> Result<usize, MetricStoreError> {
         let metrics = self
             .metrics
@@ -375,6 +596,20 @@ impl MetricStore for InMemoryMetricStore {
             .map_err(|_| MetricStoreError::LockError)?;
         Ok(metrics.iter().map(|m| m.timestamp).max())
     }
+
+    fn expire(
+        &self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<usize, MetricStoreError> {
+        let mut metrics = self
+            .metrics
+            .write()
+            .map_err(|_| MetricStoreError::LockError)?;
+        let before = metrics.len();
+        metrics.retain(|metric| !policy.is_expired(metric, metric.timestamp, now));
+        Ok(before - metrics.len())
+    }
 }
 
 /// `ClickHouse`-backed metric store implementation.
@@ -410,6 +645,140 @@ impl ClickHouseMetricStore {
                 .map_err(|e| MetricStoreError::StorageError(e.to_string()))
         })
     }
+
+    /// Like [`Self::block_on`], but stops waiting when the query is cancelled or
+    /// `deadline` passes, and reports queries stopped by `limits`.
+    fn block_on_limited<F, T>(
+        limits: &QueryLimits,
+        deadline: Option<Instant>,
+        future: F,
+    ) -> Result<T, MetricStoreError>
+    where
+        F: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(limits.run(deadline, future))
+        })?
+        .map_err(|e| {
+            LimitError::from_clickhouse(&e).map_or_else(
+                || MetricStoreError::StorageError(e.to_string()),
+                MetricStoreError::Limit,
+            )
+        })
+    }
+
+    /// Builds the count and `SELECT` statements executed by [`MetricStore::query`].
+    fn build_statements(query: &MetricQuery, params: &mut SqlParams) -> (String, String) {
+        use std::fmt::Write as _;
+
+        let filter = Self::build_filter(query, params);
+        let count_sql = format!("SELECT count() FROM metrics{filter}");
+
+        let mut sql = format!(
+            "SELECT timestamp, name, metric_type, value, labels, service, bucket_counts, bucket_bounds FROM metrics{filter}"
+        );
+        if let Some(ref cursor) = query.cursor {
+            let predicate = cursor.to_sql("timestamp", METRIC_TIEBREAKER, params);
+            write!(&mut sql, " AND {predicate}").unwrap();
+        }
+
+        // Add ordering
+        let mut order = compile_order(&Source::Metrics, &query.order_by, params);
+        order.push(format!("timestamp DESC, {METRIC_TIEBREAKER} DESC"));
+        write!(&mut sql, " ORDER BY {}", order.join(", ")).unwrap();
+
+        // Add limit and offset
+        let offset = page_offset(query.cursor.as_ref(), query.offset);
+        match query.limit {
+            Some(limit) => write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap(),
+            None if offset > 0 => write!(&mut sql, " OFFSET {offset} ROWS").unwrap(),
+            None => {}
+        }
+
+        (count_sql, sql)
+    }
+
+    /// Builds the `SELECT` executed by [`MetricStore::series`], with one branch
+    /// per segment reading the raw metrics or a tier table.
+    fn build_series_statement(query: &SeriesQuery, params: &mut SqlParams) -> String {
+        let step = query.step_secs;
+        let branches: Vec<String> = query
+            .segments
+            .iter()
+            .map(|segment| match segment.tier {
+                Tier::Raw => {
+                    let filter = Self::build_filter(&query.raw_query(segment), params);
+                    format!(
+                        "SELECT toInt64(intDiv(timestamp, {}) * {step}) AS bucket, \
+                         count() AS total, sum(value) AS value_sum, \
+                         min(value) AS value_min, max(value) AS value_max \
+                         FROM metrics{filter} GROUP BY bucket",
+                        step.saturating_mul(1_000_000_000)
+                    )
+                }
+                Tier::Rollup(interval) => {
+                    let (filter, _) = query
+                        .rollup_query(interval, segment)
+                        .to_sql(Some("name"), params);
+                    format!(
+                        "SELECT toInt64(intDiv(toUnixTimestamp(timestamp), {step}) * {step}) AS bucket, \
+                         sum(count) AS total, sum(sum) AS value_sum, \
+                         min(min) AS value_min, max(max) AS value_max \
+                         FROM metrics_{}{filter} GROUP BY bucket",
+                        interval.table_suffix()
+                    )
+                }
+            })
+            .collect();
+        format!(
+            "SELECT bucket, total, value_sum, value_min, value_max FROM ({}) ORDER BY bucket",
+            branches.join(" UNION ALL ")
+        )
+    }
+
+    /// Builds the `WHERE` clause for a metric query, registering values in `params`.
+    fn build_filter(query: &MetricQuery, params: &mut SqlParams) -> String {
+        use std::fmt::Write as _;
+
+        let mut filter = String::from(" WHERE 1=1");
+
+        // Add name filter
+        if let Some(ref name) = query.name {
+            let p = params.push(SqlParam::String(name.clone()));
+            write!(&mut filter, " AND name = {p}").unwrap();
+        }
+
+        // Add type filter
+        if let Some(ref metric_type) = query.metric_type {
+            let p = params.push(SqlParam::String(metric_type.to_string()));
+            write!(&mut filter, " AND metric_type = {p}").unwrap();
+        }
+
+        // Add time range filters
+        if let Some(start) = query.start_time {
+            let p = params.push(SqlParam::Int(start.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND timestamp >= {p}").unwrap();
+        }
+        if let Some(end) = query.end_time {
+            let p = params.push(SqlParam::Int(end.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND timestamp < {p}").unwrap();
+        }
+
+        // Add label filters
+        for (key, value) in &query.labels {
+            let k = params.push(SqlParam::String(key.clone()));
+            let v = params.push(SqlParam::String(value.clone()));
+            write!(&mut filter, " AND labels[{k}] = {v}").unwrap();
+        }
+
+        // Add query language filter
+        if let Some(ref clause) = query.filter {
+            let predicate = compile_filter(&Source::Metrics, clause, params);
+            write!(&mut filter, " AND {predicate}").unwrap();
+        }
+
+        filter
+    }
 }
 
 impl MetricStore for ClickHouseMetricStore {
@@ -483,8 +852,6 @@ impl MetricStore for ClickHouseMetricStore {
 
     #[allow(clippy::too_many_lines)]
     fn query(&self, query: MetricQuery) -> Result<MetricQueryResult, MetricStoreError> {
-        use std::fmt::Write as _;
-
         // Define row structure for deserialization
         #[derive(clickhouse::Row, serde::Deserialize)]
         struct MetricRow {
@@ -499,71 +866,28 @@ impl MetricStore for ClickHouseMetricStore {
             bucket_bounds: Vec<f64>,
         }
 
-        // Build SQL query
-        let mut sql = String::from("SELECT timestamp, name, metric_type, value, labels, service, bucket_counts, bucket_bounds FROM metrics WHERE 1=1");
-
-        // Add name filter
-        if let Some(ref name) = query.name {
-            write!(&mut sql, " AND name = '{}'", name.replace('\'', "''")).unwrap();
-        }
-
-        // Add type filter
-        if let Some(ref metric_type) = query.metric_type {
-            write!(&mut sql, " AND metric_type = '{metric_type}'").unwrap();
-        }
-
-        // Add time range filters
-        if let Some(start) = query.start_time {
-            write!(
-                &mut sql,
-                " AND timestamp >= {}",
-                start.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-        if let Some(end) = query.end_time {
-            write!(
-                &mut sql,
-                " AND timestamp < {}",
-                end.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-
-        // Add label filters
-        for (key, value) in &query.labels {
-            write!(
-                &mut sql,
-                " AND labels['{}'] = '{}'",
-                key.replace('\'', "''"),
-                value.replace('\'', "''")
-            )
-            .unwrap();
-        }
-
-        // Add ordering
-        sql.push_str(" ORDER BY timestamp DESC");
-
-        // Calculate total count query
-        let count_sql = sql.replace(
-            "SELECT timestamp, name, metric_type, value, labels, service, bucket_counts, bucket_bounds FROM metrics",
-            "SELECT count() FROM metrics",
-        );
-
-        // Add limit and offset
-        let offset = query.offset.unwrap_or(0);
-        let limit = query.limit.unwrap_or(1000);
-        write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap();
+        // Build SQL query; all user-supplied values are bound as query parameters
+        let mut params = SqlParams::new();
+        let (count_sql, sql) = Self::build_statements(&query, &mut params);
 
+        let limit = query.limit;
+        let default_order = query.order_by.is_empty();
+        let cursor = query.cursor.clone();
         let client = Arc::clone(&self.client);
-        let count_sql_clone = count_sql.clone();
 
-        Self::block_on(async move {
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
             // Execute count query
-            let total_count: u64 = client.query(&count_sql_clone).fetch_one::<u64>().await?;
+            let total_count: u64 = limits
+                .apply(params.bind(client.query(&count_sql)))
+                .fetch_one::<u64>()
+                .await?;
 
             // Execute main query
-            let rows: Vec<MetricRow> = client.query(&sql).fetch_all::<MetricRow>().await?;
+            let rows: Vec<MetricRow> = limits
+                .apply(params.bind(client.query(&sql)))
+                .fetch_all::<MetricRow>()
+                .await?;
 
             // Convert rows to Metric
             let metrics: Vec<Metric> = rows
@@ -613,12 +937,114 @@ impl MetricStore for ClickHouseMetricStore {
                 .collect();
 
             Ok(MetricQueryResult {
+                next_cursor: default_order
+                    .then(|| next_cursor(&metrics, limit, cursor.as_ref()))
+                    .flatten(),
                 metrics,
                 total_count: usize::try_from(total_count).unwrap_or(usize::MAX),
             })
         })
     }
 
+    fn query_groups(
+        &self,
+        query: MetricQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, MetricStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_group_query(&Source::Metrics, "metrics", &filter, group, &mut params);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_groups(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn fields(&self, query: MetricQuery) -> Result<Vec<FieldInfo>, MetricStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_fields_query(&Source::Metrics, "metrics", &filter);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_fields(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn rollups(&self, query: RollupQuery) -> Result<Vec<MetricRollup>, MetricStoreError> {
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            fetch_metric_rollups(&client, &query).await
+        })
+    }
+
+    fn series(&self, query: SeriesQuery) -> Result<Vec<SeriesPoint>, MetricStoreError> {
+        #[derive(clickhouse::Row, serde::Deserialize)]
+        struct Row {
+            bucket: i64,
+            total: u64,
+            value_sum: f64,
+            value_min: f64,
+            value_max: f64,
+        }
+
+        if query.segments.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut params = SqlParams::new();
+        let sql = Self::build_series_statement(&query, &mut params);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        let rows = Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            limits
+                .apply(params.bind(client.query(&sql)))
+                .fetch_all::<Row>()
+                .await
+        })?;
+
+        let mut buckets: BTreeMap<i64, SeriesBucket> = BTreeMap::new();
+        for row in rows {
+            buckets
+                .entry(row.bucket)
+                .or_insert_with(SeriesBucket::new)
+                .merge(row.total, row.value_sum, row.value_min, row.value_max);
+        }
+        Ok(buckets
+            .into_iter()
+            .map(|(start, bucket)| {
+                let timestamp = DateTime::from_timestamp(start, 0).unwrap_or_default();
+                bucket.point(timestamp, query.function)
+            })
+            .collect())
+    }
+
+    fn explain(
+        &self,
+        query: MetricQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, MetricStoreError> {
+        let mut params = SqlParams::new();
+        let statements = if let Some(group) = group {
+            let filter = Self::build_filter(&query, &mut params);
+            let sql = compile_group_query(&Source::Metrics, "metrics", &filter, group, &mut params);
+            vec![sql.count, sql.select]
+        } else {
+            let (count_sql, sql) = Self::build_statements(&query, &mut params);
+            vec![count_sql, sql]
+        };
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            Ok(explain_statements(&client, statements, &params, limits).await)
+        })
+    }
+
     fn count(&self) -> Result<usize, MetricStoreError> {
         let client = Arc::clone(&self.client);
         let count: u64 = Self::block_on(async move {
@@ -641,8 +1067,6 @@ impl MetricStore for ClickHouseMetricStore {
         query: MetricQuery,
         function: AggregationFunction,
     ) -> Result<AggregationResult, MetricStoreError> {
-        use std::fmt::Write as _;
-
         // Define row structure for deserialization
         #[derive(clickhouse::Row, serde::Deserialize)]
         struct AggRow {
@@ -659,53 +1083,18 @@ impl MetricStore for ClickHouseMetricStore {
             AggregationFunction::Count => "count()",
         };
 
-        let mut sql = format!(
-            "SELECT {agg_func} as agg_value, count() as sample_count FROM metrics WHERE 1=1"
-        );
-
-        // Add name filter
-        if let Some(ref name) = query.name {
-            write!(&mut sql, " AND name = '{}'", name.replace('\'', "''")).unwrap();
-        }
-
-        // Add type filter
-        if let Some(ref metric_type) = query.metric_type {
-            write!(&mut sql, " AND metric_type = '{metric_type}'").unwrap();
-        }
-
-        // Add time range filters
-        if let Some(start) = query.start_time {
-            write!(
-                &mut sql,
-                " AND timestamp >= {}",
-                start.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-        if let Some(end) = query.end_time {
-            write!(
-                &mut sql,
-                " AND timestamp < {}",
-                end.timestamp_nanos_opt().unwrap_or(0)
-            )
-            .unwrap();
-        }
-
-        // Add label filters
-        for (key, value) in &query.labels {
-            write!(
-                &mut sql,
-                " AND labels['{}'] = '{}'",
-                key.replace('\'', "''"),
-                value.replace('\'', "''")
-            )
-            .unwrap();
-        }
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql =
+            format!("SELECT {agg_func} as agg_value, count() as sample_count FROM metrics{filter}");
 
         let client = Arc::clone(&self.client);
 
         Self::block_on(async move {
-            let row: AggRow = client.query(&sql).fetch_one::<AggRow>().await?;
+            let row: AggRow = params
+                .bind(client.query(&sql))
+                .fetch_one::<AggRow>()
+                .await?;
 
             Ok(AggregationResult {
                 value: row.agg_value,
@@ -731,6 +1120,15 @@ impl MetricStore for ClickHouseMetricStore {
             Ok(result.map(DateTime::from_timestamp_nanos))
         })
     }
+
+    fn expire(
+        &self,
+        _policy: &RetentionPolicy,
+        _now: DateTime<Utc>,
+    ) -> Result<usize, MetricStoreError> {
+        // The table TTL deletes expired rows
+        Ok(0)
+    }
 }
 
 #[cfg(test)]
@@ -871,6 +1269,41 @@ mod tests {
         assert_eq!(result.total_count, 10);
     }
 
+    #[test]
+    fn test_query_with_cursor() {
+        let store = InMemoryMetricStore::new();
+        let timestamp = Utc::now();
+
+        // Same name and timestamp; only the labels tell the metrics apart
+        for host in ["web-1", "web-2", "web-3"] {
+            store
+                .insert(
+                    create_test_metric("cpu", 1.0)
+                        .with_label("host", host)
+                        .with_timestamp(timestamp),
+                )
+                .unwrap();
+        }
+
+        let first = store.query(MetricQuery::new().with_limit(2)).unwrap();
+        let second = store
+            .query(
+                MetricQuery::new()
+                    .with_limit(2)
+                    .with_cursor(first.next_cursor.unwrap()),
+            )
+            .unwrap();
+
+        let hosts: Vec<&str> = first
+            .metrics
+            .iter()
+            .chain(&second.metrics)
+            .map(|m| m.labels["host"].as_str())
+            .collect();
+        assert_eq!(hosts, ["web-3", "web-2", "web-1"]);
+        assert!(second.next_cursor.is_none());
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn test_aggregation_sum() {
@@ -958,4 +1391,61 @@ mod tests {
 
         assert_eq!(store.count().unwrap(), 0);
     }
+
+    #[test]
+    fn test_query_with_filter_and_order() {
+        use crate::query::parse_query;
+
+        let store = InMemoryMetricStore::new();
+        store
+            .insert(create_test_metric("cpu_usage", 75.5).with_label("host", "server1"))
+            .unwrap();
+        store
+            .insert(create_test_metric("cpu_usage", 95.0).with_label("host", "server1"))
+            .unwrap();
+        store
+            .insert(create_test_metric("cpu_usage", 99.0).with_label("host", "server2"))
+            .unwrap();
+
+        let parsed =
+            parse_query("SELECT * FROM metrics WHERE labels.host = 'server1' ORDER BY value DESC")
+                .unwrap();
+        let result = store
+            .query(
+                MetricQuery::new()
+                    .with_filter(parsed.where_clause.unwrap())
+                    .with_order_by(parsed.order_by),
+            )
+            .unwrap();
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.metrics[0].simple_value(), Some(95.0));
+        assert_eq!(result.metrics[1].simple_value(), Some(75.5));
+    }
+
+    #[test]
+    fn test_series_statement() {
+        use crate::config::AggregationConfig;
+
+        let now = DateTime::from_timestamp(1_717_245_000, 0).unwrap();
+        let config = AggregationConfig {
+            enabled: true,
+            ..AggregationConfig::default()
+        };
+        let query = SeriesQuery::new(
+            AggregationFunction::Avg,
+            3600,
+            now - chrono::TimeDelta::days(2),
+            now,
+        )
+        .with_name("http_requests_total")
+        .with_tiers(&config, now);
+
+        let mut params = SqlParams::new();
+        let sql = ClickHouseMetricStore::build_series_statement(&query, &mut params);
+        assert_eq!(sql.matches(" UNION ALL ").count(), 2);
+        assert!(sql.contains("FROM metrics WHERE 1=1 AND name = {p0:String}"));
+        assert!(sql.contains("FROM metrics_1hour WHERE 1=1 AND timestamp >= toDateTime("));
+        assert!(sql.ends_with(") ORDER BY bucket"));
+    }
 }
@@ -4,18 +4,99 @@
 //! The `LogStore` trait defines the interface for log storage, allowing different
 //! implementations (in-memory, database-backed, etc.).
 
+pub mod cursor;
+pub mod limits;
 pub mod log_store;
 pub mod metric_store;
+pub mod rollup;
+pub mod saved_query_store;
+pub mod settings_store;
 pub mod trace_store;
 
+pub use cursor::{Cursor, CursorError, CursorKey};
+pub(crate) use limits::Scan;
+pub use limits::{Limit, LimitError, QueryLimits};
 pub use log_store::{
     ClickHouseLogStore, InMemoryLogStore, LogQuery, LogQueryResult, LogStore, LogStoreError,
+    LogStream,
 };
 pub use metric_store::{
     AggregationFunction, AggregationResult, ClickHouseMetricStore, InMemoryMetricStore,
     MetricQuery, MetricQueryResult, MetricStore, MetricStoreError,
 };
+pub use rollup::{
+    LogCount, MetricRollup, RollupQuery, SeriesPoint, SeriesQuery, SpanStats, Tier, TierSegment,
+    TraceStats,
+};
+pub use saved_query_store::{
+    ClickHouseSavedQueryStore, FileSavedQueryStore, InMemorySavedQueryStore, SavedQueryStore,
+    SavedQueryStoreError,
+};
+pub use settings_store::{
+    ClickHouseSettingsStore, FileSettingsStore, InMemorySettingsStore, Settings, SettingsStore,
+    SettingsStoreError,
+};
 pub use trace_store::{
-    ClickHouseTraceStore, InMemoryTraceStore, TraceQuery, TraceQueryResult, TraceStore,
-    TraceStoreError,
+    ClickHouseTraceStore, InMemoryTraceStore, SpanQueryResult, TraceQuery, TraceQueryResult,
+    TraceStore, TraceStoreError,
 };
+
+use std::collections::HashMap;
+
+/// Encodes attribute values as JSON text for a `ClickHouse` `Map(String, String)` column.
+pub(crate) fn encode_attributes(
+    attributes: &HashMap<String, serde_json::Value>,
+) -> HashMap<String, String> {
+    attributes
+        .iter()
+        .map(|(k, v)| (k.clone(), v.to_string()))
+        .collect()
+}
+
+/// Decodes attribute values written by [`encode_attributes`], preserving their JSON types.
+///
+/// Values that are not valid JSON are returned as strings.
+pub(crate) fn decode_attributes(
+    attributes: HashMap<String, String>,
+) -> HashMap<String, serde_json::Value> {
+    attributes
+        .into_iter()
+        .map(|(k, v)| {
+            let value = serde_json::from_str(&v).unwrap_or(serde_json::Value::String(v));
+            (k, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_attributes_round_trip() {
+        let attributes: HashMap<String, serde_json::Value> = [
+            ("status".to_string(), json!(503)),
+            ("ratio".to_string(), json!(0.5)),
+            ("retry".to_string(), json!(true)),
+            ("user".to_string(), json!("42")),
+            ("http".to_string(), json!({"method": "GET"})),
+            ("tags".to_string(), json!(["a", "b"])),
+            ("missing".to_string(), json!(null)),
+        ]
+        .into_iter()
+        .collect();
+
+        let encoded = encode_attributes(&attributes);
+        assert_eq!(encoded["status"], "503");
+        assert_eq!(encoded["user"], "\"42\"");
+
+        assert_eq!(decode_attributes(encoded), attributes);
+    }
+
+    #[test]
+    fn test_decode_attributes_plain_text() {
+        let decoded = decode_attributes([("user".to_string(), "alice".to_string())].into());
+        assert_eq!(decoded["user"], json!("alice"));
+    }
+}
@@ -0,0 +1,1409 @@
+//! Queries over the downsampled aggregation tiers.
+//!
+//! `ClickHouse` maintains the tiers with materialized views over the raw tables
+//! (see `schema/04_aggregations.sql`). The stores read the tier tables and merge
+//! rows of the same series and bucket that have not been merged in the
+//! background yet. The in-memory stores compute the same rollups from the raw
+//! records, which remains the reference implementation.
+//!
+//! Metric series ([`SeriesQuery`]) read each part of their time range from the
+//! coarsest tier that serves it, and stitch the parts together at the tier
+//! boundaries.
+
+use super::{AggregationFunction, MetricQuery, QueryLimits};
+use crate::config::{AggregationConfig, AggregationInterval};
+use crate::models::{LogEntry, LogLevel, Metric, MetricValue, Span};
+use crate::query::{SqlParam, SqlParams};
+use chrono::{DateTime, TimeDelta, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+/// Query parameters for reading an aggregation tier.
+#[derive(Debug, Clone)]
+pub struct RollupQuery {
+    /// The tier to read.
+    pub interval: AggregationInterval,
+
+    /// Only buckets starting from this time (inclusive).
+    pub start_time: Option<DateTime<Utc>>,
+
+    /// Only buckets starting before this time (exclusive).
+    pub end_time: Option<DateTime<Utc>>,
+
+    /// Filter by service.
+    pub service: Option<String>,
+
+    /// Filter by metric name, or by operation for span statistics. Ignored
+    /// for log and trace counts.
+    pub name: Option<String>,
+
+    /// Filter by log level (log counts only).
+    pub level: Option<LogLevel>,
+
+    /// Filter by metric labels (all must match).
+    pub labels: HashMap<String, String>,
+
+    /// Maximum number of rows to return.
+    pub limit: Option<usize>,
+
+    /// Limits on the execution of the query.
+    pub limits: QueryLimits,
+}
+
+impl RollupQuery {
+    /// Creates a query for all series of the tier at `interval`.
+    #[must_use]
+    pub fn new(interval: AggregationInterval) -> Self {
+        Self {
+            interval,
+            start_time: None,
+            end_time: None,
+            service: None,
+            name: None,
+            level: None,
+            labels: HashMap::new(),
+            limit: None,
+            limits: QueryLimits::default(),
+        }
+    }
+
+    /// Sets the start time filter.
+    #[must_use]
+    pub fn with_start_time(mut self, start: DateTime<Utc>) -> Self {
+        self.start_time = Some(start);
+        self
+    }
+
+    /// Sets the end time filter.
+    #[must_use]
+    pub fn with_end_time(mut self, end: DateTime<Utc>) -> Self {
+        self.end_time = Some(end);
+        self
+    }
+
+    /// Sets the service filter.
+    #[must_use]
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Sets the metric name or operation filter.
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the log level filter.
+    #[must_use]
+    pub fn with_level(mut self, level: LogLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Adds a label filter.
+    #[must_use]
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the maximum number of rows to return.
+    #[must_use]
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the limits on the execution of the query.
+    #[must_use]
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the start of the bucket containing `time`.
+    pub(crate) fn bucket(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = i64::try_from(self.interval.as_secs()).unwrap_or(i64::MAX);
+        let start = time.timestamp() - time.timestamp().rem_euclid(secs);
+        DateTime::from_timestamp(start, 0).unwrap_or_default()
+    }
+
+    /// Returns whether the bucket starting at `bucket` is in the time range.
+    fn contains(&self, bucket: DateTime<Utc>) -> bool {
+        self.start_time.is_none_or(|start| bucket >= start)
+            && self.end_time.is_none_or(|end| bucket < end)
+    }
+
+    /// Returns whether a record at `time` falls in a bucket in the time range.
+    pub(crate) fn contains_time(&self, time: DateTime<Utc>) -> bool {
+        self.contains(self.bucket(time))
+    }
+
+    /// Returns whether records of `service` pass the service filter.
+    fn matches_service(&self, service: &str) -> bool {
+        self.service.as_deref().is_none_or(|s| s == service)
+    }
+
+    /// Returns whether records named `name` pass the name filter.
+    fn matches_name(&self, name: &str) -> bool {
+        self.name.as_deref().is_none_or(|n| n == name)
+    }
+
+    /// Builds the `WHERE` clause and `LIMIT` for a tier table, registering
+    /// values in `params`. `name_column` is the column filtered by
+    /// [`Self::name`], if the table has one.
+    pub(crate) fn to_sql(
+        &self,
+        name_column: Option<&str>,
+        params: &mut SqlParams,
+    ) -> (String, String) {
+        use std::fmt::Write as _;
+
+        let mut filter = String::from(" WHERE 1=1");
+        if let Some(start) = self.start_time {
+            let p = params.push(SqlParam::Int(start.timestamp()));
+            write!(&mut filter, " AND timestamp >= toDateTime({p})").unwrap();
+        }
+        if let Some(end) = self.end_time {
+            let p = params.push(SqlParam::Int(end.timestamp()));
+            write!(&mut filter, " AND timestamp < toDateTime({p})").unwrap();
+        }
+        if let Some(ref service) = self.service {
+            let p = params.push(SqlParam::String(service.clone()));
+            write!(&mut filter, " AND service = {p}").unwrap();
+        }
+        if let (Some(column), Some(name)) = (name_column, &self.name) {
+            let p = params.push(SqlParam::String(name.clone()));
+            write!(&mut filter, " AND {column} = {p}").unwrap();
+        }
+        if let Some(level) = self.level {
+            let p = params.push(SqlParam::String(level.to_string()));
+            write!(&mut filter, " AND level = {p}").unwrap();
+        }
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort();
+        for (key, value) in labels {
+            let k = params.push(SqlParam::String(key.clone()));
+            let v = params.push(SqlParam::String(value.clone()));
+            write!(&mut filter, " AND labels[{k}] = {v}").unwrap();
+        }
+
+        let limit = self
+            .limit
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        (filter, limit)
+    }
+
+    /// Truncates `rows` to the query's limit.
+    fn truncate<T>(&self, mut rows: Vec<T>) -> Vec<T> {
+        rows.truncate(self.limit.unwrap_or(usize::MAX));
+        rows
+    }
+}
+
+/// Where a [`TierSegment`] of a metric series is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    /// The raw metrics.
+    Raw,
+    /// The aggregation tier at an interval.
+    #[serde(untagged)]
+    Rollup(AggregationInterval),
+}
+
+/// A time range of a metric series and the tier it is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierSegment {
+    /// Start of the range (inclusive).
+    pub start: DateTime<Utc>,
+    /// End of the range (exclusive).
+    pub end: DateTime<Utc>,
+    /// The tier serving the range.
+    pub tier: Tier,
+}
+
+impl TierSegment {
+    /// Returns whether `time` is in the range.
+    fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Query parameters for a metric series aggregated into fixed steps.
+///
+/// The time range is split into [`TierSegment`]s, each read from the raw
+/// metrics or from an aggregation tier. Segments start and end on step
+/// boundaries, except where the time range does not, so every step is read
+/// from a single tier.
+#[derive(Debug, Clone)]
+pub struct SeriesQuery {
+    /// Aggregate computed for each step.
+    pub function: AggregationFunction,
+
+    /// Width of the steps, in seconds. Steps are aligned to the Unix epoch.
+    pub step_secs: u64,
+
+    /// Start of the series (inclusive).
+    pub start_time: DateTime<Utc>,
+
+    /// End of the series (exclusive).
+    pub end_time: DateTime<Utc>,
+
+    /// Filter by metric name.
+    pub name: Option<String>,
+
+    /// Filter by labels (all must match).
+    pub labels: HashMap<String, String>,
+
+    /// The segments of the time range and their tiers, ordered by time.
+    pub segments: Vec<TierSegment>,
+
+    /// Limits on the execution of the query.
+    pub limits: QueryLimits,
+}
+
+impl SeriesQuery {
+    /// Creates a query for the steps of `step_secs` seconds between `start`
+    /// and `end`, read from the raw metrics.
+    #[must_use]
+    pub fn new(
+        function: AggregationFunction,
+        step_secs: u64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            function,
+            step_secs: step_secs.max(1),
+            start_time: start,
+            end_time: end,
+            name: None,
+            labels: HashMap::new(),
+            segments: vec![TierSegment {
+                start,
+                end,
+                tier: Tier::Raw,
+            }],
+            limits: QueryLimits::default(),
+        }
+    }
+
+    /// Sets the metric name filter.
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Adds a label filter.
+    #[must_use]
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the limits on the execution of the query.
+    #[must_use]
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reads each part of the time range from the coarsest tier enabled in
+    /// `config` that still retains it at `now` and whose interval divides the
+    /// step. The rest is read from the raw metrics.
+    ///
+    /// `min` and `max` series are read from the raw metrics unless
+    /// `config.tiers_migrated` is set: tier tables created before
+    /// `schema/MIGRATE_METRIC_TIERS.md` sum the extremes when they merge.
+    /// `avg` is rebuilt from the sums and counts.
+    #[must_use]
+    pub fn with_tiers(mut self, config: &AggregationConfig, now: DateTime<Utc>) -> Self {
+        let from_tiers = config.tiers_migrated
+            || !matches!(
+                self.function,
+                AggregationFunction::Min | AggregationFunction::Max
+            );
+        let intervals = AggregationInterval::ALL
+            .into_iter()
+            .rev()
+            .filter(|interval| {
+                from_tiers
+                    && config.enabled
+                    && config.get_policy(*interval).enabled
+                    && self.step_secs.is_multiple_of(interval.as_secs())
+            });
+
+        let mut gaps = vec![(self.start_time, self.end_time)];
+        let mut segments = Vec::new();
+        for interval in intervals {
+            let retention = TimeDelta::days(i64::from(config.get_policy(interval).retention_days));
+            let retained_from = self.align_up(now - retention);
+            let mut remaining = Vec::new();
+            for (start, end) in gaps {
+                let tier_start = self.align_up(start.max(retained_from));
+                let tier_end = self.bucket(end);
+                if tier_start >= tier_end {
+                    remaining.push((start, end));
+                    continue;
+                }
+                segments.push(TierSegment {
+                    start: tier_start,
+                    end: tier_end,
+                    tier: Tier::Rollup(interval),
+                });
+                if start < tier_start {
+                    remaining.push((start, tier_start));
+                }
+                if tier_end < end {
+                    remaining.push((tier_end, end));
+                }
+            }
+            gaps = remaining;
+        }
+
+        segments.extend(gaps.into_iter().map(|(start, end)| TierSegment {
+            start,
+            end,
+            tier: Tier::Raw,
+        }));
+        segments.sort_by_key(|segment| segment.start);
+        self.segments = segments;
+        self
+    }
+
+    /// Returns the number of steps between the start and the end of the series.
+    #[must_use]
+    pub fn step_count(&self) -> u64 {
+        let span = (self.end_time - self.start_time).num_seconds().max(0);
+        u64::try_from(span).unwrap_or(0).div_ceil(self.step_secs)
+    }
+
+    /// Returns the start of the step containing `time`.
+    pub(crate) fn bucket(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = i64::try_from(self.step_secs).unwrap_or(i64::MAX);
+        let start = time.timestamp() - time.timestamp().rem_euclid(secs);
+        DateTime::from_timestamp(start, 0).unwrap_or_default()
+    }
+
+    /// Returns the first step boundary at or after `time`.
+    fn align_up(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let bucket = self.bucket(time);
+        if bucket == time {
+            bucket
+        } else {
+            bucket + TimeDelta::seconds(i64::try_from(self.step_secs).unwrap_or(i64::MAX))
+        }
+    }
+
+    /// Returns whether `metric` passes the name and label filters.
+    fn matches(&self, metric: &Metric) -> bool {
+        self.name.as_deref().is_none_or(|name| name == metric.name)
+            && self
+                .labels
+                .iter()
+                .all(|(k, v)| metric.labels.get(k) == Some(v))
+    }
+
+    /// Returns the query reading `segment` from the raw metrics.
+    pub(crate) fn raw_query(&self, segment: &TierSegment) -> MetricQuery {
+        let mut query = MetricQuery::new()
+            .with_start_time(segment.start)
+            .with_end_time(segment.end);
+        query.name.clone_from(&self.name);
+        query.labels.clone_from(&self.labels);
+        query
+    }
+
+    /// Returns the query reading `segment` from the tier at `interval`.
+    pub(crate) fn rollup_query(
+        &self,
+        interval: AggregationInterval,
+        segment: &TierSegment,
+    ) -> RollupQuery {
+        let mut query = RollupQuery::new(interval)
+            .with_start_time(segment.start)
+            .with_end_time(segment.end);
+        query.name.clone_from(&self.name);
+        query.labels.clone_from(&self.labels);
+        query
+    }
+}
+
+/// A step of a metric series.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    /// Start of the step.
+    pub timestamp: DateTime<Utc>,
+    /// The aggregate of the values in the step.
+    pub value: f64,
+    /// Number of values in the step.
+    pub count: u64,
+}
+
+/// Accumulates the values of a step, from raw values or merged rollups.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SeriesBucket {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl SeriesBucket {
+    pub(crate) const fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Merges `count` values with the given sum and extremes.
+    pub(crate) fn merge(&mut self, count: u64, sum: f64, min: f64, max: f64) {
+        self.count += count;
+        self.sum += sum;
+        self.min = self.min.min(min);
+        self.max = self.max.max(max);
+    }
+
+    /// Returns the point of the step starting at `timestamp`.
+    pub(crate) fn point(
+        self,
+        timestamp: DateTime<Utc>,
+        function: AggregationFunction,
+    ) -> SeriesPoint {
+        #[allow(clippy::cast_precision_loss)]
+        let value = match function {
+            AggregationFunction::Sum => self.sum,
+            AggregationFunction::Avg => self.sum / self.count as f64,
+            AggregationFunction::Min => self.min,
+            AggregationFunction::Max => self.max,
+            AggregationFunction::Count => self.count as f64,
+        };
+        SeriesPoint {
+            timestamp,
+            value,
+            count: self.count,
+        }
+    }
+}
+
+/// A bucket of a metric series (`metrics_1min` ... `metrics_1day`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricRollup {
+    /// Start of the bucket.
+    pub timestamp: DateTime<Utc>,
+    /// Metric name.
+    pub name: String,
+    /// Metric type.
+    pub metric_type: String,
+    /// The `service` label, or `unknown` without one.
+    pub service: String,
+    /// Labels of the series.
+    pub labels: BTreeMap<String, String>,
+    /// Number of values in the bucket.
+    pub count: u64,
+    /// Sum of the values.
+    pub sum: f64,
+    /// Smallest value.
+    pub min: f64,
+    /// Largest value.
+    pub max: f64,
+    /// Mean of the values.
+    pub avg: f64,
+}
+
+/// A bucket of log counts by level, service and message pattern
+/// (`logs_1hour_counts`, `logs_1day_counts`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogCount {
+    /// Start of the bucket.
+    pub timestamp: DateTime<Utc>,
+    /// Log level.
+    pub level: String,
+    /// Service name.
+    pub service: String,
+    /// Message with timestamps, ids, addresses and numbers replaced by
+    /// placeholders such as `<NUM>`.
+    pub normalized_message: String,
+    /// One of the messages counted.
+    pub sample_message: String,
+    /// Number of logs in the bucket.
+    pub count: u64,
+}
+
+/// A bucket of span latency statistics by operation (`spans_1hour_stats`,
+/// `spans_1day_stats`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpanStats {
+    /// Start of the bucket.
+    pub timestamp: DateTime<Utc>,
+    /// Service name.
+    pub service: String,
+    /// Operation (span name).
+    pub operation: String,
+    /// Span kind.
+    pub span_kind: String,
+    /// Span status.
+    pub status_code: String,
+    /// Number of spans in the bucket.
+    pub span_count: u64,
+    /// Mean duration in nanoseconds.
+    pub avg_duration_ns: f64,
+    /// Shortest duration in nanoseconds.
+    pub min_duration_ns: u64,
+    /// Longest duration in nanoseconds.
+    pub max_duration_ns: u64,
+    /// Median duration in nanoseconds.
+    pub p50_duration_ns: f64,
+    /// 95th percentile duration in nanoseconds.
+    pub p95_duration_ns: f64,
+    /// 99th percentile duration in nanoseconds.
+    pub p99_duration_ns: f64,
+}
+
+/// A bucket of trace counts by service (`traces_1hour_stats`,
+/// `traces_1day_stats`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStats {
+    /// Start of the bucket.
+    pub timestamp: DateTime<Utc>,
+    /// Service name.
+    pub service: String,
+    /// Number of distinct traces with spans of the service in the bucket.
+    pub unique_traces: u64,
+    /// Number of spans of the service in the bucket.
+    pub total_spans: u64,
+}
+
+/// Converts a `ClickHouse` `DateTime` to a UTC timestamp.
+fn from_datetime(secs: u32) -> DateTime<Utc> {
+    DateTime::from_timestamp(i64::from(secs), 0).unwrap_or_default()
+}
+
+/// Computes the metric rollups of `metrics`, ordered by series, then time.
+pub(crate) fn metric_rollups<'a>(
+    metrics: impl IntoIterator<Item = &'a Metric>,
+    query: &RollupQuery,
+) -> Vec<MetricRollup> {
+    type Key = (
+        String,
+        String,
+        String,
+        BTreeMap<String, String>,
+        DateTime<Utc>,
+    );
+    let mut buckets: BTreeMap<Key, MetricRollup> = BTreeMap::new();
+
+    for metric in metrics {
+        let bucket = query.bucket(metric.timestamp);
+        let service = metric
+            .labels
+            .get("service")
+            .map_or("unknown", String::as_str);
+        if !query.contains(bucket)
+            || !query.matches_service(service)
+            || !query.matches_name(&metric.name)
+            || query
+                .labels
+                .iter()
+                .any(|(k, v)| metric.labels.get(k) != Some(v))
+        {
+            continue;
+        }
+
+        let value = metric_value(metric);
+        let labels: BTreeMap<String, String> = metric.labels.clone().into_iter().collect();
+        let key = (
+            service.to_string(),
+            metric.name.clone(),
+            metric.metric_type.to_string(),
+            labels,
+            bucket,
+        );
+        let rollup = buckets.entry(key.clone()).or_insert_with(|| MetricRollup {
+            timestamp: bucket,
+            name: key.1,
+            metric_type: key.2,
+            service: key.0,
+            labels: key.3,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            avg: 0.0,
+        });
+        rollup.count += 1;
+        rollup.sum += value;
+        rollup.min = rollup.min.min(value);
+        rollup.max = rollup.max.max(value);
+    }
+
+    let rollups = buckets
+        .into_values()
+        .map(|mut rollup| {
+            #[allow(clippy::cast_precision_loss)]
+            let count = rollup.count as f64;
+            rollup.avg = rollup.sum / count;
+            rollup
+        })
+        .collect();
+    query.truncate(rollups)
+}
+
+/// Computes the points of the metric series of `metrics`, ordered by time.
+///
+/// Tier segments are computed from the raw metrics like their tier tables.
+pub(crate) fn metric_series<M: Borrow<Metric>>(
+    metrics: &[M],
+    query: &SeriesQuery,
+) -> Vec<SeriesPoint> {
+    let mut buckets: BTreeMap<DateTime<Utc>, SeriesBucket> = BTreeMap::new();
+
+    for segment in &query.segments {
+        match segment.tier {
+            Tier::Raw => {
+                for metric in metrics.iter().map(Borrow::borrow) {
+                    if segment.contains(metric.timestamp) && query.matches(metric) {
+                        let value = metric_value(metric);
+                        buckets
+                            .entry(query.bucket(metric.timestamp))
+                            .or_insert_with(SeriesBucket::new)
+                            .merge(1, value, value, value);
+                    }
+                }
+            }
+            Tier::Rollup(interval) => {
+                for rollup in metric_rollups(
+                    metrics.iter().map(Borrow::borrow),
+                    &query.rollup_query(interval, segment),
+                ) {
+                    buckets
+                        .entry(query.bucket(rollup.timestamp))
+                        .or_insert_with(SeriesBucket::new)
+                        .merge(rollup.count, rollup.sum, rollup.min, rollup.max);
+                }
+            }
+        }
+    }
+
+    buckets
+        .into_iter()
+        .map(|(timestamp, bucket)| bucket.point(timestamp, query.function))
+        .collect()
+}
+
+/// Returns the value a metric contributes to its rollups: the value itself,
+/// or the sum of a histogram, like the `value` column of the raw table.
+fn metric_value(metric: &Metric) -> f64 {
+    match &metric.value {
+        MetricValue::Simple(v) => *v,
+        MetricValue::Histogram(hist) => hist.sum,
+    }
+}
+
+/// Computes the log counts of `logs`, ordered by service, level and message
+/// pattern, then time.
+pub(crate) fn log_counts<'a>(
+    logs: impl IntoIterator<Item = &'a LogEntry>,
+    query: &RollupQuery,
+) -> Vec<LogCount> {
+    let mut buckets: BTreeMap<(String, String, String, DateTime<Utc>), LogCount> = BTreeMap::new();
+
+    for log in logs {
+        let bucket = query.bucket(log.timestamp);
+        if !query.contains(bucket)
+            || !query.matches_service(&log.service)
+            || query.level.is_some_and(|level| level != log.level)
+        {
+            continue;
+        }
+
+        let key = (
+            log.service.clone(),
+            log.level.to_string(),
+            normalize_message(&log.message),
+            bucket,
+        );
+        buckets
+            .entry(key.clone())
+            .or_insert_with(|| LogCount {
+                timestamp: bucket,
+                level: key.1,
+                service: key.0,
+                normalized_message: key.2,
+                sample_message: log.message.clone(),
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    query.truncate(buckets.into_values().collect())
+}
+
+/// Computes the span statistics of `spans`, ordered by service, operation,
+/// kind and status, then time.
+pub(crate) fn span_stats<'a>(
+    spans: impl IntoIterator<Item = &'a Span>,
+    query: &RollupQuery,
+) -> Vec<SpanStats> {
+    type Key = (String, String, String, String, DateTime<Utc>);
+    let mut buckets: BTreeMap<Key, Vec<u64>> = BTreeMap::new();
+
+    for span in spans {
+        let bucket = query.bucket(span.start_time);
+        if !query.contains(bucket)
+            || !query.matches_service(&span.service)
+            || !query.matches_name(&span.name)
+        {
+            continue;
+        }
+
+        let duration = (span.end_time - span.start_time)
+            .num_nanoseconds()
+            .and_then(|ns| u64::try_from(ns).ok())
+            .unwrap_or(0);
+        let key = (
+            span.service.clone(),
+            span.name.clone(),
+            span.kind.to_string(),
+            span.status.to_string(),
+            bucket,
+        );
+        buckets.entry(key).or_default().push(duration);
+    }
+
+    let stats = buckets
+        .into_iter()
+        .map(
+            |((service, operation, span_kind, status_code, timestamp), mut durations)| {
+                durations.sort_unstable();
+                #[allow(clippy::cast_precision_loss)]
+                let avg = durations.iter().sum::<u64>() as f64 / durations.len() as f64;
+                SpanStats {
+                    timestamp,
+                    service,
+                    operation,
+                    span_kind,
+                    status_code,
+                    span_count: durations.len() as u64,
+                    avg_duration_ns: avg,
+                    min_duration_ns: durations[0],
+                    max_duration_ns: durations[durations.len() - 1],
+                    p50_duration_ns: quantile(&durations, 0.5),
+                    p95_duration_ns: quantile(&durations, 0.95),
+                    p99_duration_ns: quantile(&durations, 0.99),
+                }
+            },
+        )
+        .collect();
+    query.truncate(stats)
+}
+
+/// Computes the trace counts of `spans`, ordered by service, then time.
+pub(crate) fn trace_stats<'a>(
+    spans: impl IntoIterator<Item = &'a Span>,
+    query: &RollupQuery,
+) -> Vec<TraceStats> {
+    let mut buckets: BTreeMap<(String, DateTime<Utc>), Vec<&str>> = BTreeMap::new();
+
+    for span in spans {
+        let bucket = query.bucket(span.start_time);
+        if !query.contains(bucket) || !query.matches_service(&span.service) {
+            continue;
+        }
+        buckets
+            .entry((span.service.clone(), bucket))
+            .or_default()
+            .push(&span.trace_id);
+    }
+
+    let stats = buckets
+        .into_iter()
+        .map(|((service, timestamp), mut traces)| {
+            let total_spans = traces.len() as u64;
+            traces.sort_unstable();
+            traces.dedup();
+            TraceStats {
+                timestamp,
+                service,
+                unique_traces: traces.len() as u64,
+                total_spans,
+            }
+        })
+        .collect();
+    query.truncate(stats)
+}
+
+/// Compiles the `SELECT` reading metric rollups from their tier table.
+pub(crate) fn metric_rollups_sql(query: &RollupQuery, params: &mut SqlParams) -> String {
+    // Maps with the same entries in another order are not equal, so series are
+    // grouped by their sorted labels
+    const SERIES_LABELS: &str = "arraySort(arrayZip(mapKeys(labels), mapValues(labels)))";
+
+    let (filter, limit) = query.to_sql(Some("name"), params);
+    format!(
+        "SELECT timestamp, name, metric_type, service, any(labels) AS series_labels, \
+         sum(count) AS total, sum(sum) AS value_sum, min(min) AS value_min, \
+         max(max) AS value_max \
+         FROM metrics_{}{filter} \
+         GROUP BY service, name, metric_type, {SERIES_LABELS}, timestamp \
+         ORDER BY service, name, metric_type, {SERIES_LABELS}, timestamp{limit}",
+        query.interval.table_suffix()
+    )
+}
+
+/// Compiles the `SELECT` reading log counts from their tier table.
+pub(crate) fn log_counts_sql(query: &RollupQuery, params: &mut SqlParams) -> String {
+    let (filter, limit) = query.to_sql(None, params);
+    format!(
+        "SELECT timestamp, level, service, normalized_message, \
+         any(sample_message) AS sample, sum(count) AS total \
+         FROM logs_{}_counts{filter} \
+         GROUP BY service, level, normalized_message, timestamp \
+         ORDER BY service, level, normalized_message, timestamp{limit}",
+        query.interval.table_suffix()
+    )
+}
+
+/// Compiles the `SELECT` reading span statistics from their tier table.
+///
+/// Rows of the same bucket are merged by weighting their means and
+/// percentiles by their span counts, so merged percentiles are approximate.
+pub(crate) fn span_stats_sql(query: &RollupQuery, params: &mut SqlParams) -> String {
+    let (filter, limit) = query.to_sql(Some("operation"), params);
+    format!(
+        "SELECT timestamp, service, operation, span_kind, status_code, \
+         sum(span_count) AS spans, \
+         sum(avg_duration_ns * span_count) / sum(span_count) AS avg_ns, \
+         min(min_duration_ns) AS min_ns, max(max_duration_ns) AS max_ns, \
+         sum(p50_duration_ns * span_count) / sum(span_count) AS p50_ns, \
+         sum(p95_duration_ns * span_count) / sum(span_count) AS p95_ns, \
+         sum(p99_duration_ns * span_count) / sum(span_count) AS p99_ns \
+         FROM spans_{}_stats{filter} \
+         GROUP BY service, operation, span_kind, status_code, timestamp \
+         ORDER BY service, operation, span_kind, status_code, timestamp{limit}",
+        query.interval.table_suffix()
+    )
+}
+
+/// Compiles the `SELECT` reading trace counts from their tier table.
+pub(crate) fn trace_stats_sql(query: &RollupQuery, params: &mut SqlParams) -> String {
+    let (filter, limit) = query.to_sql(None, params);
+    format!(
+        "SELECT timestamp, service, sum(unique_traces) AS traces, sum(total_spans) AS spans \
+         FROM traces_{}_stats{filter} \
+         GROUP BY service, timestamp \
+         ORDER BY service, timestamp{limit}",
+        query.interval.table_suffix()
+    )
+}
+
+/// Reads metric rollups from `ClickHouse`.
+pub(crate) async fn fetch_metric_rollups(
+    client: &clickhouse::Client,
+    query: &RollupQuery,
+) -> Result<Vec<MetricRollup>, clickhouse::error::Error> {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct Row {
+        timestamp: u32,
+        name: String,
+        metric_type: String,
+        service: String,
+        series_labels: HashMap<String, String>,
+        total: u64,
+        value_sum: f64,
+        value_min: f64,
+        value_max: f64,
+    }
+
+    let mut params = SqlParams::new();
+    let sql = metric_rollups_sql(query, &mut params);
+    let rows = query
+        .limits
+        .apply(params.bind(client.query(&sql)))
+        .fetch_all::<Row>()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            #[allow(clippy::cast_precision_loss)]
+            let avg = row.value_sum / row.total as f64;
+            MetricRollup {
+                timestamp: from_datetime(row.timestamp),
+                name: row.name,
+                metric_type: row.metric_type,
+                service: row.service,
+                labels: row.series_labels.into_iter().collect(),
+                count: row.total,
+                sum: row.value_sum,
+                min: row.value_min,
+                max: row.value_max,
+                avg,
+            }
+        })
+        .collect())
+}
+
+/// Reads log counts from `ClickHouse`.
+pub(crate) async fn fetch_log_counts(
+    client: &clickhouse::Client,
+    query: &RollupQuery,
+) -> Result<Vec<LogCount>, clickhouse::error::Error> {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct Row {
+        timestamp: u32,
+        level: String,
+        service: String,
+        normalized_message: String,
+        sample: String,
+        total: u64,
+    }
+
+    let mut params = SqlParams::new();
+    let sql = log_counts_sql(query, &mut params);
+    let rows = query
+        .limits
+        .apply(params.bind(client.query(&sql)))
+        .fetch_all::<Row>()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| LogCount {
+            timestamp: from_datetime(row.timestamp),
+            level: row.level,
+            service: row.service,
+            normalized_message: row.normalized_message,
+            sample_message: row.sample,
+            count: row.total,
+        })
+        .collect())
+}
+
+/// Reads span statistics from `ClickHouse`.
+pub(crate) async fn fetch_span_stats(
+    client: &clickhouse::Client,
+    query: &RollupQuery,
+) -> Result<Vec<SpanStats>, clickhouse::error::Error> {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct Row {
+        timestamp: u32,
+        service: String,
+        operation: String,
+        span_kind: String,
+        status_code: String,
+        spans: u64,
+        avg_ns: f64,
+        min_ns: u64,
+        max_ns: u64,
+        p50_ns: f64,
+        p95_ns: f64,
+        p99_ns: f64,
+    }
+
+    let mut params = SqlParams::new();
+    let sql = span_stats_sql(query, &mut params);
+    let rows = query
+        .limits
+        .apply(params.bind(client.query(&sql)))
+        .fetch_all::<Row>()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| SpanStats {
+            timestamp: from_datetime(row.timestamp),
+            service: row.service,
+            operation: row.operation,
+            span_kind: row.span_kind,
+            status_code: row.status_code,
+            span_count: row.spans,
+            avg_duration_ns: row.avg_ns,
+            min_duration_ns: row.min_ns,
+            max_duration_ns: row.max_ns,
+            p50_duration_ns: row.p50_ns,
+            p95_duration_ns: row.p95_ns,
+            p99_duration_ns: row.p99_ns,
+        })
+        .collect())
+}
+
+/// Reads trace counts from `ClickHouse`.
+pub(crate) async fn fetch_trace_stats(
+    client: &clickhouse::Client,
+    query: &RollupQuery,
+) -> Result<Vec<TraceStats>, clickhouse::error::Error> {
+    #[derive(clickhouse::Row, serde::Deserialize)]
+    struct Row {
+        timestamp: u32,
+        service: String,
+        traces: u64,
+        spans: u64,
+    }
+
+    let mut params = SqlParams::new();
+    let sql = trace_stats_sql(query, &mut params);
+    let rows = query
+        .limits
+        .apply(params.bind(client.query(&sql)))
+        .fetch_all::<Row>()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| TraceStats {
+            timestamp: from_datetime(row.timestamp),
+            service: row.service,
+            unique_traces: row.traces,
+            total_spans: row.spans,
+        })
+        .collect())
+}
+
+/// Returns the `level` quantile of sorted `values`, interpolating linearly
+/// between the closest ranks like `ClickHouse`'s `quantile`.
+#[allow(clippy::cast_precision_loss)]
+fn quantile(values: &[u64], level: f64) -> f64 {
+    let position = level * (values.len() - 1) as f64;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let lower = position.floor() as usize;
+    let upper = (lower + 1).min(values.len() - 1);
+    let fraction = position - position.floor();
+    (values[upper] as f64 - values[lower] as f64).mul_add(fraction, values[lower] as f64)
+}
+
+/// Replaces the variable parts of a log message with placeholders, like the
+/// `normalizeLogMessage` function in `schema/00_functions.sql`.
+pub(crate) fn normalize_message(message: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (
+                r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d{3,9})?(?:Z|[+-]\d{2}:\d{2})?",
+                "<TIMESTAMP>",
+            ),
+            (
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                "<UUID>",
+            ),
+            (r"\b\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}\b", "<IP>"),
+            (r"\b(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}\b", "<IPv6>"),
+            (r"\b0x[0-9a-fA-F]+\b", "<HEX>"),
+            (r"https?://[\w./?=&-]+", "<URL>"),
+            (r"\b[\w.-]+@[\w.-]+\.\w{2,}\b", "<EMAIL>"),
+            (r"(?:^|\s)(\./|/)[\w./-]+", " <PATH>"),
+            (r"\b-?\d+\.\d+", "<NUM>"),
+            (r"\b-?\d+", "<NUM>"),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+        .collect()
+    });
+
+    patterns
+        .iter()
+        .fold(message.to_string(), |message, (regex, replacement)| {
+            regex.replace_all(&message, *replacement).into_owned()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MetricType, SpanStatus};
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_message() {
+        assert_eq!(
+            normalize_message(
+                "Error at 2024-12-09T10:15:23.456Z: Connection to 192.168.1.1 failed"
+            ),
+            "Error at <TIMESTAMP>: Connection to <IP> failed"
+        );
+        assert_eq!(
+            normalize_message("Query took 5.01ms and returned 250MB"),
+            "Query took <NUM>ms and returned <NUM>MB"
+        );
+        assert_eq!(
+            normalize_message("Request 550e8400-e29b-41d4-a716-446655440000 failed"),
+            "Request <UUID> failed"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_metric_rollups() {
+        let metric = |minute, value, host: &str| {
+            let mut metric = Metric::new("cpu", MetricType::Gauge, MetricValue::Simple(value))
+                .with_label("service", "api")
+                .with_label("host", host);
+            metric.timestamp = at(10, minute);
+            metric
+        };
+        let metrics = vec![
+            metric(1, 10.0, "a"),
+            metric(3, 30.0, "a"),
+            metric(7, 50.0, "a"),
+            metric(2, 5.0, "b"),
+        ];
+
+        let query = RollupQuery::new(AggregationInterval::FiveMinutes).with_label("host", "a");
+        let rollups = metric_rollups(&metrics, &query);
+        assert_eq!(rollups.len(), 2);
+        assert_eq!(rollups[0].timestamp, at(10, 0));
+        assert_eq!(rollups[0].service, "api");
+        assert_eq!(rollups[0].count, 2);
+        assert_eq!(
+            (rollups[0].min, rollups[0].max, rollups[0].avg),
+            (10.0, 30.0, 20.0)
+        );
+        assert_eq!(rollups[1].timestamp, at(10, 5));
+
+        let query = RollupQuery::new(AggregationInterval::OneHour)
+            .with_start_time(at(10, 0))
+            .with_end_time(at(11, 0));
+        let rollups = metric_rollups(&metrics, &query);
+        assert_eq!(rollups.len(), 2);
+        assert_eq!(rollups[0].labels["host"], "a");
+        assert_eq!(rollups[0].sum, 90.0);
+
+        let query = RollupQuery::new(AggregationInterval::OneHour).with_start_time(at(10, 30));
+        assert!(metric_rollups(&metrics, &query).is_empty());
+    }
+
+    #[test]
+    fn test_series_tiers() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 30, 0).unwrap();
+        let start = now - TimeDelta::days(60);
+        let mut config = AggregationConfig {
+            enabled: false,
+            ..AggregationConfig::default()
+        };
+        let query = |step| SeriesQuery::new(AggregationFunction::Avg, step, start, now);
+        let segment = |start, end, tier| TierSegment { start, end, tier };
+
+        // Aggregation disabled: everything is read from the raw metrics
+        assert_eq!(
+            query(3600).with_tiers(&config, now).segments,
+            vec![segment(start, now, Tier::Raw)]
+        );
+
+        // The hourly tier serves whole hours, the raw metrics the partial hour
+        config.enabled = true;
+        let hour = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            query(3600).with_tiers(&config, now).segments,
+            vec![
+                segment(start, start + TimeDelta::minutes(30), Tier::Raw),
+                segment(
+                    start + TimeDelta::minutes(30),
+                    hour,
+                    Tier::Rollup(AggregationInterval::OneHour)
+                ),
+                segment(hour, now, Tier::Raw),
+            ]
+        );
+
+        // One-minute steps: the one-minute tier only retains 30 days
+        let month_ago = now - TimeDelta::days(30);
+        assert_eq!(
+            query(60).with_tiers(&config, now).segments,
+            vec![
+                segment(start, month_ago, Tier::Raw),
+                segment(month_ago, now, Tier::Rollup(AggregationInterval::OneMinute)),
+            ]
+        );
+
+        // Ten-minute steps are served by the five-minute tier, unless disabled
+        assert_eq!(
+            query(600).with_tiers(&config, now).segments[0].tier,
+            Tier::Rollup(AggregationInterval::FiveMinutes)
+        );
+        config.five_minutes.enabled = false;
+        assert_eq!(
+            query(600).with_tiers(&config, now).segments[1].tier,
+            Tier::Rollup(AggregationInterval::OneMinute)
+        );
+
+        // Extremes are read from the raw metrics, means from the tiers
+        for function in [AggregationFunction::Min, AggregationFunction::Max] {
+            assert_eq!(
+                SeriesQuery::new(function, 3600, start, now)
+                    .with_tiers(&config, now)
+                    .segments,
+                vec![segment(start, now, Tier::Raw)]
+            );
+        }
+        assert_eq!(
+            query(3600).with_tiers(&config, now).segments[1].tier,
+            Tier::Rollup(AggregationInterval::OneHour)
+        );
+
+        // Migrated tiers keep their extremes, so they serve them too
+        config.tiers_migrated = true;
+        for function in [AggregationFunction::Min, AggregationFunction::Max] {
+            assert_eq!(
+                SeriesQuery::new(function, 3600, start, now)
+                    .with_tiers(&config, now)
+                    .segments,
+                query(3600).with_tiers(&config, now).segments
+            );
+        }
+
+        // No tier divides 90-second steps
+        assert_eq!(
+            query(90).with_tiers(&config, now).segments,
+            vec![segment(start, now, Tier::Raw)]
+        );
+        assert_eq!(query(90).step_count(), 57_600);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_metric_series() {
+        let metric = |hour, minute, value| {
+            let mut metric = Metric::new("cpu", MetricType::Gauge, MetricValue::Simple(value))
+                .with_label("host", "a");
+            metric.timestamp = at(hour, minute);
+            metric
+        };
+        let metrics = vec![
+            metric(8, 50, 1.0),
+            metric(9, 10, 2.0),
+            metric(9, 40, 4.0),
+            metric(10, 20, 6.0),
+            metric(10, 40, 9.0),
+        ];
+
+        let config = AggregationConfig {
+            enabled: true,
+            ..AggregationConfig::default()
+        };
+        let query = SeriesQuery::new(AggregationFunction::Sum, 3600, at(8, 30), at(10, 30))
+            .with_name("cpu")
+            .with_label("host", "a");
+        let stitched = query.clone().with_tiers(&config, at(12, 0));
+        assert_eq!(stitched.segments.len(), 3);
+
+        let points = metric_series(&metrics, &stitched);
+        assert_eq!(points, metric_series(&metrics, &query));
+        let values: Vec<(DateTime<Utc>, f64, u64)> = points
+            .iter()
+            .map(|point| (point.timestamp, point.value, point.count))
+            .collect();
+        assert_eq!(
+            values,
+            vec![(at(8, 0), 1.0, 1), (at(9, 0), 6.0, 2), (at(10, 0), 6.0, 1)]
+        );
+
+        let query = SeriesQuery::new(AggregationFunction::Max, 86_400, at(0, 0), at(12, 0))
+            .with_tiers(&config, at(12, 0));
+        assert_eq!(query.segments[0].tier, Tier::Raw);
+        let points = metric_series(&metrics, &query);
+        assert_eq!((points[0].value, points[0].count), (9.0, 5));
+    }
+
+    #[test]
+    fn test_log_counts() {
+        let log = |minute, level, message: &str| {
+            let mut log = LogEntry::new(level, message, "api");
+            log.timestamp = at(9, minute);
+            log
+        };
+        let logs = vec![
+            log(1, LogLevel::Error, "Timeout after 30s"),
+            log(2, LogLevel::Error, "Timeout after 45s"),
+            log(3, LogLevel::Info, "Started"),
+        ];
+
+        let query = RollupQuery::new(AggregationInterval::OneHour).with_level(LogLevel::Error);
+        let counts = log_counts(&logs, &query);
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].normalized_message, "Timeout after <NUM>s");
+        assert_eq!(counts[0].sample_message, "Timeout after 30s");
+        assert_eq!(counts[0].count, 2);
+        assert_eq!(counts[0].timestamp, at(9, 0));
+
+        let query = RollupQuery::new(AggregationInterval::OneDay).with_limit(1);
+        assert_eq!(log_counts(&logs, &query)[0].level, "error");
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_span_and_trace_stats() {
+        let span = |trace: &str, id: &str, ms, status| {
+            Span::new(trace, id, "GET /", "api")
+                .with_start_time(at(8, 0))
+                .with_end_time(at(8, 0) + chrono::Duration::milliseconds(ms))
+                .with_status(status)
+        };
+        let spans = vec![
+            span("t1", "s1", 10, SpanStatus::Ok),
+            span("t1", "s2", 20, SpanStatus::Ok),
+            span("t2", "s3", 30, SpanStatus::Ok),
+            span("t2", "s4", 40, SpanStatus::Ok),
+            span("t3", "s5", 50, SpanStatus::Error),
+        ];
+
+        let query = RollupQuery::new(AggregationInterval::OneHour).with_name("GET /");
+        let stats = span_stats(&spans, &query);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].status_code, "error");
+        assert_eq!(stats[1].span_count, 4);
+        assert_eq!(stats[1].avg_duration_ns, 25_000_000.0);
+        assert_eq!(stats[1].min_duration_ns, 10_000_000);
+        assert_eq!(stats[1].max_duration_ns, 40_000_000);
+        assert_eq!(stats[1].p50_duration_ns, 25_000_000.0);
+
+        let stats = trace_stats(&spans, &RollupQuery::new(AggregationInterval::OneDay));
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].unique_traces, 3);
+        assert_eq!(stats[0].total_spans, 5);
+    }
+
+    #[test]
+    fn test_tier_tables() {
+        let query = RollupQuery::new(AggregationInterval::FiveMinutes);
+        let sql = metric_rollups_sql(&query, &mut SqlParams::new());
+        assert!(sql.contains("FROM metrics_5min WHERE"));
+
+        let query = RollupQuery::new(AggregationInterval::OneDay).with_name("GET /");
+        let sql = log_counts_sql(&query, &mut SqlParams::new());
+        assert!(sql.contains("FROM logs_1day_counts WHERE 1=1 GROUP BY"));
+        let sql = span_stats_sql(&query, &mut SqlParams::new());
+        assert!(sql.contains("FROM spans_1day_stats WHERE 1=1 AND operation = {p0:String}"));
+        let sql = trace_stats_sql(&query, &mut SqlParams::new());
+        assert!(sql.contains("FROM traces_1day_stats WHERE 1=1 GROUP BY service, timestamp"));
+    }
+
+    #[test]
+    fn test_rollup_query_to_sql() {
+        let query = RollupQuery::new(AggregationInterval::OneHour)
+            .with_start_time(at(0, 0))
+            .with_service("api")
+            .with_name("cpu")
+            .with_label("host", "a")
+            .with_limit(10);
+        let mut params = SqlParams::new();
+        let (filter, limit) = query.to_sql(Some("name"), &mut params);
+        assert_eq!(
+            filter,
+            " WHERE 1=1 AND timestamp >= toDateTime({p0:Int64}) AND service = {p1:String} \
+             AND name = {p2:String} AND labels[{p3:String}] = {p4:String}"
+        );
+        assert_eq!(limit, " LIMIT 10");
+        assert_eq!(params.as_slice()[0].1, SqlParam::Int(1_704_067_200));
+    }
+}
@@ -0,0 +1,511 @@
+//! Saved query storage.
+//!
+//! Provides the `SavedQueryStore` trait with in-memory, file-backed and
+//! `ClickHouse`-backed implementations.
+
+use crate::models::SavedQuery;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// Errors that can occur during saved query store operations.
+#[derive(Debug, Error)]
+pub enum SavedQueryStoreError {
+    /// Failed to acquire lock on the store.
+    #[error("Failed to acquire lock on saved query store")]
+    LockError,
+
+    /// No saved query has the given id.
+    #[error("Saved query not found: {0}")]
+    NotFound(String),
+
+    /// A saved query with the given id already exists.
+    #[error("Saved query already exists: {0}")]
+    AlreadyExists(String),
+
+    /// Generic storage error.
+    #[error("Storage error: {0}")]
+    StorageError(String),
+}
+
+/// Trait for saved query storage backends.
+pub trait SavedQueryStore: Send + Sync {
+    /// Returns all saved queries, ordered by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage operation fails.
+    fn list(&self) -> Result<Vec<SavedQuery>, SavedQueryStoreError>;
+
+    /// Returns the saved query with the given id, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage operation fails.
+    fn get(&self, id: &str) -> Result<Option<SavedQuery>, SavedQueryStoreError>;
+
+    /// Stores a new saved query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AlreadyExists` if a saved query with the same id exists, or an
+    /// error if the storage operation fails.
+    fn insert(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError>;
+
+    /// Replaces an existing saved query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no saved query has the same id, or an error if the
+    /// storage operation fails.
+    fn update(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError>;
+
+    /// Deletes the saved query with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no saved query has the id, or an error if the
+    /// storage operation fails.
+    fn delete(&self, id: &str) -> Result<(), SavedQueryStoreError>;
+}
+
+/// Sorts saved queries by name, then id.
+fn sorted(mut queries: Vec<SavedQuery>) -> Vec<SavedQuery> {
+    queries.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    queries
+}
+
+/// Applies an insert to a map of saved queries.
+fn insert_into(
+    queries: &mut BTreeMap<String, SavedQuery>,
+    query: SavedQuery,
+) -> Result<(), SavedQueryStoreError> {
+    if queries.contains_key(&query.id) {
+        return Err(SavedQueryStoreError::AlreadyExists(query.id));
+    }
+    queries.insert(query.id.clone(), query);
+    Ok(())
+}
+
+/// Applies an update to a map of saved queries.
+fn update_in(
+    queries: &mut BTreeMap<String, SavedQuery>,
+    query: SavedQuery,
+) -> Result<(), SavedQueryStoreError> {
+    match queries.get_mut(&query.id) {
+        Some(existing) => {
+            *existing = query;
+            Ok(())
+        }
+        None => Err(SavedQueryStoreError::NotFound(query.id)),
+    }
+}
+
+/// In-memory saved query store.
+///
+/// Saved queries are lost when the process exits.
+#[derive(Debug, Default)]
+pub struct InMemorySavedQueryStore {
+    queries: RwLock<BTreeMap<String, SavedQuery>>,
+}
+
+impl InMemorySavedQueryStore {
+    /// Creates a new empty in-memory saved query store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SavedQueryStore for InMemorySavedQueryStore {
+    fn list(&self) -> Result<Vec<SavedQuery>, SavedQueryStoreError> {
+        let queries = self
+            .queries
+            .read()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        Ok(sorted(queries.values().cloned().collect()))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<SavedQuery>, SavedQueryStoreError> {
+        let queries = self
+            .queries
+            .read()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        Ok(queries.get(id).cloned())
+    }
+
+    fn insert(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        let mut queries = self
+            .queries
+            .write()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        insert_into(&mut queries, query)
+    }
+
+    fn update(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        let mut queries = self
+            .queries
+            .write()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        update_in(&mut queries, query)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SavedQueryStoreError> {
+        let mut queries = self
+            .queries
+            .write()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        queries
+            .remove(id)
+            .map(|_| ())
+            .ok_or_else(|| SavedQueryStoreError::NotFound(id.to_string()))
+    }
+}
+
+/// Saved query store backed by a local JSON file.
+///
+/// The whole file is rewritten on every change, by writing a temporary file
+/// next to it and renaming it over the original.
+#[derive(Debug)]
+pub struct FileSavedQueryStore {
+    path: PathBuf,
+    queries: RwLock<BTreeMap<String, SavedQuery>>,
+}
+
+impl FileSavedQueryStore {
+    /// Opens the store at `path`, reading the saved queries in it.
+    ///
+    /// A missing file is treated as an empty store and created on the first change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a JSON array of
+    /// saved queries.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, SavedQueryStoreError> {
+        let path = path.into();
+        let queries = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<Vec<SavedQuery>>(&bytes)
+                .map_err(|e| {
+                    SavedQueryStoreError::StorageError(format!("{}: {e}", path.display()))
+                })?
+                .into_iter()
+                .map(|query| (query.id.clone(), query))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(SavedQueryStoreError::StorageError(format!(
+                    "{}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        Ok(Self {
+            path,
+            queries: RwLock::new(queries),
+        })
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Applies `change` to the saved queries and writes them to the file.
+    ///
+    /// The in-memory state is only changed if the file was written.
+    fn modify<F>(&self, change: F) -> Result<(), SavedQueryStoreError>
+    where
+        F: FnOnce(&mut BTreeMap<String, SavedQuery>) -> Result<(), SavedQueryStoreError>,
+    {
+        let mut queries = self
+            .queries
+            .write()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        let mut changed = queries.clone();
+        change(&mut changed)?;
+        self.write(&changed)?;
+        *queries = changed;
+        Ok(())
+    }
+
+    fn write(&self, queries: &BTreeMap<String, SavedQuery>) -> Result<(), SavedQueryStoreError> {
+        let error = |e: std::io::Error| {
+            SavedQueryStoreError::StorageError(format!("{}: {e}", self.path.display()))
+        };
+        let json = serde_json::to_vec_pretty(&queries.values().collect::<Vec<_>>())
+            .map_err(|e| SavedQueryStoreError::StorageError(e.to_string()))?;
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, json).map_err(error)?;
+        std::fs::rename(&tmp, &self.path).map_err(error)
+    }
+}
+
+impl SavedQueryStore for FileSavedQueryStore {
+    fn list(&self) -> Result<Vec<SavedQuery>, SavedQueryStoreError> {
+        let queries = self
+            .queries
+            .read()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        Ok(sorted(queries.values().cloned().collect()))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<SavedQuery>, SavedQueryStoreError> {
+        let queries = self
+            .queries
+            .read()
+            .map_err(|_| SavedQueryStoreError::LockError)?;
+        Ok(queries.get(id).cloned())
+    }
+
+    fn insert(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        self.modify(|queries| insert_into(queries, query))
+    }
+
+    fn update(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        self.modify(|queries| update_in(queries, query))
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SavedQueryStoreError> {
+        self.modify(|queries| {
+            queries
+                .remove(id)
+                .map(|_| ())
+                .ok_or_else(|| SavedQueryStoreError::NotFound(id.to_string()))
+        })
+    }
+}
+
+/// Columns of the `saved_queries` table, in [`SavedQueryRow`] order.
+const SAVED_QUERY_COLUMNS: &str = "id, name, description, owner, tags, query, parameters, \
+    default_time_range, created_at, updated_at, deleted";
+
+/// A row of the `saved_queries` table.
+#[derive(clickhouse::Row, serde::Serialize, serde::Deserialize)]
+struct SavedQueryRow {
+    id: String,
+    name: String,
+    description: String,
+    owner: Option<String>,
+    tags: Vec<String>,
+    query: String,
+    /// Default parameter values as a JSON object.
+    parameters: String,
+    default_time_range: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+    deleted: u8,
+}
+
+impl SavedQueryRow {
+    fn new(query: SavedQuery, deleted: bool) -> Self {
+        Self {
+            parameters: serde_json::Value::from(serde_json::Map::from_iter(query.parameters))
+                .to_string(),
+            id: query.id,
+            name: query.name,
+            description: query.description,
+            owner: query.owner,
+            tags: query.tags,
+            query: query.query,
+            default_time_range: query.default_time_range,
+            created_at: query.created_at.timestamp_nanos_opt().unwrap_or(0),
+            updated_at: query.updated_at.timestamp_nanos_opt().unwrap_or(0),
+            deleted: u8::from(deleted),
+        }
+    }
+}
+
+impl From<SavedQueryRow> for SavedQuery {
+    fn from(row: SavedQueryRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            owner: row.owner,
+            tags: row.tags,
+            query: row.query,
+            parameters: serde_json::from_str(&row.parameters).unwrap_or_default(),
+            default_time_range: row.default_time_range,
+            created_at: DateTime::from_timestamp_nanos(row.created_at),
+            updated_at: DateTime::from_timestamp_nanos(row.updated_at),
+        }
+    }
+}
+
+/// `ClickHouse`-backed saved query store.
+///
+/// Saved queries live in a `ReplacingMergeTree` table keyed by id. Updates and
+/// deletes insert a newer version of the row; deleted rows are marked with
+/// `deleted = 1` and filtered out when reading with `FINAL`.
+#[derive(Clone)]
+pub struct ClickHouseSavedQueryStore {
+    client: Arc<clickhouse::Client>,
+}
+
+impl ClickHouseSavedQueryStore {
+    /// Creates a new `ClickHouse` saved query store with the given client.
+    #[must_use]
+    pub fn new(client: Arc<clickhouse::Client>) -> Self {
+        Self { client }
+    }
+
+    /// Helper to execute async operations synchronously.
+    fn block_on<F, T>(future: F) -> Result<T, SavedQueryStoreError>
+    where
+        F: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current()
+                .block_on(future)
+                .map_err(|e| SavedQueryStoreError::StorageError(e.to_string()))
+        })
+    }
+
+    fn write_row(&self, row: SavedQueryRow) -> Result<(), SavedQueryStoreError> {
+        let client = Arc::clone(&self.client);
+        Self::block_on(async move {
+            let mut inserter = client.insert::<SavedQueryRow>("saved_queries").await?;
+            inserter.write(&row).await?;
+            inserter.end().await
+        })
+    }
+}
+
+impl SavedQueryStore for ClickHouseSavedQueryStore {
+    fn list(&self) -> Result<Vec<SavedQuery>, SavedQueryStoreError> {
+        let client = Arc::clone(&self.client);
+        let rows = Self::block_on(async move {
+            let sql =
+                format!("SELECT {SAVED_QUERY_COLUMNS} FROM saved_queries FINAL WHERE deleted = 0");
+            client.query(&sql).fetch_all::<SavedQueryRow>().await
+        })?;
+        Ok(sorted(rows.into_iter().map(SavedQuery::from).collect()))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<SavedQuery>, SavedQueryStoreError> {
+        let client = Arc::clone(&self.client);
+        let id = id.to_string();
+        let row = Self::block_on(async move {
+            let sql = format!(
+                "SELECT {SAVED_QUERY_COLUMNS} FROM saved_queries FINAL \
+                 WHERE id = {{id:String}} AND deleted = 0"
+            );
+            client
+                .query(&sql)
+                .param("id", &id)
+                .fetch_optional::<SavedQueryRow>()
+                .await
+        })?;
+        Ok(row.map(SavedQuery::from))
+    }
+
+    fn insert(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        if self.get(&query.id)?.is_some() {
+            return Err(SavedQueryStoreError::AlreadyExists(query.id));
+        }
+        self.write_row(SavedQueryRow::new(query, false))
+    }
+
+    fn update(&self, query: SavedQuery) -> Result<(), SavedQueryStoreError> {
+        if self.get(&query.id)?.is_none() {
+            return Err(SavedQueryStoreError::NotFound(query.id));
+        }
+        self.write_row(SavedQueryRow::new(query, false))
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SavedQueryStoreError> {
+        let mut query = self
+            .get(id)?
+            .ok_or_else(|| SavedQueryStoreError::NotFound(id.to_string()))?;
+        // The deletion must be a newer version than the row it replaces
+        query.updated_at = Utc::now().max(query.updated_at + chrono::Duration::nanoseconds(1));
+        self.write_row(SavedQueryRow::new(query, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_store(store: &dyn SavedQueryStore) {
+        let errors = SavedQuery::new("Errors", "SELECT * FROM logs WHERE level = 'error'");
+        let slow = SavedQuery::new(
+            "Slow spans",
+            "SELECT * FROM traces WHERE duration_ms > 1000",
+        );
+
+        store.insert(slow.clone()).unwrap();
+        store.insert(errors.clone()).unwrap();
+        assert!(matches!(
+            store.insert(errors.clone()),
+            Err(SavedQueryStoreError::AlreadyExists(id)) if id == "errors"
+        ));
+
+        let names: Vec<_> = store.list().unwrap().into_iter().map(|q| q.name).collect();
+        assert_eq!(names, vec!["Errors", "Slow spans"]);
+
+        let updated = errors.with_description("All errors");
+        store.update(updated.clone()).unwrap();
+        assert_eq!(store.get("errors").unwrap(), Some(updated));
+
+        store.delete("errors").unwrap();
+        assert_eq!(store.get("errors").unwrap(), None);
+        assert!(matches!(
+            store.delete("errors"),
+            Err(SavedQueryStoreError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.update(SavedQuery::new("Errors", "SELECT * FROM logs")),
+            Err(SavedQueryStoreError::NotFound(_))
+        ));
+        assert_eq!(store.list().unwrap(), vec![slow]);
+    }
+
+    #[test]
+    fn test_in_memory_store() {
+        check_store(&InMemorySavedQueryStore::new());
+    }
+
+    #[test]
+    fn test_file_store_persists() {
+        let dir =
+            std::env::temp_dir().join(format!("heimsight-saved-queries-{}", std::process::id()));
+        let path = dir.join("saved_queries.json");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = FileSavedQueryStore::open(&path).unwrap();
+        check_store(&store);
+
+        let reopened = FileSavedQueryStore::open(&path).unwrap();
+        assert_eq!(reopened.list().unwrap(), store.list().unwrap());
+        assert_eq!(reopened.path(), path);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileSavedQueryStore::open(&path),
+            Err(SavedQueryStoreError::StorageError(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_row_round_trip() {
+        let query = SavedQuery::new("q", "SELECT * FROM logs WHERE service = $service")
+            .with_owner("ops")
+            .with_tag("oncall")
+            .with_parameter("service", serde_json::json!("api"))
+            .with_default_time_range("1h");
+        let row = SavedQueryRow::new(query.clone(), false);
+        assert_eq!(row.parameters, r#"{"service":"api"}"#);
+        assert_eq!(SavedQuery::from(row), query);
+    }
+}
@@ -0,0 +1,357 @@
+//! Server settings storage.
+//!
+//! Provides the `SettingsStore` trait with in-memory, file-backed and
+//! `ClickHouse`-backed implementations. It keeps the retention and aggregation
+//! configuration across server restarts.
+
+use crate::config::{AggregationConfig, RetentionConfig};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// Errors that can occur during settings store operations.
+#[derive(Debug, Error)]
+pub enum SettingsStoreError {
+    /// Failed to acquire lock on the store.
+    #[error("Failed to acquire lock on settings store")]
+    LockError,
+
+    /// Generic storage error.
+    #[error("Storage error: {0}")]
+    StorageError(String),
+}
+
+/// The saved server settings.
+///
+/// A setting that was never saved is `None`, and the server uses its default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// The retention configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionConfig>,
+    /// The aggregation configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregation: Option<AggregationConfig>,
+}
+
+/// Trait for settings storage backends.
+pub trait SettingsStore: Send + Sync {
+    /// Returns the saved settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage operation fails.
+    fn load(&self) -> Result<Settings, SettingsStoreError>;
+
+    /// Saves the retention configuration, replacing the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage operation fails.
+    fn save_retention(&self, config: &RetentionConfig) -> Result<(), SettingsStoreError>;
+
+    /// Saves the aggregation configuration, replacing the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage operation fails.
+    fn save_aggregation(&self, config: &AggregationConfig) -> Result<(), SettingsStoreError>;
+}
+
+/// In-memory settings store.
+///
+/// Settings are lost when the process exits.
+#[derive(Debug, Default)]
+pub struct InMemorySettingsStore {
+    settings: RwLock<Settings>,
+}
+
+impl InMemorySettingsStore {
+    /// Creates a new empty in-memory settings store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SettingsStore for InMemorySettingsStore {
+    fn load(&self) -> Result<Settings, SettingsStoreError> {
+        let settings = self
+            .settings
+            .read()
+            .map_err(|_| SettingsStoreError::LockError)?;
+        Ok(settings.clone())
+    }
+
+    fn save_retention(&self, config: &RetentionConfig) -> Result<(), SettingsStoreError> {
+        let mut settings = self
+            .settings
+            .write()
+            .map_err(|_| SettingsStoreError::LockError)?;
+        settings.retention = Some(config.clone());
+        Ok(())
+    }
+
+    fn save_aggregation(&self, config: &AggregationConfig) -> Result<(), SettingsStoreError> {
+        let mut settings = self
+            .settings
+            .write()
+            .map_err(|_| SettingsStoreError::LockError)?;
+        settings.aggregation = Some(config.clone());
+        Ok(())
+    }
+}
+
+/// Settings store backed by a local JSON file.
+///
+/// The whole file is rewritten on every change, by writing a temporary file
+/// next to it and renaming it over the original.
+#[derive(Debug)]
+pub struct FileSettingsStore {
+    path: PathBuf,
+    settings: RwLock<Settings>,
+}
+
+impl FileSettingsStore {
+    /// Opens the store at `path`, reading the settings in it.
+    ///
+    /// A missing file is treated as an empty store and created on the first change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a JSON settings object.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, SettingsStoreError> {
+        let path = path.into();
+        let error = |e: &dyn std::fmt::Display| {
+            SettingsStoreError::StorageError(format!("{}: {e}", path.display()))
+        };
+        let settings = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| error(&e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => return Err(error(&e)),
+        };
+        Ok(Self {
+            path,
+            settings: RwLock::new(settings),
+        })
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Applies `change` to the settings and writes them to the file.
+    ///
+    /// The in-memory state is only changed if the file was written.
+    fn modify<F>(&self, change: F) -> Result<(), SettingsStoreError>
+    where
+        F: FnOnce(&mut Settings),
+    {
+        let mut settings = self
+            .settings
+            .write()
+            .map_err(|_| SettingsStoreError::LockError)?;
+        let mut changed = settings.clone();
+        change(&mut changed);
+        self.write(&changed)?;
+        *settings = changed;
+        Ok(())
+    }
+
+    fn write(&self, settings: &Settings) -> Result<(), SettingsStoreError> {
+        let error = |e: std::io::Error| {
+            SettingsStoreError::StorageError(format!("{}: {e}", self.path.display()))
+        };
+        let json = serde_json::to_vec_pretty(settings)
+            .map_err(|e| SettingsStoreError::StorageError(e.to_string()))?;
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, json).map_err(error)?;
+        std::fs::rename(&tmp, &self.path).map_err(error)
+    }
+}
+
+impl SettingsStore for FileSettingsStore {
+    fn load(&self) -> Result<Settings, SettingsStoreError> {
+        let settings = self
+            .settings
+            .read()
+            .map_err(|_| SettingsStoreError::LockError)?;
+        Ok(settings.clone())
+    }
+
+    fn save_retention(&self, config: &RetentionConfig) -> Result<(), SettingsStoreError> {
+        self.modify(|settings| settings.retention = Some(config.clone()))
+    }
+
+    fn save_aggregation(&self, config: &AggregationConfig) -> Result<(), SettingsStoreError> {
+        self.modify(|settings| settings.aggregation = Some(config.clone()))
+    }
+}
+
+/// Name of the retention configuration row in the `settings` table.
+const RETENTION_SETTING: &str = "retention";
+
+/// Name of the aggregation configuration row in the `settings` table.
+const AGGREGATION_SETTING: &str = "aggregation";
+
+/// A row of the `settings` table.
+#[derive(clickhouse::Row, serde::Serialize, serde::Deserialize)]
+struct SettingRow {
+    name: String,
+    /// The setting as a JSON document.
+    value: String,
+    updated_at: i64,
+}
+
+/// `ClickHouse`-backed settings store.
+///
+/// Settings live in a `ReplacingMergeTree` table with one row per setting.
+/// Saving inserts a newer version of the row, and reading uses `FINAL`.
+#[derive(Clone)]
+pub struct ClickHouseSettingsStore {
+    client: Arc<clickhouse::Client>,
+}
+
+impl ClickHouseSettingsStore {
+    /// Creates a new `ClickHouse` settings store with the given client.
+    #[must_use]
+    pub fn new(client: Arc<clickhouse::Client>) -> Self {
+        Self { client }
+    }
+
+    /// Helper to execute async operations synchronously.
+    fn block_on<F, T>(future: F) -> Result<T, SettingsStoreError>
+    where
+        F: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current()
+                .block_on(future)
+                .map_err(|e| SettingsStoreError::StorageError(e.to_string()))
+        })
+    }
+
+    fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<(), SettingsStoreError> {
+        let row = SettingRow {
+            name: name.to_string(),
+            value: serde_json::to_string(value)
+                .map_err(|e| SettingsStoreError::StorageError(e.to_string()))?,
+            updated_at: Utc::now().timestamp_nanos_opt().unwrap_or(0),
+        };
+        let client = Arc::clone(&self.client);
+        Self::block_on(async move {
+            let mut inserter = client.insert::<SettingRow>("settings").await?;
+            inserter.write(&row).await?;
+            inserter.end().await
+        })
+    }
+}
+
+/// Parses the JSON value of the setting `name`.
+fn parse_setting<T: serde::de::DeserializeOwned>(
+    name: &str,
+    value: &str,
+) -> Result<T, SettingsStoreError> {
+    serde_json::from_str(value)
+        .map_err(|e| SettingsStoreError::StorageError(format!("Invalid {name} setting: {e}")))
+}
+
+impl SettingsStore for ClickHouseSettingsStore {
+    fn load(&self) -> Result<Settings, SettingsStoreError> {
+        let client = Arc::clone(&self.client);
+        let rows = Self::block_on(async move {
+            client
+                .query("SELECT name, value, updated_at FROM settings FINAL")
+                .fetch_all::<SettingRow>()
+                .await
+        })?;
+
+        let mut settings = Settings::default();
+        for row in rows {
+            match row.name.as_str() {
+                RETENTION_SETTING => {
+                    settings.retention = Some(parse_setting(&row.name, &row.value)?);
+                }
+                AGGREGATION_SETTING => {
+                    settings.aggregation = Some(parse_setting(&row.name, &row.value)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    fn save_retention(&self, config: &RetentionConfig) -> Result<(), SettingsStoreError> {
+        self.save(RETENTION_SETTING, config)
+    }
+
+    fn save_aggregation(&self, config: &AggregationConfig) -> Result<(), SettingsStoreError> {
+        self.save(AGGREGATION_SETTING, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AggregationInterval, DataType};
+
+    fn check_store(store: &dyn SettingsStore) {
+        assert_eq!(store.load().unwrap(), Settings::default());
+
+        let mut retention = RetentionConfig::new(7, 180, 14);
+        store.save_retention(&retention).unwrap();
+        let mut aggregation = AggregationConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        aggregation.update_policy(AggregationInterval::OneHour, 100, false);
+        store.save_aggregation(&aggregation).unwrap();
+
+        retention.update_policy(DataType::Logs, 10);
+        store.save_retention(&retention).unwrap();
+
+        assert_eq!(
+            store.load().unwrap(),
+            Settings {
+                retention: Some(retention),
+                aggregation: Some(aggregation),
+            }
+        );
+    }
+
+    #[test]
+    fn test_in_memory_store() {
+        check_store(&InMemorySettingsStore::new());
+    }
+
+    #[test]
+    fn test_file_store_persists() {
+        let dir = std::env::temp_dir().join(format!("heimsight-settings-{}", std::process::id()));
+        let path = dir.join("settings.json");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = FileSettingsStore::open(&path).unwrap();
+        check_store(&store);
+
+        let reopened = FileSettingsStore::open(&path).unwrap();
+        assert_eq!(reopened.load().unwrap(), store.load().unwrap());
+        assert_eq!(reopened.path(), path);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileSettingsStore::open(&path),
+            Err(SettingsStoreError::StorageError(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
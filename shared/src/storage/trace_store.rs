@@ -3,11 +3,25 @@
 //! Provides the `TraceStore` trait for abstracting trace storage operations
 //! and an `InMemoryTraceStore` implementation for development and testing.
 
+use super::cursor::{next_cursor, page_offset, sort_newest_first, SPAN_TIEBREAKER};
+use super::rollup::{
+    fetch_span_stats, fetch_trace_stats, span_stats, trace_stats, RollupQuery, SpanStats,
+    TraceStats,
+};
+use super::{decode_attributes, encode_attributes, Cursor, LimitError, QueryLimits, Scan};
+use crate::config::RetentionPolicy;
 use crate::models::{Span, SpanStatus, Trace};
+use crate::query::{
+    collect_fields, compile_fields_query, compile_filter, compile_group_query, compile_order,
+    explain_statements, fetch_fields, fetch_groups, group_records, matches_filter, sort_records,
+    FieldInfo, GroupQuery, GroupQueryResult, OrderBy, Source, SqlParam, SqlParams, StorePlan,
+    WhereClause,
+};
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use thiserror::Error;
+use tokio::time::Instant;
 
 /// Errors that can occur during trace store operations.
 #[derive(Debug, Error)]
@@ -23,6 +37,9 @@ pub enum TraceStoreError {
     /// Generic storage error.
     #[error("Storage error: {0}")]
     StorageError(String),
+    /// The query was stopped by its limits.
+    #[error("{0}")]
+    Limit(#[from] LimitError),
 }
 
 /// Query parameters for retrieving traces.
@@ -51,6 +68,20 @@ pub struct TraceQuery {
 
     /// Number of traces to skip (for pagination).
     pub offset: Option<usize>,
+
+    /// Additional span filter expressed in the query language.
+    pub filter: Option<WhereClause>,
+
+    /// Span sort keys for [`TraceStore::query_spans`], most significant first.
+    /// Ties, and queries without keys, are ordered newest first.
+    pub order_by: Vec<OrderBy>,
+
+    /// Return only traces (or spans) after this position in the default order
+    /// (keyset pagination).
+    pub cursor: Option<Cursor>,
+
+    /// Limits on the execution of the query.
+    pub limits: QueryLimits,
 }
 
 impl TraceQuery {
@@ -115,6 +146,34 @@ impl TraceQuery {
         self.offset = Some(offset);
         self
     }
+
+    /// Sets an additional span filter expressed in the query language.
+    #[must_use]
+    pub fn with_filter(mut self, filter: WhereClause) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets the span sort keys.
+    #[must_use]
+    pub fn with_order_by(mut self, order_by: Vec<OrderBy>) -> Self {
+        self.order_by = order_by;
+        self
+    }
+
+    /// Continues after the given cursor.
+    #[must_use]
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Sets the limits on the execution of the query.
+    #[must_use]
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
 /// Result of a trace query operation.
@@ -123,8 +182,24 @@ pub struct TraceQueryResult {
     /// The traces matching the query.
     pub traces: Vec<Trace>,
 
-    /// Total count of matching traces (before limit/offset applied).
+    /// Total count of matching traces (before cursor/limit/offset applied).
     pub total_count: usize,
+
+    /// Cursor for the next page, if the page is full.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Result of a span query operation.
+#[derive(Debug, Clone)]
+pub struct SpanQueryResult {
+    /// The spans matching the query.
+    pub spans: Vec<Span>,
+
+    /// Total count of matching spans (before cursor/limit/offset applied).
+    pub total_count: usize,
+
+    /// Cursor for the next page, if the page is full and in the default order.
+    pub next_cursor: Option<Cursor>,
 }
 
 /// Trait for trace storage implementations.
@@ -162,6 +237,75 @@ pub trait TraceStore: Send + Sync {
     /// Returns an error if the query operation fails.
     fn query(&self, query: TraceQuery) -> Result<TraceQueryResult, TraceStoreError>;
 
+    /// Queries individual spans based on the provided parameters.
+    ///
+    /// Unlike [`TraceStore::query`], filters and pagination apply to spans rather
+    /// than whole traces.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn query_spans(&self, query: TraceQuery) -> Result<SpanQueryResult, TraceStoreError>;
+
+    /// Groups and aggregates the spans matching the query.
+    ///
+    /// Ordering and pagination of `query` are ignored; `group` carries its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn query_groups(
+        &self,
+        query: TraceQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, TraceStoreError>;
+
+    /// Lists the fields of the spans matching the query, with their types and
+    /// cardinality.
+    ///
+    /// Ordering and pagination of `query` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn fields(&self, query: TraceQuery) -> Result<Vec<FieldInfo>, TraceStoreError>;
+
+    /// Reads the span statistics of the tier at `query.interval`, ordered by
+    /// service, operation, kind and status, then time. Traces only have hourly
+    /// and daily tiers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn span_stats(&self, query: RollupQuery) -> Result<Vec<SpanStats>, TraceStoreError>;
+
+    /// Reads the trace counts of the tier at `query.interval`, ordered by
+    /// service, then time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query operation fails.
+    fn trace_stats(&self, query: RollupQuery) -> Result<Vec<TraceStats>, TraceStoreError>;
+
+    /// Describes how the store would execute `query` as a span query, or the
+    /// aggregation `group` over the spans matching `query` if one is given,
+    /// without running it.
+    ///
+    /// The default implementation describes a store that evaluates queries in
+    /// memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails.
+    fn explain(
+        &self,
+        query: TraceQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, TraceStoreError> {
+        let _ = (query, group);
+        Ok(StorePlan::in_memory())
+    }
+
     /// Returns the total number of spans in the store.
     ///
     /// # Errors
@@ -196,6 +340,21 @@ pub trait TraceStore: Send + Sync {
     ///
     /// Returns an error if the operation fails.
     fn get_newest_timestamp(&self) -> Result<Option<DateTime<Utc>>, TraceStoreError>;
+
+    /// Deletes the spans that have outlived `policy` at `now`, returning how
+    /// many were deleted.
+    ///
+    /// Stores whose database expires data itself, like `ClickHouse` with its
+    /// table TTLs, delete nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails.
+    fn expire(
+        &self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<usize, TraceStoreError>;
 }
 
 /// In-memory trace store implementation.
@@ -248,8 +407,21 @@ impl TraceStore for InMemoryTraceStore {
     fn query(&self, query: TraceQuery) -> Result<TraceQueryResult, TraceStoreError> {
         let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
 
-        let mut traces: Vec<Trace> = spans
-            .values()
+        // Like the partitions `ClickHouse` skips, traces outside the time
+        // range (based on any span in the trace) are not counted as scanned
+        let mut scan = Scan::new(&query.limits);
+        let in_range = scan.groups(
+            spans.values().filter(|spans| {
+                query
+                    .start_time
+                    .is_none_or(|start| spans.iter().any(|s| s.start_time >= start))
+                    && query
+                        .end_time
+                        .is_none_or(|end| spans.iter().any(|s| s.start_time < end))
+            }),
+            |spans| spans.len(),
+        );
+        let mut traces: Vec<Trace> = in_range
             .filter_map(|s| Trace::from_spans(s.clone()))
             .filter(|trace| {
                 // Service filter
@@ -259,18 +431,6 @@ impl TraceStore for InMemoryTraceStore {
                     }
                 }
 
-                // Time range filter (based on any span in the trace)
-                if let Some(start) = query.start_time {
-                    if !trace.spans.iter().any(|s| s.start_time >= start) {
-                        return false;
-                    }
-                }
-                if let Some(end) = query.end_time {
-                    if !trace.spans.iter().any(|s| s.start_time < end) {
-                        return false;
-                    }
-                }
-
                 // Duration filter
                 if let Some(duration) = trace.duration() {
                     let duration_ms = duration.num_milliseconds();
@@ -294,33 +454,193 @@ impl TraceStore for InMemoryTraceStore {
                     }
                 }
 
+                // Query language filter (any span may match)
+                if let Some(ref filter) = query.filter {
+                    if !trace.spans.iter().any(|s| matches_filter(filter, s)) {
+                        return false;
+                    }
+                }
+
                 true
             })
             .collect();
+        scan.finish()?;
 
         // Sort by start time (most recent first)
-        traces.sort_by(|a, b| {
-            let a_start = a.spans.iter().map(|s| s.start_time).min();
-            let b_start = b.spans.iter().map(|s| s.start_time).min();
-            b_start.cmp(&a_start)
-        });
+        sort_newest_first(&mut traces);
 
         let total_count = traces.len();
 
-        // Apply offset and limit
+        // Apply cursor, offset and limit
+        if let Some(ref cursor) = query.cursor {
+            cursor.skip(&mut traces);
+        }
         let offset = query.offset.unwrap_or(0);
         let result: Vec<Trace> = traces
             .into_iter()
             .skip(offset)
             .take(query.limit.unwrap_or(usize::MAX))
             .collect();
+        query.limits.check_result(&result)?;
 
         Ok(TraceQueryResult {
+            next_cursor: next_cursor(&result, query.limit, query.cursor.as_ref()),
             traces: result,
             total_count,
         })
     }
 
+    fn query_spans(&self, query: TraceQuery) -> Result<SpanQueryResult, TraceStoreError> {
+        let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
+
+        // Like the partitions `ClickHouse` skips, spans outside the time range
+        // are not counted as scanned
+        let mut scan = Scan::new(&query.limits);
+        let mut filtered: Vec<Span> = scan
+            .rows(spans.values().flatten().filter(|span| {
+                query
+                    .start_time
+                    .is_none_or(|start| span.start_time >= start)
+                    && query.end_time.is_none_or(|end| span.start_time < end)
+            }))
+            .filter(|span| {
+                // Service filter
+                if let Some(ref service) = query.service {
+                    if &span.service != service {
+                        return false;
+                    }
+                }
+
+                // Duration filter
+                let duration_ms = span.duration_ms();
+                if let Some(min) = query.min_duration_ms {
+                    if duration_ms < min {
+                        return false;
+                    }
+                }
+                if let Some(max) = query.max_duration_ms {
+                    if duration_ms > max {
+                        return false;
+                    }
+                }
+
+                // Status filter
+                if let Some(ref status) = query.status {
+                    if &span.status != status {
+                        return false;
+                    }
+                }
+
+                // Query language filter
+                if let Some(ref filter) = query.filter {
+                    if !matches_filter(filter, *span) {
+                        return false;
+                    }
+                }
+
+                true
+            })
+            .cloned()
+            .collect();
+        scan.finish()?;
+
+        // Sort by start time (most recent first), then by the requested order
+        sort_newest_first(&mut filtered);
+        sort_records(&mut filtered, &query.order_by);
+
+        let total_count = filtered.len();
+
+        // Apply cursor, offset and limit
+        if let Some(ref cursor) = query.cursor {
+            cursor.skip(&mut filtered);
+        }
+        let offset = query.offset.unwrap_or(0);
+        let result: Vec<Span> = filtered
+            .into_iter()
+            .skip(offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect();
+        query.limits.check_result(&result)?;
+
+        Ok(SpanQueryResult {
+            next_cursor: query
+                .order_by
+                .is_empty()
+                .then(|| next_cursor(&result, query.limit, query.cursor.as_ref()))
+                .flatten(),
+            spans: result,
+            total_count,
+        })
+    }
+
+    fn query_groups(
+        &self,
+        query: TraceQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, TraceStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query_spans(TraceQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let groups = group_records(&result.spans, group);
+        limits.check_result(&groups.rows)?;
+        Ok(groups)
+    }
+
+    fn fields(&self, query: TraceQuery) -> Result<Vec<FieldInfo>, TraceStoreError> {
+        let limits = query.limits.clone();
+        let result = self.query_spans(TraceQuery {
+            limit: None,
+            offset: None,
+            order_by: Vec::new(),
+            cursor: None,
+            limits: limits.without_result_bytes(),
+            ..query
+        })?;
+        let fields = collect_fields(&result.spans);
+        limits.check_result(&fields)?;
+        Ok(fields)
+    }
+
+    fn span_stats(&self, query: RollupQuery) -> Result<Vec<SpanStats>, TraceStoreError> {
+        let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
+        let mut scan = Scan::new(&query.limits);
+        let stats = span_stats(
+            scan.rows(
+                spans
+                    .values()
+                    .flatten()
+                    .filter(|span| query.contains_time(span.start_time)),
+            ),
+            &query,
+        );
+        scan.finish()?;
+        query.limits.check_result(&stats)?;
+        Ok(stats)
+    }
+
+    fn trace_stats(&self, query: RollupQuery) -> Result<Vec<TraceStats>, TraceStoreError> {
+        let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
+        let mut scan = Scan::new(&query.limits);
+        let stats = trace_stats(
+            scan.rows(
+                spans
+                    .values()
+                    .flatten()
+                    .filter(|span| query.contains_time(span.start_time)),
+            ),
+            &query,
+        );
+        scan.finish()?;
+        query.limits.check_result(&stats)?;
+        Ok(stats)
+    }
+
     fn span_count(&self) -> Result<usize, TraceStoreError> {
         let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
         Ok(spans.values().map(std::vec::Vec::len).sum())
@@ -346,6 +666,103 @@ impl TraceStore for InMemoryTraceStore {
         let spans = self.spans.read().map_err(|_| TraceStoreError::LockError)?;
         Ok(spans.values().flatten().map(|span| span.start_time).max())
     }
+
+    fn expire(
+        &self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<usize, TraceStoreError> {
+        let mut spans = self.spans.write().map_err(|_| TraceStoreError::LockError)?;
+        let mut expired = 0;
+        spans.retain(|_, trace| {
+            let before = trace.len();
+            trace.retain(|span| !policy.is_expired(span, span.start_time, now));
+            expired += before - trace.len();
+            !trace.is_empty()
+        });
+        Ok(expired)
+    }
+}
+
+/// Columns selected when reading spans from `ClickHouse`.
+const SPAN_COLUMNS: &str = "trace_id, span_id, parent_span_id, \
+     start_time, end_time, duration_ns, name, span_kind, service, operation, \
+     status_code, status_message, attributes, resource_attributes, \
+     events, links";
+
+/// Row structure for reading spans from `ClickHouse`.
+#[derive(clickhouse::Row, serde::Deserialize)]
+#[allow(dead_code)]
+struct SpanRow {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    start_time: i64,
+    end_time: i64,
+    duration_ns: u64,
+    name: String,
+    span_kind: String,
+    service: String,
+    operation: String,
+    status_code: String,
+    status_message: String,
+    attributes: HashMap<String, String>,
+    resource_attributes: HashMap<String, String>,
+    events: Vec<(i64, String, HashMap<String, String>)>,
+    links: Vec<(String, String, HashMap<String, String>)>,
+}
+
+impl SpanRow {
+    /// Converts a `ClickHouse` row into a `Span`.
+    fn into_span(self) -> Span {
+        let start_time = DateTime::from_timestamp_nanos(self.start_time);
+        let end_time = DateTime::from_timestamp_nanos(self.end_time);
+
+        let status = match self.status_code.as_str() {
+            "error" => crate::models::trace::SpanStatus::Error,
+            "cancelled" => crate::models::trace::SpanStatus::Cancelled,
+            _ => crate::models::trace::SpanStatus::Ok,
+        };
+
+        let kind = match self.span_kind.as_str() {
+            "server" => crate::models::trace::SpanKind::Server,
+            "client" => crate::models::trace::SpanKind::Client,
+            "producer" => crate::models::trace::SpanKind::Producer,
+            "consumer" => crate::models::trace::SpanKind::Consumer,
+            _ => crate::models::trace::SpanKind::Internal,
+        };
+
+        let events: Vec<crate::models::trace::SpanEvent> = self
+            .events
+            .into_iter()
+            .map(|(ts, name, attrs)| {
+                let timestamp = DateTime::from_timestamp_nanos(ts);
+                crate::models::trace::SpanEvent {
+                    name,
+                    timestamp,
+                    attributes: decode_attributes(attrs),
+                }
+            })
+            .collect();
+
+        Span {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            parent_span_id: if self.parent_span_id.is_empty() {
+                None
+            } else {
+                Some(self.parent_span_id)
+            },
+            name: self.name,
+            service: self.service,
+            kind,
+            status,
+            start_time,
+            end_time,
+            attributes: decode_attributes(self.attributes),
+            events,
+        }
+    }
 }
 
 /// `ClickHouse`-backed trace store implementation.
@@ -381,6 +798,103 @@ impl ClickHouseTraceStore {
                 .map_err(|e| TraceStoreError::StorageError(e.to_string()))
         })
     }
+
+    /// Like [`Self::block_on`], but stops waiting when the query is cancelled or
+    /// `deadline` passes, and reports queries stopped by `limits`.
+    fn block_on_limited<F, T>(
+        limits: &QueryLimits,
+        deadline: Option<Instant>,
+        future: F,
+    ) -> Result<T, TraceStoreError>
+    where
+        F: std::future::Future<Output = Result<T, clickhouse::error::Error>>,
+    {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(limits.run(deadline, future))
+        })?
+        .map_err(|e| {
+            LimitError::from_clickhouse(&e).map_or_else(
+                || TraceStoreError::StorageError(e.to_string()),
+                TraceStoreError::Limit,
+            )
+        })
+    }
+
+    /// Builds the count and `SELECT` statements executed by [`TraceStore::query_spans`].
+    fn build_span_statements(query: &TraceQuery, params: &mut SqlParams) -> (String, String) {
+        use std::fmt::Write as _;
+
+        let filter = Self::build_filter(query, params);
+        let count_sql = format!("SELECT count() FROM spans{filter}");
+
+        let mut sql = format!("SELECT {SPAN_COLUMNS} FROM spans{filter}");
+        if let Some(ref cursor) = query.cursor {
+            let predicate = cursor.to_sql("start_time", SPAN_TIEBREAKER, params);
+            write!(&mut sql, " AND {predicate}").unwrap();
+        }
+
+        // Add ordering
+        let mut order = compile_order(&Source::Traces, &query.order_by, params);
+        order.push(format!("start_time DESC, {SPAN_TIEBREAKER} DESC"));
+        write!(&mut sql, " ORDER BY {}", order.join(", ")).unwrap();
+
+        // Add limit and offset
+        let offset = page_offset(query.cursor.as_ref(), query.offset);
+        match query.limit {
+            Some(limit) => write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap(),
+            None if offset > 0 => write!(&mut sql, " OFFSET {offset} ROWS").unwrap(),
+            None => {}
+        }
+
+        (count_sql, sql)
+    }
+
+    /// Builds the `WHERE` clause for a span query, registering values in `params`.
+    fn build_filter(query: &TraceQuery, params: &mut SqlParams) -> String {
+        use std::fmt::Write as _;
+
+        let mut filter = String::from(" WHERE 1=1");
+
+        // Add service filter
+        if let Some(ref service) = query.service {
+            let p = params.push(SqlParam::String(service.clone()));
+            write!(&mut filter, " AND service = {p}").unwrap();
+        }
+
+        // Add time range filters
+        if let Some(start) = query.start_time {
+            let p = params.push(SqlParam::Int(start.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND start_time >= {p}").unwrap();
+        }
+        if let Some(end) = query.end_time {
+            let p = params.push(SqlParam::Int(end.timestamp_nanos_opt().unwrap_or(0)));
+            write!(&mut filter, " AND start_time < {p}").unwrap();
+        }
+
+        // Add duration filters
+        if let Some(min_duration) = query.min_duration_ms {
+            let p = params.push(SqlParam::Int(min_duration.saturating_mul(1_000_000)));
+            write!(&mut filter, " AND duration_ns >= {p}").unwrap();
+        }
+        if let Some(max_duration) = query.max_duration_ms {
+            let p = params.push(SqlParam::Int(max_duration.saturating_mul(1_000_000)));
+            write!(&mut filter, " AND duration_ns <= {p}").unwrap();
+        }
+
+        // Add status filter
+        if let Some(ref status) = query.status {
+            let p = params.push(SqlParam::String(status.to_string()));
+            write!(&mut filter, " AND status_code = {p}").unwrap();
+        }
+
+        // Add query language filter
+        if let Some(ref clause) = query.filter {
+            let predicate = compile_filter(&Source::Traces, clause, params);
+            write!(&mut filter, " AND {predicate}").unwrap();
+        }
+
+        filter
+    }
 }
 
 impl TraceStore for ClickHouseTraceStore {
@@ -423,27 +937,15 @@ impl TraceStore for ClickHouseTraceStore {
                     .num_nanoseconds()
                     .unwrap_or(0);
 
-                // Convert attributes to Map
-                let attributes: HashMap<String, String> = span
-                    .attributes
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.to_string()))
-                    .collect();
-
                 // Convert events to array of tuples
                 let events: Vec<(i64, String, HashMap<String, String>)> = span
                     .events
                     .iter()
                     .map(|e| {
-                        let attrs: HashMap<String, String> = e
-                            .attributes
-                            .iter()
-                            .map(|(k, v)| (k.clone(), v.to_string()))
-                            .collect();
                         (
                             e.timestamp.timestamp_nanos_opt().unwrap_or(0),
                             e.name.clone(),
-                            attrs,
+                            encode_attributes(&e.attributes),
                         )
                     })
                     .collect();
@@ -455,13 +957,13 @@ impl TraceStore for ClickHouseTraceStore {
                     start_time: span.start_time.timestamp_nanos_opt().unwrap_or(0),
                     end_time: span.end_time.timestamp_nanos_opt().unwrap_or(0),
                     duration_ns: u64::try_from(duration_ns).unwrap_or(0),
-                    name: span.name,
+                    name: span.name.clone(),
                     span_kind: span.kind.to_string(),
                     service: span.service.clone(),
-                    operation: span.service,
+                    operation: span.name,
                     status_code: span.status.to_string(),
                     status_message: String::new(),
-                    attributes,
+                    attributes: encode_attributes(&span.attributes),
                     resource_attributes: HashMap::new(),
                     events,
                     links: Vec::new(),
@@ -475,45 +977,21 @@ impl TraceStore for ClickHouseTraceStore {
         })
     }
 
-    #[allow(clippy::too_many_lines)]
     fn get_trace(&self, trace_id: &str) -> Result<Trace, TraceStoreError> {
-        // Define row structure for deserialization
-        #[derive(clickhouse::Row, serde::Deserialize)]
-        #[allow(dead_code)]
-        struct SpanRow {
-            trace_id: String,
-            span_id: String,
-            parent_span_id: String,
-            start_time: i64,
-            end_time: i64,
-            duration_ns: u64,
-            name: String,
-            span_kind: String,
-            service: String,
-            operation: String,
-            status_code: String,
-            status_message: String,
-            attributes: HashMap<String, String>,
-            resource_attributes: HashMap<String, String>,
-            events: Vec<(i64, String, HashMap<String, String>)>,
-            links: Vec<(String, String, HashMap<String, String>)>,
-        }
-
         let trace_id = trace_id.to_string();
         let trace_id_for_error = trace_id.clone();
         let client = Arc::clone(&self.client);
 
         Self::block_on(async move {
             let sql = format!(
-                "SELECT trace_id, span_id, parent_span_id, \
-                 start_time, end_time, duration_ns, name, span_kind, service, operation, \
-                 status_code, status_message, attributes, resource_attributes, \
-                 events, links \
-                 FROM spans WHERE trace_id = '{}' ORDER BY start_time",
-                trace_id.replace('\'', "''")
+                "SELECT {SPAN_COLUMNS} FROM spans WHERE trace_id = {{trace_id:String}} ORDER BY start_time"
             );
 
-            let rows: Vec<SpanRow> = client.query(&sql).fetch_all::<SpanRow>().await?;
+            let rows: Vec<SpanRow> = client
+                .query(&sql)
+                .param("trace_id", &trace_id)
+                .fetch_all::<SpanRow>()
+                .await?;
 
             if rows.is_empty() {
                 return Err(clickhouse::error::Error::Custom(format!(
@@ -522,68 +1000,7 @@ impl TraceStore for ClickHouseTraceStore {
             }
 
             // Convert rows to Spans
-            let spans: Vec<Span> = rows
-                .into_iter()
-                .map(|row| {
-                    let start_time = DateTime::from_timestamp_nanos(row.start_time);
-                    let end_time = DateTime::from_timestamp_nanos(row.end_time);
-
-                    let status = match row.status_code.as_str() {
-                        "error" => crate::models::trace::SpanStatus::Error,
-                        "cancelled" => crate::models::trace::SpanStatus::Cancelled,
-                        _ => crate::models::trace::SpanStatus::Ok,
-                    };
-
-                    let kind = match row.span_kind.as_str() {
-                        "server" => crate::models::trace::SpanKind::Server,
-                        "client" => crate::models::trace::SpanKind::Client,
-                        "producer" => crate::models::trace::SpanKind::Producer,
-                        "consumer" => crate::models::trace::SpanKind::Consumer,
-                        _ => crate::models::trace::SpanKind::Internal,
-                    };
-
-                    let attributes: HashMap<String, serde_json::Value> = row
-                        .attributes
-                        .into_iter()
-                        .map(|(k, v)| (k, serde_json::Value::String(v)))
-                        .collect();
-
-                    let events: Vec<crate::models::trace::SpanEvent> = row
-                        .events
-                        .into_iter()
-                        .map(|(ts, name, attrs)| {
-                            let timestamp = DateTime::from_timestamp_nanos(ts);
-                            let event_attrs: HashMap<String, serde_json::Value> = attrs
-                                .into_iter()
-                                .map(|(k, v)| (k, serde_json::Value::String(v)))
-                                .collect();
-                            crate::models::trace::SpanEvent {
-                                name,
-                                timestamp,
-                                attributes: event_attrs,
-                            }
-                        })
-                        .collect();
-
-                    Span {
-                        trace_id: row.trace_id,
-                        span_id: row.span_id,
-                        parent_span_id: if row.parent_span_id.is_empty() {
-                            None
-                        } else {
-                            Some(row.parent_span_id)
-                        },
-                        name: row.name,
-                        service: row.service,
-                        kind,
-                        status,
-                        start_time,
-                        end_time,
-                        attributes,
-                        events,
-                    }
-                })
-                .collect();
+            let spans: Vec<Span> = rows.into_iter().map(SpanRow::into_span).collect();
 
             Trace::from_spans(spans).ok_or_else(|| {
                 clickhouse::error::Error::Custom("Failed to construct trace".to_string())
@@ -597,170 +1014,70 @@ impl TraceStore for ClickHouseTraceStore {
         })
     }
 
-    #[allow(clippy::too_many_lines)]
     fn query(&self, query: TraceQuery) -> Result<TraceQueryResult, TraceStoreError> {
         use std::fmt::Write as _;
 
-        // Define row structure for deserialization
-        #[derive(clickhouse::Row, serde::Deserialize)]
-        #[allow(dead_code)]
-        struct SpanRow {
-            trace_id: String,
-            span_id: String,
-            parent_span_id: String,
-            start_time: i64,
-            end_time: i64,
-            duration_ns: u64,
-            name: String,
-            span_kind: String,
-            service: String,
-            operation: String,
-            status_code: String,
-            status_message: String,
-            attributes: HashMap<String, String>,
-            resource_attributes: HashMap<String, String>,
-            events: Vec<(i64, String, HashMap<String, String>)>,
-            links: Vec<(String, String, HashMap<String, String>)>,
-        }
+        // Build SQL to get unique trace IDs matching filters
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
 
-        let client = Arc::clone(&self.client);
-
-        Self::block_on(async move {
-            // Build SQL to get unique trace IDs matching filters
-            let mut sql = String::from("SELECT DISTINCT trace_id FROM spans WHERE 1=1");
+        let count_sql = format!("SELECT count(DISTINCT trace_id) FROM spans{filter}");
 
-            // Add service filter
-            if let Some(ref service) = query.service {
-                write!(&mut sql, " AND service = '{}'", service.replace('\'', "''")).unwrap();
-            }
+        // Traces are ordered by their earliest matching span, newest first
+        let mut sql = format!(
+            "SELECT trace_id, min(start_time) AS trace_start FROM spans{filter} GROUP BY trace_id"
+        );
+        if let Some(ref cursor) = query.cursor {
+            let predicate = cursor.to_sql("trace_start", "trace_id", &mut params);
+            write!(&mut sql, " HAVING {predicate}").unwrap();
+        }
+        sql.push_str(" ORDER BY trace_start DESC, trace_id DESC");
 
-            // Add time range filters
-            if let Some(start) = query.start_time {
-                write!(
-                    &mut sql,
-                    " AND start_time >= {}",
-                    start.timestamp_nanos_opt().unwrap_or(0)
-                )
-                .unwrap();
-            }
-            if let Some(end) = query.end_time {
-                write!(
-                    &mut sql,
-                    " AND start_time < {}",
-                    end.timestamp_nanos_opt().unwrap_or(0)
-                )
-                .unwrap();
-            }
+        // Add limit and offset
+        let offset = page_offset(query.cursor.as_ref(), query.offset);
+        let limit = query.limit.unwrap_or(100);
+        write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap();
 
-            // Add duration filters
-            if let Some(min_duration) = query.min_duration_ms {
-                write!(&mut sql, " AND duration_ns >= {}", min_duration * 1_000_000).unwrap();
-            }
-            if let Some(max_duration) = query.max_duration_ms {
-                write!(&mut sql, " AND duration_ns <= {}", max_duration * 1_000_000).unwrap();
-            }
+        let cursor = query.cursor.clone();
+        let client = Arc::clone(&self.client);
 
-            // Add status filter
-            if let Some(ref status) = query.status {
-                write!(&mut sql, " AND status_code = '{status}'").unwrap();
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            #[derive(clickhouse::Row, serde::Deserialize)]
+            struct TraceIdRow {
+                trace_id: String,
+                #[allow(dead_code)]
+                trace_start: i64,
             }
 
-            sql.push_str(" ORDER BY trace_id DESC");
-
-            // Calculate total count
-            let count_sql = sql.replace(
-                "SELECT DISTINCT trace_id FROM spans",
-                "SELECT count(DISTINCT trace_id) FROM spans",
-            );
-
-            // Add limit and offset
-            let offset = query.offset.unwrap_or(0);
-            let limit = query.limit.unwrap_or(100);
-            write!(&mut sql, " LIMIT {limit} OFFSET {offset}").unwrap();
-
             // Execute count query
-            let total_count: u64 = client.query(&count_sql).fetch_one::<u64>().await?;
+            let total_count: u64 = limits
+                .apply(params.bind(client.query(&count_sql)))
+                .fetch_one::<u64>()
+                .await?;
 
             // Execute main query to get trace IDs
-            let trace_ids: Vec<String> = client.query(&sql).fetch_all::<String>().await?;
+            let trace_ids: Vec<String> = limits
+                .apply(params.bind(client.query(&sql)))
+                .fetch_all::<TraceIdRow>()
+                .await?
+                .into_iter()
+                .map(|row| row.trace_id)
+                .collect();
 
             // Fetch full traces for each ID
+            let span_sql = format!(
+                "SELECT {SPAN_COLUMNS} FROM spans WHERE trace_id = {{trace_id:String}} ORDER BY start_time"
+            );
             let mut traces = Vec::new();
             for trace_id in trace_ids {
-                // Use internal get_trace implementation
-                let span_sql = format!(
-                    "SELECT trace_id, span_id, parent_span_id, \
-                     start_time, end_time, duration_ns, name, span_kind, service, operation, \
-                     status_code, status_message, attributes, resource_attributes, \
-                     events, links \
-                     FROM spans WHERE trace_id = '{}' ORDER BY start_time",
-                    trace_id.replace('\'', "''")
-                );
-
-                let rows: Vec<SpanRow> = client.query(&span_sql).fetch_all::<SpanRow>().await?;
-
-                let spans: Vec<Span> = rows
-                    .into_iter()
-                    .map(|row| {
-                        let start_time = DateTime::from_timestamp_nanos(row.start_time);
-                        let end_time = DateTime::from_timestamp_nanos(row.end_time);
-
-                        let status = match row.status_code.as_str() {
-                            "error" => crate::models::trace::SpanStatus::Error,
-                            "cancelled" => crate::models::trace::SpanStatus::Cancelled,
-                            _ => crate::models::trace::SpanStatus::Ok,
-                        };
-
-                        let kind = match row.span_kind.as_str() {
-                            "server" => crate::models::trace::SpanKind::Server,
-                            "client" => crate::models::trace::SpanKind::Client,
-                            "producer" => crate::models::trace::SpanKind::Producer,
-                            "consumer" => crate::models::trace::SpanKind::Consumer,
-                            _ => crate::models::trace::SpanKind::Internal,
-                        };
-
-                        let attributes: HashMap<String, serde_json::Value> = row
-                            .attributes
-                            .into_iter()
-                            .map(|(k, v)| (k, serde_json::Value::String(v)))
-                            .collect();
-
-                        let events: Vec<crate::models::trace::SpanEvent> = row
-                            .events
-                            .into_iter()
-                            .map(|(ts, name, attrs)| {
-                                let timestamp = DateTime::from_timestamp_nanos(ts);
-                                let event_attrs: HashMap<String, serde_json::Value> = attrs
-                                    .into_iter()
-                                    .map(|(k, v)| (k, serde_json::Value::String(v)))
-                                    .collect();
-                                crate::models::trace::SpanEvent {
-                                    name,
-                                    timestamp,
-                                    attributes: event_attrs,
-                                }
-                            })
-                            .collect();
-
-                        Span {
-                            trace_id: row.trace_id,
-                            span_id: row.span_id,
-                            parent_span_id: if row.parent_span_id.is_empty() {
-                                None
-                            } else {
-                                Some(row.parent_span_id)
-                            },
-                            name: row.name,
-                            service: row.service,
-                            kind,
-                            status,
-                            start_time,
-                            end_time,
-                            attributes,
-                            events,
-                        }
-                    })
-                    .collect();
+                let rows: Vec<SpanRow> = limits
+                    .apply(client.query(&span_sql))
+                    .param("trace_id", &trace_id)
+                    .fetch_all::<SpanRow>()
+                    .await?;
+
+                let spans: Vec<Span> = rows.into_iter().map(SpanRow::into_span).collect();
 
                 if let Some(trace) = Trace::from_spans(spans) {
                     traces.push(trace);
@@ -768,12 +1085,112 @@ impl TraceStore for ClickHouseTraceStore {
             }
 
             Ok(TraceQueryResult {
+                next_cursor: next_cursor(&traces, Some(limit), cursor.as_ref()),
                 traces,
                 total_count: usize::try_from(total_count).unwrap_or(usize::MAX),
             })
         })
     }
 
+    fn query_spans(&self, query: TraceQuery) -> Result<SpanQueryResult, TraceStoreError> {
+        let mut params = SqlParams::new();
+        let (count_sql, sql) = Self::build_span_statements(&query, &mut params);
+
+        let limit = query.limit;
+        let default_order = query.order_by.is_empty();
+        let cursor = query.cursor.clone();
+        let client = Arc::clone(&self.client);
+
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            let total_count: u64 = limits
+                .apply(params.bind(client.query(&count_sql)))
+                .fetch_one::<u64>()
+                .await?;
+
+            let rows: Vec<SpanRow> = limits
+                .apply(params.bind(client.query(&sql)))
+                .fetch_all::<SpanRow>()
+                .await?;
+
+            let spans: Vec<Span> = rows.into_iter().map(SpanRow::into_span).collect();
+
+            Ok(SpanQueryResult {
+                next_cursor: default_order
+                    .then(|| next_cursor(&spans, limit, cursor.as_ref()))
+                    .flatten(),
+                spans,
+                total_count: usize::try_from(total_count).unwrap_or(usize::MAX),
+            })
+        })
+    }
+
+    fn query_groups(
+        &self,
+        query: TraceQuery,
+        group: &GroupQuery,
+    ) -> Result<GroupQueryResult, TraceStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_group_query(&Source::Traces, "spans", &filter, group, &mut params);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_groups(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn fields(&self, query: TraceQuery) -> Result<Vec<FieldInfo>, TraceStoreError> {
+        let mut params = SqlParams::new();
+        let filter = Self::build_filter(&query, &mut params);
+        let sql = compile_fields_query(&Source::Traces, "spans", &filter);
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async move {
+            fetch_fields(&client, &sql, &params, limits).await
+        })
+    }
+
+    fn span_stats(&self, query: RollupQuery) -> Result<Vec<SpanStats>, TraceStoreError> {
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            fetch_span_stats(&client, &query).await
+        })
+    }
+
+    fn trace_stats(&self, query: RollupQuery) -> Result<Vec<TraceStats>, TraceStoreError> {
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            fetch_trace_stats(&client, &query).await
+        })
+    }
+
+    fn explain(
+        &self,
+        query: TraceQuery,
+        group: Option<&GroupQuery>,
+    ) -> Result<StorePlan, TraceStoreError> {
+        let mut params = SqlParams::new();
+        let statements = if let Some(group) = group {
+            let filter = Self::build_filter(&query, &mut params);
+            let sql = compile_group_query(&Source::Traces, "spans", &filter, group, &mut params);
+            vec![sql.count, sql.select]
+        } else {
+            let (count_sql, sql) = Self::build_span_statements(&query, &mut params);
+            vec![count_sql, sql]
+        };
+
+        let client = Arc::clone(&self.client);
+        let limits = &query.limits;
+        Self::block_on_limited(limits, limits.deadline(Instant::now()), async {
+            Ok(explain_statements(&client, statements, &params, limits).await)
+        })
+    }
+
     fn span_count(&self) -> Result<usize, TraceStoreError> {
         let client = Arc::clone(&self.client);
         let count: u64 = Self::block_on(async move {
@@ -820,6 +1237,15 @@ impl TraceStore for ClickHouseTraceStore {
             Ok(result.map(DateTime::from_timestamp_nanos))
         })
     }
+
+    fn expire(
+        &self,
+        _policy: &RetentionPolicy,
+        _now: DateTime<Utc>,
+    ) -> Result<usize, TraceStoreError> {
+        // The table TTL deletes expired rows
+        Ok(0)
+    }
 }
 
 #[cfg(test)]
@@ -1003,6 +1429,45 @@ mod tests {
         assert_eq!(result.total_count, 10);
     }
 
+    #[test]
+    fn test_query_with_cursor() {
+        let store = InMemoryTraceStore::new();
+        let start = Utc::now();
+
+        // Traces starting at the same time are ordered by trace ID
+        for (trace_id, offset) in [("a", 0), ("b", 0), ("c", 1), ("d", 2)] {
+            store
+                .insert_span(
+                    create_test_span(trace_id, "root", "api")
+                        .with_start_time(start + Duration::seconds(offset)),
+                )
+                .unwrap();
+        }
+
+        let first = store.query(TraceQuery::new().with_limit(3)).unwrap();
+        let ids: Vec<&str> = first.traces.iter().map(|t| t.trace_id.as_str()).collect();
+        assert_eq!(ids, ["d", "c", "b"]);
+
+        let cursor = first.next_cursor.unwrap();
+        let second = store
+            .query(TraceQuery::new().with_limit(3).with_cursor(cursor))
+            .unwrap();
+        assert_eq!(second.traces.len(), 1);
+        assert_eq!(second.traces[0].trace_id, "a");
+        assert_eq!(second.total_count, 4);
+        assert!(second.next_cursor.is_none());
+
+        // Spans are paginated by start time, trace ID and span ID; all span IDs
+        // are the same here
+        let spans = store.query_spans(TraceQuery::new().with_limit(3)).unwrap();
+        let cursor = spans.next_cursor.unwrap();
+        let rest = store
+            .query_spans(TraceQuery::new().with_limit(3).with_cursor(cursor))
+            .unwrap();
+        assert_eq!(rest.spans.len(), 1);
+        assert_eq!(rest.spans[0].trace_id, "a");
+    }
+
     #[test]
     fn test_clear_store() {
         let store = InMemoryTraceStore::new();
@@ -1015,4 +1480,41 @@ mod tests {
         assert_eq!(store.span_count().unwrap(), 0);
         assert_eq!(store.trace_count().unwrap(), 0);
     }
+
+    #[test]
+    fn test_query_spans_with_filter_and_order() {
+        use crate::query::parse_query;
+
+        let store = InMemoryTraceStore::new();
+        let start = Utc::now();
+        store
+            .insert_spans(vec![
+                create_test_span("trace-1", "span-1", "api")
+                    .with_start_time(start)
+                    .with_end_time(start + Duration::milliseconds(300)),
+                create_test_span("trace-1", "span-2", "db")
+                    .with_parent("span-1")
+                    .with_start_time(start)
+                    .with_end_time(start + Duration::milliseconds(200)),
+                create_test_span("trace-2", "span-3", "api")
+                    .with_start_time(start)
+                    .with_end_time(start + Duration::milliseconds(5)),
+            ])
+            .unwrap();
+
+        let parsed =
+            parse_query("SELECT * FROM traces WHERE duration_ms >= 100 ORDER BY duration_ms ASC")
+                .unwrap();
+        let result = store
+            .query_spans(
+                TraceQuery::new()
+                    .with_filter(parsed.where_clause.unwrap())
+                    .with_order_by(parsed.order_by),
+            )
+            .unwrap();
+
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.spans[0].span_id, "span-2");
+        assert_eq!(result.spans[1].span_id, "span-1");
+    }
 }